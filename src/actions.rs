@@ -1,129 +1,932 @@
-//! Side-effectful actions applied to duplicate groups: delete or hardlink.
+//! Side-effectful actions applied to duplicate groups: delete, trash, hardlink, reflink,
+//! dedupe-extents or symlink.
+//!
+//! Each action implements [`Action`]: it plans one [`Step`] per duplicate and executes
+//! steps; [`run_action`] wraps any implementation in the shared safety checks, dry-run
+//! handling, stats and journaling. The built-ins are listed in [`BUILTIN`].
 
+use crate::budget::{Budget, Errors};
+use crate::fs_utils::{path_glob_matches, resolve_parent, FileMeta};
+use crate::hashing::{full_hash, same_bytes};
+use crate::journal::{Journal, Op};
 use crate::output::DuplicateGroup;
+use crate::reflink;
+use crate::status::Stage;
+use crate::warnings::warning;
+use std::collections::HashSet;
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
-/// Apply --delete or --hardlink on duplicate groups.
-/// Keeps the first path in each group as the canonical file.
+/// Which action to apply and the safety switches that guard it.
+#[derive(Debug, Default, Clone)]
+pub struct ActionOptions {
+    pub delete: bool,
+    /// Like `delete`, but send duplicates to the OS trash / Recycle Bin.
+    pub trash: bool,
+    pub hardlink: bool,
+    pub reflink: bool,
+    /// Share extents with the canonical in place (FIDEDUPERANGE); the kernel compares the bytes.
+    pub dedupe_extents: bool,
+    pub symlink: bool,
+    /// With `symlink`, link with a path relative to the duplicate's directory.
+    pub symlink_relative: bool,
+    /// With `symlink`, hardlink instead where creating symlinks is not permitted.
+    pub symlink_fallback_hardlink: bool,
+    /// Side effects only happen when this is set.
+    pub yes: bool,
+    /// Run every check and report what would happen, without touching the filesystem.
+    pub dry_run: bool,
+    /// Re-hash the canonical before touching a group; abort the group on mismatch.
+    pub paranoid: bool,
+    /// Re-hash each file right before it is modified or kept as the canonical, on top of the
+    /// size and mtime check (see `unchanged_since_scan`).
+    pub verify: bool,
+    /// When non-empty, only files below these (canonicalized) directories are modified.
+    pub act_only_under: Vec<PathBuf>,
+    /// Files matching any of these patterns are never modified (see `is_protected`).
+    pub protect: Vec<glob::Pattern>,
+    /// Files below these (canonicalized) `--compare` reference directories are never modified.
+    pub reference: Vec<PathBuf>,
+    /// Members inside read-only snapshots (`snapshots::Snapshots`); never modified.
+    pub snapshots: HashSet<PathBuf>,
+    /// Halves of RAW+JPEG pairs that can't be acted on without splitting the pair
+    /// (`rawpairs::split_pairs`).
+    pub held_pairs: HashSet<PathBuf>,
+    /// Leave the first N members of each group alone instead of only the canonical; 0 and 1
+    /// both keep one.
+    pub keep_copies: usize,
+    /// Errors of the run (`Scanner::errors`); failed steps are counted here as they happen.
+    pub errors: Arc<Errors>,
+    /// Stop before the next group once `errors` exceed this budget.
+    pub budget: Budget,
+    /// Files found by the walk, for the budget's error rate.
+    pub files_found: u64,
+}
+
+/// Counters of one run of an action over duplicate groups (see `run_action`); the first path
+/// of each group is the canonical and is never counted.
 #[derive(Debug, Default, Clone, Copy)]
 pub struct ActionStats {
     pub deleted: usize,
+    pub trashed: usize,
     pub linked: usize,
+    pub reflinked: usize,
+    /// Duplicates now sharing the canonical's extents (--dedupe-extents).
+    pub deduped: usize,
+    pub symlinked: usize,
+    /// Copies relocated to another device by --replicate.
+    pub moved: usize,
     pub skipped_same_inode: usize,
     pub skipped_cross_device: usize,
+    /// Clones or extent sharing the filesystem refused (no support, or across filesystems).
+    pub skipped_unsupported: usize,
+    /// Symlinks the OS refused for lack of privilege (Windows without Developer Mode).
+    pub skipped_privilege: usize,
+    /// Operations the journal shows were already applied by an earlier run.
+    pub already_applied: usize,
+    /// Groups skipped because --paranoid could not re-verify the canonical (also counted in errors).
+    pub aborted_groups: usize,
+    /// Groups that changed after hashing and failed re-verification.
+    pub skipped_stale: usize,
+    /// Duplicates left alone because `may_touch` refused them: outside every --act-only-under
+    /// directory, matching --protect, under a --compare reference or inside a snapshot.
+    pub skipped_protected: usize,
+    /// Halves of RAW+JPEG pairs left alone so the pair stays whole (--raw-jpeg-pairs).
+    pub skipped_pair: usize,
+    /// Files whose size or mtime (or, with --verify, content) differ from the scan's; a
+    /// changed canonical skips its whole group.
+    pub changed_since_scan: usize,
     pub errors: usize,
+    /// Bytes freed by removing or replacing duplicates (projected under --dry-run).
+    pub freed_bytes: u64,
 }
 
-/// Apply the requested action and return stats. Side effects only when `yes` is true.
-pub fn apply_actions(groups: &[DuplicateGroup], delete: bool, hardlink: bool, yes: bool) -> ActionStats {
-    let mut stats = ActionStats::default();
-    if !(delete || hardlink) {
-        return stats; // no-op
+impl ActionStats {
+    /// Accumulate the counters of another batch.
+    pub fn add(&mut self, o: &ActionStats) {
+        self.deleted += o.deleted;
+        self.trashed += o.trashed;
+        self.linked += o.linked;
+        self.reflinked += o.reflinked;
+        self.deduped += o.deduped;
+        self.symlinked += o.symlinked;
+        self.moved += o.moved;
+        self.skipped_same_inode += o.skipped_same_inode;
+        self.skipped_cross_device += o.skipped_cross_device;
+        self.skipped_unsupported += o.skipped_unsupported;
+        self.skipped_privilege += o.skipped_privilege;
+        self.already_applied += o.already_applied;
+        self.aborted_groups += o.aborted_groups;
+        self.skipped_stale += o.skipped_stale;
+        self.skipped_protected += o.skipped_protected;
+        self.skipped_pair += o.skipped_pair;
+        self.changed_since_scan += o.changed_since_scan;
+        self.errors += o.errors;
+        self.freed_bytes += o.freed_bytes;
+    }
+
+    /// Print the `actions: ...` line to stderr, plus a "nothing to do" note when every
+    /// operation had already been applied by an earlier run.
+    pub fn report(&self) {
+        eprintln!(
+            "actions: deleted={} trashed={} linked={} reflinked={} deduped={} symlinked={} moved={} skipped_same_inode={} skipped_cross_device={} skipped_unsupported={} skipped_privilege={} already_applied={} aborted_groups={} skipped_stale={} skipped_protected={} skipped_pair={} changed_since_scan={} errors={} freed={}",
+            self.deleted,
+            self.trashed,
+            self.linked,
+            self.reflinked,
+            self.deduped,
+            self.symlinked,
+            self.moved,
+            self.skipped_same_inode,
+            self.skipped_cross_device,
+            self.skipped_unsupported,
+            self.skipped_privilege,
+            self.already_applied,
+            self.aborted_groups,
+            self.skipped_stale,
+            self.skipped_protected,
+            self.skipped_pair,
+            self.changed_since_scan,
+            self.errors,
+            self.freed_bytes
+        );
+        let done = self.deleted
+            + self.trashed
+            + self.linked
+            + self.reflinked
+            + self.deduped
+            + self.symlinked
+            + self.moved;
+        if self.already_applied > 0 && done + self.errors == 0 {
+            eprintln!(
+                "nothing to do: {} operations were already applied by a previous run",
+                self.already_applied
+            );
+        }
+    }
+
+    /// Count one completed (or, under --dry-run, projected) operation.
+    fn count_done(&mut self, op: Op) {
+        match op {
+            Op::Delete => self.deleted += 1,
+            Op::Trash => self.trashed += 1,
+            Op::Hardlink => self.linked += 1,
+            Op::Reflink => self.reflinked += 1,
+            Op::Dedupe => self.deduped += 1,
+            Op::Symlink => self.symlinked += 1,
+            Op::Move => self.moved += 1,
+            Op::Undo => {}
+        }
+    }
+}
+
+/// Under `--dry-run`, print the operation that would be applied and return true.
+pub(crate) fn dry_run(opts: &ActionOptions, what: std::fmt::Arguments) -> bool {
+    if opts.dry_run {
+        eprintln!("dry-run: {}", what);
+    }
+    opts.dry_run
+}
+
+/// One operation on one duplicate: `path` is the duplicate, `target` the canonical it is
+/// replaced by (or, for moves, the destination). Recorded as-is in the journal.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Step {
+    pub op: Op,
+    pub path: PathBuf,
+    pub target: PathBuf,
+}
+
+impl std::fmt::Display for Step {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (path, target) = (self.path.display(), self.target.display());
+        match self.op {
+            Op::Delete | Op::Trash => write!(f, "{} {} (dup of {})", self.op.name(), path, target),
+            op => write!(f, "{} {} -> {}", op.name(), path, target),
+        }
+    }
+}
+
+/// What an action decided for one duplicate.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Planned {
+    Run(Step),
+    /// Already the same file as the canonical.
+    SameInode,
+    /// The operation can't cross filesystems.
+    CrossDevice,
+    /// The journal shows an earlier run did this.
+    AlreadyApplied,
+}
+
+/// Result of executing one step.
+#[derive(Debug)]
+pub enum Outcome {
+    Done,
+    AlreadyApplied,
+    /// The filesystem can't do this (e.g. no reflink support); not an error.
+    Unsupported(std::io::Error),
+    /// The OS refused for lack of privilege; reported once per run rather than per file.
+    NoPrivilege(std::io::Error),
+    /// Done, but with a different operation than planned (e.g. a hardlink instead of a symlink).
+    DoneAs(Op),
+    Failed(std::io::Error),
+}
+
+/// A way of acting on duplicate groups. Implementations only decide and perform operations;
+/// `run_action` supplies the shared safety checks (stale re-verification, `--paranoid`,
+/// `--yes`, `--dry-run`), the stats and the journal.
+pub trait Action {
+    /// Plan every non-canonical member of `group` (`group.members[0]` is kept).
+    fn plan(&self, group: &DuplicateGroup, journal: &Journal) -> Vec<Planned>;
+
+    /// Perform one planned step.
+    fn execute(&self, step: &Step, group: &DuplicateGroup, journal: &Journal) -> Outcome;
+}
+
+/// Builds an action from the command-line options.
+pub type MakeAction = fn(&ActionOptions) -> Box<dyn Action>;
+
+/// Built-in actions selected by `ActionOptions` flags, by name.
+pub const BUILTIN: &[(&str, MakeAction)] = &[
+    ("delete", |_| Box::new(Delete)),
+    ("trash", |_| Box::new(Trash)),
+    ("hardlink", |_| Box::new(Hardlink)),
+    ("reflink", |_| Box::new(Reflink)),
+    ("dedupe-extents", |_| Box::new(DedupeExtents)),
+    ("symlink", |o| {
+        let fallback_hardlink = o.symlink_fallback_hardlink;
+        Box::new(Symlink { relative: o.symlink_relative, fallback_hardlink })
+    }),
+];
+
+impl ActionOptions {
+    /// Name of the built-in action these options select, if any.
+    pub fn selected(&self) -> Option<&'static str> {
+        [
+            (self.delete, "delete"),
+            (self.trash, "trash"),
+            (self.hardlink, "hardlink"),
+            (self.reflink, "reflink"),
+            (self.dedupe_extents, "dedupe-extents"),
+            (self.symlink, "symlink"),
+        ]
+        .into_iter()
+        .find_map(|(on, name)| on.then_some(name))
+    }
+
+    /// Whether `path` may be modified under `act_only_under`, `protect`, `reference` and
+    /// `snapshots`. Its directory is resolved, so symlinked or `..` paths can't slip out of the
+    /// allowed subtrees, past a protect pattern or into a reference tree.
+    pub fn may_touch(&self, path: &Path) -> bool {
+        if self.snapshots.contains(path) {
+            return false;
+        }
+        if self.act_only_under.is_empty() && self.protect.is_empty() && self.reference.is_empty() {
+            return true;
+        }
+        let Some(real) = resolve_parent(path) else { return false };
+        (self.act_only_under.is_empty()
+            || self.act_only_under.iter().any(|allowed| real.starts_with(allowed)))
+            && !path_glob_matches(&self.protect, &real)
+            && !self.reference.iter().any(|r| real.starts_with(r))
+    }
+
+    /// Why acting must stop: the run's errors exceed the budget.
+    pub fn over_budget(&self) -> Option<String> {
+        self.budget.exceeded(&self.errors.counts(), self.files_found)
+    }
+
+    /// Count a failed step, in `stats` and in the run's errors.
+    pub(crate) fn failed(&self, stats: &mut ActionStats) {
+        stats.errors += 1;
+        self.errors.record(Stage::Act);
+    }
+}
+
+/// `g` without the members `--keep-copies n` keeps next to the canonical; None when nothing is
+/// left to act on. Copies are counted by inode: a hard link of a kept copy is another name for
+/// it, so it is kept too but doesn't count towards `n`.
+fn spare_copies(g: &DuplicateGroup, n: usize) -> Option<DuplicateGroup> {
+    let mut copies: Vec<&PathBuf> = Vec::new();
+    let mut spare = g.clone();
+    spare.members.clear();
+    for m in &g.members {
+        if copies.iter().any(|c| same_inode(c, m)) {
+            continue;
+        }
+        if copies.len() < n && !g.links.contains(m) {
+            copies.push(m);
+            if copies.len() > 1 {
+                continue;
+            }
+        }
+        spare.members.push(m.clone());
+    }
+    if spare.members.len() < 2 {
+        return None;
     }
+    spare.links.retain(|l| spare.members.contains(l));
+    Some(spare)
+}
+
+/// Whether `path` matches a `--protect` pattern (see `fs_utils::path_glob_matches`). A path
+/// that can't be resolved counts as protected.
+pub fn is_protected(patterns: &[glob::Pattern], path: &Path) -> bool {
+    !patterns.is_empty()
+        && resolve_parent(path).is_none_or(|real| path_glob_matches(patterns, &real))
+}
+
+/// Duplicate groups together with the action to take on them, for embedders that don't build
+/// their own options from a command line.
+#[derive(Debug, Default)]
+pub struct ActionPlan {
+    /// `members[0]` of each group is kept.
+    pub groups: Vec<DuplicateGroup>,
+    pub options: ActionOptions,
+}
+
+impl ActionPlan {
+    pub fn new(groups: Vec<DuplicateGroup>, options: ActionOptions) -> Self {
+        Self { groups, options }
+    }
+
+    /// Run the plan through `apply_actions`.
+    pub fn apply(&self, journal: &mut Journal) -> ActionStats {
+        apply_actions(&self.groups, &self.options, journal)
+    }
+}
+
+/// Apply the requested action and return stats. Side effects only when `opts.yes` is true;
+/// with `opts.dry_run` every check runs but the operations are only printed.
+/// Completed operations are recorded in `journal`, which is also consulted so that
+/// re-running the same actions reports them as already applied rather than as errors.
+pub fn apply_actions(groups: &[DuplicateGroup], opts: &ActionOptions, journal: &mut Journal) -> ActionStats {
+    let Some(name) = opts.selected() else {
+        return ActionStats::default(); // no-op
+    };
+    let Some((_, make)) = BUILTIN.iter().find(|(n, _)| *n == name) else {
+        return ActionStats::default();
+    };
+    run_action(make(opts).as_ref(), groups, opts, journal)
+}
+
+/// Delete (or trash) every member but the first of each group for `ducky apply`, and print the
+/// stats; `verify` is `--paranoid`.
+pub fn apply_groups(
+    groups: &[DuplicateGroup],
+    trash: bool,
+    yes: bool,
+    dry_run: bool,
+    verify: bool,
+) -> ActionStats {
+    let opts = ActionOptions {
+        delete: !trash,
+        trash,
+        yes,
+        dry_run,
+        paranoid: verify,
+        ..Default::default()
+    };
+    let mut journal =
+        if yes && !dry_run { Journal::open_default() } else { Journal::in_memory() };
+    let stats = apply_actions(groups, &opts, &mut journal);
+    stats.report();
+    if dry_run {
+        eprintln!("dry-run: would free {}", bytesize::ByteSize(stats.freed_bytes));
+    }
+    stats
+}
+
+/// Drive `action` over `groups` with the shared checks, stats and journaling.
+pub fn run_action(
+    action: &dyn Action,
+    groups: &[DuplicateGroup],
+    opts: &ActionOptions,
+    journal: &mut Journal,
+) -> ActionStats {
+    let mut stats = ActionStats::default();
     if groups.is_empty() {
-        eprintln!("No duplicate groups to modify.");
+        warning!("No duplicate groups to modify.");
         return stats;
     }
-    if !yes {
-        eprintln!("Refusing to modify files without --yes.");
+    if !opts.yes && !opts.dry_run {
+        warning!("Refusing to modify files without --yes.");
         return stats;
     }
 
-    if delete {
-        for g in groups {
-            if g.members.len() < 2 { continue; }
-            let canonical = &g.members[0];
-            for dupe in g.members.iter().skip(1) {
-                if same_inode(canonical, dupe) {
-                    stats.skipped_same_inode += 1;
-                    continue;
-                }
-                match fs::remove_file(dupe) {
-                    Ok(_) => stats.deleted += 1,
-                    Err(e) => {
-                        stats.errors += 1;
-                        eprintln!("Failed to delete {}: {}", dupe.display(), e);
-                    }
+    let mut denied = false;
+    for g in groups {
+        if let Some(why) = opts.over_budget() {
+            warning!("stopping before the remaining groups: {}", why);
+            break;
+        }
+        let trimmed;
+        let g = match opts.keep_copies {
+            n if n > 1 => match spare_copies(g, n) {
+                Some(spare) => {
+                    trimmed = spare;
+                    &trimmed
                 }
-            }
+                None => continue,
+            },
+            _ => g,
+        };
+        if g.members.len() < 2 {
+            continue;
+        }
+        if g.stale && !reverify_members(g) {
+            stats.skipped_stale += 1;
+            continue;
         }
-    } else if hardlink {
-        for g in groups {
-            if g.members.len() < 2 { continue; }
-            let canonical = &g.members[0];
-            for dupe in g.members.iter().skip(1) {
-                if same_inode(canonical, dupe) {
+        if opts.paranoid && !verify_canonical(g) {
+            stats.aborted_groups += 1;
+            opts.failed(&mut stats);
+            continue;
+        }
+        if !unchanged_since_scan(g, &g.members[0], opts.verify) {
+            stats.changed_since_scan += 1;
+            continue;
+        }
+        for planned in action.plan(g, journal) {
+            let step = match planned {
+                Planned::Run(step) => step,
+                Planned::SameInode => {
                     stats.skipped_same_inode += 1;
                     continue;
                 }
-                if !same_device(canonical, dupe) {
+                Planned::CrossDevice => {
                     stats.skipped_cross_device += 1;
-                    eprintln!(
-                        "cross-device: cannot hardlink {} -> {}",
-                        dupe.display(),
-                        canonical.display()
-                    );
                     continue;
                 }
-                // Replace dupe with a hard link to canonical
-                if let Err(e) = fs::remove_file(dupe) {
-                    stats.errors += 1;
-                    eprintln!("Failed to remove {}: {}", dupe.display(), e);
+                Planned::AlreadyApplied => {
+                    stats.already_applied += 1;
                     continue;
                 }
-                if let Err(e) = fs::hard_link(canonical, dupe) {
-                    stats.errors += 1;
-                    eprintln!(
-                        "Failed to hardlink {} -> {}: {}",
-                        dupe.display(),
-                        canonical.display(),
-                        e
-                    );
-                    continue;
+            };
+            let (src, dst) = (journal_key(&step.path), journal_key(&step.target));
+            if !opts.may_touch(&step.path) {
+                stats.skipped_protected += 1;
+                journal.attempt(step.op, &src, &dst, g.size, "skipped_protected", None);
+                continue;
+            }
+            if opts.held_pairs.contains(&step.path) {
+                stats.skipped_pair += 1;
+                journal.attempt(step.op, &src, &dst, g.size, "skipped_pair", None);
+                continue;
+            }
+            if !unchanged_since_scan(g, &step.path, opts.verify) {
+                stats.changed_since_scan += 1;
+                journal.attempt(step.op, &src, &dst, g.size, "changed_since_scan", None);
+                continue;
+            }
+            // Moves keep the bytes on disk (in quarantine), so they don't count as freed.
+            let freed = if step.op == Op::Move { 0 } else { g.size };
+            if dry_run(opts, format_args!("{}", step)) {
+                stats.count_done(step.op);
+                stats.freed_bytes += freed;
+                journal.attempt(step.op, &src, &dst, g.size, "planned", None);
+                continue;
+            }
+            match action.execute(&step, g, journal) {
+                Outcome::Done => {
+                    stats.count_done(step.op);
+                    stats.freed_bytes += freed;
+                    journal.record(step.op, &src, &dst, &g.key());
+                    journal.attempt(step.op, &src, &dst, g.size, "done", None);
+                }
+                Outcome::AlreadyApplied => {
+                    stats.already_applied += 1;
+                    journal.attempt(step.op, &src, &dst, g.size, "already_applied", None);
+                }
+                Outcome::Unsupported(e) => {
+                    stats.skipped_unsupported += 1;
+                    warning!("{} unsupported: cannot {} ({})", step.op.name(), step, e);
+                    journal.attempt(step.op, &src, &dst, g.size, "unsupported", Some(e.to_string()));
+                }
+                Outcome::NoPrivilege(e) => {
+                    stats.skipped_privilege += 1;
+                    if !denied {
+                        denied = true;
+                        warning!(
+                            "cannot {}: {}; enable Developer Mode or run as administrator, or \
+                             pass --symlink-fallback-hardlink (further files are counted as \
+                             skipped_privilege)",
+                            step,
+                            e
+                        );
+                    }
+                    journal.attempt(step.op, &src, &dst, g.size, "skipped_privilege", Some(e.to_string()));
+                }
+                Outcome::DoneAs(op) => {
+                    stats.count_done(op);
+                    stats.freed_bytes += freed;
+                    journal.record(op, &src, &dst, &g.key());
+                    journal.attempt(op, &src, &dst, g.size, "done", None);
+                }
+                Outcome::Failed(e) => {
+                    opts.failed(&mut stats);
+                    warning!("Failed to {}: {}", step, e);
+                    journal.attempt(step.op, &src, &dst, g.size, "failed", Some(e.to_string()));
                 }
-                stats.linked += 1;
             }
         }
     }
 
-    eprintln!(
-        "actions: deleted={} linked={} skipped_same_inode={} skipped_cross_device={} errors={}",
-        stats.deleted, stats.linked, stats.skipped_same_inode, stats.skipped_cross_device, stats.errors
-    );
     stats
 }
 
-/// Helper to check whether two paths are on the same device.
-/// The current flow relies on `fs::hard_link` errors for feasibility.
-#[allow(dead_code)]
-fn _same_device(_a: &Path, _b: &Path) -> bool {
+/// Plan `op` for every duplicate, skipping those that already are the canonical's inode.
+fn plan_each(g: &DuplicateGroup, op: Op, mut check: impl FnMut(&Path, &Path) -> Option<Planned>) -> Vec<Planned> {
+    let canonical = &g.members[0];
+    g.members
+        .iter()
+        .skip(1)
+        .map(|dupe| {
+            check(canonical, dupe).unwrap_or_else(|| {
+                Planned::Run(Step { op, path: dupe.clone(), target: canonical.clone() })
+            })
+        })
+        .collect()
+}
+
+/// Remove duplicates.
+pub struct Delete;
+
+impl Action for Delete {
+    fn plan(&self, g: &DuplicateGroup, _: &Journal) -> Vec<Planned> {
+        plan_each(g, Op::Delete, |c, d| same_inode(c, d).then_some(Planned::SameInode))
+    }
+
+    fn execute(&self, step: &Step, g: &DuplicateGroup, journal: &Journal) -> Outcome {
+        match fs::remove_file(&step.path) {
+            Ok(()) => Outcome::Done,
+            Err(e)
+                if e.kind() == std::io::ErrorKind::NotFound
+                    && journal.contains(Op::Delete, &journal_key(&step.path), &g.key()) =>
+            {
+                Outcome::AlreadyApplied
+            }
+            Err(e) => Outcome::Failed(e),
+        }
+    }
+}
+
+/// Send duplicates to the OS trash / Recycle Bin.
+pub struct Trash;
+
+impl Action for Trash {
+    fn plan(&self, g: &DuplicateGroup, journal: &Journal) -> Vec<Planned> {
+        plan_each(g, Op::Trash, |c, d| {
+            if same_inode(c, d) {
+                Some(Planned::SameInode)
+            } else if d.symlink_metadata().is_err() && journal.contains(Op::Trash, &journal_key(d), &g.key()) {
+                Some(Planned::AlreadyApplied)
+            } else {
+                None
+            }
+        })
+    }
+
+    fn execute(&self, step: &Step, _: &DuplicateGroup, _: &Journal) -> Outcome {
+        match trash::delete(&step.path) {
+            Ok(()) => Outcome::Done,
+            Err(e) => Outcome::Failed(std::io::Error::other(e)),
+        }
+    }
+}
+
+/// Replace duplicates with hard links to the canonical.
+pub struct Hardlink;
+
+impl Action for Hardlink {
+    fn plan(&self, g: &DuplicateGroup, journal: &Journal) -> Vec<Planned> {
+        plan_each(g, Op::Hardlink, |c, d| {
+            if same_inode(c, d) {
+                Some(if journal.contains(Op::Hardlink, &journal_key(d), &g.key()) {
+                    Planned::AlreadyApplied
+                } else {
+                    Planned::SameInode
+                })
+            } else if same_device(c, d) == Some(false) {
+                warning!("cross-device: cannot hardlink {} -> {}", d.display(), c.display());
+                Some(Planned::CrossDevice)
+            } else {
+                None
+            }
+        })
+    }
+
+    fn execute(&self, step: &Step, _: &DuplicateGroup, _: &Journal) -> Outcome {
+        match replace_with_hardlink(&step.target, &step.path) {
+            Ok(()) => Outcome::Done,
+            Err(e) => Outcome::Failed(e),
+        }
+    }
+}
+
+/// Replace duplicates with copy-on-write clones of the canonical.
+pub struct Reflink;
+
+impl Action for Reflink {
+    fn plan(&self, g: &DuplicateGroup, journal: &Journal) -> Vec<Planned> {
+        plan_each(g, Op::Reflink, |c, d| {
+            if journal.contains(Op::Reflink, &journal_key(d), &g.key()) {
+                Some(Planned::AlreadyApplied)
+            } else {
+                // Cloning over a hard link would silently split it from its other names.
+                same_inode(c, d).then_some(Planned::SameInode)
+            }
+        })
+    }
+
+    fn execute(&self, step: &Step, _: &DuplicateGroup, _: &Journal) -> Outcome {
+        match replace_with_clone(&step.target, &step.path) {
+            Ok(()) => Outcome::Done,
+            Err(e) if reflink::is_unsupported(&e) => Outcome::Unsupported(e),
+            Err(e) => Outcome::Failed(e),
+        }
+    }
+}
+
+/// Make duplicates share the canonical's extents in place. Unlike `Reflink` nothing is
+/// replaced: each file keeps its inode, permissions and timestamps, and the kernel only shares
+/// data it has compared equal itself.
+pub struct DedupeExtents;
+
+impl Action for DedupeExtents {
+    fn plan(&self, g: &DuplicateGroup, journal: &Journal) -> Vec<Planned> {
+        plan_each(g, Op::Dedupe, |c, d| {
+            if journal.contains(Op::Dedupe, &journal_key(d), &g.key()) {
+                Some(Planned::AlreadyApplied)
+            } else {
+                same_inode(c, d).then_some(Planned::SameInode)
+            }
+        })
+    }
+
+    fn execute(&self, step: &Step, _: &DuplicateGroup, _: &Journal) -> Outcome {
+        match reflink::dedupe_range(&step.target, &step.path) {
+            Ok(()) => Outcome::Done,
+            Err(e) if reflink::is_unsupported(&e) => Outcome::Unsupported(e),
+            Err(e) => Outcome::Failed(e),
+        }
+    }
+}
+
+/// Replace duplicates with symlinks to the canonical (absolute, or relative with `relative`).
+/// With `fallback_hardlink`, duplicates on the canonical's device are hardlinked instead
+/// when the OS does not permit creating symlinks.
+pub struct Symlink {
+    pub relative: bool,
+    pub fallback_hardlink: bool,
+}
+
+impl Action for Symlink {
+    fn plan(&self, g: &DuplicateGroup, journal: &Journal) -> Vec<Planned> {
+        plan_each(g, Op::Symlink, |c, d| {
+            // With --follow-symlinks an existing link to the canonical shows up as the same inode.
+            same_inode(c, d).then(|| {
+                if journal.contains(Op::Symlink, &journal_key(d), &g.key()) {
+                    Planned::AlreadyApplied
+                } else {
+                    Planned::SameInode
+                }
+            })
+        })
+    }
+
+    fn execute(&self, step: &Step, _: &DuplicateGroup, _: &Journal) -> Outcome {
+        match replace_with_symlink(&step.target, &step.path, self.relative) {
+            Ok(()) => Outcome::Done,
+            Err(e) if is_privilege_error(&e) => {
+                let cross_device = same_device(&step.target, &step.path) == Some(false);
+                if !self.fallback_hardlink || cross_device {
+                    return Outcome::NoPrivilege(e);
+                }
+                match replace_with_hardlink(&step.target, &step.path) {
+                    Ok(()) => Outcome::DoneAs(Op::Hardlink),
+                    Err(e) => Outcome::Failed(e),
+                }
+            }
+            Err(e) => Outcome::Failed(e),
+        }
+    }
+}
+
+/// Replace `dupe` with a hard link to `canonical`: link under a temporary name next to `dupe`,
+/// then rename over it, so `dupe` is left as it was when linking fails.
+fn replace_with_hardlink(canonical: &Path, dupe: &Path) -> std::io::Result<()> {
+    let name = dupe.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+    let tmp = dupe.with_file_name(format!(".{}.ducky-hardlink-{}", name, std::process::id()));
+    let res = fs::hard_link(canonical, &tmp).and_then(|_| fs::rename(&tmp, dupe));
+    if res.is_err() {
+        let _ = fs::remove_file(&tmp);
+    }
+    res
+}
+
+/// Replace `dupe` with a clone of `canonical`: clone into a temporary file next to `dupe`,
+/// carry over `dupe`'s permissions, then rename over it so `dupe` is never missing.
+fn replace_with_clone(canonical: &Path, dupe: &Path) -> std::io::Result<()> {
+    let perms = fs::metadata(dupe)?.permissions();
+    let name = dupe.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+    let tmp = dupe.with_file_name(format!(".{}.ducky-reflink-{}", name, std::process::id()));
+    let res = reflink::clone_file(canonical, &tmp)
+        .and_then(|_| fs::set_permissions(&tmp, perms))
+        .and_then(|_| fs::rename(&tmp, dupe));
+    if res.is_err() {
+        let _ = fs::remove_file(&tmp);
+    }
+    res
+}
+
+/// Replace `dupe` with a symlink to `canonical` (absolute, or relative to `dupe`'s directory).
+/// The link is created under a temporary name and renamed over `dupe`, so it works across
+/// devices and `dupe` is never missing.
+fn replace_with_symlink(canonical: &Path, dupe: &Path, relative: bool) -> std::io::Result<()> {
+    let resolve = |p: &Path| -> std::io::Result<PathBuf> {
+        let parent = p.parent().filter(|d| !d.as_os_str().is_empty()).unwrap_or(Path::new("."));
+        Ok(fs::canonicalize(parent)?.join(p.file_name().unwrap_or_default()))
+    };
+    let target_abs = resolve(canonical)?;
+    let dupe_abs = resolve(dupe)?;
+    let target = if relative {
+        relative_path(&target_abs, dupe_abs.parent().unwrap_or(Path::new("/")))
+    } else {
+        target_abs
+    };
+    let name = dupe.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+    let tmp = dupe.with_file_name(format!(".{}.ducky-symlink-{}", name, std::process::id()));
+    let res = make_symlink(&target, &tmp).and_then(|_| fs::rename(&tmp, dupe));
+    if res.is_err() {
+        let _ = fs::remove_file(&tmp);
+    }
+    res
+}
+
+/// Path to `target` as seen from directory `from`; both must be absolute.
+fn relative_path(target: &Path, from: &Path) -> PathBuf {
+    let t: Vec<_> = target.components().collect();
+    let f: Vec<_> = from.components().collect();
+    let common = t.iter().zip(&f).take_while(|(a, b)| a == b).count();
+    let mut out = PathBuf::new();
+    for _ in common..f.len() {
+        out.push("..");
+    }
+    for c in &t[common..] {
+        out.push(c);
+    }
+    out
+}
+
+#[cfg(unix)]
+fn make_symlink(target: &Path, link: &Path) -> std::io::Result<()> {
+    std::os::unix::fs::symlink(target, link)
+}
+
+#[cfg(windows)]
+fn make_symlink(target: &Path, link: &Path) -> std::io::Result<()> {
+    std::os::windows::fs::symlink_file(target, link)
+}
+
+/// Whether creating a symlink failed for lack of privilege: ERROR_PRIVILEGE_NOT_HELD on
+/// Windows without Developer Mode or SeCreateSymbolicLinkPrivilege.
+#[cfg(windows)]
+fn is_privilege_error(e: &std::io::Error) -> bool {
+    e.raw_os_error() == Some(1314)
+}
+
+#[cfg(not(windows))]
+fn is_privilege_error(_: &std::io::Error) -> bool {
+    false
+}
+
+/// Re-read the canonical and check it still has the group's size and content hash, then
+/// compare every other member with it byte by byte. Guards against removing every copy when
+/// the kept file sits on failing media, and against hash collisions.
+pub(crate) fn verify_canonical(g: &DuplicateGroup) -> bool {
+    let canonical = &g.members[0];
+    if g.hash.is_empty() {
+        warning!("paranoid: no content hash for {}; skipping group", canonical.display());
+        return false;
+    }
+    match fs::metadata(canonical).map(|m| m.len()) {
+        Ok(len) if len == g.size => {}
+        Ok(len) => {
+            warning!(
+                "paranoid: canonical {} changed size ({} -> {}); skipping group",
+                canonical.display(),
+                g.size,
+                len
+            );
+            return false;
+        }
+        Err(e) => {
+            warning!("paranoid: cannot stat canonical {}: {}; skipping group", canonical.display(), e);
+            return false;
+        }
+    }
+    match full_hash(canonical) {
+        Ok(h) if h == g.hash => {}
+        Ok(_) => {
+            warning!("paranoid: canonical {} content changed; skipping group", canonical.display());
+            return false;
+        }
+        Err(e) => {
+            warning!("paranoid: cannot re-read canonical {}: {:#}; skipping group", canonical.display(), e);
+            return false;
+        }
+    }
+    g.members[1..].iter().all(|p| match same_bytes(canonical, p) {
+        Ok(true) => true,
+        Ok(false) => {
+            warning!(
+                "paranoid: {} differs from canonical {}; skipping group",
+                p.display(),
+                canonical.display()
+            );
+            false
+        }
+        Err(e) => {
+            warning!("paranoid: cannot compare {}: {:#}; skipping group", p.display(), e);
+            false
+        }
+    })
+}
+
+/// Whether `path` still is what the scan grouped: the group's size and the modification time
+/// recorded for it, and with `verify` the group's hash. Members of a stale group were just
+/// re-hashed by `reverify_members`, so only their size is checked. Files scanned without
+/// metadata (`degraded`) can't be shown to be unchanged, nor to be a distinct inode.
+pub(crate) fn unchanged_since_scan(g: &DuplicateGroup, path: &Path, verify: bool) -> bool {
+    if g.degraded.iter().any(|d| d == path) {
+        warning!("{} was scanned without its metadata; leaving it", path.display());
+        return false;
+    }
+    let Some(meta) = FileMeta::of(path) else {
+        warning!("{} is gone since the scan; leaving it", path.display());
+        return false;
+    };
+    let mtime_moved = !g.stale && g.mtimes.get(path).is_some_and(|t| *t != meta.mtime_ns);
+    if meta.size != g.size || mtime_moved {
+        warning!("{} changed since the scan; leaving it", path.display());
+        return false;
+    }
+    if verify && !g.stale && !g.hash.is_empty() && !full_hash(path).is_ok_and(|h| h == g.hash) {
+        warning!("{} no longer matches its group; leaving it", path.display());
+        return false;
+    }
+    true
+}
+
+/// Re-hash every member of a group flagged stale; all must still match the group hash.
+pub(crate) fn reverify_members(g: &DuplicateGroup) -> bool {
+    for p in &g.members {
+        let ok = fs::metadata(p).map(|m| m.len() == g.size).unwrap_or(false)
+            && full_hash(p).map(|h| h == g.hash).unwrap_or(false);
+        if !ok {
+            warning!("{} changed since it was hashed; skipping group", p.display());
+            return false;
+        }
+    }
     true
 }
 
+/// Journal entries use absolute paths so runs from different working directories match.
+/// The path itself may no longer exist, so symlinks are not resolved.
+fn journal_key(path: &Path) -> PathBuf {
+    std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
 #[cfg(unix)]
-fn same_inode(a: &Path, b: &Path) -> bool {
+pub(crate) fn same_inode(a: &Path, b: &Path) -> bool {
     use std::os::unix::fs::MetadataExt;
     let (Ok(ma), Ok(mb)) = (fs::metadata(a), fs::metadata(b)) else { return false };
     ma.ino() == mb.ino() && ma.dev() == mb.dev()
 }
 
 #[cfg(not(unix))]
-fn same_inode(a: &Path, b: &Path) -> bool { a == b }
+pub(crate) fn same_inode(a: &Path, b: &Path) -> bool { a == b }
 
+/// Whether `a` and `b` are on one filesystem; `None` when that can't be told (unreadable, or
+/// a platform without device numbers), in which case linking is tried and fails on its own.
 #[cfg(unix)]
-fn same_device(a: &Path, b: &Path) -> bool {
+fn same_device(a: &Path, b: &Path) -> Option<bool> {
     use std::os::unix::fs::MetadataExt;
-    let (Ok(ma), Ok(mb)) = (fs::metadata(a), fs::metadata(b)) else { return true };
-    ma.dev() == mb.dev()
+    let (Ok(ma), Ok(mb)) = (fs::metadata(a), fs::metadata(b)) else { return None };
+    Some(ma.dev() == mb.dev())
 }
 
 #[cfg(not(unix))]
-fn same_device(_a: &Path, _b: &Path) -> bool { true }
+fn same_device(_a: &Path, _b: &Path) -> Option<bool> { None }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::fs_utils::parse_path_glob;
+    use std::collections::HashMap;
     use std::io::Write;
 
     #[test]
@@ -139,7 +942,8 @@ mod tests {
         std::fs::hard_link(&canon, &dupe).unwrap();
 
         let group = DuplicateGroup::new(6, vec![canon.clone(), dupe.clone()]);
-        let stats = apply_actions(&[group], true, false, true);
+        let opts = ActionOptions { delete: true, yes: true, ..Default::default() };
+        let stats = apply_actions(&[group], &opts, &mut Journal::in_memory());
         assert_eq!(stats.deleted, 0);
         assert_eq!(stats.skipped_same_inode, 1);
         assert!(canon.exists());
@@ -148,4 +952,452 @@ mod tests {
         let _ = std::fs::remove_file(canon);
         let _ = std::fs::remove_file(dupe);
     }
+
+    #[test]
+    fn act_only_under_protects_other_subtrees() {
+        let dir = std::env::temp_dir().join(format!("ducky_act_only_{}", std::process::id()));
+        for sub in ["keep", "scratch"] {
+            std::fs::create_dir_all(dir.join(sub)).unwrap();
+        }
+        let (canon, inside, outside) =
+            (dir.join("keep/a"), dir.join("scratch/b"), dir.join("keep/c"));
+        for p in [&canon, &inside, &outside] {
+            std::fs::write(p, b"same").unwrap();
+        }
+        let group = DuplicateGroup::new(4, vec![canon.clone(), inside.clone(), outside.clone()]);
+        let opts = ActionOptions {
+            delete: true,
+            yes: true,
+            act_only_under: vec![std::fs::canonicalize(dir.join("scratch")).unwrap()],
+            ..Default::default()
+        };
+        // `keep/../scratch` style paths resolve before the check.
+        assert!(opts.may_touch(&dir.join("keep/../scratch/b")));
+        assert!(!opts.may_touch(&dir.join("scratch/../keep/c")));
+        let reference = vec![std::fs::canonicalize(dir.join("keep")).unwrap()];
+        let compare = ActionOptions { reference, ..Default::default() };
+        assert!(!compare.may_touch(&dir.join("scratch/../keep/c")));
+        assert!(compare.may_touch(&inside));
+
+        let log = dir.join("actions.ndjson");
+        let mut journal = Journal::in_memory();
+        journal.log_attempts_to(&log).unwrap();
+        let stats = apply_actions(&[group], &opts, &mut journal);
+        assert_eq!((stats.deleted, stats.skipped_protected), (1, 1));
+        assert!(canon.exists() && outside.exists() && !inside.exists());
+
+        let lines: Vec<serde_json::Value> = std::fs::read_to_string(&log)
+            .unwrap()
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+        let mut results: Vec<String> = lines.iter().map(|l| l["result"].to_string()).collect();
+        results.sort();
+        assert_eq!(results, ["\"done\"", "\"skipped_protected\""]);
+        assert!(lines.iter().all(|l| l["size"] == 4 && l["op"] == "delete"));
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn protected_members_are_kept_and_preferred() {
+        let dir = std::env::temp_dir().join(format!("ducky_protect_{}", std::process::id()));
+        for sub in ["Originals/2019", "copies"] {
+            std::fs::create_dir_all(dir.join(sub)).unwrap();
+        }
+        let (copy, original, raw) =
+            (dir.join("copies/a"), dir.join("Originals/2019/a"), dir.join("copies/b.RAW"));
+        for p in [&copy, &original, &raw] {
+            std::fs::write(p, b"same").unwrap();
+        }
+        let by_name = [parse_path_glob("originals").unwrap(), parse_path_glob("*.raw").unwrap()];
+        let by_path = [parse_path_glob(&format!("{}/Originals", dir.display())).unwrap()];
+        assert!(is_protected(&by_name, &original) && is_protected(&by_name, &raw));
+        assert!(is_protected(&by_path, &dir.join("copies/../Originals/2019/a")));
+        assert!(!is_protected(&by_path, &copy) && !is_protected(&[], &copy));
+
+        // Both protected members move ahead of copies/a, keeping their path order.
+        let mut group = DuplicateGroup::new(4, vec![copy.clone(), original.clone(), raw.clone()]);
+        group.rank_members(|p| !is_protected(&by_name, p));
+        assert_eq!(group.members, vec![original.clone(), raw.clone(), copy.clone()]);
+
+        let opts = ActionOptions { delete: true, yes: true, protect: by_name.to_vec(), ..Default::default() };
+        let stats = apply_actions(&[group], &opts, &mut Journal::in_memory());
+        assert_eq!((stats.deleted, stats.skipped_protected), (1, 1));
+        assert!(original.exists() && raw.exists() && !copy.exists());
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn keep_copies_leaves_the_first_members() {
+        let dir = std::env::temp_dir().join(format!("ducky_keep_copies_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let paths: Vec<PathBuf> = ["a", "b", "c", "d"].iter().map(|n| dir.join(n)).collect();
+        for p in &paths {
+            std::fs::write(p, b"same").unwrap();
+        }
+        let opts = ActionOptions { delete: true, yes: true, keep_copies: 2, ..Default::default() };
+        let small = DuplicateGroup::new(4, paths[..2].to_vec());
+        let group = DuplicateGroup::new(4, paths.clone());
+        let stats = apply_actions(&[small, group], &opts, &mut Journal::in_memory());
+        assert_eq!((stats.deleted, stats.freed_bytes), (2, 8));
+        let left: Vec<bool> = paths.iter().map(|p| p.exists()).collect();
+        assert_eq!(left, [true, true, false, false]);
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn keep_copies_counts_hard_links_once() {
+        let dir = std::env::temp_dir().join(format!("ducky_keep_links_{}", std::process::id()));
+        let paths = same_files(&dir, &["a", "c", "d"]);
+        let b = dir.join("b");
+        std::fs::hard_link(&paths[0], &b).unwrap();
+        let mut group = DuplicateGroup::new(3, vec![paths[0].clone(), b.clone(), paths[1].clone()]);
+        group.links = vec![b.clone()];
+        group.members.push(paths[2].clone());
+        let opts = ActionOptions { delete: true, yes: true, keep_copies: 2, ..Default::default() };
+        let stats = apply_actions(&[group], &opts, &mut Journal::in_memory());
+        // b is another name for a: c is the second copy kept, and only d goes.
+        assert_eq!(stats.deleted, 1);
+        assert!(paths[0].exists() && b.exists() && paths[1].exists() && !paths[2].exists());
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn custom_action_runs_through_shared_checks() {
+        // Plans a rename of every duplicate but never touches the filesystem.
+        struct Rename(std::cell::Cell<usize>);
+        impl Action for Rename {
+            fn plan(&self, g: &DuplicateGroup, _: &Journal) -> Vec<Planned> {
+                let to = |p: &PathBuf| p.with_extension("dup");
+                let step = |p| Step { op: Op::Move, path: PathBuf::clone(p), target: to(p) };
+                g.members.iter().skip(1).map(|p| Planned::Run(step(p))).collect()
+            }
+            fn execute(&self, _: &Step, _: &DuplicateGroup, _: &Journal) -> Outcome {
+                self.0.set(self.0.get() + 1);
+                Outcome::Done
+            }
+        }
+        // The shared checks stat every file, so they have to exist.
+        let dir = std::env::temp_dir().join(format!("ducky_act_custom_{}", std::process::id()));
+        let group = DuplicateGroup::new(3, same_files(&dir, &["a", "b", "c"]));
+        let action = Rename(Default::default());
+        let mut journal = Journal::in_memory();
+
+        let stats = run_action(&action, std::slice::from_ref(&group), &Default::default(), &mut journal);
+        assert_eq!((stats.moved, action.0.get()), (0, 0), "needs --yes");
+
+        let dry = ActionOptions { dry_run: true, ..Default::default() };
+        let stats = run_action(&action, std::slice::from_ref(&group), &dry, &mut journal);
+        assert_eq!((stats.moved, action.0.get()), (2, 0));
+
+        let yes = ActionOptions { yes: true, ..Default::default() };
+        let stats = run_action(&action, &[group], &yes, &mut journal);
+        assert_eq!((stats.moved, stats.freed_bytes, action.0.get()), (2, 0, 2));
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn the_error_budget_stops_between_groups() {
+        struct Failing(std::cell::Cell<usize>);
+        impl Action for Failing {
+            fn plan(&self, g: &DuplicateGroup, _: &Journal) -> Vec<Planned> {
+                plan_each(g, Op::Delete, |_, _| None)
+            }
+            fn execute(&self, _: &Step, _: &DuplicateGroup, _: &Journal) -> Outcome {
+                self.0.set(self.0.get() + 1);
+                Outcome::Failed(std::io::Error::other("read-only"))
+            }
+        }
+        let dir = std::env::temp_dir().join(format!("ducky_act_budget_{}", std::process::id()));
+        let first = DuplicateGroup::new(3, same_files(&dir, &["a", "b", "c"]));
+        let second = DuplicateGroup::new(3, same_files(&dir, &["d", "e"]));
+        let opts = ActionOptions {
+            yes: true,
+            budget: Budget { max_errors: Some(1), max_rate: None },
+            ..Default::default()
+        };
+        let action = Failing(Default::default());
+        let stats = run_action(&action, &[first, second], &opts, &mut Journal::in_memory());
+        assert_eq!((stats.errors, action.0.get()), (2, 2));
+        assert_eq!(opts.errors.counts().act, 2);
+        assert!(opts.over_budget().is_some());
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    /// `names` created in `dir` with the same three bytes.
+    fn same_files(dir: &Path, names: &[&str]) -> Vec<PathBuf> {
+        std::fs::create_dir_all(dir).unwrap();
+        let paths: Vec<PathBuf> = names.iter().map(|n| dir.join(n)).collect();
+        for p in &paths {
+            std::fs::write(p, b"abc").unwrap();
+        }
+        paths
+    }
+
+    #[test]
+    fn privilege_errors_are_counted_not_failed() {
+        // Refuses every other step for lack of privilege and hardlinks the rest instead.
+        struct Denied(std::cell::Cell<usize>);
+        impl Action for Denied {
+            fn plan(&self, g: &DuplicateGroup, _: &Journal) -> Vec<Planned> {
+                plan_each(g, Op::Symlink, |_, _| None)
+            }
+            fn execute(&self, _: &Step, _: &DuplicateGroup, _: &Journal) -> Outcome {
+                self.0.set(self.0.get() + 1);
+                if self.0.get().is_multiple_of(2) {
+                    return Outcome::DoneAs(Op::Hardlink);
+                }
+                Outcome::NoPrivilege(std::io::Error::from(std::io::ErrorKind::PermissionDenied))
+            }
+        }
+        let dir = std::env::temp_dir().join(format!("ducky_act_denied_{}", std::process::id()));
+        let group = DuplicateGroup::new(3, same_files(&dir, &["a", "b", "c", "d"]));
+        let yes = ActionOptions { yes: true, ..Default::default() };
+        let mut journal = Journal::in_memory();
+        let stats = run_action(&Denied(Default::default()), &[group], &yes, &mut journal);
+        assert_eq!((stats.skipped_privilege, stats.linked, stats.symlinked), (2, 1, 0));
+        assert_eq!((stats.errors, stats.freed_bytes), (0, 3));
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn files_changed_since_the_scan_are_left_alone() {
+        let dir = std::env::temp_dir().join(format!("ducky_act_changed_{}", std::process::id()));
+        let paths = same_files(&dir, &["a", "b", "c", "d"]);
+        let seen: HashMap<PathBuf, i64> =
+            paths.iter().map(|p| (p.clone(), FileMeta::of(p).unwrap().mtime_ns)).collect();
+        let group = DuplicateGroup::new(3, paths.clone()).with_hash(full_hash(&paths[0]).unwrap());
+        let group = group.with_mtimes(&seen);
+        // b grows, c is touched, d keeps its size and mtime but not its content.
+        std::fs::write(&paths[1], b"abcd").unwrap();
+        let later = std::time::SystemTime::now() + std::time::Duration::from_secs(5);
+        std::fs::File::options().write(true).open(&paths[2]).unwrap().set_modified(later).unwrap();
+        let before = std::fs::metadata(&paths[3]).unwrap().modified().unwrap();
+        std::fs::write(&paths[3], b"xyz").unwrap();
+        std::fs::File::options().write(true).open(&paths[3]).unwrap().set_modified(before).unwrap();
+
+        let run = |verify| {
+            let opts = ActionOptions { delete: true, dry_run: true, verify, ..Default::default() };
+            apply_actions(std::slice::from_ref(&group), &opts, &mut Journal::in_memory())
+        };
+        assert_eq!((run(false).deleted, run(false).changed_since_scan), (1, 2));
+        assert_eq!((run(true).deleted, run(true).changed_since_scan), (0, 3));
+
+        // A changed canonical holds back the whole group.
+        std::fs::write(&paths[0], b"abcd").unwrap();
+        assert_eq!((run(false).deleted, run(false).changed_since_scan), (0, 1));
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn rerun_reports_already_applied() {
+        let dir = std::env::temp_dir().join(format!("ducky_act_rerun_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let canon = dir.join("a");
+        let dupe = dir.join("b");
+        std::fs::write(&canon, b"same").unwrap();
+        std::fs::write(&dupe, b"same").unwrap();
+
+        let mut journal = Journal::in_memory();
+        let group = DuplicateGroup::new(4, vec![canon.clone(), dupe.clone()]).with_hash("h".into());
+        let groups = [group];
+        let opts = ActionOptions { hardlink: true, yes: true, ..Default::default() };
+        let first = apply_actions(&groups, &opts, &mut journal);
+        assert_eq!(first.linked, 1);
+
+        let second = apply_actions(&groups, &opts, &mut journal);
+        assert_eq!(second.linked, 0);
+        assert_eq!(second.already_applied, 1);
+        assert_eq!(second.errors, 0);
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn paranoid_aborts_group_when_canonical_changed() {
+        let dir = std::env::temp_dir().join(format!("ducky_act_paranoid_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let canon = dir.join("a");
+        let dupe = dir.join("b");
+        std::fs::write(&canon, b"same").unwrap();
+        std::fs::write(&dupe, b"same").unwrap();
+        let hash = full_hash(&canon).unwrap();
+        // Canonical is rewritten after the scan, keeping its size.
+        std::fs::write(&canon, b"diff").unwrap();
+
+        let group = DuplicateGroup::new(4, vec![canon, dupe.clone()]).with_hash(hash);
+        let opts = ActionOptions { delete: true, yes: true, paranoid: true, ..Default::default() };
+        let stats = apply_actions(&[group], &opts, &mut Journal::in_memory());
+        assert_eq!(stats.deleted, 0);
+        assert_eq!(stats.aborted_groups, 1);
+        assert!(dupe.exists());
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn paranoid_compares_members_byte_by_byte() {
+        let dir = std::env::temp_dir().join(format!("ducky_act_bytes_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (canon, same, other) = (dir.join("a"), dir.join("b"), dir.join("c"));
+        std::fs::write(&canon, b"same").unwrap();
+        std::fs::write(&same, b"same").unwrap();
+        // Stands in for a hash collision: grouped with the canonical, different bytes.
+        std::fs::write(&other, b"diff").unwrap();
+        let hash = full_hash(&canon).unwrap();
+        let opts = ActionOptions { delete: true, yes: true, paranoid: true, ..Default::default() };
+
+        let group = DuplicateGroup::new(4, vec![canon.clone(), same.clone(), other.clone()])
+            .with_hash(hash.clone());
+        let stats = apply_actions(&[group], &opts, &mut Journal::in_memory());
+        assert_eq!((stats.deleted, stats.aborted_groups), (0, 1));
+        assert!(same.exists() && other.exists());
+
+        let group = DuplicateGroup::new(4, vec![canon, same.clone()]).with_hash(hash);
+        let stats = apply_actions(&[group], &opts, &mut Journal::in_memory());
+        assert_eq!(stats.deleted, 1);
+        assert!(!same.exists());
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn stale_group_is_skipped_when_member_changed() {
+        let dir = std::env::temp_dir().join(format!("ducky_act_stale_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let canon = dir.join("a");
+        let dupe = dir.join("b");
+        std::fs::write(&canon, b"same").unwrap();
+        std::fs::write(&dupe, b"same").unwrap();
+        let hash = full_hash(&canon).unwrap();
+        std::fs::write(&dupe, b"edit").unwrap();
+
+        let mut group = DuplicateGroup::new(4, vec![canon, dupe.clone()]).with_hash(hash);
+        group.stale = true;
+        let opts = ActionOptions { delete: true, yes: true, ..Default::default() };
+        let stats = apply_actions(&[group], &opts, &mut Journal::in_memory());
+        assert_eq!(stats.deleted, 0);
+        assert_eq!(stats.skipped_stale, 1);
+        assert!(dupe.exists());
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn dedupe_extents_keeps_both_files_in_place() {
+        let dir = std::env::temp_dir().join(format!("ducky_act_dedupe_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let canon = dir.join("a");
+        let dupe = dir.join("b");
+        std::fs::write(&canon, b"same").unwrap();
+        std::fs::write(&dupe, b"same").unwrap();
+        let ino = FileMeta::of(&dupe).unwrap().ino;
+
+        let group = DuplicateGroup::new(4, vec![canon.clone(), dupe.clone()]).with_hash("h".into());
+        let opts = ActionOptions { dedupe_extents: true, yes: true, ..Default::default() };
+        let stats = apply_actions(&[group], &opts, &mut Journal::in_memory());
+        // Whether the temp filesystem can share extents depends on the machine.
+        assert_eq!(stats.deduped + stats.skipped_unsupported, 1);
+        assert_eq!(stats.errors, 0);
+        assert_eq!(std::fs::read(&dupe).unwrap(), b"same");
+        assert_eq!(FileMeta::of(&dupe).unwrap().ino, ino, "dupe was replaced");
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 2);
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn reflink_clones_or_skips_with_reason() {
+        let dir = std::env::temp_dir().join(format!("ducky_act_reflink_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let canon = dir.join("a");
+        let dupe = dir.join("b");
+        std::fs::write(&canon, b"same").unwrap();
+        std::fs::write(&dupe, b"same").unwrap();
+
+        let group = DuplicateGroup::new(4, vec![canon.clone(), dupe.clone()]).with_hash("h".into());
+        let opts = ActionOptions { reflink: true, yes: true, ..Default::default() };
+        let stats = apply_actions(&[group], &opts, &mut Journal::in_memory());
+        // Whether the temp filesystem supports cloning depends on the machine.
+        assert_eq!(stats.reflinked + stats.skipped_unsupported, 1);
+        assert_eq!(stats.errors, 0);
+        assert_eq!(std::fs::read(&dupe).unwrap(), b"same");
+        assert!(!same_inode(&canon, &dupe));
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 2, "temporary clone left behind");
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn failed_hardlink_leaves_the_dupe_in_place() {
+        let dir = std::env::temp_dir().join(format!("ducky_act_hardlink_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (canon, dupe) = (dir.join("a"), dir.join("b"));
+        std::fs::write(&canon, b"same").unwrap();
+        std::fs::write(&dupe, b"same").unwrap();
+
+        replace_with_hardlink(&canon, &dupe).unwrap();
+        assert!(same_inode(&canon, &dupe));
+
+        // Linking from a vanished canonical fails before the dupe is touched.
+        std::fs::write(dir.join("c"), b"same").unwrap();
+        assert!(replace_with_hardlink(&dir.join("gone"), &dir.join("c")).is_err());
+        assert_eq!(std::fs::read(dir.join("c")).unwrap(), b"same");
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 3, "temporary link left behind");
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn symlink_relative_points_at_canonical() {
+        let dir = std::env::temp_dir().join(format!("ducky_act_symlink_{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("x/y")).unwrap();
+        std::fs::create_dir_all(dir.join("z")).unwrap();
+        let canon = dir.join("x/y/a");
+        let dupe = dir.join("z/b");
+        std::fs::write(&canon, b"same").unwrap();
+        std::fs::write(&dupe, b"same").unwrap();
+
+        let group = DuplicateGroup::new(4, vec![canon.clone(), dupe.clone()]).with_hash("h".into());
+        let opts = ActionOptions { symlink: true, symlink_relative: true, yes: true, ..Default::default() };
+        let stats = apply_actions(&[group], &opts, &mut Journal::in_memory());
+        assert_eq!(stats.symlinked, 1);
+        assert_eq!(std::fs::read_link(&dupe).unwrap(), PathBuf::from("../x/y/a"));
+        assert_eq!(std::fs::read(&dupe).unwrap(), b"same");
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn dry_run_counts_without_touching_files() {
+        let dir = std::env::temp_dir().join(format!("ducky_act_dry_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let canon = dir.join("a");
+        let dupe = dir.join("b");
+        std::fs::write(&canon, b"same").unwrap();
+        std::fs::write(&dupe, b"same").unwrap();
+        let hash = full_hash(&canon).unwrap();
+
+        let group = DuplicateGroup::new(4, vec![canon, dupe.clone()]).with_hash(hash);
+        let opts = ActionOptions { delete: true, dry_run: true, ..Default::default() };
+        let mut journal = Journal::in_memory();
+        let stats = apply_actions(&[group], &opts, &mut journal);
+        assert_eq!((stats.deleted, stats.freed_bytes, stats.errors), (1, 4, 0));
+        assert!(dupe.exists());
+        assert_eq!(journal.len(), 0);
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
 }
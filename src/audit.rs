@@ -0,0 +1,156 @@
+//! Post-action audit: re-check a random sample of the groups this run modified.
+
+use crate::actions::same_inode;
+use crate::hashing::{full_hash, ContentKey};
+use crate::journal::{Entry, Op};
+use crate::rng::Rng;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+#[derive(Debug, Default, Serialize)]
+pub struct AuditReport {
+    /// Seed the sample was drawn with; `--seed` with it repeats the choice.
+    pub seed: u64,
+    /// Groups modified during this run.
+    pub processed_groups: usize,
+    pub sampled_groups: usize,
+    pub checked_paths: usize,
+    pub failures: Vec<String>,
+}
+
+/// Parse a sample size given as a percentage (`5%`) or a fraction (`0.05`).
+pub fn parse_fraction(s: &str) -> Result<f64, String> {
+    let (num, scale) = match s.trim().strip_suffix('%') {
+        Some(p) => (p, 100.0),
+        None => (s.trim(), 1.0),
+    };
+    let v: f64 = num.trim().parse().map_err(|_| format!("invalid sample size: {}", s))?;
+    let f = v / scale;
+    if f > 0.0 && f <= 1.0 {
+        Ok(f)
+    } else {
+        Err(format!("sample size must be in (0%, 100%]: {}", s))
+    }
+}
+
+/// Verify a `fraction` of the groups touched by `entries` (journal entries of this run):
+/// the kept file must still hash to the journaled content, deleted paths must be gone,
+/// hardlinked paths must share the canonical's inode, clones must still match, symlinks must
+/// resolve to the canonical, and moved copies must be intact.
+pub fn audit(entries: &[Entry], fraction: f64, rng: &mut Rng) -> AuditReport {
+    let mut by_group: BTreeMap<ContentKey, Vec<&Entry>> = BTreeMap::new();
+    for e in entries {
+        by_group.entry(e.key()).or_default().push(e);
+    }
+    let groups: Vec<Vec<&Entry>> = by_group.into_values().collect();
+    let mut report =
+        AuditReport { seed: rng.seed(), processed_groups: groups.len(), ..Default::default() };
+    if groups.is_empty() {
+        return report;
+    }
+
+    let want = ((groups.len() as f64 * fraction).ceil() as usize).clamp(1, groups.len());
+    for i in sample_indices(groups.len(), want, rng) {
+        report.sampled_groups += 1;
+        for e in &groups[i] {
+            report.checked_paths += 1;
+            if let Some(problem) = check_entry(e) {
+                report.failures.push(problem);
+            }
+        }
+    }
+    report
+}
+
+fn check_entry(e: &Entry) -> Option<String> {
+    let hash_ok = |p: &Path| full_hash(p).map(|h| h == e.hash).unwrap_or(false);
+    match e.op {
+        Op::Delete | Op::Trash | Op::Hardlink | Op::Reflink | Op::Dedupe | Op::Symlink
+            if !hash_ok(&e.canonical) =>
+        {
+            Some(format!("canonical {} no longer matches {}", e.canonical.display(), e.hash))
+        }
+        Op::Delete | Op::Trash if e.path.symlink_metadata().is_ok() => {
+            Some(format!("{} should have been deleted but still exists", e.path.display()))
+        }
+        Op::Hardlink if !same_inode(&e.path, &e.canonical) => {
+            Some(format!("{} is not linked to {}", e.path.display(), e.canonical.display()))
+        }
+        Op::Reflink | Op::Dedupe if !hash_ok(&e.path) => {
+            Some(format!("clone {} no longer matches {}", e.path.display(), e.hash))
+        }
+        Op::Symlink if !e.path.is_symlink() || !same_inode(&e.path, &e.canonical) => {
+            Some(format!("{} is not a symlink to {}", e.path.display(), e.canonical.display()))
+        }
+        Op::Move if e.path.symlink_metadata().is_ok() || !hash_ok(&e.canonical) => Some(format!(
+            "move {} -> {} is incomplete",
+            e.path.display(),
+            e.canonical.display()
+        )),
+        _ => None,
+    }
+}
+
+/// Pick `k` distinct indices out of `0..n` (partial Fisher–Yates), in ascending order.
+fn sample_indices(n: usize, k: usize, rng: &mut Rng) -> Vec<usize> {
+    let mut idx: Vec<usize> = (0..n).collect();
+    for i in 0..k.min(n) {
+        let j = i + rng.below((n - i) as u64) as usize;
+        idx.swap(i, j);
+    }
+    idx.truncate(k.min(n));
+    idx.sort_unstable();
+    idx
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_percent_and_fraction() {
+        assert_eq!(parse_fraction("5%"), Ok(0.05));
+        assert_eq!(parse_fraction("0.25"), Ok(0.25));
+        assert!(parse_fraction("0").is_err());
+        assert!(parse_fraction("150%").is_err());
+    }
+
+    #[test]
+    fn sample_is_distinct_and_bounded() {
+        let s = sample_indices(10, 4, &mut Rng::new(42));
+        assert_eq!(s.len(), 4);
+        assert!(s.windows(2).all(|w| w[0] < w[1]));
+        assert!(s.iter().all(|i| *i < 10));
+        assert_eq!(sample_indices(3, 5, &mut Rng::new(1)), vec![0, 1, 2]);
+        // The same seed draws the same sample.
+        let draw = |seed| sample_indices(1000, 10, &mut Rng::new(seed));
+        assert_eq!(draw(42), draw(42));
+    }
+
+    #[test]
+    fn detects_resurrected_delete() {
+        let dir = std::env::temp_dir().join(format!("ducky_audit_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let canon = dir.join("a");
+        let dupe = dir.join("b");
+        std::fs::write(&canon, b"data").unwrap();
+        std::fs::write(&dupe, b"data").unwrap();
+        let entry = Entry {
+            op: Op::Delete,
+            path: dupe,
+            canonical: canon.clone(),
+            hash: full_hash(&canon).unwrap(),
+            algo: crate::hashing::ALGORITHM.to_string(),
+            size: 4,
+            ts: 0,
+            run: 1,
+            undoes: None,
+        };
+        let report = audit(&[entry], 1.0, &mut Rng::new(7));
+        assert_eq!(report.sampled_groups, 1);
+        assert_eq!(report.failures.len(), 1);
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
@@ -0,0 +1,141 @@
+//! Error budget: `--max-errors N` and `--max-error-rate R` stop a run that hits too many
+//! errors with exit code [`EXIT_CODE`], instead of reporting a "clean" result built on
+//! thousands of silently skipped files.
+//!
+//! Errors are counted per stage in an [`Errors`] shared by the scan and the actions: entries
+//! the walk could not read, failed quick and full hashes, and failed actions. The budget is
+//! checked after the walk, after hashing (before anything is modified) and between groups
+//! while acting. The rate is errors per file found by the walk.
+
+use crate::status::Stage;
+use serde::Serialize;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Exit code of a run stopped by the error budget.
+pub const EXIT_CODE: i32 = 3;
+
+/// The errors of one run, by stage, as they happen.
+#[derive(Debug, Default)]
+pub struct Errors {
+    walk: AtomicU64,
+    hash: AtomicU64,
+    act: AtomicU64,
+}
+
+impl Errors {
+    fn counter(&self, stage: Stage) -> Option<&AtomicU64> {
+        match stage {
+            Stage::Walk => Some(&self.walk),
+            Stage::QuickHash | Stage::FullHash => Some(&self.hash),
+            Stage::Act => Some(&self.act),
+            Stage::Size | Stage::Report => None,
+        }
+    }
+
+    /// Count `n` errors in `stage`.
+    pub fn add(&self, stage: Stage, n: u64) {
+        if let Some(c) = self.counter(stage) {
+            c.fetch_add(n, Ordering::Relaxed);
+        }
+    }
+
+    /// Count one error in `stage`.
+    pub fn record(&self, stage: Stage) {
+        self.add(stage, 1);
+    }
+
+    /// Errors so far.
+    pub fn counts(&self) -> ErrorCounts {
+        let load = |c: &AtomicU64| c.load(Ordering::Relaxed);
+        ErrorCounts { walk: load(&self.walk), hash: load(&self.hash), act: load(&self.act) }
+    }
+}
+
+/// Errors so far, by stage.
+#[derive(Debug, Clone, Copy, Default, Serialize, PartialEq, Eq)]
+pub struct ErrorCounts {
+    pub walk: u64,
+    pub hash: u64,
+    pub act: u64,
+}
+
+impl ErrorCounts {
+    pub fn total(&self) -> u64 {
+        self.walk + self.hash + self.act
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Budget {
+    pub max_errors: Option<u64>,
+    /// Fraction of the files found, in [0, 1].
+    pub max_rate: Option<f64>,
+}
+
+impl Budget {
+    /// Why the run must stop, if `errors` exceed the budget for `files` found by the walk.
+    pub fn exceeded(&self, errors: &ErrorCounts, files: u64) -> Option<String> {
+        let total = errors.total();
+        let detail = || {
+            format!("walk {}, hash {}, act {}", errors.walk, errors.hash, errors.act)
+        };
+        if let Some(max) = self.max_errors.filter(|max| total > *max) {
+            return Some(format!("{} errors ({}) exceed --max-errors {}", total, detail(), max));
+        }
+        let rate = total as f64 / files.max(1) as f64;
+        if let Some(max) = self.max_rate.filter(|max| total > 0 && rate > *max) {
+            return Some(format!(
+                "{} errors in {} files ({:.1}%; {}) exceed --max-error-rate {}%",
+                total,
+                files,
+                rate * 100.0,
+                detail(),
+                max * 100.0
+            ));
+        }
+        None
+    }
+}
+
+/// Parse `--max-error-rate`: a percentage (`2%`) or a fraction (`0.02`).
+pub fn parse_rate(s: &str) -> Result<f64, String> {
+    let (num, scale) = match s.trim().strip_suffix('%') {
+        Some(p) => (p, 100.0),
+        None => (s.trim(), 1.0),
+    };
+    let v: f64 = num.trim().parse().map_err(|_| format!("invalid error rate: {}", s))?;
+    let f = v / scale;
+    if (0.0..=1.0).contains(&f) {
+        Ok(f)
+    } else {
+        Err(format!("error rate must be in [0%, 100%]: {}", s))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn budget_counts_and_rates() {
+        let errors = ErrorCounts { walk: 2, hash: 1, act: 0 };
+        let budget = Budget { max_errors: Some(3), max_rate: None };
+        assert_eq!(budget.exceeded(&errors, 10), None);
+        let budget = Budget { max_errors: Some(2), max_rate: None };
+        assert!(budget.exceeded(&errors, 10).unwrap().contains("walk 2, hash 1, act 0"));
+
+        let budget = Budget { max_errors: None, max_rate: Some(parse_rate("5%").unwrap()) };
+        assert_eq!(budget.exceeded(&errors, 100), None);
+        assert!(budget.exceeded(&errors, 50).is_some());
+        assert_eq!(budget.exceeded(&ErrorCounts::default(), 0), None);
+
+        let errors = Errors::default();
+        errors.record(Stage::Walk);
+        errors.add(Stage::FullHash, 2);
+        errors.record(Stage::Size);
+        assert_eq!(errors.counts(), ErrorCounts { walk: 1, hash: 2, act: 0 });
+
+        assert_eq!(parse_rate("0.25"), Ok(0.25));
+        assert!(parse_rate("150%").is_err());
+    }
+}
@@ -0,0 +1,444 @@
+//! Persistent hash cache keyed by (dev, inode, size, mtime).
+//!
+//! Repeated scans of an unchanged tree skip rehashing: a file whose identity and mtime match
+//! a cached record reuses its quick/full digests. Since the key doesn't include the path, a
+//! renamed or moved file is still a hit; the record's last-seen path is updated and the
+//! rename reported (on Unix, where files have stable inode numbers). The cache is a compact
+//! binary file in the user cache directory:
+//!
+//! ```text
+//! "DUCKYHC2" | algo_len u8 | algo bytes | records...
+//! record: dev u64 | ino u64 | size u64 | mtime_ns i64 | flags u8
+//!         [full: 32 bytes if flags & 1] [quick_bytes u64 + quick: 32 bytes if flags & 2]
+//!         [path_len u32 + path bytes if flags & 4]
+//! ```
+//!
+//! `quick_bytes` is the sample length, with the `--quick-sample` mode in its top byte (zero
+//! for `head`). Version 1 files (no paths) are still read.
+//!
+//! All integers are little-endian. A cache written with another digest algorithm is ignored
+//! with a loud warning rather than mixed in.
+
+use crate::fs_utils::{cache_dir, create_artifact_dir};
+use crate::hashing::ALGORITHM;
+use crate::warnings::warning;
+use anyhow::{bail, Context, Result};
+use std::collections::HashMap;
+use std::fs;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+const MAGIC: &[u8; 8] = b"DUCKYHC2";
+const MAGIC_V1: &[u8; 8] = b"DUCKYHC1";
+const DIGEST_LEN: usize = 32;
+
+/// Identity of one version of a file. Any change to content normally changes size or mtime.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FileId {
+    pub dev: u64,
+    pub ino: u64,
+    pub size: u64,
+    pub mtime_ns: i64,
+}
+
+impl FileId {
+    #[cfg(unix)]
+    pub fn of(path: &Path) -> Option<Self> {
+        use std::os::unix::fs::MetadataExt;
+        let m = fs::metadata(path).ok()?;
+        Some(Self {
+            dev: m.dev(),
+            ino: m.ino(),
+            size: m.len(),
+            mtime_ns: m.mtime().saturating_mul(1_000_000_000).saturating_add(m.mtime_nsec()),
+        })
+    }
+
+    #[cfg(not(unix))]
+    pub fn of(path: &Path) -> Option<Self> {
+        // No stable inode numbers: identify by a hash of the absolute path instead.
+        use std::hash::{Hash, Hasher};
+        let m = fs::metadata(path).ok()?;
+        let mtime_ns = m
+            .modified()
+            .ok()?
+            .duration_since(std::time::UNIX_EPOCH)
+            .ok()?
+            .as_nanos() as i64;
+        let mut h = std::collections::hash_map::DefaultHasher::new();
+        std::path::absolute(path).ok()?.hash(&mut h);
+        Some(Self { dev: 0, ino: h.finish(), size: m.len(), mtime_ns })
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct Record {
+    full: Option<[u8; DIGEST_LEN]>,
+    quick: Option<(u64, [u8; DIGEST_LEN])>,
+    /// Where the file was last seen.
+    path: Option<PathBuf>,
+}
+
+#[derive(Debug, Default)]
+pub struct HashCache {
+    path: Option<PathBuf>,
+    records: Mutex<HashMap<FileId, Record>>,
+    dirty: AtomicBool,
+    renames: Mutex<Vec<(PathBuf, PathBuf)>>,
+    pub hits: AtomicUsize,
+    pub misses: AtomicUsize,
+}
+
+impl HashCache {
+    /// A cache that never hits and is never written (`--no-cache`).
+    pub fn disabled() -> Self {
+        Self::default()
+    }
+
+    /// Default location: `<cache dir>/hashes.bin`.
+    pub fn default_path() -> Option<PathBuf> {
+        cache_dir().map(|d| d.join("hashes.bin"))
+    }
+
+    /// Open the default cache; problems disable caching with a warning.
+    pub fn open_default() -> Self {
+        let Some(path) = Self::default_path() else {
+            warning!("no cache directory; hash cache disabled");
+            return Self::disabled();
+        };
+        match Self::open(&path) {
+            Ok(c) => c,
+            Err(e) => {
+                warning!("hash cache disabled: {:#}", e);
+                Self::disabled()
+            }
+        }
+    }
+
+    /// Load `path` if it exists. A cache from a different algorithm or a corrupt file
+    /// starts empty (and is overwritten on save).
+    pub fn open(path: &Path) -> Result<Self> {
+        let mut cache = Self { path: Some(path.to_path_buf()), ..Default::default() };
+        let bytes = match fs::read(path) {
+            Ok(b) => b,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(cache),
+            Err(e) => return Err(e).with_context(|| format!("read {}", path.display())),
+        };
+        match decode(&bytes) {
+            Ok(records) => cache.records = Mutex::new(records),
+            Err(e) => warning!("WARNING: ignoring hash cache {}: {:#}", path.display(), e),
+        }
+        Ok(cache)
+    }
+
+    pub fn len(&self) -> usize {
+        self.lock().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<FileId, Record>> {
+        self.records.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Cached quick hash for `id`, if it was computed with the same sample length and mode
+    /// (see `QuickSample::cache_tag`).
+    pub fn quick(&self, id: &FileId, quick_bytes: u64) -> Option<String> {
+        let hit = self
+            .lock()
+            .get(id)
+            .and_then(|r| r.quick)
+            .filter(|(qb, _)| *qb == quick_bytes)
+            .map(|(_, d)| to_hex(&d));
+        self.count(hit.is_some());
+        hit
+    }
+
+    pub fn full(&self, id: &FileId) -> Option<String> {
+        let hit = self.lock().get(id).and_then(|r| r.full).map(|d| to_hex(&d));
+        self.count(hit.is_some());
+        hit
+    }
+
+    pub fn put_quick(&self, id: FileId, quick_bytes: u64, hash: &str) {
+        if let (Some(d), true) = (from_hex(hash), self.path.is_some()) {
+            self.lock().entry(id).or_default().quick = Some((quick_bytes, d));
+            self.dirty.store(true, Ordering::Relaxed);
+        }
+    }
+
+    pub fn put_full(&self, id: FileId, hash: &str) {
+        if let (Some(d), true) = (from_hex(hash), self.path.is_some()) {
+            self.lock().entry(id).or_default().full = Some(d);
+            self.dirty.store(true, Ordering::Relaxed);
+        }
+    }
+
+    /// Note that `id` was found at `path` in this scan. If its record last saw it at another
+    /// path that no longer exists, the file was renamed or moved; the rename is remembered
+    /// for `renames`. (An old path that still exists is another hard link, not a rename.)
+    pub fn seen(&self, id: &FileId, path: &Path) {
+        if self.path.is_none() {
+            return;
+        }
+        let mut records = self.lock();
+        let Some(rec) = records.get_mut(id) else { return };
+        if rec.path.as_deref() == Some(path) {
+            return;
+        }
+        let old = rec.path.replace(path.to_path_buf());
+        drop(records);
+        self.dirty.store(true, Ordering::Relaxed);
+        if let Some(old) = old.filter(|o| o.symlink_metadata().is_err()) {
+            self.renames.lock().unwrap_or_else(|e| e.into_inner()).push((old, path.to_path_buf()));
+        }
+    }
+
+    /// Files found under a new path since the previous scan, as (old, new), sorted.
+    pub fn renames(&self) -> Vec<(PathBuf, PathBuf)> {
+        let mut out = self.renames.lock().unwrap_or_else(|e| e.into_inner()).clone();
+        out.sort();
+        out.dedup();
+        out
+    }
+
+    fn count(&self, hit: bool) {
+        if self.path.is_none() {
+            return;
+        }
+        let counter = if hit { &self.hits } else { &self.misses };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Write the cache back (atomically) if anything changed.
+    pub fn save(&self) -> Result<()> {
+        let Some(path) = &self.path else { return Ok(()) };
+        if !self.dirty.load(Ordering::Relaxed) {
+            return Ok(());
+        }
+        if let Some(parent) = path.parent() {
+            create_artifact_dir(parent).with_context(|| format!("create {}", parent.display()))?;
+        }
+        let tmp = path.with_extension(format!("tmp{}", std::process::id()));
+        {
+            let f = fs::File::create(&tmp).with_context(|| format!("write {}", tmp.display()))?;
+            let mut w = BufWriter::new(f);
+            encode(&self.lock(), &mut w)?;
+            w.flush()?;
+        }
+        fs::rename(&tmp, path).with_context(|| format!("write {}", path.display()))?;
+        Ok(())
+    }
+}
+
+/// Look `path` up in the cache, hashing and storing it on a miss. The result is only stored
+/// if the file's identity did not change while it was being hashed.
+pub fn cached(
+    path: &Path,
+    cache: &HashCache,
+    get: impl Fn(&FileId) -> Option<String>,
+    put: impl Fn(FileId, &str),
+    compute: impl Fn() -> Result<String>,
+) -> Result<String> {
+    let Some(id) = FileId::of(path) else { return compute() };
+    let h = match get(&id) {
+        Some(h) => h,
+        None => {
+            let h = compute()?;
+            if FileId::of(path) != Some(id) {
+                return Ok(h);
+            }
+            put(id, &h);
+            h
+        }
+    };
+    cache.seen(&id, path);
+    Ok(h)
+}
+
+fn encode(records: &HashMap<FileId, Record>, w: &mut impl Write) -> Result<()> {
+    w.write_all(MAGIC)?;
+    w.write_all(&[ALGORITHM.len() as u8])?;
+    w.write_all(ALGORITHM.as_bytes())?;
+    for (id, r) in records {
+        w.write_all(&id.dev.to_le_bytes())?;
+        w.write_all(&id.ino.to_le_bytes())?;
+        w.write_all(&id.size.to_le_bytes())?;
+        w.write_all(&id.mtime_ns.to_le_bytes())?;
+        let flags = r.full.is_some() as u8
+            | (r.quick.is_some() as u8) << 1
+            | (r.path.is_some() as u8) << 2;
+        w.write_all(&[flags])?;
+        if let Some(d) = &r.full {
+            w.write_all(d)?;
+        }
+        if let Some((qb, d)) = &r.quick {
+            w.write_all(&qb.to_le_bytes())?;
+            w.write_all(d)?;
+        }
+        if let Some(p) = &r.path {
+            let bytes = path_bytes(p);
+            w.write_all(&(bytes.len() as u32).to_le_bytes())?;
+            w.write_all(&bytes)?;
+        }
+    }
+    Ok(())
+}
+
+fn decode(bytes: &[u8]) -> Result<HashMap<FileId, Record>> {
+    let mut r = Reader { bytes, pos: 0 };
+    let magic = r.take(8)?;
+    if magic != MAGIC && magic != MAGIC_V1 {
+        bail!("not a ducky hash cache");
+    }
+    let algo_len = r.take(1)?[0] as usize;
+    let algo = String::from_utf8_lossy(r.take(algo_len)?).to_string();
+    if algo != ALGORITHM {
+        bail!("cache was written with {} (current: {})", algo, ALGORITHM);
+    }
+    let mut out = HashMap::new();
+    while r.pos < bytes.len() {
+        let id = FileId { dev: r.u64()?, ino: r.u64()?, size: r.u64()?, mtime_ns: r.u64()? as i64 };
+        let flags = r.take(1)?[0];
+        let mut rec = Record::default();
+        if flags & 1 != 0 {
+            rec.full = Some(r.digest()?);
+        }
+        if flags & 2 != 0 {
+            let qb = r.u64()?;
+            rec.quick = Some((qb, r.digest()?));
+        }
+        if flags & 4 != 0 {
+            let len = u32::from_le_bytes(r.take(4)?.try_into()?) as usize;
+            rec.path = Some(path_from_bytes(r.take(len)?));
+        }
+        out.insert(id, rec);
+    }
+    Ok(out)
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8]> {
+        let Some(s) = self.bytes.get(self.pos..self.pos + n) else { bail!("truncated cache file") };
+        self.pos += n;
+        Ok(s)
+    }
+
+    fn u64(&mut self) -> Result<u64> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into()?))
+    }
+
+    fn digest(&mut self) -> Result<[u8; DIGEST_LEN]> {
+        Ok(self.take(DIGEST_LEN)?.try_into()?)
+    }
+}
+
+#[cfg(unix)]
+fn path_bytes(p: &Path) -> Vec<u8> {
+    use std::os::unix::ffi::OsStrExt;
+    p.as_os_str().as_bytes().to_vec()
+}
+
+#[cfg(unix)]
+fn path_from_bytes(b: &[u8]) -> PathBuf {
+    use std::os::unix::ffi::OsStrExt;
+    PathBuf::from(std::ffi::OsStr::from_bytes(b))
+}
+
+#[cfg(not(unix))]
+fn path_bytes(p: &Path) -> Vec<u8> {
+    p.to_string_lossy().into_owned().into_bytes()
+}
+
+#[cfg(not(unix))]
+fn path_from_bytes(b: &[u8]) -> PathBuf {
+    PathBuf::from(String::from_utf8_lossy(b).into_owned())
+}
+
+fn to_hex(d: &[u8; DIGEST_LEN]) -> String {
+    d.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn from_hex(s: &str) -> Option<[u8; DIGEST_LEN]> {
+    if s.len() != DIGEST_LEN * 2 {
+        return None;
+    }
+    let mut out = [0u8; DIGEST_LEN];
+    for (i, o) in out.iter_mut().enumerate() {
+        *o = u8::from_str_radix(s.get(2 * i..2 * i + 2)?, 16).ok()?;
+    }
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip_and_invalidation() {
+        let dir = std::env::temp_dir().join(format!("ducky_cache_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("f");
+        std::fs::write(&file, b"content").unwrap();
+        let cache_path = dir.join("hashes.bin");
+        let h = crate::hashing::full_hash(&file).unwrap();
+
+        let id = FileId::of(&file).unwrap();
+        let cache = HashCache::open(&cache_path).unwrap();
+        assert_eq!(cache.full(&id), None);
+        cache.put_full(id, &h);
+        cache.put_quick(id, 1024, &h);
+        cache.save().unwrap();
+
+        let cache = HashCache::open(&cache_path).unwrap();
+        assert_eq!(cache.full(&id), Some(h.clone()));
+        assert_eq!(cache.quick(&id, 1024), Some(h));
+        assert_eq!(cache.quick(&id, 4096), None);
+
+        // A different size (or mtime) is a different FileId.
+        std::fs::write(&file, b"changed content").unwrap();
+        let id2 = FileId::of(&file).unwrap();
+        assert_ne!(id, id2);
+        assert_eq!(cache.full(&id2), None);
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn renamed_file_hits_and_is_reported() {
+        let dir = std::env::temp_dir().join(format!("ducky_cache_rename_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (old, new, link) = (dir.join("old"), dir.join("new"), dir.join("link"));
+        std::fs::write(&old, b"content").unwrap();
+        let cache_path = dir.join("hashes.bin");
+        let h = crate::hashing::full_hash(&old).unwrap();
+
+        let cache = HashCache::open(&cache_path).unwrap();
+        let id = FileId::of(&old).unwrap();
+        cache.put_full(id, &h);
+        cache.seen(&id, &old);
+        cache.save().unwrap();
+
+        std::fs::rename(&old, &new).unwrap();
+        std::fs::hard_link(&new, &link).unwrap();
+        let cache = HashCache::open(&cache_path).unwrap();
+        let id = FileId::of(&new).unwrap();
+        assert_eq!(cache.full(&id), Some(h));
+        cache.seen(&id, &new);
+        // A second name of the same inode that still exists is not a rename.
+        cache.seen(&id, &link);
+        assert_eq!(cache.renames(), vec![(old, new)]);
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
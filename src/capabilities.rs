@@ -0,0 +1,110 @@
+//! `ducky --capabilities`: what this build can do on this platform, as one JSON document, so
+//! wrapper tools can adapt to the installed ducky instead of parsing `--help`.
+//!
+//! `schema` goes up whenever a field changes meaning or is removed; new fields may appear
+//! without it changing.
+
+use crate::actions::BUILTIN;
+use crate::hashing::{QuickSample, ALGORITHM};
+use crate::output::Format;
+use clap::ValueEnum;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// Version of the document layout.
+pub const SCHEMA: u32 = 1;
+
+#[derive(Debug, Serialize)]
+pub struct Capabilities {
+    pub schema: u32,
+    pub version: &'static str,
+    pub os: &'static str,
+    pub arch: &'static str,
+    /// Cargo features and whether this build has them.
+    pub features: BTreeMap<&'static str, bool>,
+    pub actions: Vec<ActionSupport>,
+    pub hash_algorithms: Vec<&'static str>,
+    pub quick_samples: Vec<String>,
+    pub digest_formats: Vec<&'static str>,
+    /// Layouts of the group listing: `--format` values, plus `json` for `--json`.
+    pub formats: Vec<String>,
+    pub subcommands: Vec<String>,
+}
+
+/// An action flag and whether it can work on this platform. A supported action can still be
+/// refused by a filesystem; `ducky doctor` probes that.
+#[derive(Debug, Serialize, PartialEq, Eq)]
+pub struct ActionSupport {
+    pub name: &'static str,
+    pub supported: bool,
+}
+
+impl Capabilities {
+    /// Everything but `subcommands`, which the command line parser knows.
+    pub fn detect() -> Self {
+        let features = [
+            ("media", cfg!(feature = "media")),
+            ("tui", cfg!(feature = "tui")),
+            ("decompress", cfg!(feature = "decompress")),
+            ("parquet", cfg!(feature = "parquet")),
+        ];
+        let mut formats = value_names(Format::value_variants());
+        formats.push("json".into());
+        let actions = BUILTIN
+            .iter()
+            .map(|(name, _)| *name)
+            .chain(["move-to", "replicate"])
+            .map(|name| ActionSupport { name, supported: supported(name) })
+            .collect();
+        Capabilities {
+            schema: SCHEMA,
+            version: env!("CARGO_PKG_VERSION"),
+            os: std::env::consts::OS,
+            arch: std::env::consts::ARCH,
+            features: features.into_iter().collect(),
+            actions,
+            hash_algorithms: vec![ALGORITHM],
+            quick_samples: value_names(QuickSample::value_variants()),
+            digest_formats: vec!["hex", "base64", "short:N"],
+            formats,
+            subcommands: Vec::new(),
+        }
+    }
+}
+
+fn value_names<T: ValueEnum>(variants: &[T]) -> Vec<String> {
+    variants
+        .iter()
+        .filter_map(ValueEnum::to_possible_value)
+        .map(|p| p.get_name().to_string())
+        .collect()
+}
+
+/// Whether the action named `name` can work on this platform at all.
+fn supported(name: &str) -> bool {
+    match name {
+        "reflink" => cfg!(any(target_os = "linux", target_os = "macos")),
+        "dedupe-extents" => cfg!(target_os = "linux"),
+        "trash" => cfg!(any(
+            target_os = "windows",
+            all(unix, not(target_os = "ios"), not(target_os = "android"))
+        )),
+        "symlink" => cfg!(any(unix, windows)),
+        _ => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_builtin_action_is_listed() {
+        let caps = Capabilities::detect();
+        let names: Vec<&str> = caps.actions.iter().map(|a| a.name).collect();
+        assert_eq!(&names[..BUILTIN.len()], BUILTIN.iter().map(|(n, _)| *n).collect::<Vec<_>>());
+        assert!(caps.actions.iter().any(|a| a.name == "delete" && a.supported));
+        assert_eq!(caps.formats.first().map(String::as_str), Some("human"));
+        assert_eq!(caps.formats.last().map(String::as_str), Some("json"));
+    }
+}
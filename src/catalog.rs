@@ -0,0 +1,175 @@
+//! Catalogs: content manifests of offline volumes (backup drives, mounted disk images).
+//!
+//! `ducky catalog add MOUNT --name NAME` hashes every file under MOUNT and stores a manifest
+//! in the data directory. Later scans look duplicate groups up in all catalogs and report
+//! where else the content lives, without the volume being attached.
+
+use crate::fs_utils::{collect_files, create_artifact_dir, data_dir, Bounds};
+use crate::hashing::{full_hash, ContentKey};
+use crate::output::DuplicateGroup;
+use crate::schedule;
+use crate::warnings::warning;
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CatalogEntry {
+    /// Path relative to the catalog root.
+    pub path: PathBuf,
+    pub key: ContentKey,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Catalog {
+    pub name: String,
+    /// Where the volume was mounted when it was cataloged.
+    pub root: PathBuf,
+    pub created: u64,
+    pub entries: Vec<CatalogEntry>,
+}
+
+/// Directory holding `<name>.json` manifests.
+pub fn catalog_dir() -> Result<PathBuf> {
+    data_dir()
+        .map(|d| d.join("catalogs"))
+        .context("no data directory could be determined")
+}
+
+/// Names may only contain ASCII letters, digits, `.`, `_` and `-`, and may not start with `.`.
+pub fn validate_name(name: &str) -> Result<()> {
+    let ok = !name.is_empty()
+        && !name.starts_with('.')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-'));
+    if !ok {
+        bail!("invalid catalog name {:?} (use letters, digits, '.', '_' or '-')", name);
+    }
+    Ok(())
+}
+
+/// Hash every file under `root` into a catalog called `name`.
+pub fn build(root: &Path, name: &str, min_size: u64, threads: usize) -> Result<Catalog> {
+    validate_name(name)?;
+    let bounds = Bounds { min_size, ..Default::default() };
+    let roots = [root.to_path_buf()];
+    let files = collect_files(&roots, true, false, &bounds, None, &[], &Default::default())
+        .with_context(|| format!("walking {}", root.display()))?;
+    let hashes = schedule::parallel_map(&files, threads, |f| {
+        full_hash(&f.path).map(|h| ContentKey::new(f.meta.size, h))
+    });
+    let mut entries = Vec::with_capacity(files.len());
+    for (p, res) in files.iter().map(|f| &f.path).zip(hashes) {
+        match res {
+            Ok(key) => entries.push(CatalogEntry {
+                path: p.strip_prefix(root).unwrap_or(p).to_path_buf(),
+                key,
+            }),
+            Err(e) => warning!("catalog: skipping {}: {:#}", p.display(), e),
+        }
+    }
+    Ok(Catalog {
+        name: name.to_string(),
+        root: std::path::absolute(root).unwrap_or_else(|_| root.to_path_buf()),
+        created: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0),
+        entries,
+    })
+}
+
+pub fn save(dir: &Path, catalog: &Catalog) -> Result<PathBuf> {
+    create_artifact_dir(dir).with_context(|| format!("create {}", dir.display()))?;
+    let path = dir.join(format!("{}.json", catalog.name));
+    let tmp = dir.join(format!(".{}.json.tmp", catalog.name));
+    fs::write(&tmp, serde_json::to_vec(catalog)?).with_context(|| format!("write {}", tmp.display()))?;
+    fs::rename(&tmp, &path).with_context(|| format!("write {}", path.display()))?;
+    Ok(path)
+}
+
+/// Load every catalog in `dir`; unreadable manifests are skipped with a warning.
+pub fn load_all(dir: &Path) -> Vec<Catalog> {
+    let Ok(rd) = fs::read_dir(dir) else { return Vec::new() };
+    let mut paths: Vec<PathBuf> = rd
+        .filter_map(|e| e.ok().map(|e| e.path()))
+        .filter(|p| p.extension().is_some_and(|e| e == "json"))
+        .collect();
+    paths.sort();
+    paths
+        .into_iter()
+        .filter_map(|p| {
+            let res = fs::read(&p)
+                .map_err(anyhow::Error::from)
+                .and_then(|b| serde_json::from_slice::<Catalog>(&b).map_err(Into::into));
+            match res {
+                Ok(c) => Some(c),
+                Err(e) => {
+                    warning!("ignoring catalog {}: {:#}", p.display(), e);
+                    None
+                }
+            }
+        })
+        .collect()
+}
+
+pub fn remove(dir: &Path, name: &str) -> Result<()> {
+    validate_name(name)?;
+    let path = dir.join(format!("{}.json", name));
+    fs::remove_file(&path).with_context(|| format!("no catalog named {}", name))
+}
+
+/// Fill in `also_on` for every group whose content appears in a catalog,
+/// as `name:relative/path` (first match per catalog).
+pub fn annotate(groups: &mut [DuplicateGroup], catalogs: &[Catalog]) {
+    let mut index: HashMap<&ContentKey, Vec<(&str, &Path)>> = HashMap::new();
+    for c in catalogs {
+        for e in &c.entries {
+            let hits = index.entry(&e.key).or_default();
+            if !hits.iter().any(|(n, _)| *n == c.name) {
+                hits.push((&c.name, &e.path));
+            }
+        }
+    }
+    for g in groups.iter_mut() {
+        if let Some(hits) = index.get(&g.key()) {
+            g.also_on = hits
+                .iter()
+                .map(|(n, p)| format!("{}:{}", n, p.display()))
+                .collect();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn build_save_load_and_annotate() {
+        let base = std::env::temp_dir().join(format!("ducky_catalog_{}", std::process::id()));
+        let vol = base.join("vol");
+        std::fs::create_dir_all(vol.join("photos")).unwrap();
+        std::fs::write(vol.join("photos/a.jpg"), b"pixels").unwrap();
+
+        let cat = build(&vol, "backup-2021", 0, 2).unwrap();
+        assert_eq!(cat.entries.len(), 1);
+        let dir = base.join("catalogs");
+        save(&dir, &cat).unwrap();
+        let loaded = load_all(&dir);
+        assert_eq!(loaded.len(), 1);
+
+        let hash = full_hash(&vol.join("photos/a.jpg")).unwrap();
+        let mut groups = vec![DuplicateGroup::new(6, vec!["/x".into(), "/y".into()]).with_hash(hash)];
+        annotate(&mut groups, &loaded);
+        assert_eq!(groups[0].also_on, vec![format!("backup-2021:{}", Path::new("photos/a.jpg").display())]);
+
+        remove(&dir, "backup-2021").unwrap();
+        assert!(load_all(&dir).is_empty());
+        assert!(validate_name("../etc").is_err());
+
+        let _ = std::fs::remove_dir_all(base);
+    }
+}
@@ -1,12 +1,153 @@
 //! CLI option parsing with clap for the ducky deduper.
 
+use anyhow::{bail, Result};
+use ducky::config::{self, Config};
+use ducky::hashing::{DigestFormat, QuickSample};
+use ducky::output::Format;
+use ducky::schedule::Strategy;
 use bytesize::ByteSize;
-use clap::{ArgAction, Parser};
+use clap::{ArgAction, Args, CommandFactory, FromArgMatches, Parser, Subcommand};
+use std::ffi::OsString;
 use std::path::PathBuf;
 
 #[derive(Parser, Debug)]
-#[command(author, version, about = "Find candidate files for deduplication")]
+#[command(
+    author,
+    version,
+    about = "Find candidate files for deduplication",
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
+)]
 pub struct Opts {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// Read option defaults from FILE instead of ~/.config/ducky/config.toml
+    #[arg(long, global = true, value_name = "FILE")]
+    pub config: Option<PathBuf>,
+
+    /// Apply the [profile.NAME] table of the config file on top of its top-level keys; the
+    /// profile's `roots` stand in for PATHS
+    #[arg(long, global = true, value_name = "NAME")]
+    pub profile: Option<String>,
+
+    /// Print what this build supports (actions on this platform, hash algorithms, formats,
+    /// features) as JSON and exit
+    #[arg(long, exclusive = true)]
+    pub capabilities: bool,
+
+    /// The flat `ducky PATHS...` form, kept as shorthand for `ducky scan PATHS...`.
+    #[command(flatten)]
+    pub scan: ScanOpts,
+}
+
+/// Parse the command line, with scan option defaults taken from the config file and the
+/// profile named by `--profile`. Other commands don't read the config file.
+pub fn parse() -> Result<Opts> {
+    let args: Vec<OsString> = std::env::args_os().collect();
+    let explicit = early_arg(&args, "config").map(PathBuf::from);
+    let profile = early_arg(&args, "profile");
+    let mut cmd = Opts::command();
+    // A missing default file is fine; a file named with --config must exist.
+    let path = explicit
+        .clone()
+        .or_else(config::default_path)
+        .filter(|p| explicit.is_some() || p.exists());
+    let (path, profile) = if is_scan(&cmd, &args) { (path, profile) } else { (None, None) };
+    match (path, profile) {
+        (Some(path), profile) => {
+            let config = config::load(&path)?;
+            cmd = with_defaults(cmd, &config, &config.entries)?;
+            if let Some(name) = profile {
+                let name = name.to_string_lossy();
+                cmd = with_defaults(cmd, &config, &config.profile(&name)?.entries)?;
+            }
+        }
+        (None, Some(name)) => {
+            let path = config::default_path().unwrap_or_else(|| config::FILE_NAME.into());
+            bail!("--profile {}: no config file at {}", name.to_string_lossy(), path.display());
+        }
+        (None, None) => {}
+    }
+    let matches = cmd.get_matches_from(args);
+    Ok(Opts::from_arg_matches(&matches).unwrap_or_else(|e| e.exit()))
+}
+
+/// Whether `args` run a scan: the flat form, `ducky scan` or `ducky report`. Errors are left
+/// for the real parse, where the config file may still supply missing PATHS.
+fn is_scan(cmd: &clap::Command, args: &[OsString]) -> bool {
+    match cmd.clone().ignore_errors(true).try_get_matches_from(args) {
+        Ok(m) => {
+            let scan = matches!(m.subcommand_name(), None | Some("scan" | "report"));
+            scan && !m.get_flag("capabilities")
+        }
+        Err(_) => true,
+    }
+}
+
+/// The value of `--NAME VALUE` or `--NAME=VALUE`, looked for before clap parses anything.
+fn early_arg(args: &[OsString], name: &str) -> Option<OsString> {
+    let (flag, prefix) = (format!("--{}", name), format!("--{}=", name));
+    let mut it = args.iter().skip(1);
+    while let Some(a) = it.next() {
+        if a == "--" {
+            break;
+        }
+        if *a == *flag {
+            return it.next().cloned();
+        }
+        if let Some(v) = a.to_str().and_then(|s| s.strip_prefix(&prefix)) {
+            return Some(v.into());
+        }
+    }
+    None
+}
+
+/// Make every setting in `entries` (read from `config`) the default of the scan option with
+/// the same long name, in the flat form and in `ducky scan` / `ducky report`. `roots` sets
+/// the default PATHS.
+fn with_defaults(
+    mut cmd: clap::Command,
+    config: &Config,
+    entries: &config::Entries,
+) -> Result<clap::Command> {
+    // Groups declared on arguments (`group = "action"`) only exist once a command is built,
+    // and a built command can't be parsed with again: inspect a copy.
+    let mut built = cmd.clone();
+    built.build();
+    let refused: Vec<clap::Id> = built
+        .get_groups()
+        .filter(|g| g.get_id() == "action")
+        .flat_map(|g| g.get_args().cloned())
+        .chain(["yes", "config", "profile"].map(clap::Id::from))
+        .collect();
+    for (key, value) in entries {
+        let id = if key == "roots" {
+            clap::Id::from("paths")
+        } else {
+            let long = |a: &&clap::Arg| a.get_long() == Some(key.as_str());
+            let Some(id) = cmd.get_arguments().find(long).map(|a| a.get_id().clone()) else {
+                bail!("{}: unknown option {:?}", config.path.display(), key);
+            };
+            id
+        };
+        if refused.contains(&id) {
+            bail!("{}: --{} can only be given on the command line", config.path.display(), key);
+        }
+        let values = value.args();
+        // PATHS is required; default roots satisfy it.
+        let set = |a: clap::Arg| a.default_values(values.clone()).required(false);
+        cmd = cmd
+            .mut_arg(&id, set)
+            .mut_subcommand("scan", |s| s.mut_arg(&id, set))
+            .mut_subcommand("report", |s| s.mut_arg(&id, set));
+    }
+    Ok(cmd)
+}
+
+/// Options of a scan: what to look at, how to hash, what to report and what to do.
+#[derive(Args, Debug)]
+pub struct ScanOpts {
     /// Paths to scan
     #[arg(required = true)]
     pub paths: Vec<PathBuf>,
@@ -15,10 +156,52 @@ pub struct Opts {
     #[arg(long, default_value = "1KB")]
     pub min_size: ByteSize,
 
+    /// Maximum file size to consider (e.g. 4GB), to leave out VM images or videos
+    #[arg(long)]
+    pub max_size: Option<ByteSize>,
+
+    /// Only report and act on groups of at least N files
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u64).range(2..))]
+    pub min_group_size: Option<u64>,
+
+    /// Only report and act on groups that would free at least SIZE (file size × extra copies)
+    #[arg(long, value_name = "SIZE")]
+    pub min_reclaim: Option<ByteSize>,
+
+    /// Only files modified on or after this date (YYYY-MM-DD, UTC) or this long ago (e.g. 30d)
+    #[arg(long, value_name = "DATE|DURATION", value_parser = ducky::fs_utils::parse_time)]
+    pub modified_after: Option<std::time::SystemTime>,
+
+    /// Only files modified before this date or longer ago than this (e.g. 90d: untouched
+    /// for 90 days)
+    #[arg(long, value_name = "DATE|DURATION", value_parser = ducky::fs_utils::parse_time)]
+    pub modified_before: Option<std::time::SystemTime>,
+
+    /// Descend at most N directory levels below each path (1 = only files directly inside)
+    #[arg(long, value_name = "N")]
+    pub max_depth: Option<usize>,
+
+    /// Skip files fewer than N levels below each path
+    #[arg(long, value_name = "N", default_value_t = 0)]
+    pub min_depth: usize,
+
     /// Only include files with these extensions (comma-separated, no dots)
     #[arg(long)]
     pub ext: Option<String>,
 
+    /// Skip paths matching this glob (repeatable). Without a '/', matches any file or
+    /// directory name (`node_modules`, `*.iso`); with one, the path relative to the scan root
+    #[arg(long, value_name = "GLOB", action = ArgAction::Append, value_parser = ducky::fs_utils::parse_exclude)]
+    pub exclude: Vec<glob::Pattern>,
+
+    /// Only scan files whose full path matches this regular expression (repeatable: any)
+    #[arg(long, value_name = "RE", action = ArgAction::Append, value_parser = ducky::fs_utils::parse_regex)]
+    pub path_regex: Vec<regex::Regex>,
+
+    /// Skip files whose full path matches this regular expression (repeatable)
+    #[arg(long, value_name = "RE", action = ArgAction::Append, value_parser = ducky::fs_utils::parse_regex)]
+    pub path_regex_exclude: Vec<regex::Regex>,
+
     /// Include hidden files
     #[arg(long)]
     pub hidden: bool,
@@ -31,35 +214,457 @@ pub struct Opts {
     #[arg(long, short = 'l', action = ArgAction::SetTrue)]
     pub list: bool,
 
-    /// Quick-hash sample size (first N bytes)
+    /// Quick-hash sample size (N bytes from each sampled spot)
     #[arg(long, default_value = "64KB")]
     pub quick_bytes: ByteSize,
 
+    /// Where the quick hash samples each file: the head, head and tail, or head, middle and
+    /// tail (separates files with identical headers, like videos)
+    #[arg(long, value_enum, default_value_t = QuickSample::default())]
+    pub quick_sample: QuickSample,
+
+    /// Number of hashing threads (default: number of CPUs)
+    #[arg(long, short = 'j', value_parser = clap::value_parser!(u64).range(1..))]
+    pub threads: Option<u64>,
+
+    /// Approximate cap on memory for the path tables; a scan whose tables come near it groups
+    /// by size through a temporary spill file so only files sharing a size stay in memory
+    #[arg(long, value_name = "SIZE")]
+    pub max_memory: Option<ByteSize>,
+
+    /// Also group MP4/MOV/MP3 files whose media payload matches while container metadata
+    /// differs (remuxed or re-tagged copies); reported as near-duplicates, never acted on
+    #[cfg(feature = "media")]
+    #[arg(long)]
+    pub video_headless: bool,
+
+    /// Also hash files compressed with these formats (comma-separated: gz,xz,zst) by their
+    /// decompressed content, so `dump.sql` and `dump.sql.gz` match; reported as compressed
+    /// duplicates, never acted on
+    #[cfg(feature = "decompress")]
+    #[arg(long, value_name = "FORMATS", value_delimiter = ',')]
+    pub decompress: Vec<ducky::compressed::Codec>,
+
     /// Output machine-readable JSON instead of human text
     #[arg(long)]
     pub json: bool,
 
+    /// Layout of the group listing: `human`, or `fdupes` (one path per line, groups
+    /// separated by a blank line) for scripts written against fdupes
+    #[arg(long, value_enum, default_value_t = Format::default(), conflicts_with_all = ["json", "summary_json"])]
+    pub format: Format,
+
+    /// List at most N members of each group in human output, then "... and M more"
+    /// (0: all); JSON, fdupes and Parquet always list every member
+    #[arg(long, value_name = "N", default_value_t = 20)]
+    pub max_members_shown: usize,
+
+    /// Also write the groups as CSV, one row per member with an empty "decision" column to
+    /// fill in and execute with `ducky apply --from-csv`
+    #[arg(long, value_name = "FILE")]
+    pub csv_out: Option<PathBuf>,
+
+    /// Save the groups with hashes, sizes and mtimes to FILE, to act on later with
+    /// `ducky apply FILE --delete`
+    #[arg(long, value_name = "FILE")]
+    pub save: Option<PathBuf>,
+
+    /// Write a shields.io endpoint badge ("duplicates: 12.3 GiB reclaimable") to FILE
+    #[arg(long, value_name = "FILE")]
+    pub badge_json: Option<PathBuf>,
+
+    /// Add group ids, full hashes and per-member mtime/inode/device/nlink to JSON groups
+    #[arg(long)]
+    pub json_detail: bool,
+
+    /// How hashes are written to --json-detail, --csv-out and Parquet output: hex, base64 or
+    /// short:N (first N bytes; `ducky apply` then needs --verify)
+    #[arg(long, value_name = "FORMAT", default_value = "hex")]
+    pub digest_format: DigestFormat,
+
+    /// Also report whole directory trees that are identical, as one group each; file groups
+    /// inside them are not listed again. Report only: combine with an action is refused
+    #[arg(long, conflicts_with = "action")]
+    pub dirs: bool,
+
+    /// List the files that have no copy anywhere in the scanned paths instead of the groups,
+    /// to check that nothing in a folder is the last copy before deleting it. Report only
+    #[arg(long, conflicts_with_all = ["action", "format"])]
+    pub unique: bool,
+
+    /// Also report names in one directory that differ only by case, which collide when the
+    /// tree is synced to Windows or macOS
+    #[arg(long)]
+    pub case_conflicts: bool,
+
+    /// Also rank directories by the share of their bytes that is duplicated, densest first,
+    /// to show where cleaning up pays off most
+    #[arg(long)]
+    pub heatmap: bool,
+
+    /// List, per group, which copies could be hardlinked or reflinked (same device) and which
+    /// need a symlink or move, with the space each method frees; modifies nothing
+    #[arg(long)]
+    pub link_report: bool,
+
     /// Emit only a single summary JSON object with aggregate stats
     #[arg(long)]
     pub summary_json: bool,
 
+    /// In --json/--summary-json modes, collect warnings into a "warnings" array instead of stderr
+    #[arg(long)]
+    pub embed_warnings: bool,
+
     /// Quiet human output: suppress per-group listings and print only the final summary
     #[arg(long, short = 'q')]
     pub quiet: bool,
 
+    /// Print the final summary as a single plain line instead of a boxed panel
+    #[arg(long)]
+    pub no_fancy: bool,
+
     /// Delete duplicates (keep the first path in each group as canonical)
-    #[arg(long, conflicts_with = "hardlink")]
+    #[arg(long, group = "action")]
     pub delete: bool,
 
+    /// Move duplicates to the OS trash (freedesktop Trash, macOS Trash, Windows Recycle Bin)
+    /// instead of deleting them permanently
+    #[arg(long, group = "action")]
+    pub trash: bool,
+
     /// Replace duplicates with hard links to the canonical file (first path)
-    #[arg(long, conflicts_with = "delete")]
+    #[arg(long, group = "action")]
     pub hardlink: bool,
 
+    /// Replace duplicates with copy-on-write clones of the canonical file (btrfs, XFS, APFS);
+    /// unlike hard links, later writes to either copy don't affect the other
+    #[arg(long, group = "action")]
+    pub reflink: bool,
+
+    /// Make duplicates share the canonical's extents in place (Linux FIDEDUPERANGE, btrfs and
+    /// XFS); the kernel compares the bytes itself and leaves files that differ untouched
+    #[arg(long, group = "action")]
+    pub dedupe_extents: bool,
+
+    /// Replace duplicates with symbolic links to the canonical file (works across devices)
+    #[arg(long, group = "action")]
+    pub symlink: bool,
+
+    /// With --symlink, use link targets relative to each duplicate's directory
+    #[arg(long, requires = "symlink")]
+    pub symlink_relative: bool,
+
+    /// With --symlink, hardlink duplicates on the canonical's device instead where the OS
+    /// does not permit creating symlinks (Windows without Developer Mode or admin rights)
+    #[arg(long, requires = "symlink")]
+    pub symlink_fallback_hardlink: bool,
+
+    /// Soft-delete: move duplicates into DIR (mirroring their absolute paths) instead of
+    /// deleting them
+    #[arg(long, value_name = "DIR", group = "action")]
+    pub move_to: Option<PathBuf>,
+
+    /// With --move-to, let `ducky quarantine purge` remove the files after this long
+    /// (e.g. 30d, 12h, 2w)
+    #[arg(long, value_name = "DURATION", requires = "move_to", value_parser = ducky::quarantine::parse_expiry)]
+    pub expire_after: Option<u64>,
+
+    /// Instead of deleting, keep at most N copies of each content on distinct devices,
+    /// moving surplus copies onto a scan root on another device when needed
+    #[arg(long, value_name = "N", group = "action",
+          value_parser = clap::value_parser!(u64).range(1..))]
+    pub replicate: Option<u64>,
+
+    /// Keep the first N members of each group (the canonical and the next N-1, in listing
+    /// order) and act only on the rest, for intentional redundancy
+    #[arg(long, value_name = "N", requires = "action", conflicts_with = "replicate",
+          value_parser = clap::value_parser!(u64).range(1..))]
+    pub keep_copies: Option<u64>,
+
+    /// Stream every attempted operation (op, src, dst, result, error) to FILE as NDJSON
+    #[arg(long, value_name = "FILE", requires = "action")]
+    pub action_log: Option<PathBuf>,
+
+    /// Only modify duplicates below this directory (repeatable); members elsewhere are kept,
+    /// and one of them is the canonical, even when the scan covers them
+    #[arg(long, visible_alias = "delete-in", value_name = "DIR", action = ArgAction::Append,
+          requires = "action")]
+    pub act_only_under: Vec<PathBuf>,
+
+    /// Never modify files matching this glob (repeatable), and keep a matching member as the
+    /// group's canonical. Without a '/', matches any file or directory name (`originals`,
+    /// `*.raw`); with one, the path or a directory above it (`~/Photos/originals`)
+    #[arg(long, value_name = "GLOB", action = ArgAction::Append, value_parser = ducky::fs_utils::parse_path_glob)]
+    pub protect: Vec<glob::Pattern>,
+
+    /// Keep a member matching this glob (repeatable) as the group's canonical rather than the
+    /// first path; patterns work as for --protect. Groups list members in that order
+    #[arg(long, value_name = "GLOB", action = ArgAction::Append, value_parser = ducky::fs_utils::parse_path_glob)]
+    pub prefer: Vec<glob::Pattern>,
+
+    /// Keep members matching this glob (repeatable) only when every member matches: they are
+    /// listed last and are the first to go
+    #[arg(long, value_name = "GLOB", action = ArgAction::Append, value_parser = ducky::fs_utils::parse_path_glob)]
+    pub avoid: Vec<glob::Pattern>,
+
+    /// Treat a camera RAW file and the JPEG next to it (same name) as one photo: list each
+    /// member's other half, keep a complete pair as the canonical, and only act on a half
+    /// when its other half goes too. Not with --replicate
+    #[arg(long, conflicts_with = "replicate")]
+    pub raw_jpeg_pairs: bool,
+
+    /// Reference tree to compare against (repeatable): scanned and hashed, never modified.
+    /// Only files elsewhere that duplicate something in it are reported
+    #[arg(long, value_name = "REF_DIR", action = ArgAction::Append)]
+    pub compare: Vec<PathBuf>,
+
+    /// Confirm the action group by group on a terminal (apply, skip, quit or choose which
+    /// file to keep); without a terminal nothing is modified
+    #[arg(long, requires = "action", conflicts_with = "yes")]
+    pub interactive: bool,
+
+    /// Like --interactive, but only for as long as DURATION (e.g. 10m): the groups that
+    /// free the most space come first, and the confirmed ones are applied when time is up
+    #[arg(
+        long,
+        value_name = "DURATION",
+        requires = "action",
+        conflicts_with_all = ["yes", "interactive"],
+        value_parser = ducky::quarantine::parse_expiry
+    )]
+    pub triage: Option<u64>,
+
+    /// Review groups full-screen (choose the file to keep, skip groups) and apply the action
+    /// on exit
+    #[cfg(feature = "tui")]
+    #[arg(long, requires = "action", conflicts_with_all = ["yes", "interactive", "triage"])]
+    pub tui: bool,
+
+    /// Choose which duplicates the action applies to via an interactive picker
+    #[arg(long)]
+    pub pick: bool,
+
+    /// Picker command for --pick; reads candidate lines on stdin, prints selected lines
+    #[arg(long, default_value = ducky::pick::DEFAULT_PICKER, requires = "pick")]
+    pub picker: String,
+
+    /// Re-hash the canonical file and compare every member with it byte by byte right before
+    /// modifying each group; skip the group if anything is unreadable or differs
+    #[arg(long)]
+    pub paranoid: bool,
+
+    /// Re-hash each file right before modifying it (and the canonical before its group) on
+    /// top of the size and mtime check every action makes; files that changed since the
+    /// scan are left alone and counted as changed_since_scan
+    #[arg(long, requires = "action")]
+    pub verify: bool,
+
+    /// Stop with exit code 3 once more than N errors (unreadable entries, failed hashes,
+    /// failed actions) have occurred; checked after the walk, before acting and at the end
+    #[arg(long, value_name = "N")]
+    pub max_errors: Option<u64>,
+
+    /// Like --max-errors, as a share of the files found (e.g. 1% or 0.01)
+    #[arg(long, value_name = "RATE", value_parser = ducky::budget::parse_rate)]
+    pub max_error_rate: Option<f64>,
+
+    /// Keep files whose metadata can't be read (flaky FUSE or SMB mounts) instead of dropping
+    /// them: they are scanned by size alone, shown as "metadata unknown" and never acted on
+    #[arg(long)]
+    pub tolerate_metadata_errors: bool,
+
+    /// After acting, re-verify a random sample of the modified groups (e.g. 5% or 0.05)
+    #[arg(long, value_name = "SIZE", value_parser = ducky::audit::parse_fraction)]
+    pub audit_sample: Option<f64>,
+
+    /// Seed for every random choice (the --audit-sample sample), so a run can be repeated
+    /// exactly; the seed used is reported either way
+    #[arg(long, value_name = "N")]
+    pub seed: Option<u64>,
+
     /// Don't ask for confirmation before modifying files
     #[arg(long, short = 'y')]
     pub yes: bool,
 
+    /// Run every action check and print what would be changed and how much would be freed,
+    /// without touching files
+    #[arg(long)]
+    pub dry_run: bool,
+
     /// Print basic timings for each phase to stderr; included in summary JSON when used
     #[arg(long)]
     pub timings: bool,
+
+    /// Write the group listing (human or JSON, per the output mode) to FILE; stdout keeps a
+    /// short human summary (or the summary JSON with --summary-json)
+    #[arg(long, value_name = "FILE")]
+    pub groups_out: Option<PathBuf>,
+
+    /// Don't show the hashing progress line (only shown when stderr is a terminal)
+    #[arg(long)]
+    pub no_progress: bool,
+
+    /// Don't read or update the persistent hash cache
+    #[arg(long)]
+    pub no_cache: bool,
+
+    /// Hashing work order (tuning knob)
+    #[arg(long, value_enum, default_value_t = Strategy::default(), hide = true)]
+    pub schedule: Strategy,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Find duplicates and optionally act on them (also the default: `ducky PATHS...`)
+    Scan(Box<ScanOpts>),
+    /// Scan and report only: the same listing and summary, but action flags are refused
+    Report(Box<ScanOpts>),
+    /// Print a shell completion script (bash, zsh, fish, elvish, powershell)
+    Completions {
+        shell: clap_complete::Shell,
+    },
+    /// Check hardlink/reflink/xattr/trash support, cache directory and limits
+    Doctor(DoctorOpts),
+    /// Manage manifests of offline volumes that scans can report matches against
+    Catalog {
+        #[command(subcommand)]
+        action: CatalogCommand,
+    },
+    /// Manage files soft-deleted with --move-to
+    Quarantine {
+        #[command(subcommand)]
+        action: QuarantineCommand,
+    },
+    /// Restore what the most recent modifying run changed (from the action journal)
+    Undo {
+        /// Actually restore files; without it, only list what would be restored
+        #[arg(long, short = 'y')]
+        yes: bool,
+    },
+    /// Act on a scan saved with --save, or execute keep/delete decisions from a CSV exported
+    /// with --csv-out and filled in by hand
+    Apply {
+        /// Scan saved with --save; members changed since then are left alone
+        #[arg(required_unless_present = "from_csv", conflicts_with = "from_csv")]
+        file: Option<PathBuf>,
+        /// The reviewed CSV file
+        #[arg(long, value_name = "FILE")]
+        from_csv: Option<PathBuf>,
+        /// Delete every copy but the first of each saved group
+        #[arg(long, conflicts_with = "trash")]
+        delete: bool,
+        /// Move files to the OS trash instead of removing them
+        #[arg(long)]
+        trash: bool,
+        /// Actually modify files
+        #[arg(long, short = 'y', conflicts_with = "dry_run")]
+        yes: bool,
+        /// Verify and print what would be done without touching files
+        #[arg(long)]
+        dry_run: bool,
+        /// Compare files byte by byte before deleting; required when the hash column holds
+        /// truncated (short:N) digests
+        #[arg(long)]
+        verify: bool,
+    },
+    /// Report files in DIR whose content already exists in an archive, checked through the
+    /// hash cache, and optionally move them to the trash
+    Sentinel {
+        /// Directory to check, such as ~/Downloads
+        dir: PathBuf,
+        /// Archive to check against (repeatable); never modified
+        #[arg(long, value_name = "DIR", required = true, action = ArgAction::Append)]
+        against: Vec<PathBuf>,
+        /// Minimum file size to consider
+        #[arg(long, default_value = "1KB")]
+        min_size: ByteSize,
+        /// Move the matching files to the OS trash
+        #[arg(long)]
+        trash: bool,
+        /// Actually modify files
+        #[arg(long, short = 'y', requires = "trash", conflicts_with = "dry_run")]
+        yes: bool,
+        /// Verify and print what would be trashed without touching files
+        #[arg(long, requires = "trash")]
+        dry_run: bool,
+        /// Output the matches as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Copy a tree into DST, skipping files whose content already exists anywhere under DST
+    Cp {
+        /// Directory (or file) to import, such as a camera card
+        src: PathBuf,
+        /// Destination; files keep their path relative to SRC
+        dst: PathBuf,
+        /// Put a hard link or reflink to the existing copy at each skipped file's place
+        #[arg(long, value_enum)]
+        link: Option<ducky::copy::LinkMode>,
+        /// Print what would be copied or linked without touching files
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Inspect or clear the persistent hash cache
+    Cache {
+        #[command(subcommand)]
+        action: CacheCommand,
+    },
+    /// Create a reproducible directory tree from a fixture spec (developer tool)
+    #[command(hide = true)]
+    GenFixture {
+        /// Spec file (`-` for stdin); see src/fixture.rs for the format
+        spec: PathBuf,
+        /// Directory to populate
+        dir: PathBuf,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum QuarantineCommand {
+    /// Permanently remove quarantined files whose --expire-after period has passed
+    Purge {
+        /// Quarantine directory given to --move-to
+        dir: PathBuf,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum CacheCommand {
+    /// Show the cache location, entry count and size on disk
+    Stats,
+    /// Delete the cache file
+    Clear,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum CatalogCommand {
+    /// Hash every file under a mounted volume or disk image and store its manifest
+    Add {
+        /// Mount point of the volume to catalog
+        path: PathBuf,
+
+        /// Name reported by later scans (letters, digits, '.', '_', '-')
+        #[arg(long)]
+        name: String,
+
+        /// Minimum file size to record
+        #[arg(long, default_value = "1KB")]
+        min_size: ByteSize,
+    },
+    /// List stored catalogs
+    List,
+    /// Delete a stored catalog
+    Remove {
+        name: String,
+    },
+}
+
+#[derive(Args, Debug)]
+pub struct DoctorOpts {
+    /// Directories to probe (defaults to the current directory)
+    pub paths: Vec<PathBuf>,
+
+    /// Output the capabilities report as JSON
+    #[arg(long)]
+    pub json: bool,
 }
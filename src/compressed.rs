@@ -0,0 +1,225 @@
+//! `--decompress gz,xz,zst`: hash single-file compressed blobs by their decompressed content,
+//! so `backup.sql` and `backup.sql.gz` (or the same dump compressed twice at different levels)
+//! are recognized as one content.
+//!
+//! Compressed files are picked by extension and streamed through the decoder into BLAKE3, so
+//! nothing is written to disk. Plain files are only hashed when their size equals a
+//! decompressed size, through the persistent cache. Matches are reported as compressed
+//! duplicates, with the on-disk size of each member, and never acted on.
+
+use crate::cache::{cached, HashCache};
+use crate::fs_utils::ScannedFile;
+use crate::hashing::full_hash;
+use crate::warnings::warning;
+use anyhow::{Context, Result};
+use blake3::Hasher;
+use bytesize::ByteSize;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashSet};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+/// Compression formats `--decompress` can look through.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+#[serde(rename_all = "lowercase")]
+pub enum Codec {
+    /// gzip (`.gz`, `.tgz`)
+    Gz,
+    /// xz (`.xz`, `.txz`)
+    Xz,
+    /// Zstandard (`.zst`, `.tzst`)
+    Zst,
+}
+
+/// Pick a codec from the file extension.
+pub fn codec_for(path: &Path) -> Option<Codec> {
+    let ext = path.extension()?.to_str()?.to_ascii_lowercase();
+    match ext.as_str() {
+        "gz" | "tgz" => Some(Codec::Gz),
+        "xz" | "txz" => Some(Codec::Xz),
+        "zst" | "tzst" => Some(Codec::Zst),
+        _ => None,
+    }
+}
+
+/// Most bytes a compressed file may decompress to, per byte on disk. gzip can't go past about
+/// 1032:1; anything further is more likely a decompression bomb than a backup.
+pub const MAX_RATIO: u64 = 1100;
+
+/// Most bytes any compressed file may decompress to, whatever its size.
+pub const MAX_DECOMPRESSED: u64 = 64 << 30;
+
+/// Counts and hashes everything written to it, refusing to take more than `limit` bytes.
+struct HashSink {
+    hasher: Hasher,
+    len: u64,
+    limit: u64,
+}
+
+impl Write for HashSink {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.len.saturating_add(buf.len() as u64) > self.limit {
+            return Err(io::Error::other(format!(
+                "decompresses to more than {}; skipped",
+                ByteSize(self.limit)
+            )));
+        }
+        self.hasher.update(buf);
+        self.len += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Decompressed length and BLAKE3 hash of `path`. Concatenated gzip members and zstd frames
+/// are read to the end, like `zcat` does. Decoding stops with an error past [`MAX_RATIO`]
+/// times the file's size or [`MAX_DECOMPRESSED`].
+pub fn content_hash(path: &Path, codec: Codec) -> Result<(u64, String)> {
+    let f = File::open(path).with_context(|| format!("open {}", path.display()))?;
+    let limit = f.metadata()?.len().max(1).saturating_mul(MAX_RATIO).min(MAX_DECOMPRESSED);
+    decode_hash(BufReader::with_capacity(1024 * 1024, f), codec, limit)
+}
+
+/// [`content_hash`] of an open stream, giving up past `limit` decompressed bytes.
+fn decode_hash(mut input: impl BufRead, codec: Codec, limit: u64) -> Result<(u64, String)> {
+    let mut sink = HashSink { hasher: Hasher::new(), len: 0, limit };
+    match codec {
+        Codec::Gz => {
+            io::copy(&mut flate2::bufread::MultiGzDecoder::new(input), &mut sink)?;
+        }
+        Codec::Xz => lzma_rs::xz_decompress(&mut input, &mut sink)
+            .map_err(|e| anyhow::anyhow!("{:?}", e))?,
+        Codec::Zst => {
+            while !input.fill_buf()?.is_empty() {
+                let mut frame = ruzstd::decoding::StreamingDecoder::new(&mut input)?;
+                io::copy(&mut frame, &mut sink)?;
+            }
+        }
+    }
+    Ok((sink.len, sink.hasher.finalize().to_hex().to_string()))
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CompressedMember {
+    pub path: PathBuf,
+    /// Size on disk.
+    pub size: u64,
+    /// How the member is compressed; absent for plain files.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub codec: Option<Codec>,
+}
+
+/// Files holding the same content, at least one of them compressed.
+#[derive(Debug, Clone, Serialize)]
+pub struct CompressedGroup {
+    /// Size of the content once decompressed.
+    pub size: u64,
+    pub members: Vec<CompressedMember>,
+}
+
+/// Group `files` by decompressed content. Groups whose members are all in one exact
+/// duplicate group already (per `exact`) are left out.
+pub fn compressed_duplicates(
+    files: &[ScannedFile],
+    codecs: &[Codec],
+    threads: usize,
+    cache: &HashCache,
+    exact: impl Fn(&[PathBuf]) -> bool,
+) -> Vec<CompressedGroup> {
+    let (packed, plain): (Vec<(&ScannedFile, Option<Codec>)>, Vec<_>) = files
+        .iter()
+        .map(|f| (f, codec_for(&f.path).filter(|c| codecs.contains(c))))
+        .partition(|(_, c)| c.is_some());
+    let unpacked = crate::schedule::parallel_map(&packed, threads, |(f, c)| {
+        content_hash(&f.path, c.expect("partitioned on codec"))
+    });
+
+    let mut by_content: BTreeMap<(u64, String), Vec<CompressedMember>> = BTreeMap::new();
+    for ((f, codec), res) in packed.iter().zip(unpacked) {
+        match res {
+            Ok(key) => by_content.entry(key).or_default().push(CompressedMember {
+                path: f.path.clone(),
+                size: f.meta.size,
+                codec: *codec,
+            }),
+            Err(e) => warning!("cannot decompress {}: {:#}", f.path.display(), e),
+        }
+    }
+
+    // Plain files can only match a decompressed content of their own size.
+    let sizes: HashSet<u64> = by_content.keys().map(|(size, _)| *size).collect();
+    let plain: Vec<&ScannedFile> =
+        plain.into_iter().map(|(f, _)| f).filter(|f| sizes.contains(&f.meta.size)).collect();
+    let hashes = crate::schedule::parallel_map(&plain, threads, |f| {
+        let p = &f.path;
+        cached(p, cache, |id| cache.full(id), |id, h| cache.put_full(id, h), || full_hash(p))
+    });
+    for (f, res) in plain.iter().zip(hashes) {
+        match res {
+            Ok(h) => {
+                if let Some(members) = by_content.get_mut(&(f.meta.size, h)) {
+                    let (path, size) = (f.path.clone(), f.meta.size);
+                    members.push(CompressedMember { path, size, codec: None });
+                }
+            }
+            Err(e) => warning!("hash failed {}: {}", f.path.display(), e),
+        }
+    }
+
+    by_content
+        .into_iter()
+        .filter(|(_, m)| m.len() > 1)
+        .filter_map(|((size, _), mut members)| {
+            members.sort_by(|a, b| a.path.cmp(&b.path));
+            let paths: Vec<PathBuf> = members.iter().map(|m| m.path.clone()).collect();
+            (!exact(&paths)).then_some(CompressedGroup { size, members })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs_utils::FileMeta;
+
+    #[test]
+    fn compressed_copies_match_the_plain_file() {
+        let dir = std::env::temp_dir().join(format!("ducky_decompress_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let dump: Vec<u8> = b"INSERT INTO t VALUES (1);\n".repeat(500);
+        let (plain, gz, fast, other) =
+            (dir.join("backup.sql"), dir.join("backup.sql.gz"), dir.join("b.gz"), dir.join("c.gz"));
+        std::fs::write(&plain, &dump).unwrap();
+        for (path, level, data) in [(&gz, 9, &dump), (&fast, 1, &dump), (&other, 6, &vec![7u8; 13_000])] {
+            let mut enc = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::new(level));
+            enc.write_all(data).unwrap();
+            std::fs::write(path, enc.finish().unwrap()).unwrap();
+        }
+        assert_eq!(content_hash(&gz, Codec::Gz).unwrap().0, dump.len() as u64);
+        let packed = std::fs::read(&gz).unwrap();
+        let err = decode_hash(&packed[..], Codec::Gz, dump.len() as u64 - 1).unwrap_err();
+        assert!(err.to_string().contains("decompresses to more than"), "{}", err);
+
+        let scanned: Vec<ScannedFile> = [&plain, &gz, &fast, &other]
+            .into_iter()
+            .map(|p| ScannedFile { path: p.clone(), meta: FileMeta::of(p).unwrap() })
+            .collect();
+        let cache = HashCache::disabled();
+        let groups = compressed_duplicates(&scanned, &[Codec::Gz], 2, &cache, |_| false);
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].size, dump.len() as u64);
+        let paths: Vec<&PathBuf> = groups[0].members.iter().map(|m| &m.path).collect();
+        assert_eq!(paths, vec![&fast, &plain, &gz]);
+        assert_eq!(groups[0].members[1].codec, None);
+
+        // Codecs that weren't asked for are left alone.
+        assert!(compressed_duplicates(&scanned, &[Codec::Xz], 2, &cache, |_| false).is_empty());
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
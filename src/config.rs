@@ -0,0 +1,241 @@
+//! Config file: `~/.config/ducky/config.toml` (or `--config FILE`) supplies defaults for scan
+//! options, such as a minimum size or excludes that apply to every run. Options given on the
+//! command line replace the file's values.
+//!
+//! Keys are the long option names (`min-size` or `min_size`) and values are TOML strings,
+//! integers, booleans or arrays of them:
+//!
+//! ```toml
+//! min-size = "1MB"
+//! exclude = ["**/node_modules/**", "**/.git/**"]
+//! quick-sample = "head-tail"
+//! hidden = true
+//! ```
+//!
+//! Named profiles hold the settings of a recurring scan, including its `roots`, and are
+//! applied on top of the top-level keys by `--profile NAME`:
+//!
+//! ```toml
+//! [profile.photos]
+//! roots = ["~/Pictures", "/mnt/backup/photos"]
+//! ext = "jpg,heic"
+//! min-size = "200KB"
+//! ```
+//!
+//! Tables other than `[profile.NAME]` are refused, and so are floats and dates. Actions and
+//! `--yes` can't be set here, so a config file never makes a plain scan modify files. The
+//! file is only read for scans (`ducky PATHS`, `ducky scan`, `ducky report`).
+
+use anyhow::{bail, Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub const FILE_NAME: &str = "config.toml";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Value {
+    Str(String),
+    Int(i64),
+    Bool(bool),
+    Array(Vec<Value>),
+}
+
+impl Value {
+    /// The value as option arguments: one per array element. A leading `~/` is replaced
+    /// with the home directory, as a shell would have done on the command line.
+    pub fn args(&self) -> Vec<String> {
+        match self {
+            Value::Str(s) => vec![expand_home(s, home_dir().as_deref())],
+            Value::Int(n) => vec![n.to_string()],
+            Value::Bool(b) => vec![b.to_string()],
+            Value::Array(items) => items.iter().flat_map(Value::args).collect(),
+        }
+    }
+}
+
+fn home_dir() -> Option<String> {
+    let key = if cfg!(windows) { "USERPROFILE" } else { "HOME" };
+    std::env::var(key).ok().filter(|h| !h.is_empty())
+}
+
+/// `~` or `~/rest` under `home`; anything else (including `~user/`) is left as written.
+fn expand_home(s: &str, home: Option<&str>) -> String {
+    let Some(home) = home else { return s.to_string() };
+    match s.strip_prefix('~') {
+        Some("") => home.to_string(),
+        Some(rest) if rest.starts_with('/') || (cfg!(windows) && rest.starts_with('\\')) => {
+            format!("{}{}", home.trim_end_matches(['/', '\\']), rest)
+        }
+        _ => s.to_string(),
+    }
+}
+
+/// `(key, value)` in file order, keys with `-` instead of `_`.
+pub type Entries = Vec<(String, Value)>;
+
+/// A `[profile.NAME]` table.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Profile {
+    pub name: String,
+    pub entries: Entries,
+}
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub path: PathBuf,
+    /// Keys before the first profile, applied to every scan.
+    pub entries: Entries,
+    pub profiles: Vec<Profile>,
+}
+
+impl Config {
+    pub fn profile(&self, name: &str) -> Result<&Profile> {
+        if let Some(p) = self.profiles.iter().find(|p| p.name == name) {
+            return Ok(p);
+        }
+        let names: Vec<&str> = self.profiles.iter().map(|p| p.name.as_str()).collect();
+        bail!(
+            "{}: no profile {:?} (defined: {})",
+            self.path.display(),
+            name,
+            if names.is_empty() { "none".to_string() } else { names.join(", ") }
+        )
+    }
+}
+
+/// `config.toml` in the per-user config directory.
+pub fn default_path() -> Option<PathBuf> {
+    crate::fs_utils::config_dir().map(|d| d.join(FILE_NAME))
+}
+
+pub fn load(path: &Path) -> Result<Config> {
+    let text = fs::read_to_string(path).with_context(|| format!("read {}", path.display()))?;
+    let (entries, profiles) =
+        parse(&text).with_context(|| format!("invalid config {}", path.display()))?;
+    Ok(Config { path: path.to_path_buf(), entries, profiles })
+}
+
+/// Parse the file's text. Returns the top-level entries and the profiles in file order.
+pub fn parse(text: &str) -> Result<(Entries, Vec<Profile>)> {
+    let mut file: toml::Table = toml::from_str(text)?;
+    let mut profiles = Vec::new();
+    if let Some(tables) = file.remove("profile") {
+        let toml::Value::Table(tables) = tables else { bail!("profile must be a table") };
+        for (name, table) in tables {
+            let toml::Value::Table(table) = table else {
+                bail!("profile.{} must be a table", name);
+            };
+            if name.is_empty() {
+                bail!("a profile needs a name");
+            }
+            let entries = entries(table).with_context(|| format!("[profile.{}]", name))?;
+            profiles.push(Profile { name, entries });
+        }
+    }
+    Ok((entries(file)?, profiles))
+}
+
+/// The keys of one table, with `-` instead of `_`.
+fn entries(table: toml::Table) -> Result<Entries> {
+    let mut entries = Entries::new();
+    for (key, value) in table {
+        if let toml::Value::Table(_) = value {
+            bail!("[{}]: only [profile.NAME] tables are supported", key);
+        }
+        let key = key.replace('_', "-");
+        if entries.iter().any(|(k, _)| *k == key) {
+            bail!("{} is set twice", key);
+        }
+        let value = convert(value).with_context(|| format!("{} = ...", key))?;
+        entries.push((key, value));
+    }
+    Ok(entries)
+}
+
+fn convert(value: toml::Value) -> Result<Value> {
+    Ok(match value {
+        toml::Value::String(s) => Value::Str(s),
+        toml::Value::Integer(n) => Value::Int(n),
+        toml::Value::Boolean(b) => Value::Bool(b),
+        toml::Value::Array(items) => Value::Array(
+            items
+                .into_iter()
+                .map(|item| match item {
+                    toml::Value::Array(_) => bail!("nested arrays are not supported"),
+                    item => convert(item),
+                })
+                .collect::<Result<_>>()?,
+        ),
+        other => bail!("{} is not a string, integer, boolean or array", other.type_str()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_the_supported_values() {
+        let text = r#"
+            # defaults for every scan
+            min_size = "1MB"   # trailing comment
+            exclude = [
+                "**/node_modules/**",  # JavaScript
+                '**/.git/**',
+            ]
+            threads = 4
+            hidden = true
+            path-regex = "a\"b#c"
+        "#;
+        let (entries, profiles) = parse(text).unwrap();
+        assert!(profiles.is_empty());
+        assert_eq!(entries[0], ("min-size".to_string(), Value::Str("1MB".into())));
+        assert_eq!(entries[1].1.args(), vec!["**/node_modules/**", "**/.git/**"]);
+        assert_eq!(entries[2].1, Value::Int(4));
+        assert_eq!(entries[3].1.args(), vec!["true"]);
+        assert_eq!(entries[4].1, Value::Str("a\"b#c".into()));
+
+        assert!(parse("[scan]\nmin-size = 1").is_err());
+        assert!(parse("min-size = 1MB").is_err());
+        assert!(parse("hidden = true\nhidden = false").is_err());
+        assert!(parse("exclude = [\"a\"").is_err());
+        assert!(parse("min-size = 1\nmin_size = 2").is_err());
+        assert!(parse("ratio = 0.5").is_err());
+        assert!(parse("exclude = [[\"a\"]]").is_err());
+    }
+
+    #[test]
+    fn a_leading_tilde_is_the_home_directory() {
+        let home = Some("/home/ann");
+        assert_eq!(expand_home("~/Pictures", home), "/home/ann/Pictures");
+        assert_eq!(expand_home("~", home), "/home/ann");
+        assert_eq!(expand_home("~/x", Some("/home/ann/")), "/home/ann/x");
+        assert_eq!(expand_home("~bob/x", home), "~bob/x");
+        assert_eq!(expand_home("/srv/~/x", home), "/srv/~/x");
+        assert_eq!(expand_home("~/x", None), "~/x");
+    }
+
+    #[test]
+    fn profiles_collect_the_keys_below_their_header() {
+        let text = r#"
+            hidden = true
+            [profile.photos]  # pictures only
+            roots = ["/srv/photos", "/mnt/backup"]
+            min-size = "200KB"
+            [profile."music"]
+            ext = "flac"
+        "#;
+        let (entries, profiles) = parse(text).unwrap();
+        assert_eq!(entries, vec![("hidden".to_string(), Value::Bool(true))]);
+        let names: Vec<&str> = profiles.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, ["photos", "music"]);
+        assert_eq!(profiles[0].entries[0].1.args(), ["/srv/photos", "/mnt/backup"]);
+        assert_eq!(profiles[0].entries.len(), 2);
+
+        // The same key may be set at the top level and in a profile, but not twice in one.
+        assert!(parse("hidden = true\n[profile.a]\nhidden = false").is_ok());
+        assert!(parse("[profile.a]\next = \"a\"\next = \"b\"").is_err());
+        assert!(parse("[profile.a]\n[profile.a]").is_err());
+        assert!(parse("[profile.]").is_err());
+    }
+}
@@ -0,0 +1,72 @@
+//! `--case-conflicts`: names in one directory that differ only by case. They coexist on
+//! case-sensitive filesystems but collide when the tree is synced to Windows or macOS, where
+//! one silently replaces the other.
+//!
+//! The check runs over the paths the walk already returned, plus their directories up to the
+//! scan roots, so it needs no second traversal. Files left out by the filters are not seen.
+
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+
+/// Entries of `dir` whose names are equal ignoring case.
+#[derive(Debug, Clone, Serialize, PartialEq, Eq)]
+pub struct CaseConflict {
+    pub dir: PathBuf,
+    pub names: Vec<String>,
+}
+
+/// Case conflicts among `files` and the directories between them and `roots`.
+pub fn case_conflicts<'a>(
+    files: impl IntoIterator<Item = &'a Path>,
+    roots: &[PathBuf],
+) -> Vec<CaseConflict> {
+    let mut entries: BTreeSet<&Path> = BTreeSet::new();
+    for f in files {
+        for p in f.ancestors() {
+            if roots.iter().any(|r| r == p) || !entries.insert(p) {
+                break;
+            }
+        }
+    }
+    let mut by_key: BTreeMap<(&Path, String), BTreeSet<String>> = BTreeMap::new();
+    for p in entries {
+        let (Some(dir), Some(name)) = (p.parent(), p.file_name()) else { continue };
+        let name = name.to_string_lossy();
+        by_key.entry((dir, name.to_lowercase())).or_default().insert(name.into_owned());
+    }
+    by_key
+        .into_iter()
+        .filter(|(_, names)| names.len() > 1)
+        .map(|((dir, _), names)| CaseConflict {
+            dir: dir.to_path_buf(),
+            names: names.into_iter().collect(),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_differing_only_by_case_conflict() {
+        let root = PathBuf::from("/scan");
+        let files: Vec<PathBuf> =
+            ["Photos/a.jpg", "photos/b.jpg", "docs/Readme.md", "docs/README.md", "docs/x"]
+                .iter()
+                .map(|p| root.join(p))
+                .collect();
+        let found = case_conflicts(files.iter().map(PathBuf::as_path), std::slice::from_ref(&root));
+        assert_eq!(
+            found,
+            vec![
+                CaseConflict { dir: root.clone(), names: vec!["Photos".into(), "photos".into()] },
+                CaseConflict {
+                    dir: root.join("docs"),
+                    names: vec!["README.md".into(), "Readme.md".into()],
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,341 @@
+//! `ducky cp SRC DST`: copy a tree, skipping files whose content is already somewhere under
+//! DST. Meant for ingesting camera cards or downloads into an archive that may already hold
+//! part of them.
+//!
+//! Only files sharing a size with something else are hashed, and every hash goes through the
+//! persistent cache, so importing into a large archive only reads what changed since the last
+//! run. With `--link`, a file that already exists gets a hard link or reflink to the existing
+//! copy at its place under DST instead of being left out. Nothing under SRC is modified, and
+//! an existing file under DST is never overwritten.
+
+use crate::cache::{cached, HashCache};
+use crate::fs_utils::{collect_files, Bounds, ScannedFile};
+use crate::hashing::full_hash;
+use crate::{reflink, schedule};
+use crate::warnings::warning;
+use anyhow::{bail, Context, Result};
+use bytesize::ByteSize;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// How a file whose content already exists under DST is placed at its own path.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum LinkMode {
+    /// A hard link to the existing copy (same filesystem only).
+    Hardlink,
+    /// A copy-on-write clone of the existing copy (btrfs, XFS, APFS).
+    Reflink,
+}
+
+/// What happens to one source file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Step {
+    Copy { from: PathBuf, to: PathBuf },
+    /// The content already exists at `existing`; with `--link`, `to` is linked to it.
+    Exists { from: PathBuf, to: PathBuf, existing: PathBuf },
+    /// `to` exists with other content and is left alone.
+    Conflict { from: PathBuf, to: PathBuf },
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct CopyStats {
+    pub copied: usize,
+    pub copied_bytes: u64,
+    pub linked: usize,
+    pub skipped: usize,
+    pub skipped_bytes: u64,
+    pub conflicts: usize,
+    pub errors: usize,
+}
+
+/// Decide, for every file under `src`, whether it is copied or already under `dst`. Files
+/// in `src` with the same content are copied once: the first in path order, and the others
+/// are treated as existing copies of it.
+pub fn plan(src: &Path, dst: &Path, cache: &HashCache, threads: usize) -> Result<Vec<Step>> {
+    let real = fs::canonicalize(src).with_context(|| format!("cp {}", src.display()))?;
+    if let Ok(other) = fs::canonicalize(dst) {
+        if real.starts_with(&other) || other.starts_with(&real) {
+            bail!("{} and {} overlap", src.display(), dst.display());
+        }
+    }
+    let walk = |root: &Path| {
+        let bounds = Bounds::default();
+        collect_files(&[root.to_path_buf()], true, false, &bounds, None, &[], &Default::default())
+    };
+    let pairs = |files: Vec<ScannedFile>| -> Vec<(PathBuf, u64)> {
+        files.into_iter().map(|f| (f.path, f.meta.size)).collect()
+    };
+    let mut sources = pairs(walk(src)?);
+    sources.sort();
+    let existing = if dst.exists() { pairs(walk(dst)?) } else { Vec::new() };
+
+    // Only sizes seen twice (in DST or within SRC) can repeat content.
+    let mut count: HashMap<u64, usize> = HashMap::new();
+    for (_, s) in sources.iter().chain(&existing) {
+        *count.entry(*s).or_default() += 1;
+    }
+    let work: Vec<&PathBuf> = existing
+        .iter()
+        .chain(&sources)
+        .filter(|(_, s)| count[s] > 1)
+        .map(|(p, _)| p)
+        .collect();
+    let hashes = schedule::parallel_map(&work, threads, |p| {
+        cached(p, cache, |id| cache.full(id), |id, h| cache.put_full(id, h), || full_hash(p))
+    });
+    let mut hash_of: HashMap<&Path, String> = HashMap::new();
+    for (p, res) in work.iter().zip(hashes) {
+        match res {
+            Ok(h) => {
+                hash_of.insert(p.as_path(), h);
+            }
+            Err(e) => warning!("hash failed {}: {}", p.display(), e),
+        }
+    }
+
+    // Where each content already is (or will be, once copied).
+    let mut known: HashMap<(u64, &str), PathBuf> = HashMap::new();
+    for (p, s) in &existing {
+        if let Some(h) = hash_of.get(p.as_path()) {
+            known.entry((*s, h.as_str())).or_insert_with(|| p.clone());
+        }
+    }
+    let mut steps = Vec::with_capacity(sources.len());
+    for (from, s) in &sources {
+        let rel = from.strip_prefix(src).ok().filter(|r| !r.as_os_str().is_empty());
+        let to = dst.join(rel.or(from.file_name().map(Path::new)).unwrap_or(from));
+        let hash = hash_of.get(from.as_path()).map(String::as_str);
+        if let Some(existing) = hash.and_then(|h| known.get(&(*s, h))) {
+            steps.push(Step::Exists { from: from.clone(), to, existing: existing.clone() });
+            continue;
+        }
+        if to.exists() {
+            steps.push(Step::Conflict { from: from.clone(), to });
+            continue;
+        }
+        if let Some(h) = hash {
+            known.insert((*s, h), to.clone());
+        }
+        steps.push(Step::Copy { from: from.clone(), to });
+    }
+    Ok(steps)
+}
+
+/// Copy `from` to the new file `to`, keeping its permissions and modification time. The bytes
+/// go to a temporary name next to `to` and are synced before they take its name, so an
+/// interrupted copy never leaves a partial file at `to`.
+fn copy_file(from: &Path, to: &Path) -> io::Result<u64> {
+    if let Some(parent) = to.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let name = to.file_name().unwrap_or_default().to_string_lossy();
+    let tmp = to.with_file_name(format!(".{}.ducky-copy-{}", name, std::process::id()));
+    let res = write_synced(from, &tmp).and_then(|bytes| place(&tmp, to).map(|()| bytes));
+    if res.is_err() {
+        let _ = fs::remove_file(&tmp);
+    }
+    res
+}
+
+fn write_synced(from: &Path, to: &Path) -> io::Result<u64> {
+    let mut src = fs::File::open(from)?;
+    let mut dst = fs::OpenOptions::new().write(true).create_new(true).open(to)?;
+    let bytes = io::copy(&mut src, &mut dst)?;
+    let meta = src.metadata()?;
+    dst.set_permissions(meta.permissions())?;
+    if let Ok(mtime) = meta.modified() {
+        dst.set_modified(mtime)?;
+    }
+    dst.sync_all()?;
+    Ok(bytes)
+}
+
+/// Give `tmp` the name `to` unless something already has it. A hard link fails on an existing
+/// name where a rename would replace it; filesystems without hard links fall back to a rename.
+fn place(tmp: &Path, to: &Path) -> io::Result<()> {
+    match fs::hard_link(tmp, to) {
+        Ok(()) => fs::remove_file(tmp),
+        Err(e) if e.kind() == io::ErrorKind::AlreadyExists => Err(e),
+        Err(_) if to.exists() => Err(io::Error::from(io::ErrorKind::AlreadyExists)),
+        Err(_) => fs::rename(tmp, to),
+    }
+}
+
+/// Link `to` to `existing`, which has the content of `from`. A reflink is a new file, so it
+/// takes `from`'s modification time as a copy would; a hard link shares `existing`'s.
+fn link_file(mode: LinkMode, from: &Path, existing: &Path, to: &Path) -> io::Result<()> {
+    if let Some(parent) = to.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    match mode {
+        LinkMode::Hardlink => fs::hard_link(existing, to),
+        LinkMode::Reflink => {
+            reflink::clone_file(existing, to)?;
+            let res = fs::metadata(from).and_then(|m| m.modified()).and_then(|mtime| {
+                fs::File::options().write(true).open(to)?.set_modified(mtime)
+            });
+            if res.is_err() {
+                let _ = fs::remove_file(to);
+            }
+            res
+        }
+    }
+}
+
+/// Copy one file for `apply`, counting it in `stats`; false if the copy failed.
+fn copy_counted(from: &Path, to: &Path, stats: &mut CopyStats) -> bool {
+    match copy_file(from, to) {
+        Ok(bytes) => {
+            stats.copied += 1;
+            stats.copied_bytes += bytes;
+            true
+        }
+        Err(e) => {
+            warning!("copy {} -> {} failed: {}", from.display(), to.display(), e);
+            stats.errors += 1;
+            false
+        }
+    }
+}
+
+/// Carry out `steps`. Under `dry_run`, only print them. A file planned as existing at the
+/// target of an earlier copy that failed is copied itself, as that content isn't under DST.
+pub fn apply(steps: &[Step], link: Option<LinkMode>, dry_run: bool) -> CopyStats {
+    let mut stats = CopyStats::default();
+    let mut failed: HashSet<&Path> = HashSet::new();
+    for step in steps {
+        match step {
+            Step::Copy { from, to } => {
+                if dry_run {
+                    println!("copy {} -> {}", from.display(), to.display());
+                    stats.copied += 1;
+                    stats.copied_bytes += fs::metadata(from).map(|m| m.len()).unwrap_or(0);
+                    continue;
+                }
+                if !copy_counted(from, to, &mut stats) {
+                    failed.insert(to);
+                }
+            }
+            Step::Exists { from, to, existing } if failed.contains(existing.as_path()) => {
+                if !copy_counted(from, to, &mut stats) {
+                    failed.insert(to);
+                }
+            }
+            Step::Exists { from, to, existing } => {
+                stats.skipped_bytes += fs::metadata(from).map(|m| m.len()).unwrap_or(0);
+                // Re-running an import finds each file at its own place: nothing to link.
+                let Some(mode) = link.filter(|_| to != existing && !to.exists()) else {
+                    stats.skipped += 1;
+                    continue;
+                };
+                if dry_run {
+                    println!("link {} -> {}", to.display(), existing.display());
+                    stats.linked += 1;
+                    continue;
+                }
+                match link_file(mode, from, existing, to) {
+                    Ok(()) => stats.linked += 1,
+                    Err(e) => {
+                        warning!("cannot link {} to {}: {}", to.display(), existing.display(), e);
+                        stats.skipped += 1;
+                    }
+                }
+            }
+            Step::Conflict { from, to } => {
+                let (to, from) = (to.display(), from.display());
+                warning!("{} exists with other content; not copying {}", to, from);
+                stats.conflicts += 1;
+            }
+        }
+    }
+    stats
+}
+
+/// `ducky cp SRC DST`. Files that failed to copy are counted in the returned `errors`.
+pub fn run(src: &Path, dst: &Path, link: Option<LinkMode>, dry_run: bool) -> Result<CopyStats> {
+    let cache = HashCache::open_default();
+    let steps = plan(src, dst, &cache, schedule::default_threads())?;
+    if let Err(e) = cache.save() {
+        warning!("failed to save hash cache: {:#}", e);
+    }
+    let s = apply(&steps, link, dry_run);
+    let verb = if dry_run { "would copy" } else { "copied" };
+    println!(
+        "{} {} files ({}); {} already in {} ({}), {} linked; {} conflicts",
+        verb,
+        s.copied,
+        ByteSize(s.copied_bytes),
+        s.skipped + s.linked,
+        dst.display(),
+        ByteSize(s.skipped_bytes),
+        s.linked,
+        s.conflicts
+    );
+    Ok(s)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_new_content_is_copied() {
+        let base = std::env::temp_dir().join(format!("ducky_cp_{}", std::process::id()));
+        let (src, dst) = (base.join("card"), base.join("archive"));
+        fs::create_dir_all(src.join("DCIM")).unwrap();
+        fs::create_dir_all(dst.join("2024")).unwrap();
+        fs::write(src.join("DCIM/old.jpg"), b"imported before").unwrap();
+        fs::write(src.join("DCIM/new.jpg"), b"fresh from card").unwrap();
+        fs::write(src.join("DCIM/new_copy.jpg"), b"fresh from card").unwrap();
+        fs::write(dst.join("2024/old.jpg"), b"imported before").unwrap();
+
+        let cache = HashCache::disabled();
+        let steps = plan(&src, &dst, &cache, 2).unwrap();
+        let stats = apply(&steps, None, false);
+        assert_eq!((stats.copied, stats.skipped, stats.conflicts), (1, 2, 0), "{:?}", steps);
+        assert_eq!(fs::read(dst.join("DCIM/new.jpg")).unwrap(), b"fresh from card");
+        assert!(!dst.join("DCIM/old.jpg").exists() && !dst.join("DCIM/new_copy.jpg").exists());
+
+        let names = |dir: &Path| -> Vec<_> {
+            fs::read_dir(dir).unwrap().map(|e| e.unwrap().file_name()).collect()
+        };
+        assert_eq!(names(&dst.join("DCIM")), ["new.jpg"], "no temporary file is left behind");
+
+        // A second run has nothing left to do.
+        let again = apply(&plan(&src, &dst, &cache, 2).unwrap(), None, false);
+        assert_eq!((again.copied, again.skipped), (0, 3));
+
+        // A name taken in the meantime is never overwritten.
+        let to = dst.join("DCIM/new.jpg");
+        let err = copy_file(&src.join("DCIM/old.jpg"), &to).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+        assert_eq!(fs::read(&to).unwrap(), b"fresh from card");
+        assert_eq!(names(&dst.join("DCIM")), ["new.jpg"]);
+
+        let _ = fs::remove_dir_all(base);
+    }
+
+    #[test]
+    fn a_failed_copy_is_not_taken_for_an_existing_one() {
+        let base = std::env::temp_dir().join(format!("ducky_cp_failed_{}", std::process::id()));
+        let (src, dst) = (base.join("card"), base.join("archive"));
+        fs::create_dir_all(&src).unwrap();
+        fs::create_dir_all(&dst).unwrap();
+        fs::write(src.join("a.jpg"), b"fresh from card").unwrap();
+        fs::write(src.join("b.jpg"), b"fresh from card").unwrap();
+
+        let steps = plan(&src, &dst, &HashCache::disabled(), 2).unwrap();
+        let Step::Exists { existing, .. } = &steps[1] else { panic!("{:?}", steps) };
+        assert_eq!(*existing, dst.join("a.jpg"));
+        // a.jpg's name is taken before the copy, so b.jpg's content only gets there as b.jpg.
+        fs::write(dst.join("a.jpg"), b"something else").unwrap();
+        let stats = apply(&steps, None, false);
+        assert_eq!((stats.copied, stats.skipped, stats.errors), (1, 0, 1));
+        assert_eq!(fs::read(dst.join("b.jpg")).unwrap(), b"fresh from card");
+
+        let _ = fs::remove_dir_all(base);
+    }
+}
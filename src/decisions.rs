@@ -0,0 +1,255 @@
+//! Spreadsheet review: `--csv-out FILE` exports one row per member with an empty
+//! `decision` column; `ducky apply --from-csv FILE` executes the filled-in decisions.
+//!
+//! A decision is `keep`, `delete` or empty (leave the file alone). Within a group, files to
+//! delete need at least one kept copy. Every group is re-hashed before anything is removed,
+//! so files that changed since the export are skipped rather than lost.
+//!
+//! Hashes may be in any `--digest-format`. A truncated (`short:N`) digest only narrows things
+//! down, so such sheets are refused unless `--verify` compares the files byte by byte.
+
+use crate::actions::{apply_groups, ActionStats};
+use crate::hashing::{full_hash, parse_digest, Digest, DigestFormat};
+use crate::output::DuplicateGroup;
+use crate::warnings::warning;
+use anyhow::{bail, Context, Result};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+const HEADER: [&str; 5] = ["group", "hash", "size", "path", "decision"];
+
+/// Quote a field when it contains a separator, quote or line break (RFC 4180).
+fn field(s: &str) -> String {
+    if s.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+/// Write one row per group member, groups numbered from 1 in the order given.
+pub fn write_csv(
+    out: &mut dyn Write,
+    groups: &[DuplicateGroup],
+    digest: DigestFormat,
+) -> io::Result<()> {
+    writeln!(out, "{}", HEADER.join(","))?;
+    for (i, g) in groups.iter().enumerate() {
+        let hash = digest.encode(&g.hash);
+        for p in &g.members {
+            let path = field(&p.to_string_lossy());
+            writeln!(out, "{},{},{},{},", i + 1, hash, g.size, path)?;
+        }
+    }
+    Ok(())
+}
+
+/// `--csv-out FILE`.
+pub fn export(path: &Path, groups: &[DuplicateGroup], digest: DigestFormat) -> Result<()> {
+    let mut f = io::BufWriter::new(
+        fs::File::create(path).with_context(|| format!("create {}", path.display()))?,
+    );
+    write_csv(&mut f, groups, digest)?;
+    f.flush().with_context(|| format!("write {}", path.display()))
+}
+
+/// Split CSV text into records of fields; quoted fields may hold commas, quotes and newlines.
+/// The byte order mark spreadsheets put at the start of a saved file is dropped.
+fn parse_csv(text: &str) -> Result<Vec<Vec<String>>> {
+    let text = text.strip_prefix('\u{feff}').unwrap_or(text);
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut field = String::new();
+    let mut chars = text.chars().peekable();
+    let mut quoted = false;
+    while let Some(c) = chars.next() {
+        match (quoted, c) {
+            (true, '"') if chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            (true, '"') => quoted = false,
+            (true, c) => field.push(c),
+            (false, '"') if field.is_empty() => quoted = true,
+            (false, ',') => record.push(std::mem::take(&mut field)),
+            (false, '\r') if chars.peek() == Some(&'\n') => {}
+            (false, '\n') => {
+                record.push(std::mem::take(&mut field));
+                records.push(std::mem::take(&mut record));
+            }
+            (false, c) => field.push(c),
+        }
+    }
+    if quoted {
+        bail!("unterminated quoted field");
+    }
+    if !field.is_empty() || !record.is_empty() {
+        record.push(field);
+        records.push(record);
+    }
+    Ok(records)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Decision {
+    Keep,
+    Delete,
+    Leave,
+}
+
+/// Turn the reviewed rows into groups to act on: the first kept file is the canonical and the
+/// files marked `delete` follow it. Groups without deletions are dropped. Truncated digests
+/// are kept as prefixes, and only accepted with `verify`.
+fn plan(records: &[Vec<String>], verify: bool) -> Result<Vec<DuplicateGroup>> {
+    let Some((header, rows)) = records.split_first() else {
+        bail!("empty file");
+    };
+    let col = |name: &str| {
+        header
+            .iter()
+            .position(|h| h.trim().eq_ignore_ascii_case(name))
+            .with_context(|| format!("missing column {:?}", name))
+    };
+    let [group, hash, size, path, decision] = HEADER.map(col);
+    let (group, hash, size, path, decision) = (group?, hash?, size?, path?, decision?);
+
+    // group id -> (hash, size, keep, delete)
+    let mut groups: BTreeMap<u64, (String, u64, Vec<PathBuf>, Vec<PathBuf>)> = BTreeMap::new();
+    for (n, row) in rows.iter().enumerate() {
+        let line = n + 2;
+        if row.iter().all(|f| f.trim().is_empty()) {
+            continue;
+        }
+        let get = |i: usize| row.get(i).map(|s| s.trim()).unwrap_or("");
+        let id: u64 = get(group).parse().with_context(|| format!("line {}: bad group id", line))?;
+        let bytes: u64 = get(size).parse().with_context(|| format!("line {}: bad size", line))?;
+        let d = match get(decision).to_ascii_lowercase().as_str() {
+            "keep" => Decision::Keep,
+            "delete" => Decision::Delete,
+            "" => Decision::Leave,
+            other => {
+                bail!("line {}: unknown decision {:?} (use keep, delete or leave empty)", line, other)
+            }
+        };
+        let p = PathBuf::from(row.get(path).map(String::as_str).unwrap_or(""));
+        let entry =
+            groups.entry(id).or_insert_with(|| (get(hash).to_string(), bytes, vec![], vec![]));
+        if entry.0 != get(hash) || entry.1 != bytes {
+            bail!("line {}: group {} mixes different hashes or sizes", line, id);
+        }
+        match d {
+            Decision::Keep => entry.2.push(p),
+            Decision::Delete => entry.3.push(p),
+            Decision::Leave => {}
+        }
+    }
+
+    let mut out = Vec::new();
+    for (id, (hash, size, keep, delete)) in groups {
+        if delete.is_empty() {
+            continue;
+        }
+        let Some(canonical) = keep.into_iter().next() else {
+            bail!("group {}: files marked delete but none marked keep", id);
+        };
+        if hash.is_empty() {
+            bail!("group {}: no hash to verify against", id);
+        }
+        let hash = match parse_digest(&hash) {
+            Some(Digest::Full(h)) => h,
+            Some(Digest::Truncated(h)) if verify => h,
+            Some(Digest::Truncated(_)) => bail!(
+                "group {}: a truncated digest can't prove files identical; rerun with --verify",
+                id
+            ),
+            None => bail!("group {}: unrecognised hash {:?}", id, hash),
+        };
+        let mut g = DuplicateGroup::new(size, Vec::new()).with_hash(hash);
+        g.members = std::iter::once(canonical).chain(delete).collect();
+        // Re-hash every member before acting: the export may be days old.
+        g.stale = true;
+        out.push(g);
+    }
+    Ok(out)
+}
+
+/// Swap a truncated digest for the kept file's full hash, if that still starts with it.
+fn resolve_truncated(g: &mut DuplicateGroup) -> bool {
+    if g.hash.len() == 64 {
+        return true;
+    }
+    let canonical = &g.members[0];
+    match full_hash(canonical) {
+        Ok(h) if h.starts_with(&g.hash) => {
+            g.hash = h;
+            true
+        }
+        Ok(_) => {
+            warning!("{} no longer matches its digest; skipping group", canonical.display());
+            false
+        }
+        Err(e) => {
+            warning!("cannot hash {}: {:#}; skipping group", canonical.display(), e);
+            false
+        }
+    }
+}
+
+/// `ducky apply --from-csv FILE`. With `verify`, members are compared with the kept file
+/// byte by byte (as under `--paranoid`) before anything is removed.
+pub fn run(
+    file: &Path,
+    trash: bool,
+    yes: bool,
+    dry_run: bool,
+    verify: bool,
+) -> Result<ActionStats> {
+    let text = fs::read_to_string(file).with_context(|| format!("read {}", file.display()))?;
+    let records = parse_csv(&text).with_context(|| format!("parse {}", file.display()))?;
+    let mut groups = plan(&records, verify)
+        .with_context(|| format!("invalid decisions in {}", file.display()))?;
+    groups.retain_mut(resolve_truncated);
+    Ok(apply_groups(&groups, trash, yes, dry_run, verify))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exported_csv_round_trips_into_a_plan() {
+        let h = "ab".repeat(32);
+        let g = DuplicateGroup::new(4, vec!["a,1".into(), "b\"2".into(), "c".into()])
+            .with_hash(h.clone());
+        let mut out = Vec::new();
+        write_csv(&mut out, std::slice::from_ref(&g), DigestFormat::Hex).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        let rows = format!("1,{h},4,\"a,1\",\n1,{h},4,\"b\"\"2\",\n");
+        assert!(text.contains(&rows), "{}", text);
+
+        // Untouched export: nothing to do.
+        assert!(plan(&parse_csv(&text).unwrap(), false).unwrap().is_empty());
+
+        let filled = text
+            .replace("\"a,1\",\n", "\"a,1\",delete\n")
+            .replace("\"b\"\"2\",\n", "\"b\"\"2\",Keep\n");
+        let groups = plan(&parse_csv(&filled).unwrap(), false).unwrap();
+        assert_eq!(groups[0].members, vec![PathBuf::from("b\"2"), "a,1".into()]);
+        assert_eq!(groups[0].hash, h);
+        assert!(groups[0].stale);
+        // Saved again by a spreadsheet, with a byte order mark.
+        let bom = format!("\u{feff}{}", filled);
+        assert_eq!(plan(&parse_csv(&bom).unwrap(), false).unwrap()[0].members, groups[0].members);
+
+        // Deleting every copy is refused.
+        let all = filled.replace("Keep", "delete");
+        assert!(plan(&parse_csv(&all).unwrap(), false).is_err());
+
+        // Truncated digests need --verify.
+        let short = filled.replace(&h, &DigestFormat::Short(8).encode(&h));
+        assert!(plan(&parse_csv(&short).unwrap(), false).is_err());
+        assert_eq!(plan(&parse_csv(&short).unwrap(), true).unwrap()[0].hash, h[..16]);
+    }
+}
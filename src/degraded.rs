@@ -0,0 +1,44 @@
+//! `--tolerate-metadata-errors`: keep files whose metadata can't be read instead of dropping
+//! them from the walk.
+//!
+//! On flaky filesystems (FUSE mounts, some SMB servers) `stat` fails for files that can still
+//! be read. The walk tries `stat` and `lstat` a second time; files that still fail are scanned
+//! with only a size, found by seeking to the end of the file, and no modification time or
+//! inode: they are never taken for hard links, never served from the hash cache, shown as
+//! "metadata unknown" and never acted on, since nothing could prove they are not the canonical
+//! under another name.
+//!
+//! The walk reports such files as `Unreadable::Metadata`. A scan with
+//! `ScanOptions::tolerate_metadata_errors` keeps them and lists them in
+//! `ScanResult::degraded` and in the `degraded` members of their groups.
+
+use crate::fs_utils::FileMeta;
+use std::fs;
+use std::io::{Seek, SeekFrom};
+use std::path::Path;
+
+/// A second try at the metadata of a file the walk could not `stat`: `stat`, then `lstat`.
+pub fn retry(path: &Path) -> Option<fs::Metadata> {
+    fs::metadata(path).ok().or_else(|| fs::symlink_metadata(path).ok().filter(|m| m.is_file()))
+}
+
+/// What is known of a file without metadata: its size, found by seeking to the end.
+pub fn size_only(path: &Path) -> Option<FileMeta> {
+    let size = fs::File::open(path).and_then(|mut f| f.seek(SeekFrom::End(0))).ok()?;
+    Some(FileMeta { size, mtime_ns: 0, dev: 0, ino: 0, nlink: 1 })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_size_is_found_without_metadata() {
+        let path = std::env::temp_dir().join(format!("ducky_degraded_{}", std::process::id()));
+        fs::write(&path, b"12345").unwrap();
+        assert_eq!(retry(&path).unwrap().len(), 5);
+        assert_eq!(size_only(&path).unwrap().size, 5);
+        assert!(size_only(&path.with_extension("missing")).is_none());
+        let _ = fs::remove_file(path);
+    }
+}
@@ -0,0 +1,171 @@
+//! `--dirs`: whole directory trees with identical content, reported as one group each.
+//!
+//! A directory's hash is a Merkle hash over its sorted entries: each file contributes its
+//! name and content key, each subdirectory its name and own hash. Only directories where
+//! every file is a member of some duplicate group can match another, so hashes are only
+//! computed for those, from the file hashes already known. A directory holding anything the
+//! scan did not cover (filtered files, symlinks, special files) never matches, so a reported
+//! directory really is a complete copy of the others.
+
+use crate::output::DuplicateGroup;
+use blake3::Hasher;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Directories whose trees are identical.
+#[derive(Debug, Clone, Serialize, PartialEq, Eq)]
+pub struct DirGroup {
+    /// Files in each directory (recursively).
+    pub files: usize,
+    /// Bytes in each directory.
+    pub bytes: u64,
+    pub dirs: Vec<PathBuf>,
+}
+
+#[derive(Clone)]
+struct Tree {
+    hash: String,
+    files: usize,
+    bytes: u64,
+}
+
+struct Hashing<'a> {
+    content: HashMap<&'a Path, String>,
+    memo: HashMap<PathBuf, Option<Tree>>,
+}
+
+impl Hashing<'_> {
+    /// Merkle hash of `dir`, or `None` when it holds anything that isn't a duplicate file.
+    fn tree(&mut self, dir: &Path) -> Option<Tree> {
+        if let Some(t) = self.memo.get(dir) {
+            return t.clone();
+        }
+        let t = self.compute(dir);
+        self.memo.insert(dir.to_path_buf(), t.clone());
+        t
+    }
+
+    fn compute(&mut self, dir: &Path) -> Option<Tree> {
+        let mut entries: BTreeMap<Vec<u8>, String> = BTreeMap::new();
+        let (mut files, mut bytes) = (0, 0u64);
+        for e in fs::read_dir(dir).ok()? {
+            let e = e.ok()?;
+            let path = e.path();
+            let ty = e.file_type().ok()?;
+            let name = e.file_name().as_encoded_bytes().to_vec();
+            if ty.is_dir() {
+                let sub = self.tree(&path)?;
+                files += sub.files;
+                bytes = bytes.saturating_add(sub.bytes);
+                entries.insert(name, format!("d:{}", sub.hash));
+            } else if ty.is_file() {
+                let key = self.content.get(path.as_path())?;
+                files += 1;
+                bytes = bytes.saturating_add(fs::metadata(&path).ok()?.len());
+                entries.insert(name, format!("f:{}", key));
+            } else {
+                return None;
+            }
+        }
+        let mut h = Hasher::new();
+        for (name, value) in &entries {
+            h.update(name);
+            h.update(&[0]);
+            h.update(value.as_bytes());
+            h.update(&[0]);
+        }
+        Some(Tree { hash: h.finalize().to_hex().to_string(), files, bytes })
+    }
+}
+
+/// Identical directory trees below `roots`, built from the file `groups`. Directories nested
+/// in other reported directories are left out; largest groups first.
+pub fn find(groups: &[DuplicateGroup], roots: &[PathBuf]) -> Vec<DirGroup> {
+    let content: HashMap<&Path, String> = groups
+        .iter()
+        .flat_map(|g| g.members.iter().map(move |p| (p.as_path(), g.key().to_string())))
+        .collect();
+    // Every directory above a duplicate, up to the scan roots.
+    let mut candidates: HashSet<&Path> = HashSet::new();
+    for p in groups.iter().flat_map(|g| &g.members) {
+        for dir in p.ancestors().skip(1) {
+            if !roots.iter().any(|r| dir.starts_with(r)) || !candidates.insert(dir) {
+                break;
+            }
+        }
+    }
+    let mut hashing = Hashing { content, memo: HashMap::new() };
+    let mut by_hash: HashMap<String, (Tree, Vec<PathBuf>)> = HashMap::new();
+    for dir in candidates {
+        if let Some(t) = hashing.tree(dir).filter(|t| t.files > 0) {
+            by_hash.entry(t.hash.clone()).or_insert((t, Vec::new())).1.push(dir.to_path_buf());
+        }
+    }
+    let matched: Vec<(Tree, Vec<PathBuf>)> =
+        by_hash.into_values().filter(|(_, dirs)| dirs.len() > 1).collect();
+    let in_group: HashSet<&Path> =
+        matched.iter().flat_map(|(_, dirs)| dirs.iter().map(PathBuf::as_path)).collect();
+    let mut out: Vec<DirGroup> = matched
+        .iter()
+        .filter(|(_, dirs)| !dirs.iter().all(|d| d.parent().is_some_and(|p| in_group.contains(p))))
+        .map(|(t, dirs)| {
+            let mut dirs = dirs.clone();
+            dirs.sort();
+            DirGroup { files: t.files, bytes: t.bytes, dirs }
+        })
+        .collect();
+    out.sort_by(|a, b| b.bytes.cmp(&a.bytes).then_with(|| a.dirs.cmp(&b.dirs)));
+    out
+}
+
+/// Whether every member of `g` lies in a directory of the same `DirGroup`, so the directory
+/// listing already covers it.
+pub fn covered(g: &DuplicateGroup, dirs: &[DirGroup]) -> bool {
+    dirs.iter().any(|d| g.members.iter().all(|m| d.dirs.iter().any(|dir| m.starts_with(dir))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hashing::full_hash;
+
+    #[test]
+    fn finds_copied_trees_only_when_complete() {
+        let base = std::env::temp_dir().join(format!("ducky_dirs_{}", std::process::id()));
+        let write = |rel: &str, body: &[u8]| {
+            let p = base.join(rel);
+            fs::create_dir_all(p.parent().unwrap()).unwrap();
+            fs::write(&p, body).unwrap();
+            p
+        };
+        let paths = [
+            write("a/x.txt", b"one"),
+            write("a/sub/y.txt", b"two"),
+            write("b/x.txt", b"one"),
+            write("b/sub/y.txt", b"two"),
+            write("c/x.txt", b"one"),
+            write("c/sub/y.txt", b"two"),
+        ];
+        // c holds an extra file the scan did not pick up, so it is not a full copy.
+        write("c/notes.md", b"unique");
+        let group = |a: usize, b: usize, c: usize| {
+            let members = vec![paths[a].clone(), paths[b].clone(), paths[c].clone()];
+            DuplicateGroup::new(3, members).with_hash(full_hash(&paths[a]).unwrap())
+        };
+        let groups = [group(0, 2, 4), group(1, 3, 5)];
+
+        let found = find(&groups, std::slice::from_ref(&base));
+        assert_eq!(found.len(), 2, "{:?}", found);
+        assert_eq!(found[0].dirs, vec![base.join("a"), base.join("b")]);
+        assert_eq!((found[0].files, found[0].bytes), (2, 6));
+        // a/sub and b/sub alone would be implied by a and b, but c/sub matches them too.
+        let subs: Vec<PathBuf> = ["a/sub", "b/sub", "c/sub"].map(|d| base.join(d)).to_vec();
+        assert_eq!(found[1].dirs, subs);
+        assert!(covered(&groups[1], &found));
+        assert!(!covered(&groups[0], &found), "c/x.txt is outside every matched directory");
+
+        let _ = fs::remove_dir_all(base);
+    }
+}
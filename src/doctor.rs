@@ -0,0 +1,218 @@
+//! `ducky doctor`: probe filesystem capabilities and environment limits before a real run.
+
+use crate::fs_utils::{cache_dir, create_artifact_dir};
+use anyhow::Result;
+use serde::Serialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Ok,
+    Warn,
+    Unsupported,
+    Unknown,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Check {
+    pub name: &'static str,
+    pub status: Status,
+    pub detail: String,
+}
+
+impl Check {
+    fn new(name: &'static str, status: Status, detail: impl Into<String>) -> Self {
+        Self { name, status, detail: detail.into() }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct TargetReport {
+    pub path: PathBuf,
+    pub checks: Vec<Check>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Report {
+    pub version: &'static str,
+    pub targets: Vec<TargetReport>,
+    pub environment: Vec<Check>,
+}
+
+/// Probe every target directory and the environment, then print the report.
+pub fn run(paths: &[PathBuf], json: bool) -> Result<()> {
+    let targets = if paths.is_empty() { vec![PathBuf::from(".")] } else { paths.to_vec() };
+    let report = Report {
+        version: env!("CARGO_PKG_VERSION"),
+        targets: targets.iter().map(|t| probe_target(t)).collect(),
+        environment: vec![check_cache_dir(), check_open_files()],
+    };
+    if json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else {
+        print_report(&report);
+    }
+    Ok(())
+}
+
+fn print_report(report: &Report) {
+    let print_checks = |checks: &[Check]| {
+        for c in checks {
+            let status = serde_json::to_value(c.status).ok();
+            let status = status.as_ref().and_then(|v| v.as_str()).unwrap_or("?");
+            let line = format!("  {:<12} {:<12} {}", c.name, status, c.detail);
+            println!("{}", line.trim_end());
+        }
+    };
+    for t in &report.targets {
+        println!("{}", t.path.display());
+        print_checks(&t.checks);
+    }
+    println!("environment");
+    print_checks(&report.environment);
+}
+
+/// Run all per-filesystem probes inside `target` (or its parent directory for files).
+pub fn probe_target(target: &Path) -> TargetReport {
+    let dir = if target.is_file() {
+        target.parent().map(Path::to_path_buf).unwrap_or_else(|| PathBuf::from("."))
+    } else {
+        target.to_path_buf()
+    };
+    let mut checks = Vec::new();
+
+    let src = dir.join(format!(".ducky-doctor-{}-src", std::process::id()));
+    if let Err(e) = fs::write(&src, b"ducky doctor probe") {
+        checks.push(Check::new("writable", Status::Unsupported, e.to_string()));
+        return TargetReport { path: target.to_path_buf(), checks };
+    }
+    checks.push(Check::new("writable", Status::Ok, ""));
+
+    let dst = dir.join(format!(".ducky-doctor-{}-dst", std::process::id()));
+    checks.push(match fs::hard_link(&src, &dst) {
+        Ok(()) => Check::new("hardlink", Status::Ok, ""),
+        Err(e) => Check::new("hardlink", Status::Unsupported, e.to_string()),
+    });
+    let _ = fs::remove_file(&dst);
+
+    checks.push(check_reflink(&src, &dst));
+    let _ = fs::remove_file(&dst);
+
+    checks.push(check_xattr(&src));
+    checks.push(check_trash(&dir));
+
+    let _ = fs::remove_file(&src);
+    TargetReport { path: target.to_path_buf(), checks }
+}
+
+fn check_reflink(src: &Path, dst: &Path) -> Check {
+    match crate::reflink::clone_file(src, dst) {
+        Ok(()) => Check::new("reflink", Status::Ok, ""),
+        Err(e) if crate::reflink::is_unsupported(&e) => {
+            Check::new("reflink", Status::Unsupported, e.to_string())
+        }
+        Err(e) => Check::new("reflink", Status::Unknown, e.to_string()),
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn check_xattr(path: &Path) -> Check {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let Ok(cpath) = CString::new(path.as_os_str().as_bytes()) else {
+        return Check::new("xattr", Status::Unknown, "path contains NUL");
+    };
+    let name = c"user.ducky.doctor";
+    let value = b"1";
+    // SAFETY: pointers are valid NUL-terminated strings / buffers for the call.
+    let rc = unsafe {
+        libc::setxattr(cpath.as_ptr(), name.as_ptr(), value.as_ptr().cast(), value.len(), 0)
+    };
+    if rc == 0 {
+        Check::new("xattr", Status::Ok, "")
+    } else {
+        Check::new("xattr", Status::Unsupported, std::io::Error::last_os_error().to_string())
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn check_xattr(_path: &Path) -> Check {
+    Check::new("xattr", Status::Unknown, "probe not implemented on this platform")
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+fn check_trash(dir: &Path) -> Check {
+    use std::os::unix::fs::MetadataExt;
+
+    let data_home = std::env::var_os("XDG_DATA_HOME")
+        .filter(|v| !v.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|h| PathBuf::from(h).join(".local/share")));
+    let Some(data_home) = data_home else {
+        return Check::new("trash", Status::Unknown, "no home directory");
+    };
+    let trash = data_home.join("Trash");
+    let (Ok(td), Ok(md)) = (fs::metadata(&data_home), fs::metadata(dir)) else {
+        return Check::new("trash", Status::Unknown, format!("{} not found", data_home.display()));
+    };
+    if td.dev() == md.dev() {
+        Check::new("trash", Status::Ok, trash.display().to_string())
+    } else {
+        Check::new(
+            "trash",
+            Status::Warn,
+            format!("{} is on another filesystem; trashing needs a per-volume .Trash", trash.display()),
+        )
+    }
+}
+
+#[cfg(target_os = "macos")]
+fn check_trash(_dir: &Path) -> Check {
+    match std::env::var_os("HOME").map(|h| PathBuf::from(h).join(".Trash")) {
+        Some(t) if t.is_dir() => Check::new("trash", Status::Ok, t.display().to_string()),
+        _ => Check::new("trash", Status::Unknown, "~/.Trash not found"),
+    }
+}
+
+#[cfg(not(unix))]
+fn check_trash(_dir: &Path) -> Check {
+    Check::new("trash", Status::Ok, "Recycle Bin")
+}
+
+fn check_cache_dir() -> Check {
+    let Some(dir) = cache_dir() else {
+        return Check::new("cache_dir", Status::Unsupported, "no cache directory could be determined");
+    };
+    let probe = dir.join(format!(".ducky-doctor-{}", std::process::id()));
+    let res = create_artifact_dir(&dir).and_then(|_| fs::write(&probe, b""));
+    let _ = fs::remove_file(&probe);
+    match res {
+        Ok(()) => Check::new("cache_dir", Status::Ok, dir.display().to_string()),
+        Err(e) => Check::new("cache_dir", Status::Unsupported, format!("{}: {}", dir.display(), e)),
+    }
+}
+
+#[cfg(unix)]
+fn check_open_files() -> Check {
+    // The walker holds one descriptor per open directory level.
+    const RECOMMENDED: libc::rlim_t = 1024;
+    let mut lim = libc::rlimit { rlim_cur: 0, rlim_max: 0 };
+    // SAFETY: `lim` is a valid, writable rlimit struct.
+    if unsafe { libc::getrlimit(libc::RLIMIT_NOFILE, &mut lim) } != 0 {
+        return Check::new("open_files", Status::Unknown, std::io::Error::last_os_error().to_string());
+    }
+    let detail = format!("soft={} hard={}", lim.rlim_cur, lim.rlim_max);
+    if lim.rlim_cur < RECOMMENDED {
+        Check::new("open_files", Status::Warn, format!("{} (recommended >= {})", detail, RECOMMENDED))
+    } else {
+        Check::new("open_files", Status::Ok, detail)
+    }
+}
+
+#[cfg(not(unix))]
+fn check_open_files() -> Check {
+    Check::new("open_files", Status::Ok, "no descriptor limit")
+}
@@ -0,0 +1,78 @@
+//! Reclaimable-space estimates: naive upper bound and a link-aware realistic figure.
+
+use crate::output::DuplicateGroup;
+use std::collections::BTreeMap;
+use std::fs;
+
+/// Upper bound: every member except the canonical and known extra hard links is assumed to
+/// free `size` bytes.
+pub fn upper_bound(group: &DuplicateGroup) -> u64 {
+    let files = group.members.len().saturating_sub(group.links.len()) as u64;
+    group.size.saturating_mul(files.saturating_sub(1))
+}
+
+/// Realistic estimate of bytes freed by removing every non-canonical member.
+///
+/// - Members sharing an inode (existing hardlinks) are counted once.
+/// - The canonical's inode is never freed.
+/// - An inode with links outside the group is not freed, since removing our
+///   paths leaves those links in place.
+/// - Sparse files only free the blocks they actually allocate.
+#[cfg(unix)]
+pub fn realistic(group: &DuplicateGroup) -> u64 {
+    use std::os::unix::fs::MetadataExt;
+
+    // (dev, ino) -> (paths in group, nlink, allocated bytes)
+    let mut inodes: BTreeMap<(u64, u64), (u64, u64, u64)> = BTreeMap::new();
+    let mut canonical: Option<(u64, u64)> = None;
+    for (i, p) in group.members.iter().enumerate() {
+        let Ok(m) = fs::metadata(p) else { continue };
+        let key = (m.dev(), m.ino());
+        if i == 0 {
+            canonical = Some(key);
+        }
+        let allocated = m.len().min(m.blocks().saturating_mul(512));
+        let e = inodes.entry(key).or_insert((0, m.nlink(), allocated));
+        e.0 += 1;
+    }
+
+    inodes
+        .into_iter()
+        .filter(|(key, _)| Some(*key) != canonical)
+        .filter(|(_, (seen, nlink, _))| seen >= nlink)
+        .map(|(_, (_, _, allocated))| allocated)
+        .fold(0u64, |acc, b| acc.saturating_add(b))
+}
+
+#[cfg(not(unix))]
+pub fn realistic(group: &DuplicateGroup) -> u64 {
+    upper_bound(group)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[cfg(unix)]
+    fn hardlinked_members_do_not_count() {
+        let dir = std::env::temp_dir().join(format!("ducky_est_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let a = dir.join("a");
+        let b = dir.join("b");
+        let c = dir.join("c");
+        std::fs::write(&a, vec![1u8; 8192]).unwrap();
+        std::fs::hard_link(&a, &b).unwrap();
+        std::fs::write(&c, vec![1u8; 8192]).unwrap();
+
+        let group = DuplicateGroup::new(8192, vec![a, b.clone(), c]);
+        assert_eq!(upper_bound(&group), 16384);
+        let mut linked = group.clone();
+        linked.links = vec![b];
+        assert_eq!(upper_bound(&linked), 8192);
+        // Only `c` is a distinct inode that would be freed.
+        assert_eq!(realistic(&group), 8192);
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
@@ -0,0 +1,208 @@
+//! `ducky gen-fixture SPEC DIR` (hidden): build a reproducible directory tree from a spec.
+//!
+//! One entry per line, `#` starts a comment. Paths are relative to DIR; parents are created.
+//!
+//! ```text
+//! file     photos/a.jpg  size=64KB seed=7   # pseudo-random bytes (seed defaults to the path)
+//! copy     backup/a.jpg  of=photos/a.jpg    # same bytes, separate inode
+//! hardlink links/a.jpg   to=photos/a.jpg
+//! symlink  latest.jpg    to=photos/a.jpg    # link target exactly as written
+//! sparse   disk.img      size=1GB data=4KB  # `data` bytes written, the rest is a hole
+//! dir      empty/
+//! ```
+//!
+//! The same spec always yields byte-identical files, so bug reports can ship a spec instead
+//! of the data.
+
+use crate::rng::Rng;
+use anyhow::{bail, Context, Result};
+use bytesize::ByteSize;
+use std::collections::HashMap;
+use std::fs;
+use std::io::Write;
+use std::path::{Component, Path, PathBuf};
+
+#[derive(Debug, PartialEq)]
+enum Item {
+    File { path: PathBuf, size: u64, seed: u64 },
+    Copy { path: PathBuf, of: PathBuf },
+    Hardlink { path: PathBuf, to: PathBuf },
+    Symlink { path: PathBuf, to: PathBuf },
+    Sparse { path: PathBuf, size: u64, data: u64 },
+    Dir { path: PathBuf },
+}
+
+fn parse(spec: &str) -> Result<Vec<Item>> {
+    let mut items = Vec::new();
+    for (n, raw) in spec.lines().enumerate() {
+        let line = raw.split('#').next().unwrap_or("").trim();
+        if line.is_empty() {
+            continue;
+        }
+        let ctx = || format!("spec line {}: {}", n + 1, raw.trim());
+        let mut words = line.split_whitespace();
+        let kind = words.next().unwrap_or_default();
+        let path = relative(words.next().with_context(|| format!("{}: missing path", ctx()))?)
+            .with_context(ctx)?;
+        let mut kv: HashMap<&str, &str> = HashMap::new();
+        for w in words {
+            let (k, v) = w.split_once('=').with_context(|| format!("{}: expected key=value", ctx()))?;
+            kv.insert(k, v);
+        }
+        let size = |key: &str| -> Result<u64> {
+            let v = kv.get(key).with_context(|| format!("{}: missing {}=", ctx(), key))?;
+            v.parse::<ByteSize>().map(|b| b.as_u64()).map_err(|e| anyhow::anyhow!("{}: {}", ctx(), e))
+        };
+        let other = |key: &str| -> Result<PathBuf> {
+            relative(kv.get(key).with_context(|| format!("{}: missing {}=", ctx(), key))?).with_context(ctx)
+        };
+        items.push(match kind {
+            "file" => {
+                let seed = match kv.get("seed") {
+                    Some(s) => s.parse().with_context(ctx)?,
+                    None => path_seed(&path),
+                };
+                Item::File { size: size("size")?, seed, path }
+            }
+            "copy" => Item::Copy { of: other("of")?, path },
+            "hardlink" => Item::Hardlink { to: other("to")?, path },
+            "symlink" => {
+                let to = kv.get("to").with_context(|| format!("{}: missing to=", ctx()))?;
+                Item::Symlink { to: PathBuf::from(to), path }
+            }
+            "sparse" => {
+                let data = if kv.contains_key("data") { size("data")? } else { 0 };
+                Item::Sparse { size: size("size")?, data, path }
+            }
+            "dir" => Item::Dir { path },
+            other => bail!("{}: unknown entry kind {:?}", ctx(), other),
+        });
+    }
+    Ok(items)
+}
+
+/// Spec paths must stay inside DIR.
+fn relative(s: &str) -> Result<PathBuf> {
+    let p = PathBuf::from(s);
+    if p.components().any(|c| !matches!(c, Component::Normal(_) | Component::CurDir)) {
+        bail!("path must be relative and stay inside the fixture directory: {}", s);
+    }
+    Ok(p)
+}
+
+fn path_seed(p: &Path) -> u64 {
+    let h = blake3::hash(p.to_string_lossy().as_bytes());
+    u64::from_le_bytes(h.as_bytes()[..8].try_into().unwrap_or_default())
+}
+
+/// Write `size` deterministic pseudo-random bytes (xorshift64) derived from `seed`.
+fn write_pattern(out: &mut impl Write, size: u64, seed: u64) -> std::io::Result<()> {
+    let mut rng = Rng::new(seed);
+    let mut buf = vec![0u8; 64 * 1024];
+    let mut left = size;
+    while left > 0 {
+        for chunk in buf.chunks_mut(8) {
+            chunk.copy_from_slice(&rng.next_u64().to_le_bytes()[..chunk.len()]);
+        }
+        let n = left.min(buf.len() as u64) as usize;
+        out.write_all(&buf[..n])?;
+        left -= n as u64;
+    }
+    Ok(())
+}
+
+/// Create every entry of `spec` under `dir`, in order. Existing files are overwritten.
+pub fn generate(spec: &str, dir: &Path) -> Result<usize> {
+    let items = parse(spec)?;
+    fs::create_dir_all(dir).with_context(|| format!("create {}", dir.display()))?;
+    for item in &items {
+        let (Item::File { path, .. }
+        | Item::Copy { path, .. }
+        | Item::Hardlink { path, .. }
+        | Item::Symlink { path, .. }
+        | Item::Sparse { path, .. }
+        | Item::Dir { path }) = item;
+        let target = dir.join(path);
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent).with_context(|| format!("create {}", parent.display()))?;
+        }
+        if !matches!(item, Item::Dir { .. }) && target.symlink_metadata().is_ok() {
+            fs::remove_file(&target).with_context(|| format!("replace {}", target.display()))?;
+        }
+        let res = match item {
+            Item::File { size, seed, .. } => fs::File::create(&target).and_then(|f| {
+                let mut w = std::io::BufWriter::new(f);
+                write_pattern(&mut w, *size, *seed)?;
+                w.flush()
+            }),
+            Item::Copy { of, .. } => fs::copy(dir.join(of), &target).map(|_| ()),
+            Item::Hardlink { to, .. } => fs::hard_link(dir.join(to), &target),
+            Item::Symlink { to, .. } => symlink(to, &target),
+            Item::Sparse { size, data, .. } => fs::File::create(&target).and_then(|mut f| {
+                write_pattern(&mut f, (*data).min(*size), path_seed(path))?;
+                f.set_len(*size)
+            }),
+            Item::Dir { .. } => fs::create_dir_all(&target),
+        };
+        res.with_context(|| format!("create {}", target.display()))?;
+    }
+    Ok(items.len())
+}
+
+#[cfg(unix)]
+fn symlink(to: &Path, link: &Path) -> std::io::Result<()> {
+    std::os::unix::fs::symlink(to, link)
+}
+
+#[cfg(windows)]
+fn symlink(to: &Path, link: &Path) -> std::io::Result<()> {
+    std::os::windows::fs::symlink_file(to, link)
+}
+
+/// `ducky gen-fixture SPEC DIR`; SPEC `-` reads from stdin.
+pub fn run(spec: &Path, dir: &Path) -> Result<()> {
+    let text = if spec == Path::new("-") {
+        std::io::read_to_string(std::io::stdin()).context("read spec from stdin")?
+    } else {
+        fs::read_to_string(spec).with_context(|| format!("read {}", spec.display()))?
+    };
+    let n = generate(&text, dir)?;
+    println!("Created {} entries in {}", n, dir.display());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_paths_outside_dir() {
+        assert!(parse("file ../x size=1KB").is_err());
+        assert!(parse("file /etc/x size=1KB").is_err());
+        assert!(parse("copy a of=../b").is_err());
+        assert!(parse("blob a").is_err());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn generates_reproducible_tree() {
+        use std::os::unix::fs::MetadataExt;
+        let dir = std::env::temp_dir().join(format!("ducky_fixture_{}", std::process::id()));
+        let spec = "file a size=10KB\ncopy sub/b of=a\nhardlink c to=a  # same inode\n\
+                    symlink d to=a\nsparse e size=1MB data=1KB\ndir empty\n";
+        assert_eq!(generate(spec, &dir).unwrap(), 6);
+        let a = fs::read(dir.join("a")).unwrap();
+        assert_eq!(a.len(), 10_000);
+        assert_eq!(fs::read(dir.join("sub/b")).unwrap(), a);
+        assert_eq!(fs::metadata(dir.join("c")).unwrap().ino(), fs::metadata(dir.join("a")).unwrap().ino());
+        assert_eq!(fs::read_link(dir.join("d")).unwrap(), PathBuf::from("a"));
+        assert_eq!(fs::metadata(dir.join("e")).unwrap().len(), 1_000_000);
+        assert!(dir.join("empty").is_dir());
+
+        // Regenerating yields the same bytes.
+        generate(spec, &dir).unwrap();
+        assert_eq!(fs::read(dir.join("a")).unwrap(), a);
+
+        let _ = fs::remove_dir_all(dir);
+    }
+}
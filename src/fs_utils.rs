@@ -1,9 +1,13 @@
 //! Filesystem traversal utilities: walking trees, filtering, and extension parsing.
 
+use crate::degraded;
+use crate::warnings::warning;
 use anyhow::Result;
 use ignore::{DirEntry, WalkBuilder};
 use std::collections::HashSet;
+use std::fs;
 use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 /// Parse a comma-separated list of file extensions into a lowercase set.
 ///
@@ -19,52 +23,518 @@ pub fn parse_exts(exts: Option<&str>) -> Option<HashSet<String>> {
     })
 }
 
+/// Expand shell-unexpanded globs and drop redundant roots.
+///
+/// - A root that does not exist but contains glob metacharacters (`*`, `?`, `[`)
+///   is expanded in place; this covers shells that pass patterns through verbatim.
+/// - Roots that resolve to the same location are kept once.
+/// - Roots contained in another root are dropped with a warning, since walking
+///   both would count every file beneath them twice. File roots are kept: they
+///   bypass the filters, and `collect_files` lists them once.
+///
+/// The original spelling of each kept root is preserved so output paths stay
+/// as the user typed them.
+pub fn normalize_roots(roots: &[PathBuf]) -> Vec<PathBuf> {
+    let mut expanded: Vec<PathBuf> = Vec::new();
+    for root in roots {
+        if root.exists() || !is_glob(root) {
+            expanded.push(root.clone());
+            continue;
+        }
+        let pattern = root.to_string_lossy();
+        let matches: Vec<PathBuf> = match glob::glob(&pattern) {
+            Ok(paths) => paths.filter_map(|r| r.ok()).collect(),
+            Err(e) => {
+                warning!("invalid glob {}: {}", pattern, e);
+                continue;
+            }
+        };
+        if matches.is_empty() {
+            warning!("no paths match {}", pattern);
+        }
+        expanded.extend(matches);
+    }
+
+    // Resolve each root once for comparison; fall back to the literal path when
+    // it can't be canonicalized (e.g. it doesn't exist) so the walker reports it.
+    let resolved: Vec<(PathBuf, PathBuf)> = expanded
+        .into_iter()
+        .map(|p| (fs::canonicalize(&p).unwrap_or_else(|_| p.clone()), p))
+        .collect();
+
+    let mut out: Vec<PathBuf> = Vec::new();
+    for (i, (key, root)) in resolved.iter().enumerate() {
+        let covered_by = resolved.iter().enumerate().find(|(j, (other, _))| {
+            // Identical roots: keep the first occurrence only.
+            if other == key {
+                return *j < i;
+            }
+            key.starts_with(other) && !key.is_file()
+        });
+        match covered_by {
+            Some((_, (other, other_root))) if other == key => {
+                warning!(
+                    "duplicate root {} (same as {}); skipping",
+                    root.display(),
+                    other_root.display()
+                );
+            }
+            Some((_, (_, other_root))) => {
+                warning!(
+                    "root {} is inside {}; skipping",
+                    root.display(),
+                    other_root.display()
+                );
+            }
+            None => out.push(root.clone()),
+        }
+    }
+    out
+}
+
+/// Device and inode of the directory `path`; `None` for anything else, or where the platform
+/// has no stable inode numbers.
+pub fn dir_id(path: &Path) -> Option<(u64, u64)> {
+    fs::metadata(path)
+        .ok()
+        .filter(|m| m.is_dir())
+        .map(|m| FileMeta::new(&m))
+        .map(|m| (m.dev, m.ino))
+        .filter(|id| *id != (0, 0))
+}
+
+/// Split off directory roots that are the same directory as an earlier root reached by a
+/// different path, such as a bind mount of it: same device and inode, though the paths resolve
+/// differently. Walking both would double every group and the reclaimable total, so only the
+/// first is kept. Returns the kept roots and `(kept root, alias)` pairs.
+pub fn split_aliases(roots: Vec<PathBuf>) -> (Vec<PathBuf>, Vec<(PathBuf, PathBuf)>) {
+    let mut kept: Vec<(Option<(u64, u64)>, PathBuf)> = Vec::new();
+    let mut aliases = Vec::new();
+    for root in roots {
+        let id = dir_id(&root);
+        match kept.iter().find(|(other, _)| id.is_some() && *other == id) {
+            Some((_, first)) => aliases.push((first.clone(), root)),
+            None => kept.push((id, root)),
+        }
+    }
+    (kept.into_iter().map(|(_, r)| r).collect(), aliases)
+}
+
+/// Per-user cache directory for ducky (`$XDG_CACHE_HOME/ducky`, `~/Library/Caches/ducky`,
+/// or `%LOCALAPPDATA%\ducky`). Returns `None` when no home directory can be determined.
+pub fn cache_dir() -> Option<PathBuf> {
+    let env_dir = |k: &str| std::env::var_os(k).filter(|v| !v.is_empty()).map(PathBuf::from);
+    let base = if cfg!(windows) {
+        env_dir("LOCALAPPDATA")
+    } else if cfg!(target_os = "macos") {
+        env_dir("HOME").map(|h| h.join("Library").join("Caches"))
+    } else {
+        env_dir("XDG_CACHE_HOME").or_else(|| env_dir("HOME").map(|h| h.join(".cache")))
+    };
+    base.map(|b| b.join("ducky"))
+}
+
+/// Per-user data directory for state that must survive cache clears (`$XDG_DATA_HOME/ducky`,
+/// `~/Library/Application Support/ducky`, or `%APPDATA%\ducky`).
+pub fn data_dir() -> Option<PathBuf> {
+    let env_dir = |k: &str| std::env::var_os(k).filter(|v| !v.is_empty()).map(PathBuf::from);
+    let base = if cfg!(windows) {
+        env_dir("APPDATA")
+    } else if cfg!(target_os = "macos") {
+        env_dir("HOME").map(|h| h.join("Library").join("Application Support"))
+    } else {
+        env_dir("XDG_DATA_HOME").or_else(|| env_dir("HOME").map(|h| h.join(".local").join("share")))
+    };
+    base.map(|b| b.join("ducky"))
+}
+
+/// Per-user configuration directory (`$XDG_CONFIG_HOME/ducky`, `~/.config/ducky`, also on
+/// macOS, or `%APPDATA%\ducky`).
+pub fn config_dir() -> Option<PathBuf> {
+    let env_dir = |k: &str| std::env::var_os(k).filter(|v| !v.is_empty()).map(PathBuf::from);
+    let base = if cfg!(windows) {
+        env_dir("APPDATA")
+    } else {
+        env_dir("XDG_CONFIG_HOME").or_else(|| env_dir("HOME").map(|h| h.join(".config")))
+    };
+    base.map(|b| b.join("ducky"))
+}
+
+/// Marker file placed in every directory ducky creates for its own state (cache, journal,
+/// catalogs, ...). Directories containing it are skipped by every scan, so ducky's outputs
+/// never show up as duplicate candidates.
+pub const ARTIFACT_MARKER: &str = ".ducky-artifacts";
+
+/// Create `dir` (and parents) and tag it with [`ARTIFACT_MARKER`].
+pub fn create_artifact_dir(dir: &Path) -> std::io::Result<()> {
+    fs::create_dir_all(dir)?;
+    let marker = dir.join(ARTIFACT_MARKER);
+    if !marker.exists() {
+        fs::write(&marker, "This directory holds files created by ducky; ducky scans skip it.\n")?;
+    }
+    Ok(())
+}
+
+fn is_artifact_dir(ent: &DirEntry) -> bool {
+    ent.file_type().is_some_and(|ft| ft.is_dir()) && ent.path().join(ARTIFACT_MARKER).exists()
+}
+
+/// Parse an `--exclude` pattern. Validation happens up front so typos fail before the walk.
+pub fn parse_exclude(s: &str) -> Result<glob::Pattern, String> {
+    glob::Pattern::new(s).map_err(|e| format!("invalid pattern {:?}: {}", s, e))
+}
+
+/// Whether a pattern names a path rather than a single component: it holds a separator, `/`
+/// or, on Windows, `\`.
+fn has_separator(pattern: &str) -> bool {
+    pattern.contains(std::path::is_separator)
+}
+
+/// Parse a `--protect`, `--prefer` or `--avoid` pattern. One with a `/` that isn't absolute is
+/// taken relative to the current directory, since it is matched against resolved paths.
+pub fn parse_path_glob(s: &str) -> Result<glob::Pattern, String> {
+    let full = if has_separator(s) && !Path::new(s).is_absolute() {
+        let cwd = std::env::current_dir().map_err(|e| format!("pattern {:?}: {}", s, e))?;
+        let cwd = glob::Pattern::escape(&cwd.to_string_lossy());
+        format!("{}/{}", cwd.trim_end_matches('/'), s.trim_start_matches("./"))
+    } else {
+        s.to_string()
+    };
+    glob::Pattern::new(&full).map_err(|e| format!("invalid pattern {:?}: {}", s, e))
+}
+
+/// Whether the resolved path `real` matches one of `patterns`. Patterns without a separator
+/// match any component by name (`originals`, `*.raw`); patterns with one match the path or one
+/// of its directories (`/home/me/originals`). Matching ignores case, so a pattern never misses a
+/// file on a case-insensitive filesystem.
+pub fn path_glob_matches(patterns: &[glob::Pattern], real: &Path) -> bool {
+    let opts = glob::MatchOptions {
+        case_sensitive: false,
+        require_literal_separator: true,
+        require_literal_leading_dot: false,
+    };
+    patterns.iter().any(|pat| {
+        if has_separator(pat.as_str()) {
+            real.ancestors().any(|p| pat.matches_path_with(p, opts))
+        } else {
+            real.iter().any(|c| pat.matches_with(&c.to_string_lossy(), opts))
+        }
+    })
+}
+
+/// `path` with its directory canonicalized; the file itself may be a symlink.
+pub fn resolve_parent(path: &Path) -> Option<PathBuf> {
+    let dir = match path.parent() {
+        Some(d) if !d.as_os_str().is_empty() => d,
+        _ => Path::new("."),
+    };
+    Some(fs::canonicalize(dir).ok()?.join(path.file_name()?))
+}
+
+/// What the walk learned about a file. Read once, when the file is found, and carried through
+/// the size and hard-link stages so they agree on one snapshot instead of each calling
+/// `fs::metadata` again.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FileMeta {
+    pub size: u64,
+    /// Modification time in nanoseconds since the Unix epoch; 0 when unknown.
+    pub mtime_ns: i64,
+    /// Device and inode; 0 where the platform has no stable inode numbers.
+    pub dev: u64,
+    pub ino: u64,
+    /// Number of hard links; 1 where unknown, so the file is never taken for a link.
+    pub nlink: u64,
+}
+
+impl FileMeta {
+    #[cfg(unix)]
+    pub fn new(m: &fs::Metadata) -> Self {
+        use std::os::unix::fs::MetadataExt;
+        Self {
+            size: m.len(),
+            mtime_ns: m.mtime().saturating_mul(1_000_000_000).saturating_add(m.mtime_nsec()),
+            dev: m.dev(),
+            ino: m.ino(),
+            nlink: m.nlink(),
+        }
+    }
+
+    #[cfg(not(unix))]
+    pub fn new(m: &fs::Metadata) -> Self {
+        let mtime_ns = m
+            .modified()
+            .ok()
+            .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+            .map_or(0, |d| d.as_nanos() as i64);
+        Self { size: m.len(), mtime_ns, dev: 0, ino: 0, nlink: 1 }
+    }
+
+    pub fn of(path: &Path) -> Option<Self> {
+        fs::metadata(path).ok().map(|m| Self::new(&m))
+    }
+}
+
+/// A file found by the walk, with its metadata snapshot.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScannedFile {
+    pub path: PathBuf,
+    pub meta: FileMeta,
+}
+
+/// Size, modification-time and depth bounds a file must meet to be scanned.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Bounds {
+    /// Only files at least this many levels below the root (1 = directly inside it).
+    pub min_depth: usize,
+    /// Don't descend more than this many levels below the root.
+    pub max_depth: Option<usize>,
+    /// Only files at least this many bytes.
+    pub min_size: u64,
+    /// Only files at most this many bytes.
+    pub max_size: Option<u64>,
+    /// Only files modified at or after this time.
+    pub modified_after: Option<SystemTime>,
+    /// Only files modified strictly before this time.
+    pub modified_before: Option<SystemTime>,
+}
+
+impl Bounds {
+    fn admits(&self, meta: &fs::Metadata) -> bool {
+        if !self.admits_size(meta.len()) {
+            return false;
+        }
+        if self.modified_after.is_none() && self.modified_before.is_none() {
+            return true;
+        }
+        // A file without a usable mtime can't be shown to be in range.
+        let Ok(mtime) = meta.modified() else { return false };
+        self.modified_after.is_none_or(|t| mtime >= t)
+            && self.modified_before.is_none_or(|t| mtime < t)
+    }
+
+    /// Whether a file of `size` whose modification time is unknown is in bounds.
+    fn admits_unknown_mtime(&self, size: u64) -> bool {
+        self.admits_size(size) && self.modified_after.is_none() && self.modified_before.is_none()
+    }
+
+    fn admits_size(&self, size: u64) -> bool {
+        size >= self.min_size && self.max_size.is_none_or(|max| size <= max)
+    }
+}
+
+/// Parse `--modified-after`/`--modified-before`: a date (`2024-01-31`, midnight UTC) or a
+/// duration before now (`90d`, `12h`, `2w`).
+pub fn parse_time(s: &str) -> Result<SystemTime, String> {
+    if let Some(days) = parse_date(s.trim()) {
+        return Ok(UNIX_EPOCH + Duration::from_secs(days.max(0) as u64 * 86_400));
+    }
+    let secs = parse_age(s.trim())
+        .ok_or_else(|| format!("expected a date (YYYY-MM-DD) or a duration (e.g. 90d): {}", s))?;
+    Ok(SystemTime::now().checked_sub(Duration::from_secs(secs)).unwrap_or(UNIX_EPOCH))
+}
+
+/// Seconds in an age such as `90d`. The unit is required: a bare `2024` is a mistyped date,
+/// not 2024 days.
+fn parse_age(s: &str) -> Option<u64> {
+    let split = s.find(|c: char| !c.is_ascii_digit())?;
+    let (num, unit) = s.split_at(split);
+    let scale = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 3600,
+        "d" => 86_400,
+        "w" => 7 * 86_400,
+        _ => return None,
+    };
+    num.parse::<u64>().ok()?.checked_mul(scale)
+}
+
+/// Days since 1970-01-01 for a `YYYY-MM-DD` date (proleptic Gregorian).
+fn parse_date(s: &str) -> Option<i64> {
+    let mut parts = s.splitn(3, '-');
+    let y: i64 = parts.next()?.parse().ok()?;
+    let m: i64 = parts.next()?.parse().ok()?;
+    let d: i64 = parts.next()?.parse().ok()?;
+    let leap = y % 4 == 0 && (y % 100 != 0 || y % 400 == 0);
+    let days = match m {
+        2 if leap => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    };
+    if s.len() != 10 || !(1..=12).contains(&m) || !(1..=days).contains(&d) {
+        return None;
+    }
+    // Howard Hinnant's days_from_civil.
+    let y = if m <= 2 { y - 1 } else { y };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let doy = (153 * (m + if m > 2 { -3 } else { 9 }) + 2) / 5 + d - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    Some(era * 146_097 + doe - 719_468)
+}
+
+/// `--path-regex` / `--path-regex-exclude`: a file is kept when it matches any include
+/// expression (or there are none) and no exclude expression. Matched against the full path.
+#[derive(Debug, Clone, Default)]
+pub struct PathRegex {
+    pub include: Vec<regex::Regex>,
+    pub exclude: Vec<regex::Regex>,
+}
+
+impl PathRegex {
+    fn admits(&self, path: &Path) -> bool {
+        if self.include.is_empty() && self.exclude.is_empty() {
+            return true;
+        }
+        let s = path.to_string_lossy();
+        (self.include.is_empty() || self.include.iter().any(|re| re.is_match(&s)))
+            && !self.exclude.iter().any(|re| re.is_match(&s))
+    }
+}
+
+/// Parse a `--path-regex` expression up front so mistakes fail before the walk.
+pub fn parse_regex(s: &str) -> Result<regex::Regex, String> {
+    regex::Regex::new(s).map_err(|e| format!("invalid regex {:?}: {}", s, e))
+}
+
+/// Whether `path` (below `root`) matches one of the exclude patterns.
+///
+/// Patterns without a `/` match any single path component by name (`node_modules`,
+/// `*.iso`); patterns with a `/` match the whole path relative to the root (`build/**/*.o`).
+/// Matching ignores case, like `--ext`.
+fn is_excluded(root: &Path, path: &Path, excludes: &[glob::Pattern]) -> bool {
+    let Ok(rel) = path.strip_prefix(root) else { return false };
+    let opts = glob::MatchOptions {
+        case_sensitive: false,
+        require_literal_separator: true,
+        require_literal_leading_dot: false,
+    };
+    excludes.iter().any(|pat| {
+        if has_separator(pat.as_str()) {
+            pat.matches_path_with(rel, opts)
+        } else {
+            rel.file_name().is_some_and(|n| pat.matches_with(&n.to_string_lossy(), opts))
+        }
+    })
+}
+
+fn is_glob(path: &Path) -> bool {
+    path.to_string_lossy().contains(['*', '?', '['])
+}
+
 /// Walks paths respecting .gitignore unless `hidden=true`.
 ///
 /// - `roots`: paths to scan
 /// - `hidden`: include hidden files and directories when true
 /// - `follow_symlinks`: follow symlinks when true
-/// - `min_size`: only include files at least this many bytes
+/// - `bounds`: size, modification-time and depth limits
 /// - `exts`: optional set of lowercase file extensions to include
+/// - `excludes`: patterns for paths to skip; excluded directories are not descended into
+/// - `regexes`: regular expressions file paths must (not) match
+///
+/// Directories tagged with [`ARTIFACT_MARKER`] are always skipped. Roots that are files are
+/// always listed, whatever the filters say.
 ///
-/// Returns a list of regular file paths that match the criteria.
+/// Returns the regular files that match the criteria, with the metadata read for the filters.
+/// Entries that could not be read are left out.
 pub fn collect_files(
     roots: &[PathBuf],
     hidden: bool,
     follow_symlinks: bool,
-    min_size: u64,
+    bounds: &Bounds,
     exts: Option<&HashSet<String>>,
-) -> Result<Vec<PathBuf>> {
-    let mut out = Vec::new();
-    for root in roots {
+    excludes: &[glob::Pattern],
+    regexes: &PathRegex,
+) -> Result<Vec<ScannedFile>> {
+    let walk = walk_files(roots, hidden, follow_symlinks, bounds, exts, excludes, regexes);
+    Ok(walk.filter_map(Result::ok).collect())
+}
+
+/// An entry the walk found but could not read (or `stat`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Unreadable {
+    /// Nothing could be read.
+    Entry,
+    /// A file whose metadata could not be read even on a second try, with only its size
+    /// (see `degraded::size_only`); `None` when the size is out of bounds. Scans that
+    /// tolerate metadata errors keep it.
+    Metadata(Option<ScannedFile>),
+}
+
+/// [`collect_files`] one file at a time, as the walk finds them, with an [`Unreadable`] for
+/// each entry that could not be read, for the caller to count or, for
+/// [`Unreadable::Metadata`], keep.
+pub fn walk_files<'a>(
+    roots: &'a [PathBuf],
+    hidden: bool,
+    follow_symlinks: bool,
+    bounds: &'a Bounds,
+    exts: Option<&'a HashSet<String>>,
+    excludes: &'a [glob::Pattern],
+    regexes: &'a PathRegex,
+) -> impl Iterator<Item = Result<ScannedFile, Unreadable>> + 'a {
+    // File roots are pre-matched: the user named them, so no filter applies and the walk of a
+    // directory root around them skips them instead of listing them twice.
+    let (files, dirs): (Vec<&PathBuf>, Vec<&PathBuf>) = roots.iter().partition(|r| r.is_file());
+    let named: HashSet<PathBuf> = files.iter().filter_map(|f| fs::canonicalize(f).ok()).collect();
+    let file_names: HashSet<std::ffi::OsString> =
+        files.iter().filter_map(|f| f.file_name()).map(|n| n.to_os_string()).collect();
+    let named_files = files.into_iter().map(|f| {
+        let Some(meta) = FileMeta::of(f).or_else(|| degraded::retry(f).map(|m| FileMeta::new(&m)))
+        else {
+            let meta = degraded::size_only(f).ok_or(Unreadable::Entry)?;
+            let file = ScannedFile { path: f.to_path_buf(), meta };
+            return Err(Unreadable::Metadata(Some(file)));
+        };
+        crate::status::discovered();
+        Ok(ScannedFile { path: f.to_path_buf(), meta })
+    });
+    let walked = dirs.into_iter().flat_map(move |root| {
         let mut wb = WalkBuilder::new(root);
         wb.standard_filters(!hidden);
         wb.follow_links(follow_symlinks);
-        for res in wb.build() {
-            let ent = match res {
-                Ok(e) => e,
-                Err(_) => continue,
+        wb.max_depth(bounds.max_depth);
+        let (root_path, excludes) = (root.clone(), excludes.to_vec());
+        wb.filter_entry(move |ent| {
+            !is_artifact_dir(ent) && !is_excluded(&root_path, ent.path(), &excludes)
+        });
+        let (named, file_names) = (named.clone(), file_names.clone());
+        wb.build().filter_map(move |res| {
+            let Ok(ent) = res else {
+                return Some(Err(Unreadable::Entry));
             };
-            if !is_regular_file(&ent) {
-                continue;
+            if !is_regular_file(&ent) || ent.depth() < bounds.min_depth {
+                return None;
             }
             let path = ent.path();
-            let meta = match ent.metadata() {
-                Ok(m) => m,
-                Err(_) => continue,
-            };
-            if meta.len() < min_size {
-                continue;
+            if exts.is_some_and(|extlist| !matches_ext(path, extlist)) {
+                return None;
             }
-            if let Some(extlist) = exts {
-                if !matches_ext(path, extlist) {
-                    continue;
-                }
+            if !regexes.admits(path) {
+                return None;
             }
-            out.push(path.to_path_buf());
-        }
-    }
-    Ok(out)
+            let is_named = |p: &Path| fs::canonicalize(p).is_ok_and(|c| named.contains(&c));
+            if path.file_name().is_some_and(|n| file_names.contains(n)) && is_named(path) {
+                return None;
+            }
+            let meta = match ent.metadata().ok().or_else(|| degraded::retry(path)) {
+                Some(m) if !bounds.admits(&m) => return None,
+                Some(m) => FileMeta::new(&m),
+                None => {
+                    let Some(meta) = degraded::size_only(path) else {
+                        return Some(Err(Unreadable::Entry));
+                    };
+                    let file = ScannedFile { path: path.to_path_buf(), meta };
+                    let file = bounds.admits_unknown_mtime(meta.size).then_some(file);
+                    return Some(Err(Unreadable::Metadata(file)));
+                }
+            };
+            crate::status::discovered();
+            Some(Ok(ScannedFile { path: path.to_path_buf(), meta }))
+        })
+    });
+    named_files.chain(walked)
 }
 
 fn is_regular_file(ent: &DirEntry) -> bool {
@@ -80,7 +550,23 @@ fn matches_ext(path: &Path, exts: &HashSet<String>) -> bool {
 
 #[cfg(test)]
 mod tests {
-    use super::parse_exts;
+    use super::{
+        collect_files, create_artifact_dir, has_separator, normalize_roots, parse_age,
+        parse_date, parse_exclude, parse_exts, parse_regex, parse_time, split_aliases, Bounds,
+        PathRegex,
+    };
+    use std::path::{Path, PathBuf};
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    /// Walk `base` including hidden files, with no extension or regex filter.
+    fn walk(base: &Path, bounds: &Bounds, excludes: &[glob::Pattern]) -> Vec<PathBuf> {
+        let roots = [base.to_path_buf()];
+        collect_files(&roots, true, false, bounds, None, excludes, &PathRegex::default())
+            .unwrap()
+            .into_iter()
+            .map(|f| f.path)
+            .collect()
+    }
 
     #[test]
     fn parse_exts_basic() {
@@ -96,4 +582,179 @@ mod tests {
         let set = parse_exts(Some("   , ,  ")).unwrap();
         assert!(set.is_empty());
     }
+
+    #[test]
+    fn normalize_roots_drops_nested_and_duplicate() {
+        let base = std::env::temp_dir().join(format!("ducky_roots_{}", std::process::id()));
+        let sub = base.join("sub");
+        std::fs::create_dir_all(&sub).unwrap();
+
+        let roots = vec![sub.clone(), base.clone(), base.join("."), base.clone()];
+        let out = normalize_roots(&roots);
+        assert_eq!(out, vec![base.clone()]);
+
+        let _ = std::fs::remove_dir_all(base);
+    }
+
+    #[test]
+    fn file_roots_bypass_filters_and_are_listed_once() {
+        let base = std::env::temp_dir().join(format!("ducky_file_roots_{}", std::process::id()));
+        let sub = base.join("sub");
+        std::fs::create_dir_all(&sub).unwrap();
+        let (small, other) = (sub.join("small.txt"), sub.join("other.txt"));
+        std::fs::write(&small, b"tiny").unwrap();
+        std::fs::write(&other, vec![0u8; 4096]).unwrap();
+
+        let roots = normalize_roots(&[base.clone(), small.clone()]);
+        assert_eq!(roots, vec![base.clone(), small.clone()]);
+        let bounds = Bounds { min_size: 1024, ..Bounds::default() };
+        let found = collect_files(&roots, true, false, &bounds, None, &[], &PathRegex::default())
+            .unwrap();
+        let mut paths: Vec<&PathBuf> = found.iter().map(|f| &f.path).collect();
+        paths.sort();
+        assert_eq!(paths, vec![&other, &small]);
+        // The snapshot is taken for file roots too.
+        assert!(found.iter().any(|f| f.path == small && f.meta.size == 4));
+
+        let _ = std::fs::remove_dir_all(base);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn aliases_of_one_directory_are_walked_once() {
+        let base = std::env::temp_dir().join(format!("ducky_aliases_{}", std::process::id()));
+        let (real, other) = (base.join("real"), base.join("other"));
+        std::fs::create_dir_all(&real).unwrap();
+        std::fs::create_dir_all(&other).unwrap();
+        // A symlink stands in for a bind mount: same device and inode, a different path.
+        let alias = base.join("alias");
+        std::os::unix::fs::symlink(&real, &alias).unwrap();
+
+        let (kept, aliases) = split_aliases(vec![real.clone(), other.clone(), alias.clone()]);
+        assert_eq!(kept, vec![real.clone(), other]);
+        assert_eq!(aliases, vec![(real, alias)]);
+
+        let _ = std::fs::remove_dir_all(base);
+    }
+
+    #[test]
+    fn normalize_roots_expands_globs() {
+        let base = std::env::temp_dir().join(format!("ducky_glob_{}", std::process::id()));
+        std::fs::create_dir_all(base.join("a1")).unwrap();
+        std::fs::create_dir_all(base.join("a2")).unwrap();
+        std::fs::create_dir_all(base.join("b")).unwrap();
+
+        let mut out = normalize_roots(&[base.join("a*")]);
+        out.sort();
+        assert_eq!(out, vec![base.join("a1"), base.join("a2")]);
+
+        let _ = std::fs::remove_dir_all(base);
+    }
+
+    #[test]
+    fn excludes_prune_dirs_and_match_names() {
+        let base = std::env::temp_dir().join(format!("ducky_exclude_{}", std::process::id()));
+        std::fs::create_dir_all(base.join("node_modules/pkg")).unwrap();
+        std::fs::create_dir_all(base.join("build/obj")).unwrap();
+        std::fs::write(base.join("node_modules/pkg/a.js"), b"x").unwrap();
+        std::fs::write(base.join("build/obj/a.o"), b"x").unwrap();
+        std::fs::write(base.join("build/keep.txt"), b"x").unwrap();
+        std::fs::write(base.join("disk.ISO"), b"x").unwrap();
+
+        let excludes: Vec<_> = ["node_modules", "*.iso", "build/**/*.o"]
+            .iter()
+            .map(|p| parse_exclude(p).unwrap())
+            .collect();
+        let mut out = walk(&base, &Bounds::default(), &excludes);
+        out.sort();
+        assert_eq!(out, vec![base.join("build/keep.txt")]);
+        assert!(parse_exclude("[").is_err());
+        assert!(has_separator("build/**/*.o") && !has_separator("*.iso"));
+        // Windows users write paths with backslashes; elsewhere it's just a character.
+        assert_eq!(has_separator(r"build\obj"), cfg!(windows));
+
+        let _ = std::fs::remove_dir_all(base);
+    }
+
+    #[test]
+    fn size_bounds_are_inclusive() {
+        let base = std::env::temp_dir().join(format!("ducky_sizes_{}", std::process::id()));
+        std::fs::create_dir_all(&base).unwrap();
+        for (name, len) in [("small", 1), ("mid", 10), ("big", 100)] {
+            std::fs::write(base.join(name), vec![0u8; len]).unwrap();
+        }
+        let bounds = Bounds { min_size: 10, max_size: Some(10), ..Default::default() };
+        assert_eq!(walk(&base, &bounds, &[]), vec![base.join("mid")]);
+
+        // Everything was just written: nothing is older than a day.
+        let day_ago = parse_time("1d").unwrap();
+        let old = Bounds { modified_before: Some(day_ago), ..Default::default() };
+        assert!(walk(&base, &old, &[]).is_empty());
+
+        let _ = std::fs::remove_dir_all(base);
+    }
+
+    #[test]
+    fn path_regexes_include_and_exclude() {
+        let re = PathRegex {
+            include: vec![parse_regex(r"/photos/\d{4}/").unwrap()],
+            exclude: vec![parse_regex(r"(?i)\.thumb\.").unwrap()],
+        };
+        assert!(re.admits(Path::new("/home/u/photos/2019/a.jpg")));
+        assert!(!re.admits(Path::new("/home/u/photos/misc/a.jpg")));
+        assert!(!re.admits(Path::new("/home/u/photos/2019/a.THUMB.jpg")));
+        assert!(PathRegex::default().admits(Path::new("anything")));
+        assert!(parse_regex("(").is_err());
+    }
+
+    #[test]
+    fn depth_limits_select_levels() {
+        let base = std::env::temp_dir().join(format!("ducky_depth_{}", std::process::id()));
+        std::fs::create_dir_all(base.join("a/b")).unwrap();
+        for p in ["top", "a/mid", "a/b/deep"] {
+            std::fs::write(base.join(p), b"x").unwrap();
+        }
+        let depth = |min_depth, max_depth| {
+            let mut out = walk(&base, &Bounds { min_depth, max_depth, ..Default::default() }, &[]);
+            out.sort();
+            out
+        };
+        assert_eq!(depth(0, Some(1)), vec![base.join("top")]);
+        assert_eq!(depth(2, Some(2)), vec![base.join("a/mid")]);
+        assert_eq!(depth(3, None), vec![base.join("a/b/deep")]);
+
+        let _ = std::fs::remove_dir_all(base);
+    }
+
+    #[test]
+    fn parses_dates_and_durations() {
+        assert_eq!(parse_time("1970-01-02"), Ok(UNIX_EPOCH + Duration::from_secs(86_400)));
+        assert_eq!(parse_date("2024-03-01"), Some(19_783));
+        let then = parse_time("90d").unwrap();
+        let ago = SystemTime::now().duration_since(then).unwrap();
+        assert!(ago >= Duration::from_secs(90 * 86_400));
+        assert!(parse_time("2024-13-01").is_err());
+        assert!(parse_time("2024-02-31").is_err());
+        assert!(parse_time("2023-02-29").is_err());
+        assert!(parse_time("2024-04-31").is_err());
+        assert!(parse_time("2024").is_err(), "a bare number is not an age");
+        assert!(parse_time("d").is_err() && parse_time("3y").is_err());
+        assert_eq!(parse_age("2w"), Some(14 * 86_400));
+        assert_eq!(parse_date("2024-02-29"), Some(19_782));
+        assert!(parse_date("2000-02-29").is_some());
+        assert!(parse_date("2100-02-29").is_none());
+    }
+
+    #[test]
+    fn artifact_dirs_are_skipped() {
+        let base = std::env::temp_dir().join(format!("ducky_artifacts_{}", std::process::id()));
+        std::fs::create_dir_all(&base).unwrap();
+        create_artifact_dir(&base.join("cache/ducky")).unwrap();
+        std::fs::write(base.join("cache/ducky/journal.jsonl"), b"x").unwrap();
+        std::fs::write(base.join("keep"), b"x").unwrap();
+
+        assert_eq!(walk(&base, &Bounds::default(), &[]), vec![base.join("keep")]);
+
+        let _ = std::fs::remove_dir_all(base);
+    }
 }
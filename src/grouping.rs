@@ -1,30 +1,175 @@
-//! Grouping utilities: coarse grouping by file size.
+//! Grouping utilities: coarse grouping by file size, and collapsing hard links so one inode
+//! is hashed once and never counts as a duplicate of itself. Both work from the metadata the
+//! walk already read; nothing here touches the filesystem except the spill file.
 
-use std::collections::BTreeMap;
-use std::fs;
-use std::path::PathBuf;
+use crate::fs_utils::{FileMeta, ScannedFile};
+use crate::rng::Rng;
+use std::collections::{BTreeMap, HashMap};
+use std::ffi::OsString;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+/// Extra hard links dropped from the size buckets, by the path kept in their place.
+pub type Links = HashMap<PathBuf, Vec<PathBuf>>;
 
 /// Groups files by their byte size.
-/// Returns a map: size → Vec<paths>
-pub fn group_by_size(files: &[PathBuf]) -> BTreeMap<u64, Vec<PathBuf>> {
-    let mut map: BTreeMap<u64, Vec<PathBuf>> = BTreeMap::new();
-
-    for path in files {
-        if let Ok(meta) = fs::metadata(path) {
-            let size = meta.len();
-            map.entry(size).or_default().push(path.clone());
-        }
+/// Returns a map: size → Vec<files>
+pub fn group_by_size(files: &[ScannedFile]) -> BTreeMap<u64, Vec<ScannedFile>> {
+    let mut map: BTreeMap<u64, Vec<ScannedFile>> = BTreeMap::new();
+
+    for f in files {
+        map.entry(f.meta.size).or_default().push(f.clone());
     }
 
     map
 }
 
+/// Approximate heap taken by the path tables of a scan: the walked list plus the size buckets
+/// holding a second copy of every path, with allocator and map overhead per entry.
+pub fn table_bytes(files: &[ScannedFile]) -> u64 {
+    const PER_ENTRY: u64 = 2 * std::mem::size_of::<ScannedFile>() as u64 + 48;
+    files.iter().map(|f| 2 * f.path.as_os_str().len() as u64 + PER_ENTRY).sum()
+}
+
+/// A fresh name for a spill file in `dir`. The name is random, so other users can't guess it
+/// and plant a file or symlink there ahead of the scan.
+pub fn spill_path(dir: &Path) -> PathBuf {
+    let mut rng = Rng::from_seed_or_clock(None);
+    let salt = rng.next_u64() ^ u64::from(std::process::id()).rotate_left(32);
+    dir.join(format!("ducky_spill_{:016x}", salt))
+}
+
+/// `group_by_size` for scans too large to hold twice: every file is written to `spill` with its
+/// metadata and `files` is freed, then the spill is read back twice, once to count sizes and
+/// once to keep only the files that share a size with another. Singleton sizes never come back
+/// into memory. `spill` must not exist yet; it is created readable by its owner only and
+/// removed afterwards. `files` is left untouched when the spill can't be written.
+pub fn group_by_size_spilled(
+    files: &mut Vec<ScannedFile>,
+    spill: &Path,
+) -> io::Result<BTreeMap<u64, Vec<ScannedFile>>> {
+    let out = create_private(spill)?;
+    let grouped = spill_and_group(files, out, spill);
+    let _ = std::fs::remove_file(spill);
+    grouped
+}
+
+fn spill_and_group(
+    files: &mut Vec<ScannedFile>,
+    out: File,
+    spill: &Path,
+) -> io::Result<BTreeMap<u64, Vec<ScannedFile>>> {
+    let mut out = BufWriter::new(out);
+    for f in files.iter() {
+        let m = &f.meta;
+        let bytes = f.path.as_os_str().as_encoded_bytes();
+        for field in [m.size, m.mtime_ns as u64, m.dev, m.ino, m.nlink] {
+            out.write_all(&field.to_le_bytes())?;
+        }
+        out.write_all(&(bytes.len() as u32).to_le_bytes())?;
+        out.write_all(bytes)?;
+    }
+    out.into_inner().map_err(io::IntoInnerError::into_error)?.sync_all()?;
+    *files = Vec::new();
+
+    let mut counts: HashMap<u64, u32> = HashMap::new();
+    read_spill(spill, |meta, _| *counts.entry(meta.size).or_default() += 1)?;
+    let mut map: BTreeMap<u64, Vec<ScannedFile>> = BTreeMap::new();
+    read_spill(spill, |meta, path| {
+        if counts[&meta.size] > 1 {
+            map.entry(meta.size).or_default().push(ScannedFile { path: path(), meta });
+        }
+    })?;
+    Ok(map)
+}
+
+/// Create `path`, failing if anything (a file, a symlink) already has that name.
+fn create_private(path: &Path) -> io::Result<File> {
+    let mut opts = OpenOptions::new();
+    opts.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut opts, 0o600);
+    opts.open(path)
+}
+
+/// Calls `each` with the metadata of every spilled record and a way to decode its path.
+fn read_spill(
+    spill: &Path,
+    mut each: impl FnMut(FileMeta, &dyn Fn() -> PathBuf),
+) -> io::Result<()> {
+    let mut r = BufReader::new(File::open(spill)?);
+    let (mut fields, mut len, mut bytes) = ([0u8; 40], [0u8; 4], Vec::new());
+    loop {
+        match r.read_exact(&mut fields) {
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
+            other => other?,
+        }
+        let field = |i: usize| u64::from_le_bytes(fields[i * 8..i * 8 + 8].try_into().unwrap());
+        let meta = FileMeta {
+            size: field(0),
+            mtime_ns: field(1) as i64,
+            dev: field(2),
+            ino: field(3),
+            nlink: field(4),
+        };
+        r.read_exact(&mut len)?;
+        bytes.resize(u32::from_le_bytes(len) as usize, 0);
+        r.read_exact(&mut bytes)?;
+        // SAFETY: the bytes were produced by `as_encoded_bytes` in this same process.
+        let path = || PathBuf::from(unsafe { OsString::from_encoded_bytes_unchecked(bytes.clone()) });
+        each(meta, &path);
+    }
+}
+
+/// Keep one file per (device, inode) in every size bucket; the other links are returned by
+/// the kept path, so groups can list them again once hashing is done. Where the platform
+/// reports no link count, every file has `nlink` 1 and nothing is collapsed.
+pub fn collapse_hardlinks(by_size: &mut BTreeMap<u64, Vec<ScannedFile>>) -> Links {
+    use std::collections::hash_map::Entry;
+
+    let mut links = Links::new();
+    for files in by_size.values_mut().filter(|v| v.len() > 1) {
+        let mut seen: HashMap<(u64, u64), usize> = HashMap::new();
+        let mut kept: Vec<ScannedFile> = Vec::with_capacity(files.len());
+        for f in files.drain(..) {
+            if f.meta.nlink <= 1 {
+                kept.push(f);
+                continue;
+            }
+            match seen.entry((f.meta.dev, f.meta.ino)) {
+                Entry::Occupied(e) => {
+                    links.entry(kept[*e.get()].path.clone()).or_default().push(f.path)
+                }
+                Entry::Vacant(e) => {
+                    e.insert(kept.len());
+                    kept.push(f);
+                }
+            }
+        }
+        *files = kept;
+    }
+    links
+}
+
 #[cfg(test)]
 mod tests {
-    use super::group_by_size;
+    use super::{collapse_hardlinks, group_by_size, group_by_size_spilled, spill_path};
+    use crate::fs_utils::{FileMeta, ScannedFile};
     use std::fs::File;
     use std::io::Write;
-    use std::path::PathBuf;
+    use std::path::{Path, PathBuf};
+
+    fn scanned(paths: &[&PathBuf]) -> Vec<ScannedFile> {
+        paths
+            .iter()
+            .map(|p| ScannedFile { path: p.to_path_buf(), meta: FileMeta::of(p).unwrap() })
+            .collect()
+    }
+
+    fn paths(files: &[ScannedFile]) -> Vec<&Path> {
+        files.iter().map(|f| f.path.as_path()).collect()
+    }
 
     #[test]
     fn groups_by_size() {
@@ -38,7 +183,7 @@ mod tests {
         File::create(&p2).unwrap().write_all(b"xyz").unwrap();
         File::create(&p3).unwrap().write_all(b"q").unwrap();
 
-        let files: Vec<PathBuf> = vec![p1.clone(), p2.clone(), p3.clone()];
+        let files = scanned(&[&p1, &p2, &p3]);
         let map = group_by_size(&files);
         assert_eq!(map.get(&3).map(|v| v.len()).unwrap_or(0), 2);
         assert_eq!(map.get(&1).map(|v| v.len()).unwrap_or(0), 1);
@@ -48,4 +193,48 @@ mod tests {
         let _ = std::fs::remove_file(p2);
         let _ = std::fs::remove_file(p3);
     }
+
+    #[test]
+    fn spilled_grouping_keeps_only_shared_sizes() {
+        let dir = std::env::temp_dir().join(format!("ducky_spill_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (a, b, c) = (dir.join("a"), dir.join("b"), dir.join("c"));
+        std::fs::write(&a, b"abc").unwrap();
+        std::fs::write(&b, b"xyz").unwrap();
+        std::fs::write(&c, b"q").unwrap();
+
+        let mut files = scanned(&[&a, &b, &c]);
+        let expected = files[..2].to_vec();
+        let spill = spill_path(&dir);
+        // An existing name is never written through.
+        std::fs::write(&spill, b"planted").unwrap();
+        assert!(group_by_size_spilled(&mut files, &spill).is_err());
+        assert_eq!((files.len(), std::fs::read(&spill).unwrap()), (3, b"planted".to_vec()));
+        std::fs::remove_file(&spill).unwrap();
+
+        let map = group_by_size_spilled(&mut files, &spill).unwrap();
+        assert!(files.is_empty() && !spill.exists());
+        // The metadata comes back from the spill along with the path.
+        assert_eq!(map.into_iter().collect::<Vec<_>>(), vec![(3, expected)]);
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn hardlinks_collapse_to_one_path() {
+        let dir = std::env::temp_dir().join(format!("ducky_links_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (a, b, c) = (dir.join("a"), dir.join("b"), dir.join("c"));
+        std::fs::write(&a, b"same").unwrap();
+        std::fs::hard_link(&a, &b).unwrap();
+        std::fs::write(&c, b"same").unwrap();
+
+        let mut map = group_by_size(&scanned(&[&a, &b, &c]));
+        let links = collapse_hardlinks(&mut map);
+        assert_eq!(paths(&map[&4]), vec![a.as_path(), c.as_path()]);
+        assert_eq!(links[&a], vec![b]);
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
 }
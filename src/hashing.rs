@@ -1,27 +1,231 @@
 //! Hashing utilities: BLAKE3-based quick and full hashes.
 
 use anyhow::{Context, Result};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
 use blake3::Hasher;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
 use std::fs::File;
-use std::io::Read;
+use std::io::{Read, Seek, SeekFrom};
 use std::path::Path;
 
-/// Hash the first `limit` bytes of a file with BLAKE3.
-/// If file is smaller than `limit`, hashes the whole file.
-pub fn quick_hash(path: &Path, limit: u64) -> Result<String> {
+/// Name of the digest used for quick and full hashes.
+pub const ALGORITHM: &str = "blake3";
+
+/// Settings that determine what a hash means. Digests computed under different settings
+/// must never be compared with each other.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HashSettings {
+    pub algorithm: String,
+    /// Effective (clamped) quick-hash sample length in bytes.
+    pub quick_bytes: u64,
+    /// Which parts of a file the quick hash reads.
+    #[serde(default)]
+    pub quick_sample: QuickSample,
+}
+
+impl HashSettings {
+    pub fn new(quick_bytes: u64, quick_sample: QuickSample) -> Self {
+        Self { algorithm: ALGORITHM.to_string(), quick_bytes, quick_sample }
+    }
+}
+
+/// Where the quick hash samples a file: `quick_bytes` from each of the chosen spots.
+/// Sampling the tail too separates files with identical headers (videos, disk images)
+/// before they reach the full hash.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+#[serde(rename_all = "kebab-case")]
+pub enum QuickSample {
+    /// The first N bytes.
+    #[default]
+    Head,
+    /// The first and the last N bytes.
+    HeadTail,
+    /// The first, middle and last N bytes.
+    HeadMidTail,
+}
+
+impl QuickSample {
+    pub fn name(self) -> &'static str {
+        match self {
+            QuickSample::Head => "head",
+            QuickSample::HeadTail => "head-tail",
+            QuickSample::HeadMidTail => "head-mid-tail",
+        }
+    }
+
+    fn spots(self) -> u64 {
+        match self {
+            QuickSample::Head => 1,
+            QuickSample::HeadTail => 2,
+            QuickSample::HeadMidTail => 3,
+        }
+    }
+
+    /// Byte ranges `(start, len)` read from a file of `size`; the whole file when the samples
+    /// would overlap.
+    fn ranges(self, size: u64, limit: u64) -> Vec<(u64, u64)> {
+        if self == QuickSample::Head || size <= limit.saturating_mul(self.spots()) {
+            return vec![(0, self.bytes(size, limit))];
+        }
+        let tail = (size - limit, limit);
+        match self {
+            QuickSample::HeadMidTail => vec![(0, limit), ((size - limit) / 2, limit), tail],
+            _ => vec![(0, limit), tail],
+        }
+    }
+
+    /// Bytes the quick hash reads from a file of `size`.
+    pub fn bytes(self, size: u64, limit: u64) -> u64 {
+        size.min(limit.saturating_mul(self.spots()))
+    }
+
+    /// Cache key for quick hashes taken with `limit`: the mode sits in the top byte, so
+    /// `head` keys are plain lengths and stay compatible with existing caches.
+    pub fn cache_tag(self, limit: u64) -> u64 {
+        (self.spots() - 1) << 56 | limit
+    }
+}
+
+/// Identity of a piece of content: length, digest algorithm and digest.
+///
+/// Two digests only denote the same content when all three parts match, so hashes from
+/// different algorithms (or truncated digests) and files of different lengths can never be
+/// conflated. Serialized as `algo:size:hex`.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ContentKey {
+    pub size: u64,
+    pub algo: String,
+    pub hash: String,
+}
+
+impl ContentKey {
+    /// Key for a digest computed by this build (`ALGORITHM`).
+    pub fn new(size: u64, hash: impl Into<String>) -> Self {
+        Self { size, algo: ALGORITHM.to_string(), hash: hash.into() }
+    }
+}
+
+impl fmt::Display for ContentKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}:{}", self.algo, self.size, self.hash)
+    }
+}
+
+impl std::str::FromStr for ContentKey {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let mut parts = s.splitn(3, ':');
+        match (parts.next(), parts.next(), parts.next()) {
+            (Some(algo), Some(size), Some(hash)) if !algo.is_empty() && !hash.is_empty() => {
+                let size = size.parse().map_err(|_| format!("invalid size in content key: {}", s))?;
+                Ok(Self { size, algo: algo.to_string(), hash: hash.to_string() })
+            }
+            _ => Err(format!("invalid content key (expected algo:size:hash): {}", s)),
+        }
+    }
+}
+
+impl Serialize for ContentKey {
+    fn serialize<S: Serializer>(&self, s: S) -> std::result::Result<S::Ok, S::Error> {
+        s.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for ContentKey {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> std::result::Result<Self, D::Error> {
+        let s = String::deserialize(d)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// How digests are written to JSON, CSV and Parquet output (`--digest-format`).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DigestFormat {
+    /// Full digest, 64 hex digits.
+    #[default]
+    Hex,
+    /// Full digest, 43 characters of URL-safe base64 without padding.
+    Base64,
+    /// The first N bytes, in hex. Shorter, but no longer proof that two files are equal.
+    Short(usize),
+}
+
+impl DigestFormat {
+    /// Re-encode a full hex digest.
+    pub fn encode(self, hex: &str) -> String {
+        match self {
+            DigestFormat::Hex => hex.to_string(),
+            DigestFormat::Base64 => match blake3::Hash::from_hex(hex) {
+                Ok(h) => URL_SAFE_NO_PAD.encode(h.as_bytes()),
+                Err(_) => hex.to_string(),
+            },
+            DigestFormat::Short(n) => hex.chars().take(n * 2).collect(),
+        }
+    }
+}
+
+impl std::str::FromStr for DigestFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "hex" => Ok(DigestFormat::Hex),
+            "base64" => Ok(DigestFormat::Base64),
+            _ => match s.strip_prefix("short:").map(str::parse::<usize>) {
+                Some(Ok(n)) if (1..32).contains(&n) => Ok(DigestFormat::Short(n)),
+                Some(_) => Err("short:N takes 1 to 31 bytes".to_string()),
+                None => Err(format!("unknown digest format {:?} (hex, base64 or short:N)", s)),
+            },
+        }
+    }
+}
+
+/// A digest read back from an export, in hex.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Digest {
+    Full(String),
+    /// A `short:N` prefix: it can narrow down, but not prove, equality.
+    Truncated(String),
+}
+
+/// Recognise a digest written with any `DigestFormat`.
+pub fn parse_digest(s: &str) -> Option<Digest> {
+    let is_hex = !s.is_empty() && s.len().is_multiple_of(2) && s.bytes().all(|b| b.is_ascii_hexdigit());
+    if is_hex && s.len() == 64 {
+        return Some(Digest::Full(s.to_ascii_lowercase()));
+    }
+    if is_hex && s.len() < 64 {
+        return Some(Digest::Truncated(s.to_ascii_lowercase()));
+    }
+    let bytes = URL_SAFE_NO_PAD.decode(s).ok()?;
+    let bytes: [u8; 32] = bytes.try_into().ok()?;
+    Some(Digest::Full(blake3::Hash::from_bytes(bytes).to_hex().to_string()))
+}
+
+/// Hash `limit` bytes from each spot of `sample` with BLAKE3 (the first `limit` bytes for
+/// `head`). Files too small for separate samples are hashed whole.
+pub fn quick_hash(path: &Path, limit: u64, sample: QuickSample) -> Result<String> {
     let mut f = File::open(path).with_context(|| format!("open {}", path.display()))?;
+    let size = if sample == QuickSample::Head { limit } else { f.metadata()?.len() };
     let mut hasher = Hasher::new();
     let mut buf = vec![0u8; 64 * 1024]; // 64KiB buffer
-    let mut left = limit;
 
-    while left > 0 {
-        let to_read = buf.len().min(left as usize);
-        let got = f.read(&mut buf[..to_read])?;
-        if got == 0 {
-            break;
+    for (start, len) in sample.ranges(size, limit) {
+        f.seek(SeekFrom::Start(start))?;
+        let mut left = len;
+        while left > 0 {
+            let to_read = buf.len().min(left as usize);
+            let got = f.read(&mut buf[..to_read])?;
+            if got == 0 {
+                break;
+            }
+            hasher.update(&buf[..got]);
+            left -= got as u64;
         }
-        hasher.update(&buf[..got]);
-        left -= got as u64;
     }
 
     Ok(hasher.finalize().to_hex().to_string())
@@ -41,3 +245,127 @@ pub fn full_hash(path: &Path) -> Result<String> {
     }
     Ok(hasher.finalize().to_hex().to_string())
 }
+
+/// Compare two files byte by byte (streaming), stopping at the first difference.
+pub fn same_bytes(a: &Path, b: &Path) -> Result<bool> {
+    compare(a, b, None).map(|(same, _)| same)
+}
+
+/// Compare two files byte by byte, hashing the content on the way. Returns the full hash when
+/// they are identical, and how many bytes were compared (less than the size when they differ
+/// early).
+pub fn compare_and_hash(a: &Path, b: &Path) -> Result<(Option<String>, u64)> {
+    let mut hasher = Hasher::new();
+    let (same, read) = compare(a, b, Some(&mut hasher))?;
+    Ok((same.then(|| hasher.finalize().to_hex().to_string()), read))
+}
+
+fn compare(a: &Path, b: &Path, mut hasher: Option<&mut Hasher>) -> Result<(bool, u64)> {
+    let mut fa = File::open(a).with_context(|| format!("open {}", a.display()))?;
+    let mut fb = File::open(b).with_context(|| format!("open {}", b.display()))?;
+    let mut buf_a = vec![0u8; 256 * 1024];
+    let mut buf_b = vec![0u8; 256 * 1024];
+    let mut read = 0u64;
+    loop {
+        let n = fill(&mut fa, &mut buf_a).with_context(|| format!("read {}", a.display()))?;
+        let m = fill(&mut fb, &mut buf_b).with_context(|| format!("read {}", b.display()))?;
+        read += n.min(m) as u64;
+        if n != m || buf_a[..n] != buf_b[..m] {
+            return Ok((false, read));
+        }
+        if n == 0 {
+            return Ok((true, read));
+        }
+        if let Some(h) = hasher.as_deref_mut() {
+            h.update(&buf_a[..n]);
+        }
+    }
+}
+
+/// Read until `buf` is full or the file ends; returns the number of bytes read.
+fn fill(f: &mut File, buf: &mut [u8]) -> std::io::Result<usize> {
+    let mut n = 0;
+    while n < buf.len() {
+        match f.read(&mut buf[n..])? {
+            0 => break,
+            got => n += got,
+        }
+    }
+    Ok(n)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn content_key_round_trip() {
+        let k = ContentKey::new(42, "abcd");
+        let json = serde_json::to_string(&k).unwrap();
+        assert_eq!(json, "\"blake3:42:abcd\"");
+        let back: ContentKey = serde_json::from_str(&json).unwrap();
+        assert_eq!(back, k);
+        assert!("blake3:x:abcd".parse::<ContentKey>().is_err());
+        assert_ne!(ContentKey::new(42, "abcd"), ContentKey::new(43, "abcd"));
+    }
+
+    #[test]
+    fn same_bytes_stops_at_difference() {
+        let dir = std::env::temp_dir().join(format!("ducky_same_bytes_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let big = vec![7u8; 600 * 1024];
+        let mut other = big.clone();
+        *other.last_mut().unwrap() = 8;
+        let (a, b, c, d) = (dir.join("a"), dir.join("b"), dir.join("c"), dir.join("d"));
+        std::fs::write(&a, &big).unwrap();
+        std::fs::write(&b, &big).unwrap();
+        std::fs::write(&c, &other).unwrap();
+        std::fs::write(&d, &big[..1000]).unwrap();
+        assert!(same_bytes(&a, &b).unwrap());
+        assert!(!same_bytes(&a, &c).unwrap());
+        assert!(!same_bytes(&a, &d).unwrap());
+        let (hash, read) = compare_and_hash(&a, &b).unwrap();
+        assert_eq!((hash, read), (Some(full_hash(&a).unwrap()), big.len() as u64));
+        assert_eq!(compare_and_hash(&d, &c).unwrap(), (None, 1000));
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn quick_sample_reads_head_and_tail() {
+        let path = std::env::temp_dir().join(format!("ducky_quick_sample_{}", std::process::id()));
+        let other = path.with_extension("b");
+        // Same header, different endings.
+        let mut body = vec![0u8; 10_000];
+        std::fs::write(&path, &body).unwrap();
+        *body.last_mut().unwrap() = 1;
+        std::fs::write(&other, &body).unwrap();
+
+        let q = |p: &Path, s| quick_hash(p, 1024, s).unwrap();
+        assert_eq!(q(&path, QuickSample::Head), q(&other, QuickSample::Head));
+        assert_ne!(q(&path, QuickSample::HeadTail), q(&other, QuickSample::HeadTail));
+        assert_ne!(q(&path, QuickSample::HeadMidTail), q(&other, QuickSample::HeadMidTail));
+        // Small enough to be read whole.
+        let whole = quick_hash(&path, 8192, QuickSample::HeadTail).unwrap();
+        assert_eq!(whole, full_hash(&path).unwrap());
+
+        assert_eq!(QuickSample::HeadMidTail.bytes(10_000, 1024), 3072);
+        assert_eq!(QuickSample::Head.cache_tag(1024), 1024);
+        assert_ne!(QuickSample::HeadTail.cache_tag(1024), 1024);
+        let _ = std::fs::remove_file(path);
+        let _ = std::fs::remove_file(other);
+    }
+
+    #[test]
+    fn digest_formats_round_trip() {
+        let hex = blake3::hash(b"ducky").to_hex().to_string();
+        let b64 = DigestFormat::Base64.encode(&hex);
+        assert_eq!(b64.len(), 43);
+        assert_eq!(parse_digest(&b64), Some(Digest::Full(hex.clone())));
+        assert_eq!(parse_digest(&hex), Some(Digest::Full(hex.clone())));
+        let short = DigestFormat::Short(16).encode(&hex);
+        assert_eq!(parse_digest(&short), Some(Digest::Truncated(hex[..32].to_string())));
+        assert_eq!("short:16".parse(), Ok(DigestFormat::Short(16)));
+        assert!("short:32".parse::<DigestFormat>().is_err());
+        assert_eq!(parse_digest("not a digest"), None);
+    }
+}
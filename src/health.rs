@@ -0,0 +1,165 @@
+//! Failing-media detection during hashing.
+//!
+//! A read that fails with `EIO` marks the file and its device as suspect. After
+//! [`GIVE_UP_AFTER`] such errors on one device, its remaining files are skipped instead of
+//! read: a dying disk retries every bad sector, so pressing on turns the scan into a hang
+//! and puts more wear on the disk. Suspect devices are reported at the end of the run.
+//!
+//! Where the device can't be told (outside Unix, or when `stat` fails), errors are still
+//! reported but never stop reads: one failing file must not end a scan of every volume.
+
+use crate::schedule::{device_label, device_of};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// I/O errors on one device after which its files are no longer read.
+pub const GIVE_UP_AFTER: usize = 3;
+
+/// What `device_of` returns when it can't tell the device.
+const UNKNOWN_DEVICE: u64 = 0;
+
+/// A device that returned read errors.
+#[derive(Debug, Clone, Serialize, PartialEq, Eq)]
+pub struct SuspectDevice {
+    pub device: String,
+    pub errors: usize,
+    /// Files left unread because the device was given up on.
+    pub skipped: usize,
+    /// Files whose reads failed.
+    pub files: Vec<PathBuf>,
+}
+
+#[derive(Default)]
+struct Device {
+    files: Vec<PathBuf>,
+    skipped: usize,
+}
+
+#[derive(Default)]
+pub struct MediaHealth {
+    devices: Mutex<BTreeMap<u64, Device>>,
+}
+
+/// Whether `err` is a low-level read error (EIO) rather than, say, a permission problem.
+pub fn is_media_error(err: &anyhow::Error) -> bool {
+    err.chain()
+        .filter_map(|e| e.downcast_ref::<std::io::Error>())
+        .any(is_eio)
+}
+
+#[cfg(unix)]
+fn is_eio(e: &std::io::Error) -> bool {
+    e.raw_os_error() == Some(libc::EIO)
+}
+
+#[cfg(not(unix))]
+fn is_eio(e: &std::io::Error) -> bool {
+    // ERROR_CRC and ERROR_SECTOR_NOT_FOUND: what Windows reports for unreadable sectors.
+    matches!(e.raw_os_error(), Some(23) | Some(27))
+}
+
+impl MediaHealth {
+    /// Whether `path` may still be read; counts it as skipped when its device was given up.
+    pub fn admit(&self, path: &Path) -> bool {
+        let dev = device_of(path);
+        if dev == UNKNOWN_DEVICE {
+            return true;
+        }
+        let mut devices = self.devices.lock().unwrap();
+        match devices.get_mut(&dev) {
+            Some(d) if d.files.len() >= GIVE_UP_AFTER => {
+                d.skipped += 1;
+                false
+            }
+            _ => true,
+        }
+    }
+
+    /// Whether `path`'s device has been given up on, without counting the file as skipped.
+    pub fn given_up(&self, path: &Path) -> bool {
+        let dev = device_of(path);
+        let devices = self.devices.lock().unwrap();
+        dev != UNKNOWN_DEVICE && devices.get(&dev).is_some_and(|d| d.files.len() >= GIVE_UP_AFTER)
+    }
+
+    /// Note a failed read of `path`; only media errors count against its device.
+    pub fn record(&self, path: &Path, err: &anyhow::Error) {
+        if !is_media_error(err) {
+            return;
+        }
+        let dev = device_of(path);
+        let mut devices = self.devices.lock().unwrap();
+        let d = devices.entry(dev).or_default();
+        d.files.push(path.to_path_buf());
+        if d.files.len() == GIVE_UP_AFTER && dev != UNKNOWN_DEVICE {
+            crate::warnings::warning!(
+                "device {} returned {} read errors; no longer reading from it",
+                device_label(dev),
+                GIVE_UP_AFTER
+            );
+        }
+    }
+
+    /// Devices with read errors, by device id; the tally starts over afterwards.
+    pub fn report(&self) -> Vec<SuspectDevice> {
+        let devices = std::mem::take(&mut *self.devices.lock().unwrap());
+        devices
+            .into_iter()
+            .map(|(dev, mut d)| {
+                d.files.sort();
+                let known = dev != UNKNOWN_DEVICE;
+                SuspectDevice {
+                    device: if known { device_label(dev) } else { "unknown".into() },
+                    errors: d.files.len(),
+                    skipped: d.skipped,
+                    files: d.files,
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[cfg(unix)]
+    fn gives_up_on_a_device_after_repeated_eio() {
+        let path = std::env::temp_dir();
+        let health = MediaHealth::default();
+        let denied = anyhow::Error::from(std::io::Error::from_raw_os_error(libc::EACCES));
+        health.record(&path, &denied);
+        assert!(health.admit(&path), "permission errors don't count");
+
+        for _ in 0..GIVE_UP_AFTER {
+            assert!(health.admit(&path));
+            let eio = std::io::Error::from_raw_os_error(libc::EIO);
+            health.record(&path, &anyhow::Error::from(eio).context("read"));
+        }
+        assert!(health.given_up(&path));
+        assert!(!health.admit(&path));
+        let report = health.report();
+        assert_eq!(report.len(), 1);
+        assert_eq!((report[0].errors, report[0].skipped), (GIVE_UP_AFTER, 1));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn never_gives_up_on_an_unknown_device() {
+        // A path that can't be stat'ed has no known device.
+        let path = std::env::temp_dir().join("ducky_health_missing/file");
+        let health = MediaHealth::default();
+        for _ in 0..=GIVE_UP_AFTER {
+            assert!(health.admit(&path));
+            let eio = std::io::Error::from_raw_os_error(libc::EIO);
+            health.record(&path, &anyhow::Error::from(eio));
+        }
+        assert!(!health.given_up(&path));
+        let report = health.report();
+        assert_eq!(report[0].device, "unknown");
+        assert_eq!((report[0].errors, report[0].skipped), (GIVE_UP_AFTER + 1, 0));
+    }
+}
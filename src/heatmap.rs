@@ -0,0 +1,100 @@
+//! `--heatmap`: directories ranked by the share of their bytes that is duplicated, so cleanup
+//! starts where duplication is densest rather than where it is merely largest.
+//!
+//! Only files directly inside a directory count towards it. The totals come from the walk,
+//! so files left out by the filters are not seen; a member counts as duplicated whether or
+//! not it is the group's canonical, since every copy but one could go.
+
+use crate::output::DuplicateGroup;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// Directories listed in human output; JSON lists all of them.
+pub const SHOWN: usize = 20;
+
+/// Duplication in one directory.
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct DirHeat {
+    pub dir: PathBuf,
+    /// Bytes in files that have a copy elsewhere.
+    pub duplicated_bytes: u64,
+    /// Bytes in every scanned file.
+    pub total_bytes: u64,
+    /// Files that have a copy elsewhere.
+    pub duplicated_files: usize,
+    /// `duplicated_bytes / total_bytes`, from 0 to 1.
+    pub ratio: f64,
+}
+
+/// Bytes of the scanned files, by directory.
+pub fn dir_totals<'a>(files: impl IntoIterator<Item = (&'a Path, u64)>) -> HashMap<PathBuf, u64> {
+    let mut totals: HashMap<PathBuf, u64> = HashMap::new();
+    for (path, size) in files {
+        let dir = path.parent().unwrap_or(Path::new(""));
+        let total = totals.entry(dir.to_path_buf()).or_default();
+        *total = total.saturating_add(size);
+    }
+    totals
+}
+
+/// Directories holding duplicates, densest first; ties go to the one with more duplicated
+/// bytes, then to the lexicographically first.
+pub fn heatmap(totals: &HashMap<PathBuf, u64>, groups: &[DuplicateGroup]) -> Vec<DirHeat> {
+    let mut dup: HashMap<&Path, (u64, usize)> = HashMap::new();
+    for g in groups {
+        for m in &g.members {
+            let e = dup.entry(m.parent().unwrap_or(Path::new(""))).or_default();
+            e.0 = e.0.saturating_add(g.size);
+            e.1 += 1;
+        }
+    }
+    let mut heat: Vec<DirHeat> = dup
+        .into_iter()
+        .map(|(dir, (duplicated_bytes, duplicated_files))| {
+            // Never below the duplicated bytes, should a member have escaped the totals.
+            let total_bytes = totals.get(dir).copied().unwrap_or(0).max(duplicated_bytes);
+            let ratio = match total_bytes {
+                0 => 0.0,
+                t => duplicated_bytes as f64 / t as f64,
+            };
+            let dir = dir.to_path_buf();
+            DirHeat { dir, duplicated_bytes, total_bytes, duplicated_files, ratio }
+        })
+        .collect();
+    heat.sort_by(|a, b| {
+        b.ratio
+            .total_cmp(&a.ratio)
+            .then_with(|| b.duplicated_bytes.cmp(&a.duplicated_bytes))
+            .then_with(|| a.dir.cmp(&b.dir))
+    });
+    heat
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn densest_directories_come_first() {
+        let files: Vec<(PathBuf, u64)> = [
+            ("/r/big/a", 100),
+            ("/r/big/b", 900),
+            ("/r/small/a", 100),
+            ("/r/other/a", 100),
+            ("/r/other/c", 100),
+        ]
+        .into_iter()
+        .map(|(p, s)| (PathBuf::from(p), s))
+        .collect();
+        let totals = dir_totals(files.iter().map(|(p, s)| (p.as_path(), *s)));
+        let groups = [DuplicateGroup::new(
+            100,
+            vec!["/r/big/a".into(), "/r/other/a".into(), "/r/small/a".into()],
+        )];
+        let heat = heatmap(&totals, &groups);
+        let order: Vec<_> = heat.iter().map(|h| (h.dir.to_str().unwrap(), h.ratio)).collect();
+        assert_eq!(order, [("/r/small", 1.0), ("/r/other", 0.5), ("/r/big", 0.1)]);
+        assert_eq!((heat[2].duplicated_bytes, heat[2].total_bytes), (100, 1000));
+    }
+}
@@ -0,0 +1,142 @@
+//! `--interactive`: confirm the action group by group on a terminal, like `fdupes -d`.
+//!
+//! For each group the members are listed with numbers; the answer applies the action as
+//! planned (`y`), chooses another file to keep (its number), skips the group (`s` or empty)
+//! or stops asking (`q`, also on end of input) and leaves the remaining groups alone.
+//!
+//! `--triage DURATION` asks the same questions about the groups with the most reclaimable
+//! space first and stops asking once the time budget is spent.
+
+use crate::output::DuplicateGroup;
+use bytesize::ByteSize;
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::time::{Duration, Instant};
+
+/// Prompts need a terminal on both ends: answers on stdin, questions on stderr.
+pub fn available() -> bool {
+    io::stdin().is_terminal() && io::stderr().is_terminal()
+}
+
+/// Ask about every group on stdin/stderr; returns the groups to act on.
+pub fn confirm(groups: &[DuplicateGroup], action: &str) -> io::Result<Vec<DuplicateGroup>> {
+    confirm_with(groups, action, &mut io::stdin().lock(), &mut io::stderr())
+}
+
+/// Ask about the biggest groups first until `budget` runs out; returns the groups to act on.
+pub fn triage(
+    groups: &[DuplicateGroup],
+    action: &str,
+    budget: Duration,
+) -> io::Result<Vec<DuplicateGroup>> {
+    let mut by_value = groups.to_vec();
+    by_value.sort_by_key(|g| std::cmp::Reverse(reclaimable(g)));
+    let deadline = Instant::now() + budget;
+    ask(&by_value, action, &mut io::stdin().lock(), &mut io::stderr(), Some(deadline))
+}
+
+fn reclaimable(g: &DuplicateGroup) -> u64 {
+    g.size.saturating_mul(g.members.len().saturating_sub(1) as u64)
+}
+
+enum Answer {
+    Apply,
+    Keep(usize),
+    Skip,
+    Quit,
+}
+
+fn parse(line: &str, members: usize) -> Option<Answer> {
+    match line.trim() {
+        "" | "s" | "skip" => Some(Answer::Skip),
+        "y" | "yes" => Some(Answer::Apply),
+        "q" | "quit" => Some(Answer::Quit),
+        n => n.parse::<usize>().ok().filter(|n| (1..=members).contains(n)).map(Answer::Keep),
+    }
+}
+
+/// Like `confirm`, reading answers from `input` and writing prompts to `out`.
+pub fn confirm_with(
+    groups: &[DuplicateGroup],
+    action: &str,
+    input: &mut impl BufRead,
+    out: &mut impl Write,
+) -> io::Result<Vec<DuplicateGroup>> {
+    ask(groups, action, input, out, None)
+}
+
+fn ask(
+    groups: &[DuplicateGroup],
+    action: &str,
+    input: &mut impl BufRead,
+    out: &mut impl Write,
+    deadline: Option<Instant>,
+) -> io::Result<Vec<DuplicateGroup>> {
+    let mut chosen = Vec::new();
+    for (i, g) in groups.iter().enumerate() {
+        if deadline.is_some_and(|d| Instant::now() >= d) {
+            writeln!(out, "\ntime is up after {} of {} groups", i, groups.len())?;
+            break;
+        }
+        writeln!(out, "\ngroup {}/{} ({} each):", i + 1, groups.len(), ByteSize(g.size))?;
+        for (n, p) in g.members.iter().enumerate() {
+            let mark = if n == 0 { "  (keep)" } else { "" };
+            writeln!(out, "  [{}] {}{}", n + 1, p.display(), mark)?;
+        }
+        let answer = loop {
+            write!(out, "{} the others? [y]es, [s]kip, [q]uit, or a number to keep instead: ", action)?;
+            out.flush()?;
+            let mut line = String::new();
+            if input.read_line(&mut line)? == 0 {
+                break Answer::Quit;
+            }
+            match parse(&line, g.members.len()) {
+                Some(a) => break a,
+                None => writeln!(out, "  please answer y, s, q or 1-{}", g.members.len())?,
+            }
+        };
+        match answer {
+            Answer::Apply => chosen.push(g.clone()),
+            Answer::Keep(n) => {
+                let mut g = g.clone();
+                g.members.swap(0, n - 1);
+                chosen.push(g);
+            }
+            Answer::Skip => {}
+            Answer::Quit => break,
+        }
+    }
+    Ok(chosen)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[test]
+    fn answers_select_reorder_skip_and_quit() {
+        let group = |names: &[&str]| {
+            DuplicateGroup::new(1, names.iter().map(PathBuf::from).collect())
+        };
+        let groups =
+            vec![group(&["a", "b"]), group(&["c", "d", "e"]), group(&["f", "g"]), group(&["h", "i"])];
+        let mut input = "y\n7\n3\n\nq\n".as_bytes();
+        let mut out = Vec::new();
+        let chosen = confirm_with(&groups, "delete", &mut input, &mut out).unwrap();
+        assert_eq!(chosen.len(), 2);
+        assert_eq!(chosen[0].members, groups[0].members);
+        // "7" is out of range and asked again; "3" keeps e instead of c.
+        assert_eq!(chosen[1].members, vec![PathBuf::from("e"), "d".into(), "c".into()]);
+        assert!(String::from_utf8(out).unwrap().contains("please answer"));
+    }
+
+    #[test]
+    fn triage_stops_when_the_budget_is_spent() {
+        let groups = vec![DuplicateGroup::new(1, vec![PathBuf::from("a"), "b".into()])];
+        let mut out = Vec::new();
+        let past = Some(Instant::now());
+        let chosen = ask(&groups, "delete", &mut "y\n".as_bytes(), &mut out, past).unwrap();
+        assert!(chosen.is_empty());
+        assert!(String::from_utf8(out).unwrap().contains("time is up after 0 of 1 groups"));
+    }
+}
@@ -0,0 +1,298 @@
+//! Append-only action journal (JSON Lines) used to recognise already-applied operations
+//! and to undo the most recent run (`ducky undo`).
+//!
+//! With `--action-log FILE` every attempted operation, including failures and dry-run
+//! plans, is also streamed to FILE as it happens.
+
+use crate::fs_utils::{cache_dir, create_artifact_dir, data_dir};
+use crate::hashing::{ContentKey, ALGORITHM};
+use crate::warnings::warning;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Op {
+    Delete,
+    Trash,
+    Hardlink,
+    Reflink,
+    /// Extents shared in place with the canonical (FIDEDUPERANGE).
+    Dedupe,
+    Symlink,
+    Move,
+    /// `ducky undo` restored `path`; `undoes` names the run it belonged to.
+    Undo,
+}
+
+impl Op {
+    /// Lowercase name, as serialized.
+    pub fn name(self) -> &'static str {
+        match self {
+            Op::Delete => "delete",
+            Op::Trash => "trash",
+            Op::Hardlink => "hardlink",
+            Op::Reflink => "reflink",
+            Op::Dedupe => "dedupe",
+            Op::Symlink => "symlink",
+            Op::Move => "move",
+            Op::Undo => "undo",
+        }
+    }
+}
+
+/// One completed operation. `hash` is the full content hash of the group.
+/// For moves, `canonical` holds the destination path.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Entry {
+    pub op: Op,
+    pub path: PathBuf,
+    pub canonical: PathBuf,
+    pub hash: String,
+    /// Digest algorithm `hash` was computed with.
+    #[serde(default = "default_algorithm")]
+    pub algo: String,
+    pub size: u64,
+    pub ts: u64,
+    /// Run that recorded the entry (its start time in milliseconds); 0 for old journals.
+    #[serde(default)]
+    pub run: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub undoes: Option<u64>,
+}
+
+impl Entry {
+    /// Content this entry refers to.
+    pub fn key(&self) -> ContentKey {
+        ContentKey { size: self.size, algo: self.algo.clone(), hash: self.hash.clone() }
+    }
+}
+
+fn default_algorithm() -> String {
+    ALGORITHM.to_string()
+}
+
+/// One line of `--action-log`: an operation that was attempted and how it ended.
+#[derive(Debug, Serialize)]
+pub struct Attempt<'a> {
+    pub ts: u64,
+    pub run: u64,
+    pub op: Op,
+    pub src: &'a Path,
+    /// The canonical, or the destination for moves.
+    pub dst: &'a Path,
+    /// Size of the file in bytes.
+    pub size: u64,
+    /// `done`, `planned` (dry-run), `already_applied`, `skipped_protected`, `skipped_pair`,
+    /// `changed_since_scan`, `unsupported`, `skipped_privilege` or `failed`.
+    pub result: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Default)]
+pub struct Journal {
+    entries: Vec<Entry>,
+    file: Option<File>,
+    run: u64,
+    action_log: Option<File>,
+}
+
+const FILE_NAME: &str = "journal.jsonl";
+
+/// Move the journal earlier versions kept in the cache directory to `path`, unless there is
+/// one there already.
+fn migrate(old: &Path, path: &Path) {
+    if path.exists() || !old.exists() || old == path {
+        return;
+    }
+    let moved = path.parent().map_or(Ok(()), create_artifact_dir).and_then(|()| {
+        fs::rename(old, path).or_else(|_| fs::copy(old, path).and_then(|_| fs::remove_file(old)))
+    });
+    if let Err(e) = moved {
+        let (old, path) = (old.display(), path.display());
+        warning!("could not move the action journal {} to {}: {}", old, path, e);
+    }
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis() as u64).unwrap_or(0)
+}
+
+impl Journal {
+    /// Journal that only lives in memory (nothing is persisted).
+    pub fn in_memory() -> Self {
+        Self { run: now_ms(), ..Self::default() }
+    }
+
+    /// Open the journal in the user data directory, where clearing caches doesn't lose what
+    /// `ducky undo` needs; falls back to in-memory with a warning.
+    pub fn open_default() -> Self {
+        let Some(dir) = data_dir() else {
+            warning!("no data directory; action journal disabled");
+            return Self::in_memory();
+        };
+        let path = dir.join(FILE_NAME);
+        if let Some(old) = cache_dir().map(|d| d.join(FILE_NAME)) {
+            migrate(&old, &path);
+        }
+        match Self::open(&path) {
+            Ok(j) => j,
+            Err(e) => {
+                warning!("action journal disabled: {:#}", e);
+                Self::in_memory()
+            }
+        }
+    }
+
+    /// Load existing entries from `path` and keep it open for appending.
+    /// Unparseable lines (e.g. a torn final write) are skipped.
+    pub fn open(path: &Path) -> Result<Self> {
+        if let Some(parent) = path.parent() {
+            create_artifact_dir(parent).with_context(|| format!("create {}", parent.display()))?;
+        }
+        let mut entries = Vec::new();
+        if let Ok(f) = File::open(path) {
+            for line in BufReader::new(f).lines() {
+                let line = line.with_context(|| format!("read {}", path.display()))?;
+                if let Ok(e) = serde_json::from_str::<Entry>(&line) {
+                    entries.push(e);
+                }
+            }
+        }
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("open {}", path.display()))?;
+        // Keep run ids increasing even if two runs start within the same millisecond.
+        let last = entries.iter().map(|e| e.run).max().unwrap_or(0);
+        Ok(Self { entries, file: Some(file), run: now_ms().max(last + 1), action_log: None })
+    }
+
+    /// Number of entries loaded or recorded so far.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Entries from position `start` on (e.g. those recorded during this run).
+    pub fn entries_since(&self, start: usize) -> &[Entry] {
+        self.entries.get(start..).unwrap_or(&[])
+    }
+
+    /// Whether `op` was already applied to `path` for content `key`.
+    /// Entries hashed with a different algorithm never match, and are reported loudly.
+    pub fn contains(&self, op: Op, path: &Path, key: &ContentKey) -> bool {
+        if key.hash.is_empty() {
+            return false;
+        }
+        let mut found = false;
+        for e in self.entries.iter().filter(|e| e.op == op && e.path == path) {
+            if e.algo != key.algo {
+                warning!(
+                    "WARNING: journal entry for {} was hashed with {} (current: {}); ignoring it",
+                    path.display(),
+                    e.algo,
+                    key.algo
+                );
+            } else if e.key() == *key {
+                found = true;
+            }
+        }
+        found
+    }
+
+    /// Entries of the most recent run that `ducky undo` has not restored yet, newest first.
+    pub fn pending_undo(&self) -> Vec<&Entry> {
+        let undone: std::collections::HashSet<(u64, &Path)> = self
+            .entries
+            .iter()
+            .filter_map(|e| Some((e.undoes?, e.path.as_path())))
+            .collect();
+        let pending = |e: &&Entry| {
+            e.op != Op::Undo && e.run != 0 && !undone.contains(&(e.run, e.path.as_path()))
+        };
+        let Some(run) = self.entries.iter().filter(pending).map(|e| e.run).max() else {
+            return Vec::new();
+        };
+        self.entries.iter().rev().filter(pending).filter(|e| e.run == run).collect()
+    }
+
+    /// Also stream every attempted operation to `path` (appending), one JSON object per line.
+    pub fn log_attempts_to(&mut self, path: &Path) -> Result<()> {
+        let f = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("open {}", path.display()))?;
+        self.action_log = Some(f);
+        Ok(())
+    }
+
+    /// Append one attempted operation to the action log, if there is one.
+    pub fn attempt(
+        &mut self,
+        op: Op,
+        src: &Path,
+        dst: &Path,
+        size: u64,
+        result: &str,
+        error: Option<String>,
+    ) {
+        let Some(f) = self.action_log.as_mut() else { return };
+        let a = Attempt { ts: now_ms(), run: self.run, op, src, dst, size, result, error };
+        let res = serde_json::to_string(&a)
+            .map_err(std::io::Error::from)
+            .and_then(|line| writeln!(f, "{}", line));
+        if let Err(e) = res {
+            warning!("failed to write action log: {}", e);
+            self.action_log = None;
+        }
+    }
+
+    /// Record a completed operation and flush it to disk.
+    pub fn record(&mut self, op: Op, path: &Path, canonical: &Path, key: &ContentKey) {
+        self.push(op, path, canonical, key, None);
+    }
+
+    /// Record that `undone` (an entry of an earlier run) has been restored.
+    pub fn record_undo(&mut self, undone: &Entry) {
+        let (path, canonical, key) = (undone.path.clone(), undone.canonical.clone(), undone.key());
+        self.push(Op::Undo, &path, &canonical, &key, Some(undone.run));
+    }
+
+    fn push(&mut self, op: Op, path: &Path, canonical: &Path, key: &ContentKey, undoes: Option<u64>) {
+        let entry = Entry {
+            op,
+            path: path.to_path_buf(),
+            canonical: canonical.to_path_buf(),
+            hash: key.hash.clone(),
+            algo: key.algo.clone(),
+            size: key.size,
+            ts: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0),
+            run: self.run,
+            undoes,
+        };
+        if let Some(f) = self.file.as_mut() {
+            let res = serde_json::to_string(&entry)
+                .map_err(std::io::Error::from)
+                .and_then(|line| writeln!(f, "{}", line))
+                .and_then(|_| f.flush());
+            if let Err(e) = res {
+                warning!("failed to write action journal: {}", e);
+            }
+        }
+        self.entries.push(entry);
+    }
+}
@@ -0,0 +1,75 @@
+//! ducky as a library: the scan pipeline, duplicate groups and the actions that can be taken
+//! on them, for programs (GUIs, backup tools) that embed deduplication instead of running the
+//! `ducky` binary.
+//!
+//! ```no_run
+//! use ducky::{ActionOptions, ActionPlan, ScanOptions, Scanner};
+//!
+//! let opts = ScanOptions { roots: vec!["/data".into()], ..Default::default() };
+//! let scan = Scanner::new(opts).run()?;
+//! let plan = ActionPlan::new(scan.groups, ActionOptions { trash: true, yes: true, ..Default::default() });
+//! let stats = plan.apply(&mut ducky::journal::Journal::open_default());
+//! println!("freed {} bytes", stats.freed_bytes);
+//! # anyhow::Ok(())
+//! ```
+
+pub mod actions;
+pub mod audit;
+pub mod budget;
+pub mod cache;
+#[cfg(feature = "cli")]
+pub mod capabilities;
+pub mod catalog;
+#[cfg(feature = "parquet")]
+pub mod columnar;
+#[cfg(feature = "decompress")]
+pub mod compressed;
+pub mod config;
+pub mod conflicts;
+pub mod copy;
+pub mod decisions;
+pub mod degraded;
+pub mod dirs;
+pub mod doctor;
+pub mod estimate;
+pub mod fixture;
+pub mod fs_utils;
+pub mod grouping;
+pub mod health;
+pub mod hashing;
+pub mod heatmap;
+pub mod interactive;
+pub mod journal;
+pub mod linkable;
+#[cfg(feature = "media")]
+pub mod media;
+pub mod output;
+pub mod pairs;
+pub mod pick;
+pub mod prefetch;
+pub mod progress;
+pub mod quarantine;
+pub mod rawpairs;
+pub mod reference;
+pub mod reflink;
+pub mod replicate;
+pub mod reports;
+pub mod rng;
+pub mod saved;
+pub mod scan;
+pub mod schedule;
+pub mod sentinel;
+pub mod snapshots;
+pub mod stats;
+pub mod status;
+pub mod trashcan;
+#[cfg(feature = "tui")]
+pub mod tui;
+pub mod undo;
+pub mod unique;
+pub mod watch;
+pub mod warnings;
+
+pub use actions::{ActionOptions, ActionPlan, ActionStats};
+pub use output::DuplicateGroup;
+pub use scan::{ScanOptions, ScanResult, Scanner};
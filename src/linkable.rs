@@ -0,0 +1,139 @@
+//! `--link-report`: which members of each group could be replaced by a link to the kept
+//! copy, and how much each method would free, before choosing `--hardlink`, `--reflink`,
+//! `--symlink` or a move.
+//!
+//! Hard links and reflinks only work within one filesystem, so members on the canonical's
+//! device can take either; reflinks additionally need a filesystem that clones (see
+//! `ducky doctor`). Members on another device can only be symlinked or moved. Members that
+//! already share the canonical's inode free nothing. Devices and inodes are read when the
+//! report is built; nothing is modified.
+
+use crate::cache::FileId;
+use crate::estimate;
+use crate::output::DuplicateGroup;
+use serde::Serialize;
+use std::collections::HashSet;
+use std::path::PathBuf;
+
+/// What can be done with one non-canonical member.
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Method {
+    /// Same device as the canonical: hardlink, reflink, symlink or move.
+    Hardlink,
+    /// Another device: symlink or move only.
+    CrossDevice,
+    /// Already a hard link of the canonical.
+    Linked,
+    /// Could not be read, or the platform doesn't report devices (only unix does): a
+    /// symlink or move works, a hardlink or reflink may not.
+    Unknown,
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq, Eq)]
+pub struct MemberLink {
+    pub path: PathBuf,
+    pub method: Method,
+}
+
+/// Bytes freed by each method. Deleting frees the naive upper bound (see
+/// [`estimate::upper_bound`]); a symlink or move frees what a hardlink does plus the
+/// cross-device members.
+#[derive(Debug, Clone, Copy, Default, Serialize, PartialEq, Eq)]
+pub struct Savings {
+    pub delete: u64,
+    pub hardlink: u64,
+    pub reflink: u64,
+    pub symlink: u64,
+}
+
+impl Savings {
+    pub fn add(&mut self, o: &Savings) {
+        self.delete += o.delete;
+        self.hardlink += o.hardlink;
+        self.reflink += o.reflink;
+        self.symlink += o.symlink;
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct GroupLinks {
+    pub size: u64,
+    pub canonical: PathBuf,
+    pub members: Vec<MemberLink>,
+    pub savings: Savings,
+}
+
+/// Whether `FileId::dev` tells filesystems apart; elsewhere it is always 0.
+const DEVICES_KNOWN: bool = cfg!(unix);
+
+/// Classify every non-canonical member of `g` against its canonical.
+pub fn classify(g: &DuplicateGroup) -> GroupLinks {
+    let canonical = FileId::of(&g.members[0]);
+    // Each inode frees its bytes once, however many of its paths are in the group.
+    let mut seen = HashSet::new();
+    let mut savings = Savings { delete: estimate::upper_bound(g), ..Savings::default() };
+    let members = g.members[1..]
+        .iter()
+        .map(|p| {
+            let method = match (canonical, FileId::of(p)) {
+                (Some(c), Some(m)) if (c.dev, c.ino) == (m.dev, m.ino) => Method::Linked,
+                (Some(_), Some(m)) if !DEVICES_KNOWN => {
+                    if seen.insert((m.dev, m.ino)) {
+                        savings.symlink += g.size;
+                    }
+                    Method::Unknown
+                }
+                (Some(c), Some(m)) => {
+                    let same_device = c.dev == m.dev;
+                    if seen.insert((m.dev, m.ino)) {
+                        savings.symlink += g.size;
+                        if same_device {
+                            savings.hardlink += g.size;
+                            savings.reflink += g.size;
+                        }
+                    }
+                    if same_device { Method::Hardlink } else { Method::CrossDevice }
+                }
+                _ => Method::Unknown,
+            };
+            MemberLink { path: p.clone(), method }
+        })
+        .collect();
+    GroupLinks { size: g.size, canonical: g.members[0].clone(), members, savings }
+}
+
+/// `classify` over every group, with the savings summed.
+pub fn report(groups: &[DuplicateGroup]) -> (Vec<GroupLinks>, Savings) {
+    let report: Vec<GroupLinks> = groups.iter().map(classify).collect();
+    let mut total = Savings::default();
+    for g in &report {
+        total.add(&g.savings);
+    }
+    (report, total)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[cfg(unix)]
+    fn members_are_classified_against_the_canonical() {
+        let dir = std::env::temp_dir().join(format!("ducky_linkable_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (a, b, c) = (dir.join("a"), dir.join("b"), dir.join("c"));
+        std::fs::write(&a, b"data").unwrap();
+        std::fs::hard_link(&a, &b).unwrap();
+        std::fs::write(&c, b"data").unwrap();
+
+        let g = DuplicateGroup::new(4, vec![a.clone(), b.clone(), c.clone(), dir.join("gone")]);
+        let links = classify(&g);
+        let methods: Vec<Method> = links.members.iter().map(|m| m.method).collect();
+        assert_eq!(methods, vec![Method::Linked, Method::Hardlink, Method::Unknown]);
+        let savings = Savings { delete: 12, hardlink: 4, reflink: 4, symlink: 4 };
+        assert_eq!(links.savings, savings);
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
@@ -1,25 +1,46 @@
-//! Orchestration of the deduping pipeline: parse → collect → group → hash → aggregate → output → actions.
+//! The `ducky` command line: parse → scan (the library's `Scanner`) → aggregate → output →
+//! actions.
 
-mod actions;
 mod cli;
-mod fs_utils;
-mod grouping;
-mod hashing;
-mod output;
 
-use actions::{apply_actions, ActionStats};
 use anyhow::{Context, Result};
 use bytesize::ByteSize;
-use clap::Parser;
-use std::collections::BTreeMap;
 use std::fs;
-use std::time::Instant;
+use std::io::{BufWriter, IsTerminal, Write};
+use std::sync::atomic::Ordering;
+use std::time::{Duration, Instant};
 
-use cli::Opts;
-use fs_utils::{collect_files, parse_exts};
-use grouping::group_by_size;
-use hashing::{full_hash, quick_hash};
-use output::{print_human, print_json, DuplicateGroup};
+use clap::CommandFactory;
+use cli::{Opts, ScanOpts};
+#[cfg(feature = "parquet")]
+use ducky::columnar;
+#[cfg(feature = "media")]
+use ducky::media;
+#[cfg(feature = "tui")]
+use ducky::tui;
+#[cfg(feature = "media")]
+use ducky::output::write_near_human;
+#[cfg(feature = "decompress")]
+use ducky::{compressed, output::write_compressed_human};
+use ducky::actions::{apply_actions, ActionOptions, ActionStats};
+use ducky::budget::{self, Budget, Errors};
+use ducky::cache::HashCache;
+use ducky::fs_utils::{self, normalize_roots, parse_exts};
+use ducky::hashing::{self, HashSettings};
+use ducky::rng::Rng;
+use ducky::output::{
+    escaped, has_control_chars, print_panel, print_summary_line, write_badge,
+    write_case_conflicts_human, write_dirs_human, write_fdupes, write_heatmap_human, write_human,
+    write_json, write_link_report_human, write_unique_human, write_unique_json, DuplicateGroup,
+    Format, CANONICAL_POLICY,
+};
+use ducky::warnings::{self, warning};
+use ducky::{
+    audit, capabilities, catalog, conflicts, copy, decisions, dirs, doctor, estimate,
+    fixture, heatmap, interactive, journal, linkable, pairs, pick, quarantine, rawpairs,
+    reference, replicate, reports, saved, schedule, sentinel, snapshots, stats, status, undo,
+    unique, watch, ScanOptions, ScanResult, Scanner,
+};
 
 #[derive(serde::Serialize)]
 struct Timings {
@@ -28,123 +49,432 @@ struct Timings {
     quick_hash_ms: u64,
     full_hash_ms: u64,
     actions_ms: u64,
+    predicted_duplicates: u64,
+    predicted_hash_bytes: u64,
+    hashed_bytes: u64,
+    /// Two-file buckets compared directly instead of hashed.
+    pairs: pairs::PairStats,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    by_extension: Vec<stats::ExtCost>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    actions_by_device: Vec<DeviceTiming>,
 }
 
-fn main() -> Result<()> {
-    let opts = Opts::parse();
-    let exts = parse_exts(opts.ext.as_deref());
-    let t0 = Instant::now();
-
-    let files = collect_files(
-        &opts.paths,
-        opts.hidden,
-        opts.follow_symlinks,
-        opts.min_size.as_u64(),
-        exts.as_ref(),
-    )
-    .context("collecting files failed")?;
-    let t1 = Instant::now();
+/// Time spent applying actions to the groups on one device.
+#[derive(serde::Serialize)]
+struct DeviceTiming {
+    device: String,
+    groups: usize,
+    ms: u64,
+}
 
-    if files.is_empty() {
-        println!("No files matched criteria.");
+fn main() -> Result<()> {
+    let opts = cli::parse()?;
+    if opts.capabilities {
+        let mut caps = capabilities::Capabilities::detect();
+        let cmd = cli::Opts::command();
+        let shown = cmd.get_subcommands().filter(|s| !s.is_hide_set());
+        caps.subcommands = shown.map(|s| s.get_name().to_string()).collect();
+        println!("{}", serde_json::to_string_pretty(&caps)?);
         return Ok(());
     }
-
-    let total_size: u64 = files
-        .iter()
-        .map(|p| fs::metadata(p).map(|m| m.len()).unwrap_or(0))
-        .sum();
-
-    // Human-only section: don't print in JSON or summary-json modes
-    let human_mode = !opts.json && !opts.summary_json;
-    if human_mode {
-        if opts.list {
-            for p in &files {
-                println!("{}", p.display());
+    match &opts.command {
+        Some(cli::Command::Scan(scan)) => run_scan(scan),
+        Some(cli::Command::Report(scan)) => {
+            if modifying(scan) {
+                anyhow::bail!("`ducky report` never modifies files; use `ducky scan` to act");
             }
-            println!();
+            run_scan(scan)
         }
-
-        if !opts.quiet {
-            println!(
-                "Matched {} files (>= {}) totaling {}",
-                files.len(),
-                ByteSize(opts.min_size.as_u64()),
-                ByteSize(total_size)
-            );
+        Some(cli::Command::Completions { shell }) => {
+            // clap_complete panics on a failed write; buffer so a closed pipe is an error.
+            let mut script = Vec::new();
+            clap_complete::generate(*shell, &mut Opts::command(), "ducky", &mut script);
+            std::io::stdout().write_all(&script).context("write completion script")
+        }
+        Some(cli::Command::Doctor(d)) => doctor::run(&d.paths, d.json),
+        Some(cli::Command::Catalog { action }) => run_catalog(action),
+        Some(cli::Command::Cache { action }) => run_cache(action),
+        Some(cli::Command::Undo { yes }) => {
+            exit_on_errors(undo::run(*yes)?.errors);
+            Ok(())
+        }
+        Some(cli::Command::Apply { file, from_csv, delete, trash, yes, dry_run, verify }) => {
+            match (file, from_csv) {
+                (Some(file), _) if !delete && !trash => {
+                    anyhow::bail!("choose an action for {}: --delete or --trash", file.display())
+                }
+                (Some(file), _) => {
+                    exit_on_errors(saved::run(file, *trash, *yes, *dry_run, *verify)?.errors);
+                    Ok(())
+                }
+                (None, Some(csv)) => {
+                    exit_on_errors(decisions::run(csv, *trash, *yes, *dry_run, *verify)?.errors);
+                    Ok(())
+                }
+                (None, None) => unreachable!("clap requires FILE or --from-csv"),
+            }
+        }
+        Some(cli::Command::Sentinel { dir, against, min_size, trash, yes, dry_run, json }) => {
+            let stats =
+                sentinel::run(dir, against, min_size.as_u64(), *trash, *yes, *dry_run, *json)?;
+            exit_on_errors(stats.errors);
+            Ok(())
         }
+        Some(cli::Command::Cp { src, dst, link, dry_run }) => {
+            exit_on_errors(copy::run(src, dst, *link, *dry_run)?.errors);
+            Ok(())
+        }
+        Some(cli::Command::GenFixture { spec, dir }) => fixture::run(spec, dir),
+        Some(cli::Command::Quarantine { action }) => match action {
+            cli::QuarantineCommand::Purge { dir } => quarantine::run_purge(dir),
+        },
+        None => run_scan(&opts.scan),
     }
+}
 
-    // In JSON/summary modes, do not print any human text to stdout.
-    // Warnings/timings still go to stderr via eprintln!.
+/// Exit with code 1 when a command could not do all of its work.
+fn exit_on_errors(errors: usize) {
+    if errors > 0 {
+        std::process::exit(1);
+    }
+}
 
-    // Stage 1: by size
-    let by_size = group_by_size(&files);
-    let t2 = Instant::now();
+/// Whether the scan modifies files (any action flag).
+fn modifying(opts: &ScanOpts) -> bool {
+    opts.delete
+        || opts.trash
+        || opts.hardlink
+        || opts.reflink
+        || opts.dedupe_extents
+        || opts.symlink
+        || opts.replicate.is_some()
+        || opts.move_to.is_some()
+}
 
+/// `ducky scan` (and the flat `ducky PATHS...`): the whole pipeline.
+fn run_scan(opts: &ScanOpts) -> Result<()> {
+    let embed_warnings = opts.embed_warnings && (opts.json || opts.summary_json);
+    if embed_warnings {
+        warnings::capture();
+    }
+    let mut rng = Rng::from_seed_or_clock(opts.seed);
+    // Group listings go to --groups-out when given, otherwise to stdout.
+    let mut groups_out: Box<dyn Write + Send> = match &opts.groups_out {
+        Some(p) => Box::new(BufWriter::new(
+            fs::File::create(p).with_context(|| format!("create {}", p.display()))?,
+        )),
+        None => Box::new(std::io::stdout()),
+    };
+    let exts = parse_exts(opts.ext.as_deref());
+    let max_size = opts.max_size.map(|b| b.as_u64());
+    if max_size.is_some_and(|max| max < opts.min_size.as_u64()) {
+        anyhow::bail!("--max-size must not be smaller than --min-size");
+    }
+    if opts.max_depth.is_some_and(|max| max < opts.min_depth) {
+        anyhow::bail!("--max-depth must not be smaller than --min-depth");
+    }
+    let t0 = Instant::now();
+    status::install();
+
+    // Progress is for people watching a terminal, never for JSON consumers.
+    let show_progress = !opts.no_progress
+        && !opts.quiet
+        && !opts.json
+        && !opts.summary_json
+        && std::io::stderr().is_terminal();
     // Validate quick-bytes: clamp to [1 KiB, 1 GiB]
     let mut limit = opts.quick_bytes.as_u64();
     const MIN_QB: u64 = 1024; // 1 KiB
     const MAX_QB: u64 = 1024 * 1024 * 1024; // 1 GiB
     if limit < MIN_QB {
-        eprintln!(
+        warning!(
             "--quick-bytes too small ({}); clamping to {}",
             ByteSize(limit),
             ByteSize(MIN_QB)
         );
         limit = MIN_QB;
     } else if limit > MAX_QB {
-        eprintln!(
+        warning!(
             "--quick-bytes too large ({}); clamping to {}",
             ByteSize(limit),
             ByteSize(MAX_QB)
         );
         limit = MAX_QB;
     }
+    let sample = opts.quick_sample;
+    let threads = opts.threads.map_or_else(schedule::default_threads, |n| n as usize);
 
-    let mut groups: Vec<DuplicateGroup> = Vec::new();
-    let mut reclaimable: u64 = 0;
+    // A reference tree reached under a scanned root's other name would have its files taken
+    // for copies outside the reference, and acted on.
+    let (scan_roots, compare_roots) = (normalize_roots(&opts.paths), normalize_roots(&opts.compare));
+    for root in &scan_roots {
+        let Some(id) = fs_utils::dir_id(root) else { continue };
+        if let Some(r) = compare_roots.iter().find(|r| fs_utils::dir_id(r) == Some(id)) {
+            anyhow::bail!(
+                "--compare {} is the same directory as the scanned {}; scan it only once",
+                escaped(r),
+                escaped(root)
+            );
+        }
+    }
+    let (roots, root_aliases) =
+        fs_utils::split_aliases(normalize_roots(&[scan_roots, compare_roots].concat()));
+    for (root, alias) in &root_aliases {
+        warning!(
+            "{} is the same directory as {} (bind mount?); scanning it once",
+            escaped(alias),
+            escaped(root)
+        );
+    }
+    let reference = reference::Reference::new(&opts.compare)?;
+    let scanner = Scanner::new(ScanOptions {
+        roots: roots.clone(),
+        hidden: opts.hidden,
+        follow_symlinks: opts.follow_symlinks,
+        bounds: fs_utils::Bounds {
+            min_size: opts.min_size.as_u64(),
+            max_size,
+            modified_after: opts.modified_after,
+            modified_before: opts.modified_before,
+            min_depth: opts.min_depth,
+            max_depth: opts.max_depth,
+        },
+        exts,
+        excludes: opts.exclude.clone(),
+        regexes: fs_utils::PathRegex {
+            include: opts.path_regex.clone(),
+            exclude: opts.path_regex_exclude.clone(),
+        },
+        quick_bytes: limit,
+        quick_sample: sample,
+        threads,
+        schedule: opts.schedule,
+        // A stage that reads the whole scanned list rules out spilling it.
+        max_memory: opts.max_memory.map(|m| m.as_u64()).filter(|_| !keeps_file_list(opts)),
+        reference: reference.clone(),
+        cache: !opts.no_cache,
+        progress: show_progress,
+        tolerate_metadata_errors: opts.tolerate_metadata_errors,
+    });
+    let mut files = scanner.collect()?;
+    let budget = Budget { max_errors: opts.max_errors, max_rate: opts.max_error_rate };
+    let found = files.len();
+    enforce(&budget, scanner.errors(), found);
+    // Reports written by this run or an earlier one are ducky's own output, never candidates.
+    let outputs: Vec<&std::path::PathBuf> =
+        [&opts.groups_out, &opts.save, &opts.csv_out, &opts.action_log, &opts.badge_json]
+            .into_iter()
+            .flatten()
+            .collect();
+    let reports_list = reports::default_path();
+    let mut known = reports_list.as_deref().map(reports::load).unwrap_or_default();
+    known.extend(outputs.iter().filter_map(|p| fs::canonicalize(p).ok()));
+    let own = reports::Reports::new(known);
+    files.retain(|f| !own.contains(&f.path));
+    let t1 = Instant::now();
 
-    // Stage 2: by quick hash (for all size buckets)
-    let mut quick_buckets: Vec<(u64, BTreeMap<String, Vec<&std::path::PathBuf>>)> = Vec::new();
-    for (size, paths) in by_size.iter().filter(|(_, v)| v.len() > 1) {
-        let mut by_qh: BTreeMap<String, Vec<&std::path::PathBuf>> = BTreeMap::new();
-        for p in paths {
-            match quick_hash(p, limit) {
-                Ok(h) => by_qh.entry(h).or_default().push(p),
-                Err(e) => eprintln!("quick-hash failed {}: {}", p.display(), e),
+    if files.is_empty() {
+        println!("No files matched criteria.");
+        return Ok(());
+    }
+
+    let total_size: u64 = files.iter().map(|f| f.meta.size).sum();
+
+    // Human-only section: don't print in JSON or summary-json modes, except that a JSON
+    // listing sent to --groups-out leaves stdout to the human summary.
+    // The same goes for the fdupes layout, which scripts parse from stdout.
+    let plain_listing = opts.json || opts.format != Format::Human;
+    let human_mode = !opts.summary_json && (!plain_listing || opts.groups_out.is_some());
+    let listing_on_stdout = opts.groups_out.is_none() && !opts.quiet;
+    // Names shown shell-quoted in the human output, for one warning once it's all written.
+    let mut quoted = 0;
+    if human_mode {
+        if opts.list {
+            for f in &files {
+                quoted += usize::from(has_control_chars(&f.path));
+                println!("{}", escaped(&f.path));
             }
+            println!();
+        }
+
+        if !opts.quiet {
+            let bounds = match max_size {
+                Some(max) => format!(">= {}, <= {}", ByteSize(opts.min_size.as_u64()), ByteSize(max)),
+                None => format!(">= {}", ByteSize(opts.min_size.as_u64())),
+            };
+            println!("Matched {} files ({}) totaling {}", files.len(), bounds, ByteSize(total_size));
         }
-        quick_buckets.push((*size, by_qh));
     }
-    let t3 = Instant::now();
 
-    // Stage 3: by full hash (for all quick-hash buckets)
-    for (size, by_qh) in quick_buckets.into_iter() {
-        for (_qh, bucket) in by_qh.into_iter().filter(|(_, v)| v.len() > 1) {
-            let mut by_fh: BTreeMap<String, Vec<&std::path::PathBuf>> = BTreeMap::new();
-            for p in bucket {
-                match full_hash(p) {
-                    Ok(h) => by_fh.entry(h).or_default().push(p),
-                    Err(e) => eprintln!("full-hash failed {}: {}", p.display(), e),
-                }
-            }
+    // In JSON/summary modes, do not print any human text to stdout.
+    // Warnings go to stderr unless --embed-warnings; timings always go to stderr.
 
-            for (_fh, dupes) in by_fh.into_iter().filter(|(_, v)| v.len() > 1) {
-                let members: Vec<_> = dupes.into_iter().cloned().collect();
-                reclaimable = reclaimable
-                    .saturating_add(size * ((members.len() as u64).saturating_sub(1)));
-                groups.push(DuplicateGroup::new(size, members));
-            }
+    // Needs the scanned list, which grouping may spill to disk.
+    let case_conflicts = if opts.case_conflicts {
+        conflicts::case_conflicts(files.iter().map(|f| f.path.as_path()), &roots)
+    } else {
+        Vec::new()
+    };
+    let dir_totals = if opts.heatmap {
+        heatmap::dir_totals(files.iter().map(|f| (f.path.as_path(), f.meta.size)))
+    } else {
+        std::collections::HashMap::new()
+    };
+
+    // Stage 1: by size; the hashing stages follow once the watcher is set up.
+    let files_scanned = files.len();
+    let candidates = scanner.group(&mut files)?;
+    let t2 = Instant::now();
+
+    // Watch candidate directories so files changed before actions run are re-verified.
+    let modifying = modifying(opts);
+    // --interactive, --triage and --tui stand in for --yes, but only where they can ask.
+    let reviewing = opts.interactive || opts.triage.is_some() || tui_requested(opts);
+    let interactive = reviewing && interactive::available();
+    if reviewing && !interactive {
+        warning!("--interactive/--triage/--tui need a terminal on stdin and stderr; nothing will be modified");
+    }
+    let yes = opts.yes || interactive;
+    let mut watcher = if modifying && yes {
+        watch::ChangeWatcher::new(candidates.dirs())
+    } else {
+        None
+    };
+
+    let mut scan = scanner.hash(&candidates);
+    if !scan.degraded.is_empty() {
+        warning!(
+            "{} files had unreadable metadata and were scanned by size alone (no inode or \
+             mtime checks); they are listed but never acted on",
+            scan.degraded.len()
+        );
+    }
+    // Taken before the thresholds below: a copy in a small group is still a copy.
+    let unique = opts.unique.then(|| unique::unique_files(&files, &scan.groups));
+    let min_members = opts.min_group_size.unwrap_or(2) as usize;
+    let min_reclaim = opts.min_reclaim.map_or(0, |s| s.as_u64());
+    let below_threshold = scan.retain(|g| {
+        g.members.len() >= min_members && estimate::upper_bound(g) >= min_reclaim
+    });
+    let mut snapshots = snapshots::Snapshots::default();
+    let in_snapshots: std::collections::HashSet<std::path::PathBuf> = scan
+        .groups
+        .iter()
+        .flat_map(|g| &g.members)
+        .filter(|p| snapshots.contains(p))
+        .cloned()
+        .collect();
+    // A live copy is kept rather than its snapshot; in compare mode the reference stays first.
+    if !in_snapshots.is_empty() && reference.is_empty() {
+        for g in scan.groups.iter_mut() {
+            g.rank_members(|p| in_snapshots.contains(p));
         }
     }
+    let (snapshot_files, snapshot_bytes) = scan.pin_members(|p| in_snapshots.contains(p));
+    if snapshot_files > 0 {
+        warning!(
+            "{} copies ({}) are inside read-only btrfs/ZFS snapshots and can't be changed; \
+             they are never acted on and left out of the reclaimable totals",
+            snapshot_files,
+            ByteSize(snapshot_bytes)
+        );
+    }
+    // Nothing has been reported or modified yet: stop here rather than act on a partial scan.
+    enforce(&budget, scanner.errors(), found);
     let t4 = Instant::now();
+    let ScanResult {
+        mut groups,
+        reclaimable,
+        reclaimable_realistic,
+        prediction,
+        hashed_bytes,
+        pairs,
+        by_extension,
+        renames,
+        suspect_media,
+        degraded,
+        quick_hash_time,
+        full_hash_time,
+    } = scan;
+    let cache = scanner.cache();
+    let act_only_under: Vec<std::path::PathBuf> = opts
+        .act_only_under
+        .iter()
+        .map(|d| fs::canonicalize(d).with_context(|| format!("--act-only-under {}", d.display())))
+        .collect::<Result<_>>()?;
+    // Before anything is listed or exported, so every output names the same canonical.
+    if opts.raw_jpeg_pairs {
+        rawpairs::pair_up(&mut groups, &reference);
+    }
+    let policy = canonical_policy(opts, &reference, &act_only_under, !in_snapshots.is_empty());
+    if !(act_only_under.is_empty()
+        && opts.protect.is_empty()
+        && opts.prefer.is_empty()
+        && opts.avoid.is_empty())
+    {
+        for g in groups.iter_mut() {
+            g.rank_members(|p| canonical_rank(opts, &reference, &act_only_under, p));
+        }
+    }
 
+    if let Ok(dir) = catalog::catalog_dir() {
+        let catalogs = catalog::load_all(&dir);
+        if !catalogs.is_empty() {
+            catalog::annotate(&mut groups, &catalogs);
+        }
+    }
+
+    // Whether `paths` all belong to one exact duplicate group, for the secondary categories.
+    #[cfg(any(feature = "media", feature = "decompress"))]
+    let in_group: std::collections::HashMap<&std::path::PathBuf, usize> = groups
+        .iter()
+        .enumerate()
+        .flat_map(|(i, g)| g.members.iter().map(move |m| (m, i)))
+        .collect();
+    #[cfg(any(feature = "media", feature = "decompress"))]
+    let exact = |paths: &[std::path::PathBuf]| {
+        let first = in_group.get(&paths[0]);
+        first.is_some() && paths.iter().all(|p| in_group.get(p) == first)
+    };
+    #[cfg(feature = "media")]
+    let near_groups: Option<Vec<media::NearDuplicateGroup>> =
+        opts.video_headless.then(|| media::near_duplicates(&files, threads, exact));
+    #[cfg(feature = "decompress")]
+    let compressed_groups: Option<Vec<compressed::CompressedGroup>> =
+        (!opts.decompress.is_empty()).then(|| {
+            compressed::compressed_duplicates(&files, &opts.decompress, threads, cache, exact)
+        });
+
+    if let Some(path) = &opts.csv_out {
+        decisions::export(path, &groups, opts.digest_format)?;
+    }
+    if let Some(path) = &opts.save {
+        saved::save(path, &groups)?;
+    }
+    let dir_groups = if opts.dirs { dirs::find(&groups, &roots) } else { Vec::new() };
+    // Read before any action runs, so it describes the files as they were found.
+    let link_report = opts.link_report.then(|| linkable::report(&groups));
+    let savings = link_report.as_ref().map_or_else(|| linkable::report(&groups).1, |(_, s)| *s);
     let files_in_groups: usize = groups.iter().map(|g| g.members.len()).sum();
-    if opts.json {
-        print_json(&groups);
+    let depth_stats = stats::by_depth(&groups, &roots);
+    let heat = if opts.heatmap { heatmap::heatmap(&dir_totals, &groups) } else { Vec::new() };
+    if let (true, Some(unique)) = (opts.json, &unique) {
+        if !embed_warnings {
+            write_unique_json(&mut groups_out, unique, None)?;
+        }
+    } else if opts.json {
+        // With embedded warnings the array is printed after actions, inside an envelope.
+        if !embed_warnings {
+            let (detail, digest) = (opts.json_detail, opts.digest_format);
+            write_json(&mut groups_out, &groups, None, detail, digest, &policy)?;
+        }
     } else if opts.summary_json {
-        // A single summary JSON object is printed after actions.
+        // A single summary JSON object is printed after actions; --groups-out gets the array.
+        if opts.groups_out.is_some() {
+            let (detail, digest) = (opts.json_detail, opts.digest_format);
+            write_json(&mut groups_out, &groups, None, detail, digest, &policy)?;
+        }
     } else {
         // Sort groups by descending reclaimable bytes, then by size, then by first member
         let mut groups_sorted = groups.clone();
@@ -164,59 +494,593 @@ fn main() -> Result<()> {
                     a0.cmp(&b0)
                 })
         });
-        if !opts.quiet {
-            print_human(&groups_sorted, reclaimable);
-        } else if !groups_sorted.is_empty() {
-            println!(
-                "Found {} duplicate groups; reclaimable: {}",
-                groups_sorted.len(),
-                ByteSize(reclaimable)
-            );
+        match opts.format {
+            Format::Fdupes => write_fdupes(&mut groups_out, &groups_sorted)?,
+            #[cfg(feature = "parquet")]
+            Format::Parquet => {
+                columnar::write_parquet(&mut groups_out, &groups_sorted, opts.digest_format)?
+            }
+            Format::Human if opts.groups_out.is_some() || !opts.quiet => {
+                write_dirs_human(&mut groups_out, &dir_groups)?;
+                groups_sorted.retain(|g| !dirs::covered(g, &dir_groups));
+                if let Some(unique) = &unique {
+                    write_unique_human(&mut groups_out, unique)?;
+                } else if opts.link_report {
+                    write_link_report_human(&mut groups_out, &linkable::report(&groups_sorted).0)?;
+                } else {
+                    write_human(&mut groups_out, &groups_sorted, opts.max_members_shown)?;
+                }
+                quoted += groups_sorted
+                    .iter()
+                    .flat_map(|g| &g.members)
+                    .filter(|p| has_control_chars(p))
+                    .count();
+                write_case_conflicts_human(&mut groups_out, &case_conflicts)?;
+                write_heatmap_human(&mut groups_out, &heat, heatmap::SHOWN)?;
+                #[cfg(feature = "media")]
+                write_near_human(&mut groups_out, near_groups.as_deref().unwrap_or_default())?;
+                #[cfg(feature = "decompress")]
+                write_compressed_human(
+                    &mut groups_out,
+                    compressed_groups.as_deref().unwrap_or_default(),
+                )?;
+            }
+            Format::Human => {}
         }
     }
+    if quoted > 0 {
+        warning!(
+            "{} paths contain control characters and are shown quoted as $'...'; use --json for \
+             the exact names",
+            quoted
+        );
+    }
 
     // Side effects last, and only on explicit opt-in
-    let action_stats: ActionStats = apply_actions(&groups, opts.delete, opts.hardlink, opts.yes);
+    if let Some(w) = watcher.as_mut() {
+        let changes = w.changes();
+        if !changes.is_empty() {
+            for g in groups.iter_mut() {
+                g.stale = g.members.iter().any(|p| changes.affects(p));
+            }
+            let stale = groups.iter().filter(|g| g.stale).count();
+            if stale > 0 {
+                warning!("{} groups changed after hashing; re-verifying before acting", stale);
+            }
+        }
+    }
+    status::set_stage(status::Stage::Act);
+    // A dry run never records anything: there is nothing to undo or audit.
+    let mut journal = if modifying && yes && !opts.dry_run {
+        journal::Journal::open_default()
+    } else {
+        journal::Journal::in_memory()
+    };
+    if let Some(path) = &opts.action_log {
+        journal.log_attempts_to(path)?;
+    }
+    let mut action_opts = ActionOptions {
+        delete: opts.delete,
+        trash: opts.trash,
+        hardlink: opts.hardlink,
+        reflink: opts.reflink,
+        dedupe_extents: opts.dedupe_extents,
+        symlink: opts.symlink,
+        symlink_relative: opts.symlink_relative,
+        symlink_fallback_hardlink: opts.symlink_fallback_hardlink,
+        yes,
+        dry_run: opts.dry_run,
+        paranoid: opts.paranoid,
+        verify: opts.verify,
+        act_only_under,
+        protect: opts.protect.clone(),
+        reference: reference.dirs().to_vec(),
+        snapshots: in_snapshots,
+        held_pairs: Default::default(),
+        keep_copies: opts.keep_copies.unwrap_or(1) as usize,
+        errors: scanner.errors().clone(),
+        budget,
+        files_found: found as u64,
+    };
+    let targets: Vec<DuplicateGroup> = if opts.pick && modifying {
+        pick::pick(&groups, &opts.picker)?
+    } else {
+        if opts.pick {
+            warning!("--pick has no effect without an action (--delete, --trash, --hardlink, --reflink, --dedupe-extents, --symlink, --move-to, --replicate)");
+        }
+        groups.clone()
+    };
+    let targets = if interactive { review(opts, &targets)? } else { targets };
+    // On what will really be acted on: review and --keep-copies decide which halves stay.
+    if opts.raw_jpeg_pairs {
+        action_opts.held_pairs = rawpairs::split_pairs(&targets, action_opts.keep_copies);
+    }
+    let journal_start = journal.len();
+    let apply = |batch: &[DuplicateGroup], journal: &mut journal::Journal| -> ActionStats {
+        if let Some(n) = opts.replicate {
+            replicate::apply_replicate(batch, n as usize, &roots, &action_opts, journal)
+        } else if let Some(dir) = &opts.move_to {
+            quarantine::apply_quarantine(batch, dir, opts.expire_after, &action_opts, journal)
+        } else {
+            apply_actions(batch, &action_opts, journal)
+        }
+    };
+    // Apply one device at a time so seek-bound disks aren't interleaved with fast ones.
+    let mut action_stats = ActionStats::default();
+    let mut device_timings: Vec<DeviceTiming> = Vec::new();
+    if modifying && (yes || opts.dry_run) && !targets.is_empty() {
+        for batch in schedule::by_device(&targets) {
+            if action_opts.over_budget().is_some() {
+                break;
+            }
+            let started = Instant::now();
+            action_stats.add(&apply(&batch.groups, &mut journal));
+            device_timings.push(DeviceTiming {
+                device: schedule::device_label(batch.device),
+                groups: batch.groups.len(),
+                ms: started.elapsed().as_millis() as u64,
+            });
+        }
+        action_stats.report();
+    } else if modifying && targets.is_empty() && !candidates.links.is_empty() {
+        // Every copy found is a hard link of another: an earlier --hardlink run, for example.
+        let n: usize = candidates.links.values().map(Vec::len).sum();
+        eprintln!("nothing to do: {} files are already hard links of other files in the scan", n);
+    } else {
+        action_stats = apply(&targets, &mut journal);
+    }
+    if modifying && opts.dry_run {
+        eprintln!("dry-run: would free {}", ByteSize(action_stats.freed_bytes));
+    }
     let t5 = Instant::now();
+    status::set_stage(status::Stage::Report);
+
+    let audit_report = match opts.audit_sample {
+        Some(fraction) if modifying && yes && !opts.dry_run => {
+            let report = audit::audit(journal.entries_since(journal_start), fraction, &mut rng);
+            for f in &report.failures {
+                warning!("audit: {}", f);
+            }
+            Some(report)
+        }
+        Some(_) => {
+            warning!("--audit-sample has no effect without an action and --yes");
+            None
+        }
+        None => None,
+    };
+
+    if let Some(path) = &opts.badge_json {
+        // What is still reclaimable once this run's actions are done.
+        let freed = if opts.dry_run { 0 } else { action_stats.freed_bytes };
+        write_badge(path, reclaimable_realistic.saturating_sub(freed))
+            .with_context(|| format!("write {}", path.display()))?;
+    }
+    if let Some(list) = reports_list.filter(|_| !outputs.is_empty()) {
+        if let Err(e) = reports::record(&list, &outputs) {
+            warning!("{:#}; later scans may list this run's reports", e);
+        }
+    }
+
+    let timings = Timings {
+        discover_ms: (t1 - t0).as_millis() as u64,
+        size_group_ms: (t2 - t1).as_millis() as u64,
+        quick_hash_ms: quick_hash_time.as_millis() as u64,
+        full_hash_ms: full_hash_time.as_millis() as u64,
+        actions_ms: (t5 - t4).as_millis() as u64,
+        predicted_duplicates: prediction.duplicates,
+        predicted_hash_bytes: prediction.total(),
+        hashed_bytes,
+        pairs,
+        by_extension,
+        actions_by_device: device_timings,
+    };
+
+    if opts.json && embed_warnings {
+        let (warnings, digest) = (warnings::take(), opts.digest_format);
+        match &unique {
+            Some(unique) => write_unique_json(&mut groups_out, unique, Some(&warnings))?,
+            None => {
+                let warnings = Some(&warnings[..]);
+                write_json(&mut groups_out, &groups, warnings, opts.json_detail, digest, &policy)?
+            }
+        }
+    }
+    groups_out.flush().context("writing group listing failed")?;
+
+    // Final human summary (after actions so errors are included)
+    if human_mode {
+        if opts.no_fancy {
+            if !groups.is_empty() {
+                if listing_on_stdout {
+                    println!();
+                }
+                print_summary_line(groups.len(), reclaimable_realistic, reclaimable);
+            }
+            if let Some(a) = &audit_report {
+                println!(
+                    "Audit: {} of {} groups, {} paths checked, {} failures (seed {})",
+                    a.sampled_groups,
+                    a.processed_groups,
+                    a.checked_paths,
+                    a.failures.len(),
+                    a.seed
+                );
+            }
+        } else {
+            if listing_on_stdout && !groups.is_empty() {
+                println!();
+            }
+            let mut rows = vec![
+                ("files scanned", files_scanned.to_string()),
+                ("candidates", candidates.count().to_string()),
+                ("duplicate groups", groups.len().to_string()),
+                ("files in groups", files_in_groups.to_string()),
+                (
+                    "reclaimable",
+                    format!("{} (upper bound {})", ByteSize(reclaimable_realistic), ByteSize(reclaimable)),
+                ),
+                (
+                    "time",
+                    format!(
+                        "discover {}ms · size {}ms · quick {}ms · full {}ms · actions {}ms",
+                        timings.discover_ms,
+                        timings.size_group_ms,
+                        timings.quick_hash_ms,
+                        timings.full_hash_ms,
+                        timings.actions_ms
+                    ),
+                ),
+                ("errors", action_stats.errors.to_string()),
+                (
+                    if opts.dry_run { "would free" } else { "freed" },
+                    ByteSize(action_stats.freed_bytes).to_string(),
+                ),
+                (
+                    "hashing",
+                    format!("{}, quick {} {}", hashing::ALGORITHM, ByteSize(limit), sample.name()),
+                ),
+                (
+                    "hash cache",
+                    if opts.no_cache {
+                        "off".to_string()
+                    } else {
+                        format!(
+                            "{} hits, {} misses, {} renamed",
+                            cache.hits.load(Ordering::Relaxed),
+                            cache.misses.load(Ordering::Relaxed),
+                            renames.len()
+                        )
+                    },
+                ),
+            ];
+            if !degraded.is_empty() {
+                rows.push(("degraded files", degraded.len().to_string()));
+            }
+            if let Some(unique) = &unique {
+                let bytes = unique.iter().map(|f| f.meta.size).sum::<u64>();
+                rows.push(("unique files", format!("{} ({})", unique.len(), ByteSize(bytes))));
+            }
+            if snapshot_files > 0 {
+                let what = format!("{} ({})", snapshot_files, ByteSize(snapshot_bytes));
+                rows.push(("snapshot copies", format!("{} never acted on", what)));
+            }
+            if below_threshold > 0 {
+                rows.push(("below thresholds", format!("{} groups not shown", below_threshold)));
+            }
+            for (root, alias) in &root_aliases {
+                let also = format!("{} = {}", escaped(root), escaped(alias));
+                rows.push(("root alias", also));
+            }
+            if let Some(d) = depth_stats.iter().max_by_key(|d| (d.bytes, std::cmp::Reverse(d.depth))) {
+                rows.push((
+                    "busiest depth",
+                    format!("{} ({} under {})", d.depth, ByteSize(d.top_dir_bytes), d.top_dir.display()),
+                ));
+            }
+            #[cfg(feature = "media")]
+            if let Some(near) = &near_groups {
+                rows.push(("near-duplicates", near.len().to_string()));
+            }
+            #[cfg(feature = "decompress")]
+            if let Some(groups) = &compressed_groups {
+                rows.push(("compressed duplicates", groups.len().to_string()));
+            }
+            if opts.dirs {
+                rows.push(("identical dirs", dir_groups.len().to_string()));
+            }
+            if opts.case_conflicts {
+                rows.push(("case conflicts", case_conflicts.len().to_string()));
+            }
+            if let Some(h) = heat.first() {
+                let share = format!("{:.0}% of {}", h.ratio * 100.0, ByteSize(h.total_bytes));
+                rows.push(("densest dir", format!("{} ({})", escaped(&h.dir), share)));
+            }
+            if !groups.is_empty() {
+                let freed = |b: u64| ByteSize(b).to_string();
+                rows.push((
+                    "by action",
+                    format!(
+                        "delete {} · hardlink/reflink {} · symlink/move {}",
+                        freed(savings.delete),
+                        freed(savings.hardlink),
+                        freed(savings.symlink)
+                    ),
+                ));
+            }
+            if let Some(a) = &audit_report {
+                rows.push((
+                    "audit",
+                    format!(
+                        "{} of {} groups, {} paths, {} failures (seed {})",
+                        a.sampled_groups,
+                        a.processed_groups,
+                        a.checked_paths,
+                        a.failures.len(),
+                        a.seed
+                    ),
+                ));
+            }
+            for d in &suspect_media {
+                let what = format!("{} read errors, {} files skipped", d.errors, d.skipped);
+                rows.push(("failing media?", format!("device {}: {}", d.device, what)));
+            }
+            print_panel("ducky summary", &rows);
+        }
+    }
 
     // Emit summary JSON if requested (after actions to include errors and timings)
     if opts.summary_json {
-        let timings: Option<Timings> = if opts.timings {
-            Some(Timings {
-                discover_ms: (t1 - t0).as_millis() as u64,
-                size_group_ms: (t2 - t1).as_millis() as u64,
-                quick_hash_ms: (t3 - t2).as_millis() as u64,
-                full_hash_ms: (t4 - t3).as_millis() as u64,
-                actions_ms: (t5 - t4).as_millis() as u64,
-            })
-        } else {
-            None
-        };
-        let summary = serde_json::json!({
+        let timings: Option<&Timings> = opts.timings.then_some(&timings);
+        let mut summary = serde_json::json!({
             "groups": groups.len(),
             "files": files_in_groups,
             "reclaimable": reclaimable,
+            "reclaimable_realistic": reclaimable_realistic,
             "errors": action_stats.errors,
+            "errors_by_stage": scanner.errors().counts(),
+            "savings": savings,
+            "degraded_files": degraded.len(),
+            "unique_files": unique.as_ref().map(Vec::len),
+            "groups_below_threshold": below_threshold,
+            "snapshot_copies": { "files": snapshot_files, "bytes": snapshot_bytes },
+            "root_aliases": root_aliases
+                .iter()
+                .map(|(root, alias)| serde_json::json!({ "root": root, "alias": alias }))
+                .collect::<Vec<_>>(),
+            "dry_run": opts.dry_run,
+            "freed": action_stats.freed_bytes,
             "timings": timings,
+            "settings": HashSettings::new(limit, sample),
+            "audit": audit_report,
+            "by_depth": depth_stats,
+            "suspect_media": suspect_media,
+            "cache": {
+                "enabled": !opts.no_cache,
+                "hits": cache.hits.load(Ordering::Relaxed),
+                "misses": cache.misses.load(Ordering::Relaxed),
+                "renamed": renames
+                    .iter()
+                    .map(|(from, to)| serde_json::json!({ "from": from, "to": to }))
+                    .collect::<Vec<_>>(),
+            },
         });
+        if opts.dirs {
+            summary["duplicate_dirs"] = serde_json::json!(dir_groups);
+        }
+        if opts.case_conflicts {
+            summary["case_conflicts"] = serde_json::json!(case_conflicts);
+        }
+        if opts.heatmap {
+            summary["heatmap"] = serde_json::json!(heat);
+        }
+        if let Some((groups, savings)) = &link_report {
+            summary["link_report"] = serde_json::json!({ "groups": groups, "savings": savings });
+        }
+        #[cfg(feature = "media")]
+        if let Some(near) = &near_groups {
+            summary["near_duplicates"] = serde_json::json!(near);
+        }
+        #[cfg(feature = "decompress")]
+        if let Some(groups) = &compressed_groups {
+            summary["compressed_duplicates"] = serde_json::json!(groups);
+        }
+        if embed_warnings {
+            summary["warnings"] = serde_json::json!(warnings::take());
+        }
         println!("{}", serde_json::to_string_pretty(&summary)?);
     }
 
     if opts.timings {
         eprintln!(
             "timings: discover_ms={} size_group_ms={} quick_hash_ms={} full_hash_ms={} actions_ms={}",
-            (t1 - t0).as_millis(),
-            (t2 - t1).as_millis(),
-            (t3 - t2).as_millis(),
-            (t4 - t3).as_millis(),
-            (t5 - t4).as_millis()
+            timings.discover_ms,
+            timings.size_group_ms,
+            timings.quick_hash_ms,
+            timings.full_hash_ms,
+            timings.actions_ms
+        );
+        eprintln!(
+            "timings: predicted_duplicates={} predicted_hash_bytes={} hashed_bytes={}",
+            timings.predicted_duplicates, timings.predicted_hash_bytes, timings.hashed_bytes
+        );
+        eprintln!(
+            "timings: pairs_compared={} pairs_differed={} pair_bytes_skipped={}",
+            timings.pairs.compared, timings.pairs.differed, timings.pairs.bytes_skipped
         );
+        for c in &timings.by_extension {
+            let ext = if c.ext.is_empty() { "(none)" } else { c.ext.as_str() };
+            eprintln!(
+                "timings: ext {} files={} bytes={} hash_ms={} share={}%",
+                ext, c.files, c.bytes, c.ms, c.share
+            );
+        }
+        for d in &timings.actions_by_device {
+            eprintln!("timings: device {} groups={} actions_ms={}", d.device, d.groups, d.ms);
+        }
     }
 
+    enforce(&budget, scanner.errors(), found);
     // Non-zero exit code if any action error occurred
-    if (opts.delete || opts.hardlink) && action_stats.errors > 0 {
+    let audit_failed = audit_report.as_ref().is_some_and(|a| !a.failures.is_empty());
+    if modifying && (action_stats.errors > 0 || audit_failed) {
         std::process::exit(1);
     }
 
     Ok(())
 }
+
+/// Exit with `budget::EXIT_CODE` when the errors so far exceed `budget`.
+fn enforce(budget: &Budget, errors: &Errors, files: usize) {
+    if let Some(why) = budget.exceeded(&errors.counts(), files as u64) {
+        eprintln!("error: stopping: {}", why);
+        std::process::exit(budget::EXIT_CODE);
+    }
+}
+
+/// Where `--protect`, `--prefer` and `--avoid` put a member of its group: a `--compare`
+/// reference copy first, then protected members (including those outside every
+/// `act_only_under` directory), then preferred, unmarked and avoided ones. Protection wins over
+/// --avoid, and --prefer over --avoid. A member whose directory is gone is never made the
+/// canonical.
+fn canonical_rank(
+    opts: &ScanOpts,
+    reference: &reference::Reference,
+    act_only_under: &[std::path::PathBuf],
+    path: &std::path::Path,
+) -> u8 {
+    let Some(real) = fs_utils::resolve_parent(path) else { return 4 };
+    let matches = |patterns: &[glob::Pattern]| fs_utils::path_glob_matches(patterns, &real);
+    let outside = !act_only_under.is_empty() && !act_only_under.iter().any(|d| real.starts_with(d));
+    // A --compare reference copy is the canonical whatever the patterns say.
+    if reference.dirs().iter().any(|r| real.starts_with(r)) {
+        0
+    } else if outside || matches(&opts.protect) {
+        1
+    } else if matches(&opts.prefer) {
+        2
+    } else if matches(&opts.avoid) {
+        4
+    } else {
+        3
+    }
+}
+
+/// The `canonical_policy` of the JSON groups: the rules that ordered the members, most decisive
+/// first, as applied above (see `canonical_rank`), then `first-path` for what they leave tied.
+fn canonical_policy(
+    opts: &ScanOpts,
+    reference: &reference::Reference,
+    act_only_under: &[std::path::PathBuf],
+    snapshots: bool,
+) -> String {
+    let rules = [
+        (!reference.is_empty(), "compare-reference"),
+        (!act_only_under.is_empty(), "act-only-under"),
+        (!opts.protect.is_empty(), "protect"),
+        (!opts.prefer.is_empty(), "prefer"),
+        (!opts.avoid.is_empty(), "avoid"),
+        (opts.raw_jpeg_pairs, "raw-jpeg-pairs"),
+        (snapshots && reference.is_empty(), "live-before-snapshot"),
+        (true, CANONICAL_POLICY),
+    ];
+    let rules: Vec<&str> = rules.into_iter().filter_map(|(on, rule)| on.then_some(rule)).collect();
+    rules.join(",")
+}
+
+/// Whether a later stage reads the whole scanned list, so it can't be spilled for --max-memory.
+fn keeps_file_list(opts: &ScanOpts) -> bool {
+    if opts.unique {
+        return true;
+    }
+    #[cfg(feature = "media")]
+    if opts.video_headless {
+        return true;
+    }
+    #[cfg(feature = "decompress")]
+    if !opts.decompress.is_empty() {
+        return true;
+    }
+    let _ = opts;
+    false
+}
+
+fn tui_requested(opts: &ScanOpts) -> bool {
+    #[cfg(feature = "tui")]
+    return opts.tui;
+    #[cfg(not(feature = "tui"))]
+    {
+        let _ = opts;
+        false
+    }
+}
+
+/// Let the user confirm `targets` in the TUI or with per-group prompts.
+fn review(opts: &ScanOpts, targets: &[DuplicateGroup]) -> Result<Vec<DuplicateGroup>> {
+    let action = if opts.trash {
+        "trash"
+    } else if opts.hardlink {
+        "hardlink"
+    } else if opts.reflink {
+        "reflink"
+    } else if opts.dedupe_extents {
+        "dedupe-extents"
+    } else if opts.symlink {
+        "symlink"
+    } else if opts.move_to.is_some() {
+        "quarantine"
+    } else if opts.replicate.is_some() {
+        "replicate"
+    } else {
+        "delete"
+    };
+    if tui_requested(opts) {
+        #[cfg(feature = "tui")]
+        return tui::review(targets, action);
+    }
+    let answers = match opts.triage {
+        Some(secs) => interactive::triage(targets, action, Duration::from_secs(secs)),
+        None => interactive::confirm(targets, action),
+    };
+    answers.context("interactive confirmation failed")
+}
+
+fn run_cache(action: &cli::CacheCommand) -> Result<()> {
+    let path = HashCache::default_path().context("no cache directory")?;
+    match action {
+        cli::CacheCommand::Stats => {
+            let cache = HashCache::open(&path)?;
+            let bytes = fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+            println!("Hash cache: {}", path.display());
+            println!("Entries: {}", cache.len());
+            println!("Size: {}", ByteSize::b(bytes));
+        }
+        cli::CacheCommand::Clear => match fs::remove_file(&path) {
+            Ok(()) => println!("Removed {}", path.display()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => println!("Hash cache is empty"),
+            Err(e) => return Err(e).with_context(|| format!("remove {}", path.display())),
+        },
+    }
+    Ok(())
+}
+
+fn run_catalog(action: &cli::CatalogCommand) -> Result<()> {
+    let dir = catalog::catalog_dir()?;
+    match action {
+        cli::CatalogCommand::Add { path, name, min_size } => {
+            let cat = catalog::build(path, name, min_size.as_u64(), schedule::default_threads())?;
+            let saved = catalog::save(&dir, &cat)?;
+            println!("Cataloged {} files as {} ({})", cat.entries.len(), cat.name, saved.display());
+        }
+        cli::CatalogCommand::List => {
+            for c in catalog::load_all(&dir) {
+                println!("{}\t{} files\t{}", c.name, c.entries.len(), c.root.display());
+            }
+        }
+        cli::CatalogCommand::Remove { name } => {
+            catalog::remove(&dir, name)?;
+            println!("Removed catalog {}", name);
+        }
+    }
+    Ok(())
+}
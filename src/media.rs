@@ -0,0 +1,250 @@
+//! Media-aware "headless" hashing: hash only the media payload of a container, skipping
+//! metadata (MP4/MOV `moov`/`tkhd` timestamps, MP3 ID3 tags) so that remuxed or re-tagged
+//! copies of the same stream hash identically.
+
+use crate::fs_utils::ScannedFile;
+use anyhow::{bail, Context, Result};
+use blake3::Hasher;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+/// Containers with a payload parser.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Container {
+    /// ISO base media file format: MP4, M4V, MOV, 3GP, M4A.
+    Mp4,
+    /// MPEG audio with optional ID3v1/ID3v2 tags.
+    Mp3,
+}
+
+/// Pick a parser from the file extension.
+pub fn container_for(path: &Path) -> Option<Container> {
+    let ext = path.extension()?.to_str()?.to_ascii_lowercase();
+    match ext.as_str() {
+        "mp4" | "m4v" | "mov" | "3gp" | "m4a" => Some(Container::Mp4),
+        "mp3" => Some(Container::Mp3),
+        _ => None,
+    }
+}
+
+/// Hash the media payload of `path`, ignoring container metadata.
+pub fn headless_hash(path: &Path, container: Container) -> Result<String> {
+    let mut f = File::open(path).with_context(|| format!("open {}", path.display()))?;
+    let len = f.metadata()?.len();
+    let mut hasher = Hasher::new();
+    match container {
+        Container::Mp4 => {
+            let mut found = false;
+            for (start, end) in mp4_mdat_ranges(&mut f, len)? {
+                hash_range(&mut f, start, end, &mut hasher)?;
+                found = true;
+            }
+            if !found {
+                bail!("no mdat box in {}", path.display());
+            }
+        }
+        Container::Mp3 => {
+            let (start, end) = mp3_audio_range(&mut f, len)?;
+            hash_range(&mut f, start, end, &mut hasher)?;
+        }
+    }
+    Ok(hasher.finalize().to_hex().to_string())
+}
+
+/// Byte ranges of every top-level `mdat` payload, in file order.
+fn mp4_mdat_ranges(f: &mut File, len: u64) -> Result<Vec<(u64, u64)>> {
+    let mut out = Vec::new();
+    let mut pos = 0u64;
+    while pos + 8 <= len {
+        f.seek(SeekFrom::Start(pos))?;
+        let mut hdr = [0u8; 8];
+        f.read_exact(&mut hdr)?;
+        let size32 = u32::from_be_bytes([hdr[0], hdr[1], hdr[2], hdr[3]]) as u64;
+        let kind = [hdr[4], hdr[5], hdr[6], hdr[7]];
+        let (header_len, size) = match size32 {
+            0 => (8, len - pos),
+            1 => {
+                let mut large = [0u8; 8];
+                f.read_exact(&mut large)?;
+                (16, u64::from_be_bytes(large))
+            }
+            s => (8, s),
+        };
+        // A 64-bit size can be anything: check it without overflowing.
+        let end = pos.checked_add(size).filter(|end| size >= header_len && *end <= len);
+        let Some(end) = end else {
+            bail!("malformed box at offset {}", pos);
+        };
+        if &kind == b"mdat" {
+            out.push((pos + header_len, end));
+        }
+        pos = end;
+    }
+    Ok(out)
+}
+
+/// Audio frames of an MP3: skip a leading ID3v2 tag and a trailing ID3v1 tag.
+fn mp3_audio_range(f: &mut File, len: u64) -> Result<(u64, u64)> {
+    let mut start = 0u64;
+    let mut hdr = [0u8; 10];
+    if len >= 10 {
+        f.seek(SeekFrom::Start(0))?;
+        f.read_exact(&mut hdr)?;
+        if &hdr[0..3] == b"ID3" {
+            // Syncsafe size: 7 bits per byte; +10 header, +10 footer when flagged.
+            let size = hdr[6..10].iter().fold(0u64, |acc, b| (acc << 7) | (*b as u64 & 0x7f));
+            let footer = if hdr[5] & 0x10 != 0 { 10 } else { 0 };
+            start = (10 + size + footer).min(len);
+        }
+    }
+    let mut end = len;
+    if len >= start + 128 {
+        let mut tag = [0u8; 3];
+        f.seek(SeekFrom::Start(len - 128))?;
+        f.read_exact(&mut tag)?;
+        if &tag == b"TAG" {
+            end = len - 128;
+        }
+    }
+    Ok((start, end))
+}
+
+fn hash_range(f: &mut File, start: u64, end: u64, hasher: &mut Hasher) -> Result<()> {
+    f.seek(SeekFrom::Start(start))?;
+    let mut left = end - start;
+    let mut buf = vec![0u8; 1024 * 1024];
+    while left > 0 {
+        let want = left.min(buf.len() as u64) as usize;
+        let n = f.read(&mut buf[..want])?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+        left -= n as u64;
+    }
+    Ok(())
+}
+
+/// A member of a near-duplicate group.
+#[derive(Debug, Clone, Serialize)]
+pub struct NearMember {
+    pub path: PathBuf,
+    pub size: u64,
+    pub container: Container,
+}
+
+/// Files whose media payload is identical even though the files themselves differ.
+#[derive(Debug, Clone, Serialize)]
+pub struct NearDuplicateGroup {
+    pub media_hash: String,
+    pub members: Vec<NearMember>,
+}
+
+/// Group media files by headless hash. Groups that `exact` reports as a single
+/// byte-identical duplicate group are left to the exact pipeline.
+pub fn near_duplicates(
+    files: &[ScannedFile],
+    threads: usize,
+    exact: impl Fn(&[PathBuf]) -> bool,
+) -> Vec<NearDuplicateGroup> {
+    let media: Vec<(&ScannedFile, Container)> = files
+        .iter()
+        .filter_map(|f| container_for(&f.path).map(|c| (f, c)))
+        .collect();
+    let hashes = crate::schedule::parallel_map(&media, threads, |(f, c)| headless_hash(&f.path, *c));
+    let mut by_hash: BTreeMap<String, Vec<NearMember>> = BTreeMap::new();
+    for ((f, c), res) in media.iter().zip(hashes) {
+        if let Ok(h) = res {
+            let member = NearMember { path: f.path.clone(), size: f.meta.size, container: *c };
+            by_hash.entry(h).or_default().push(member);
+        }
+    }
+    by_hash
+        .into_iter()
+        .filter(|(_, m)| m.len() > 1)
+        .filter_map(|(media_hash, mut members)| {
+            members.sort_by(|a, b| a.path.cmp(&b.path));
+            let paths: Vec<PathBuf> = members.iter().map(|m| m.path.clone()).collect();
+            (!exact(&paths)).then_some(NearDuplicateGroup { media_hash, members })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mp4_box(kind: &[u8; 4], payload: &[u8]) -> Vec<u8> {
+        let mut b = ((payload.len() + 8) as u32).to_be_bytes().to_vec();
+        b.extend_from_slice(kind);
+        b.extend_from_slice(payload);
+        b
+    }
+
+    #[test]
+    fn remuxed_mp4_hashes_equal() {
+        let dir = std::env::temp_dir().join(format!("ducky_media_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let a = dir.join("a.mp4");
+        let b = dir.join("b.mov");
+        let stream = vec![0xABu8; 5000];
+
+        let mut fa = mp4_box(b"ftyp", b"isom0000");
+        fa.extend(mp4_box(b"moov", b"tkhd created 2019"));
+        fa.extend(mp4_box(b"mdat", &stream));
+        // Different brand, moov after mdat (faststart undone), different timestamps.
+        let mut fb = mp4_box(b"ftyp", b"qt  0000");
+        fb.extend(mp4_box(b"mdat", &stream));
+        fb.extend(mp4_box(b"moov", b"tkhd created 2024, longer"));
+        std::fs::write(&a, fa).unwrap();
+        std::fs::write(&b, fb).unwrap();
+
+        let ha = headless_hash(&a, Container::Mp4).unwrap();
+        let hb = headless_hash(&b, Container::Mp4).unwrap();
+        assert_eq!(ha, hb);
+
+        let scanned = |path: PathBuf| {
+            let meta = crate::fs_utils::FileMeta::of(&path).unwrap();
+            ScannedFile { path, meta }
+        };
+        let groups = near_duplicates(&[scanned(a), scanned(b)], 2, |_| false);
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].members.len(), 2);
+
+        // A 64-bit box size that would wrap the offset is malformed, not a panic.
+        let c = dir.join("c.mp4");
+        let mut fc = mp4_box(b"ftyp", b"isom0000");
+        fc.extend(1u32.to_be_bytes());
+        fc.extend(b"free");
+        fc.extend(u64::MAX.to_be_bytes());
+        std::fs::write(&c, fc).unwrap();
+        assert!(headless_hash(&c, Container::Mp4).is_err());
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn retagged_mp3_hashes_equal() {
+        let dir = std::env::temp_dir().join(format!("ducky_media_mp3_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let frames = vec![0xFFu8; 4000];
+        let mut a = b"ID3\x03\x00\x00\x00\x00\x00\x05title".to_vec();
+        a.extend(&frames);
+        let mut b = frames.clone();
+        let mut v1 = b"TAG".to_vec();
+        v1.resize(128, b' ');
+        b.extend(v1);
+        std::fs::write(dir.join("a.mp3"), a).unwrap();
+        std::fs::write(dir.join("b.mp3"), b).unwrap();
+
+        let ha = headless_hash(&dir.join("a.mp3"), Container::Mp3).unwrap();
+        let hb = headless_hash(&dir.join("b.mp3"), Container::Mp3).unwrap();
+        assert_eq!(ha, hb);
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
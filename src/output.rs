@@ -1,13 +1,41 @@
 //! Output and data model: duplicate groups, human and JSON printers.
 
+#[cfg(feature = "decompress")]
+use crate::compressed::CompressedGroup;
+use crate::conflicts::CaseConflict;
+use crate::dirs::DirGroup;
+use crate::fs_utils::ScannedFile;
+use crate::grouping::Links;
+use crate::hashing::{ContentKey, DigestFormat};
+use crate::heatmap::DirHeat;
+use crate::linkable::{GroupLinks, Method};
+#[cfg(feature = "media")]
+use crate::media::NearDuplicateGroup;
 use bytesize::ByteSize;
 use serde::Serialize;
-use std::path::PathBuf;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
 
 #[derive(Debug, Serialize, Clone)]
 pub struct DuplicateGroup {
     pub size: u64,             // bytes per file in this group
     pub members: Vec<PathBuf>, // all paths that are identical
+    #[serde(skip)]
+    pub hash: String, // full content hash; empty when unknown
+    #[serde(skip)]
+    pub stale: bool, // a member changed after hashing; re-verify before acting
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub also_on: Vec<String>, // "catalog:path" locations on cataloged offline volumes
+    #[serde(skip)]
+    pub links: Vec<PathBuf>, // members that are extra hard links of another member's inode
+    #[serde(skip)]
+    pub mtimes: BTreeMap<PathBuf, i64>, // modification time (ns) of members when scanned
+    #[serde(skip)]
+    pub pairs: BTreeMap<PathBuf, PathBuf>, // other half of a member's RAW+JPEG pair
+    #[serde(skip)]
+    pub degraded: Vec<PathBuf>, // members scanned without their metadata; never acted on
 }
 
 impl DuplicateGroup {
@@ -16,36 +44,522 @@ impl DuplicateGroup {
     /// - the first member is the canonical path (lexicographically first)
     pub fn new(size: u64, mut members: Vec<PathBuf>) -> Self {
         members.sort_by(|a, b| a.as_os_str().cmp(b.as_os_str()));
-        Self { size, members }
+        let (hash, also_on, links) = (String::new(), Vec::new(), Vec::new());
+        let (mtimes, pairs, degraded) = (BTreeMap::new(), BTreeMap::new(), Vec::new());
+        Self { size, members, hash, stale: false, also_on, links, mtimes, pairs, degraded }
+    }
+
+    /// Composite (size, algorithm, hash) identity of the group's content.
+    pub fn key(&self) -> ContentKey {
+        ContentKey::new(self.size, self.hash.clone())
+    }
+
+    /// Attach the full content hash shared by all members.
+    pub fn with_hash(mut self, hash: String) -> Self {
+        self.hash = hash;
+        self
+    }
+
+    /// Reorder members by `rank`, lowest first, keeping path order among equals; the first
+    /// becomes the canonical. Extra hard links go after the other members of their rank, since
+    /// their inode is already represented.
+    pub fn rank_members<K: Ord>(&mut self, rank: impl Fn(&Path) -> K) {
+        let links = std::mem::take(&mut self.links);
+        self.members.sort_by_cached_key(|m| (rank(m), links.contains(m)));
+        self.links = links;
+    }
+
+    /// Add back the hard links collapsed before hashing. Of each inode's paths, all but the
+    /// first (in member order) are recorded in `links`.
+    pub fn with_links(mut self, links: &Links) -> Self {
+        let mut extra = Vec::new();
+        for m in &self.members {
+            let Some(others) = links.get(m) else { continue };
+            let mut paths: Vec<&PathBuf> = others.iter().chain([m]).collect();
+            paths.sort_by(|a, b| a.as_os_str().cmp(b.as_os_str()));
+            self.links.extend(paths[1..].iter().map(|p| (*p).clone()));
+            extra.extend(others.iter().cloned());
+        }
+        if !extra.is_empty() {
+            self.members.extend(extra);
+            self.members.sort_by(|a, b| a.as_os_str().cmp(b.as_os_str()));
+        }
+        self
+    }
+
+    /// Remember when each member was last modified as of the scan, from `seen`, so actions
+    /// can tell whether it changed since.
+    pub fn with_mtimes(mut self, seen: &HashMap<PathBuf, i64>) -> Self {
+        self.mtimes =
+            self.members.iter().filter_map(|m| Some((m.clone(), *seen.get(m)?))).collect();
+        self
+    }
+
+    /// Note the members found in `degraded`, the files scanned without their metadata.
+    pub fn with_degraded(mut self, degraded: &HashSet<PathBuf>) -> Self {
+        self.degraded = self.members.iter().filter(|m| degraded.contains(*m)).cloned().collect();
+        self
     }
 }
 
-/// Print human-readable output for duplicate groups.
+/// Write human-readable output for duplicate groups.
 /// Groups are expected to already be ordered by the caller.
-pub fn print_human(groups: &[DuplicateGroup], reclaimable: u64) {
+pub fn write_human(
+    out: &mut dyn Write,
+    groups: &[DuplicateGroup],
+    max_members: usize,
+) -> io::Result<()> {
     for g in groups {
-        println!(
+        writeln!(
+            out,
             "== {} duplicates of {} ==",
             g.members.len(),
             ByteSize(g.size)
-        );
+        )?;
+        // Groups with more than `max_members` (0: no limit) list the first ones only.
+        let shown = match max_members {
+            0 => g.members.len(),
+            n => n.min(g.members.len()),
+        };
+        let indent = match common_dir(&g.members) {
+            Some(dir) => {
+                writeln!(out, "  in {}{}", escaped(&dir), std::path::MAIN_SEPARATOR)?;
+                for p in &g.members[..shown] {
+                    let rel = p.strip_prefix(&dir).unwrap_or(p);
+                    writeln!(out, "    {}{}", escaped(rel), link_note(g, p))?;
+                }
+                "    "
+            }
+            None => {
+                for p in &g.members[..shown] {
+                    writeln!(out, "  {}{}", escaped(p), link_note(g, p))?;
+                }
+                "  "
+            }
+        };
+        if shown < g.members.len() {
+            writeln!(out, "{}... and {} more", indent, thousands(g.members.len() - shown))?;
+        }
+        for loc in &g.also_on {
+            writeln!(out, "  also on {}", loc)?;
+        }
+    }
+    Ok(())
+}
+
+/// `n` with a comma between groups of three digits.
+fn thousands(n: usize) -> String {
+    let digits = n.to_string();
+    let mut out = String::with_capacity(digits.len() + digits.len() / 3);
+    for (i, c) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            out.push(',');
+        }
+        out.push(c);
+    }
+    out
+}
+
+fn link_note(g: &DuplicateGroup, p: &PathBuf) -> String {
+    let mut note = String::new();
+    if g.links.contains(p) {
+        note.push_str("  (hard link)");
+    }
+    if g.degraded.contains(p) {
+        note.push_str("  (metadata unknown)");
+    }
+    if let Some(name) = g.pairs.get(p).and_then(|half| half.file_name()) {
+        note.push_str(&format!("  (+ {})", escaped(Path::new(name))));
+    }
+    note
+}
+
+/// `path` for human output. Names with control characters (a newline would otherwise look like
+/// two entries) are shown in ANSI-C shell quoting, `$'a\nb.txt'`, which a shell reads back as
+/// the exact name; machine formats (`--json`, `--format fdupes`) print names unchanged.
+pub fn escaped(path: &Path) -> Cow<'_, str> {
+    let shown = path.to_string_lossy();
+    if !has_control_chars(path) {
+        return shown;
+    }
+    let mut out = String::from("$'");
+    for c in shown.chars() {
+        match c {
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            '\r' => out.push_str("\\r"),
+            '\\' | '\'' => {
+                out.push('\\');
+                out.push(c);
+            }
+            c if c.is_ascii_control() => out.push_str(&format!("\\x{:02x}", c as u32)),
+            c if c.is_control() => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('\'');
+    Cow::Owned(out)
+}
+
+/// Whether `path` would be escaped in human output.
+pub fn has_control_chars(path: &Path) -> bool {
+    path.to_string_lossy().chars().any(char::is_control)
+}
+
+/// Text layout of the group listing.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum Format {
+    /// Headed groups with sizes and a shared directory.
+    #[default]
+    Human,
+    /// Exactly like `fdupes`: one path per line, each group followed by a blank line.
+    Fdupes,
+    /// A Parquet table with one row per member (binary; best sent to --groups-out).
+    #[cfg(feature = "parquet")]
+    Parquet,
+}
+
+/// Write identical directory trees (`--dirs`), before the file groups they don't cover.
+pub fn write_dirs_human(out: &mut dyn Write, groups: &[DirGroup]) -> io::Result<()> {
+    for g in groups {
+        writeln!(
+            out,
+            "== {} identical directories of {} files, {} ==",
+            g.dirs.len(),
+            g.files,
+            ByteSize(g.bytes)
+        )?;
+        for d in &g.dirs {
+            writeln!(out, "  {}{}", escaped(d), std::path::MAIN_SEPARATOR)?;
+        }
+    }
+    Ok(())
+}
+
+/// Human listing of `--case-conflicts`.
+pub fn write_case_conflicts_human(out: &mut dyn Write, conflicts: &[CaseConflict]) -> io::Result<()> {
+    for c in conflicts {
+        writeln!(out, "== {} names differing only by case in {} ==", c.names.len(), escaped(&c.dir))?;
+        for name in &c.names {
+            writeln!(out, "  {}", escaped(Path::new(name)))?;
+        }
+    }
+    Ok(())
+}
+
+/// Human listing of `--heatmap`: the `shown` densest directories (0: all), then a count of
+/// the rest.
+pub fn write_heatmap_human(out: &mut dyn Write, heat: &[DirHeat], shown: usize) -> io::Result<()> {
+    if heat.is_empty() {
+        return Ok(());
+    }
+    writeln!(out, "== duplicated share of {} directories ==", thousands(heat.len()))?;
+    let shown = if shown == 0 { heat.len() } else { shown.min(heat.len()) };
+    for h in &heat[..shown] {
+        let bytes = format!("{} of {}", ByteSize(h.duplicated_bytes), ByteSize(h.total_bytes));
+        let (pct, dir) = (h.ratio * 100.0, escaped(&h.dir));
+        writeln!(out, "  {:>5.1}%  {:<22} {}{}", pct, bytes, dir, std::path::MAIN_SEPARATOR)?;
+    }
+    if shown < heat.len() {
+        writeln!(out, "  ... and {} more", thousands(heat.len() - shown))?;
+    }
+    Ok(())
+}
+
+/// Human listing of `--unique`, in place of the group listing.
+pub fn write_unique_human(out: &mut dyn Write, files: &[ScannedFile]) -> io::Result<()> {
+    let bytes: u64 = files.iter().map(|f| f.meta.size).sum();
+    writeln!(out, "== {} files without a copy ({}) ==", thousands(files.len()), ByteSize(bytes))?;
+    for f in files {
+        writeln!(out, "  {:>10}  {}", ByteSize(f.meta.size).to_string(), escaped(&f.path))?;
+    }
+    Ok(())
+}
+
+#[derive(Serialize)]
+struct UniqueJson<'a> {
+    path: &'a Path,
+    size: u64,
+}
+
+/// `--unique --json`: the files without a copy as `{path, size}` objects, in place of the
+/// groups; with `warnings`, inside a `{"unique", "warnings"}` envelope.
+pub fn write_unique_json(
+    out: &mut dyn Write,
+    files: &[ScannedFile],
+    warnings: Option<&[String]>,
+) -> io::Result<()> {
+    let files: Vec<UniqueJson> =
+        files.iter().map(|f| UniqueJson { path: &f.path, size: f.meta.size }).collect();
+    let json = match warnings {
+        Some(w) => {
+            serde_json::to_string_pretty(&serde_json::json!({ "unique": files, "warnings": w }))
+        }
+        None => serde_json::to_string_pretty(&files),
+    };
+    writeln!(out, "{}", json?)
+}
+
+/// Human listing of `--link-report`, in place of the plain group listing.
+pub fn write_link_report_human(out: &mut dyn Write, groups: &[GroupLinks]) -> io::Result<()> {
+    for g in groups {
+        let s = &g.savings;
+        writeln!(
+            out,
+            "== {} copies of {}: hardlink/reflink frees {}, symlink/move frees {} ==",
+            g.members.len() + 1,
+            ByteSize(g.size),
+            ByteSize(s.hardlink),
+            ByteSize(s.symlink)
+        )?;
+        writeln!(out, "  keep       {}", escaped(&g.canonical))?;
+        for m in &g.members {
+            let method = match m.method {
+                Method::Hardlink => "hardlink",
+                Method::CrossDevice => "symlink",
+                Method::Linked => "linked",
+                Method::Unknown => "unknown",
+            };
+            writeln!(out, "  {:<10} {}", method, escaped(&m.path))?;
+        }
+    }
+    Ok(())
+}
+
+/// Write groups the way `fdupes` prints them, so its consumers can read ducky's output.
+pub fn write_fdupes(out: &mut dyn Write, groups: &[DuplicateGroup]) -> io::Result<()> {
+    for g in groups {
         for p in &g.members {
-            println!("  {}", p.display());
+            writeln!(out, "{}", p.display())?;
         }
+        writeln!(out)?;
     }
-    if !groups.is_empty() {
-        println!();
-        println!(
-            "Found {} duplicate groups; reclaimable: {}",
-            groups.len(),
-            ByteSize(reclaimable)
-        );
+    Ok(())
+}
+
+/// Longest directory shared by every member, if it names at least one directory (a bare
+/// `/` or drive isn't worth a line of its own).
+fn common_dir(members: &[PathBuf]) -> Option<PathBuf> {
+    let (first, rest) = members.split_first()?;
+    let mut prefix: Vec<_> = first.parent()?.components().collect();
+    for p in rest {
+        let dir: Vec<_> = p.parent()?.components().collect();
+        let shared = prefix.iter().zip(&dir).take_while(|(a, b)| a == b).count();
+        prefix.truncate(shared);
+    }
+    let named = prefix.iter().any(|c| matches!(c, std::path::Component::Normal(_)));
+    named.then(|| prefix.into_iter().collect())
+}
+
+/// Write near-duplicate media groups with each member's container and size.
+#[cfg(feature = "media")]
+pub fn write_near_human(out: &mut dyn Write, groups: &[NearDuplicateGroup]) -> io::Result<()> {
+    for g in groups {
+        writeln!(out, "== {} near-duplicates (same media stream) ==", g.members.len())?;
+        for m in &g.members {
+            let container = serde_json::to_value(m.container).ok();
+            let container = container.as_ref().and_then(|v| v.as_str()).unwrap_or("?");
+            writeln!(out, "  {}  [{}, {}]", escaped(&m.path), container, ByteSize(m.size))?;
+        }
+    }
+    Ok(())
+}
+
+/// Write compressed-duplicate groups: the content size, then each member's format and size
+/// on disk.
+#[cfg(feature = "decompress")]
+pub fn write_compressed_human(out: &mut dyn Write, groups: &[CompressedGroup]) -> io::Result<()> {
+    for g in groups {
+        writeln!(out, "== {} compressed duplicates of {} ==", g.members.len(), ByteSize(g.size))?;
+        for m in &g.members {
+            let codec = m.codec.and_then(|c| serde_json::to_value(c).ok());
+            let codec = codec.as_ref().and_then(|v| v.as_str()).unwrap_or("plain");
+            writeln!(out, "  {}  [{}, {}]", escaped(&m.path), codec, ByteSize(m.size))?;
+        }
+    }
+    Ok(())
+}
+
+/// Print the single-line summary scraped by scripts (`--no-fancy`).
+/// `realistic` accounts for existing hardlinks and sparse files; `upper` is the naive bound.
+pub fn print_summary_line(groups: usize, realistic: u64, upper: u64) {
+    println!(
+        "Found {} duplicate groups; reclaimable: {} (upper bound {})",
+        groups,
+        ByteSize(realistic),
+        ByteSize(upper)
+    );
+}
+
+/// shields.io endpoint JSON (`--badge-json`): reclaimable bytes, colored by how much.
+pub fn badge(reclaimable: u64) -> serde_json::Value {
+    const GIB: u64 = 1 << 30;
+    let color = match reclaimable {
+        0 => "brightgreen",
+        n if n < GIB => "green",
+        n if n < 10 * GIB => "yellow",
+        n if n < 100 * GIB => "orange",
+        _ => "red",
+    };
+    serde_json::json!({
+        "schemaVersion": 1,
+        "label": "duplicates",
+        "message": format!("{} reclaimable", ByteSize(reclaimable)),
+        "color": color,
+    })
+}
+
+/// Write the badge to `path` via a temporary file, so a dashboard polling it never sees a
+/// partial file.
+pub fn write_badge(path: &Path, reclaimable: u64) -> io::Result<()> {
+    let name = path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+    let tmp = path.with_file_name(format!(".{}.tmp", name));
+    std::fs::write(&tmp, format!("{}\n", badge(reclaimable)))?;
+    std::fs::rename(&tmp, path)
+}
+
+/// Print label/value rows inside an aligned box.
+pub fn print_panel(title: &str, rows: &[(&str, String)]) {
+    for line in render_panel(title, rows) {
+        println!("{}", line);
+    }
+}
+
+fn render_panel(title: &str, rows: &[(&str, String)]) -> Vec<String> {
+    let label_w = rows.iter().map(|(l, _)| l.chars().count()).max().unwrap_or(0);
+    let body: Vec<String> = rows
+        .iter()
+        .map(|(l, v)| format!("{:<label_w$}  {}", l, v))
+        .collect();
+    let inner = body
+        .iter()
+        .map(|s| s.chars().count())
+        .chain(std::iter::once(title.chars().count() + 2))
+        .max()
+        .unwrap_or(0);
+
+    let mut out = Vec::with_capacity(body.len() + 2);
+    let fill = inner - 1 - title.chars().count();
+    out.push(format!("┌─ {} {}┐", title, "─".repeat(fill)));
+    for s in body {
+        out.push(format!("│ {:<inner$} │", s));
+    }
+    out.push(format!("└{}┘", "─".repeat(inner + 2)));
+    out
+}
+
+/// How the canonical (kept) member of each group is chosen when nothing reorders members: the
+/// first path in byte order. Runs that rank members (`--protect`, `--prefer`, `--compare`, ...)
+/// report their rules ahead of it, most decisive first (`"protect,prefer,first-path"`).
+pub const CANONICAL_POLICY: &str = "first-path";
+
+/// JSON view of a group: the plain fields plus an explicit canonical flag per member, so
+/// consumers don't have to rely on member order. `--json-detail` adds `group_id`, `hash`
+/// and per-member file metadata.
+#[derive(Serialize)]
+struct GroupJson<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    group_id: Option<usize>,
+    #[serde(flatten)]
+    group: &'a DuplicateGroup,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    hash: Option<String>,
+    canonical_policy: &'a str,
+    entries: Vec<MemberJson<'a>>,
+}
+
+#[derive(Serialize)]
+struct MemberJson<'a> {
+    path: &'a PathBuf,
+    canonical: bool,
+    /// An extra hard link of another member: removing it frees nothing.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    hardlink: bool,
+    /// The other half of its RAW+JPEG pair (`--raw-jpeg-pairs`).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pair: Option<&'a PathBuf>,
+    /// `"unknown"` for a member scanned without its metadata (`--tolerate-metadata-errors`).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    metadata: Option<&'static str>,
+    #[serde(flatten, skip_serializing_if = "Option::is_none")]
+    meta: Option<MemberMeta>,
+}
+
+/// File metadata of one member (`--json-detail`); inode, device and link count are
+/// reported on Unix only.
+#[derive(Serialize, Default)]
+struct MemberMeta {
+    /// Modification time, Unix seconds.
+    mtime: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    inode: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    device: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    nlink: Option<u64>,
+}
+
+impl MemberMeta {
+    fn of(path: &std::path::Path) -> Self {
+        let Ok(m) = std::fs::metadata(path) else {
+            return MemberMeta::default();
+        };
+        let mtime = m
+            .modified()
+            .ok()
+            .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+            .map(|d| d.as_secs());
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+            MemberMeta { mtime, inode: Some(m.ino()), device: Some(m.dev()), nlink: Some(m.nlink()) }
+        }
+        #[cfg(not(unix))]
+        MemberMeta { mtime, ..Default::default() }
     }
 }
 
-/// Print stable, pretty JSON suitable for consumption.
+impl<'a> GroupJson<'a> {
+    /// `id` is the 1-based position in the output, given only with `--json-detail`.
+    fn new(
+        group: &'a DuplicateGroup,
+        id: Option<usize>,
+        digest: DigestFormat,
+        canonical_policy: &'a str,
+    ) -> Self {
+        let detail = id.is_some();
+        let entries = group
+            .members
+            .iter()
+            .enumerate()
+            .map(|(i, path)| MemberJson {
+                path,
+                canonical: i == 0,
+                hardlink: group.links.contains(path),
+                pair: group.pairs.get(path),
+                metadata: group.degraded.contains(path).then_some("unknown"),
+                meta: detail.then(|| MemberMeta::of(path)),
+            })
+            .collect();
+        let hash = (detail && !group.hash.is_empty()).then(|| digest.encode(&group.hash));
+        GroupJson { group_id: id, group, hash, canonical_policy, entries }
+    }
+}
+
+/// Write stable, pretty JSON suitable for consumption.
 /// Keys remain unchanged; member order is stable by construction.
-pub fn print_json(groups: &[DuplicateGroup]) {
+/// With `warnings`, the array is wrapped as `{"groups": [...], "warnings": [...]}`.
+/// With `detail`, groups carry ids and hashes (written as `digest`) and members their file
+/// metadata. `policy` is reported as each group's `canonical_policy`.
+pub fn write_json(
+    out: &mut dyn Write,
+    groups: &[DuplicateGroup],
+    warnings: Option<&[String]>,
+    detail: bool,
+    digest: DigestFormat,
+    policy: &str,
+) -> io::Result<()> {
     // stable, pretty JSON for GitHub README examples.
     // Deterministic group order: by reclaimable desc, size desc, then first member.
     let mut gs = groups.to_vec();
@@ -61,7 +575,16 @@ pub fn print_json(groups: &[DuplicateGroup]) {
                 a0.cmp(b0)
             })
     });
-    println!("{}", serde_json::to_string_pretty(&gs).unwrap());
+    let gs: Vec<GroupJson> = gs
+        .iter()
+        .enumerate()
+        .map(|(i, g)| GroupJson::new(g, detail.then_some(i + 1), digest, policy))
+        .collect();
+    let json = match warnings {
+        Some(w) => serde_json::to_string_pretty(&serde_json::json!({ "groups": gs, "warnings": w })),
+        None => serde_json::to_string_pretty(&gs),
+    };
+    writeln!(out, "{}", json?)
 }
 
 #[cfg(test)]
@@ -89,4 +612,113 @@ mod tests {
         assert_eq!(gs[0].size, 10);
         assert_eq!(gs[1].size, 5);
     }
+
+    #[test]
+    #[cfg(unix)]
+    fn human_output_factors_out_common_dir() {
+        let members = vec!["/home/u/pics/2019/a.jpg".into(), "/home/u/pics/b/a.jpg".into()];
+        let g = DuplicateGroup::new(4, members);
+        let mut out = Vec::new();
+        write_human(&mut out, &[g], 20).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.contains("  in /home/u/pics/\n    2019/a.jpg\n    b/a.jpg\n"), "{}", text);
+
+        // Nothing shared beyond the root: full paths.
+        assert_eq!(common_dir(&["/a/x".into(), "/b/x".into()]), None);
+    }
+
+    #[test]
+    fn large_groups_are_truncated_in_human_output() {
+        let members: Vec<PathBuf> = (0..4234).map(|i| format!("/d/{:05}", i).into()).collect();
+        let g = DuplicateGroup::new(1, members);
+        let text = |max| {
+            let mut out = Vec::new();
+            write_human(&mut out, std::slice::from_ref(&g), max).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        let short = text(3);
+        assert!(short.starts_with("== 4234 duplicates of 1 B ==\n"), "{}", short);
+        assert!(short.ends_with("    00002\n    ... and 4,231 more\n"), "{}", short);
+        assert_eq!(text(0).lines().count(), 4234 + 2);
+        assert_eq!(thousands(999), "999");
+        assert_eq!(thousands(1_234_567), "1,234,567");
+    }
+
+    #[test]
+    fn collapsed_links_are_listed_and_flagged() {
+        let links = Links::from([(PathBuf::from("/b"), vec![PathBuf::from("/a")])]);
+        let g = DuplicateGroup::new(4, vec!["/b".into(), "/c".into()]).with_links(&links);
+        assert_eq!(g.members, [Path::new("/a"), Path::new("/b"), Path::new("/c")]);
+        assert_eq!(g.links, [PathBuf::from("/b")]);
+        let mut out = Vec::new();
+        write_json(&mut out, &[g], None, false, DigestFormat::Hex, CANONICAL_POLICY).unwrap();
+        let v: serde_json::Value = serde_json::from_slice(&out).unwrap();
+        let flags: Vec<bool> = v[0]["entries"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|e| e.get("hardlink").is_some())
+            .collect();
+        assert_eq!(flags, [false, true, false]);
+    }
+
+    #[test]
+    fn fdupes_format_separates_groups_with_blank_lines() {
+        let gs = [
+            DuplicateGroup::new(3, vec!["b/x".into(), "a/x".into()]),
+            DuplicateGroup::new(1, vec!["c".into(), "d".into(), "e".into()]),
+        ];
+        let mut out = Vec::new();
+        write_fdupes(&mut out, &gs).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "a/x\nb/x\n\nc\nd\ne\n\n");
+    }
+
+    #[test]
+    fn json_flags_canonical_member() {
+        let g = DuplicateGroup::new(3, vec!["/b".into(), "/a".into()]);
+        let mut out = Vec::new();
+        write_json(&mut out, &[g], None, false, DigestFormat::Hex, CANONICAL_POLICY).unwrap();
+        let v: serde_json::Value = serde_json::from_slice(&out).unwrap();
+        assert_eq!(v[0]["members"], serde_json::json!(["/a", "/b"]));
+        assert_eq!(v[0]["canonical_policy"], CANONICAL_POLICY);
+        assert_eq!(
+            v[0]["entries"],
+            serde_json::json!([{"path": "/a", "canonical": true}, {"path": "/b", "canonical": false}])
+        );
+    }
+
+    #[test]
+    fn json_detail_adds_ids_hashes_and_metadata() {
+        let path = std::env::temp_dir().join(format!("ducky_json_detail_{}", std::process::id()));
+        std::fs::write(&path, b"x").unwrap();
+        let g = DuplicateGroup::new(1, vec![path.clone()]).with_hash("abc".into());
+        let mut out = Vec::new();
+        write_json(&mut out, &[g], None, true, DigestFormat::Hex, CANONICAL_POLICY).unwrap();
+        let v: serde_json::Value = serde_json::from_slice(&out).unwrap();
+        assert_eq!(v[0]["group_id"], 1);
+        assert_eq!(v[0]["hash"], "abc");
+        assert!(v[0]["entries"][0]["mtime"].is_u64());
+        #[cfg(unix)]
+        assert_eq!(v[0]["entries"][0]["nlink"], 1);
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[test]
+    fn badge_is_colored_by_reclaimable_bytes() {
+        let b = badge(0);
+        assert_eq!((b["schemaVersion"].as_u64(), b["color"].as_str()), (Some(1), Some("brightgreen")));
+        let b = badge(12 << 30);
+        assert_eq!(b["label"], "duplicates");
+        assert_eq!(b["message"], format!("{} reclaimable", ByteSize(12 << 30)));
+        assert_eq!(b["color"], "orange");
+    }
+
+    #[test]
+    fn panel_lines_are_aligned() {
+        let rows = [("groups", "3".to_string()), ("reclaimable", "1.2 MB".to_string())];
+        let lines = render_panel("ducky", &rows);
+        let width = lines[0].chars().count();
+        assert!(lines.iter().all(|l| l.chars().count() == width));
+        assert_eq!(lines[2], format!("│ {:<w$} │", "reclaimable  1.2 MB", w = width - 4));
+    }
 }
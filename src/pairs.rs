@@ -0,0 +1,106 @@
+//! Size buckets with exactly two files skip the quick hash: the files are compared directly,
+//! stopping at the first differing byte, and hashed on the way in case they match. Two files
+//! that differ early cost a few reads instead of two quick hashes; two that match are read
+//! once instead of twice (quick, then full).
+
+use crate::cache::{FileId, HashCache};
+use crate::hashing::compare_and_hash;
+use anyhow::Result;
+use serde::Serialize;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// What comparing pairs directly saved, for `--timings`.
+#[derive(Debug, Default, Serialize, PartialEq, Eq)]
+pub struct PairStats {
+    /// Two-file buckets compared instead of hashed.
+    pub compared: u64,
+    /// Pairs that turned out to differ, so neither file was hashed.
+    pub differed: u64,
+    /// Bytes of differing pairs never read because the comparison stopped early.
+    pub bytes_skipped: u64,
+}
+
+#[derive(Default)]
+pub struct PairCounters {
+    compared: AtomicU64,
+    differed: AtomicU64,
+    bytes_skipped: AtomicU64,
+}
+
+impl PairCounters {
+    pub fn report(&self) -> PairStats {
+        PairStats {
+            compared: self.compared.load(Ordering::Relaxed),
+            differed: self.differed.load(Ordering::Relaxed),
+            bytes_skipped: self.bytes_skipped.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Full hash of `a` and `b` when they are identical, `None` when they differ. Cached full
+/// hashes of both files are used when present; a fresh match is cached for both.
+pub fn compare(
+    a: &Path,
+    b: &Path,
+    size: u64,
+    cache: &HashCache,
+    counters: &PairCounters,
+) -> Result<Option<String>> {
+    let ids = (FileId::of(a), FileId::of(b));
+    if let (Some(ia), Some(ib)) = &ids {
+        if let (Some(ha), Some(hb)) = (cache.full(ia), cache.full(ib)) {
+            cache.seen(ia, a);
+            cache.seen(ib, b);
+            return Ok((ha == hb).then_some(ha));
+        }
+    }
+    let (hash, read) = compare_and_hash(a, b)?;
+    counters.compared.fetch_add(1, Ordering::Relaxed);
+    match &hash {
+        None => {
+            counters.differed.fetch_add(1, Ordering::Relaxed);
+            let skipped = size.saturating_sub(read).saturating_mul(2);
+            counters.bytes_skipped.fetch_add(skipped, Ordering::Relaxed);
+        }
+        Some(h) => {
+            for (path, id) in [(a, ids.0), (b, ids.1)] {
+                // Only cache what was read: the file may have changed since it was identified.
+                if let Some(id) = id.filter(|id| FileId::of(path).as_ref() == Some(id)) {
+                    cache.put_full(id, h);
+                    cache.seen(&id, path);
+                }
+            }
+        }
+    }
+    Ok(hash)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hashing::full_hash;
+
+    #[test]
+    fn pairs_match_or_stop_early() {
+        let dir = std::env::temp_dir().join(format!("ducky_pairs_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (a, b, c) = (dir.join("a"), dir.join("b"), dir.join("c"));
+        let body = vec![1u8; 1 << 20];
+        std::fs::write(&a, &body).unwrap();
+        std::fs::write(&b, &body).unwrap();
+        let mut other = body.clone();
+        other[0] = 2;
+        std::fs::write(&c, &other).unwrap();
+
+        let (cache, counters) = (HashCache::disabled(), PairCounters::default());
+        let size = body.len() as u64;
+        assert_eq!(compare(&a, &b, size, &cache, &counters).unwrap(), Some(full_hash(&a).unwrap()));
+        assert_eq!(compare(&a, &c, size, &cache, &counters).unwrap(), None);
+        let stats = counters.report();
+        assert_eq!((stats.compared, stats.differed), (2, 1));
+        assert!(stats.bytes_skipped > size, "{:?}", stats);
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
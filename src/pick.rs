@@ -0,0 +1,156 @@
+//! `--pick`: choose which duplicates to act on through an external fuzzy picker (fzf).
+//!
+//! Each non-canonical member is offered as `<id>\t<path>\t(dup of <canonical>)`. Only the
+//! leading numeric id of each selected line is parsed, so paths containing tabs, newlines or
+//! other odd characters can't confuse the mapping back to files.
+
+use crate::output::DuplicateGroup;
+use anyhow::{bail, Context, Result};
+use std::collections::BTreeSet;
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+/// Default picker command; `--multi` lets the user select several entries with Tab.
+pub const DEFAULT_PICKER: &str = "fzf --multi --prompt 'act on> '";
+
+/// [`DEFAULT_PICKER`] as an argument vector, run without a shell: `cmd` would keep the single
+/// quotes on Windows.
+const DEFAULT_PICKER_ARGV: [&str; 4] = ["fzf", "--multi", "--prompt", "act on> "];
+
+/// Run `picker` over the groups' non-canonical members and return the groups restricted to
+/// the canonical plus the selected members. Groups with nothing selected are dropped.
+pub fn pick(groups: &[DuplicateGroup], picker: &str) -> Result<Vec<DuplicateGroup>> {
+    let (input, index) = candidate_lines(groups);
+    if index.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let mut child = command(picker)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("failed to start picker `{}`", picker))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        // The picker may exit before reading everything (e.g. `head`); that's not an error.
+        let _ = stdin.write_all(input.as_bytes());
+    }
+    let out = child.wait_with_output().context("picker failed")?;
+    match out.status.code() {
+        Some(0) => {}
+        // fzf: 1 = no match, 130 = aborted with Esc/Ctrl-C. Both mean "select nothing".
+        Some(1) | Some(130) => return Ok(Vec::new()),
+        _ => bail!("picker `{}` exited with {}", picker, out.status),
+    }
+
+    let selected = parse_selection(&String::from_utf8_lossy(&out.stdout), index.len());
+    let chosen: BTreeSet<(usize, usize)> = selected.into_iter().map(|i| index[i]).collect();
+    Ok(restrict(groups, &chosen))
+}
+
+/// Build the picker input and a map from line id to (group index, member index).
+fn candidate_lines(groups: &[DuplicateGroup]) -> (String, Vec<(usize, usize)>) {
+    let mut input = String::new();
+    let mut index = Vec::new();
+    for (gi, g) in groups.iter().enumerate() {
+        let Some(canonical) = g.members.first() else { continue };
+        for (mi, m) in g.members.iter().enumerate().skip(1) {
+            input.push_str(&format!(
+                "{}\t{}\t(dup of {})\n",
+                index.len(),
+                m.display().to_string().escape_debug(),
+                canonical.display().to_string().escape_debug()
+            ));
+            index.push((gi, mi));
+        }
+    }
+    (input, index)
+}
+
+/// Parse the picker's stdout into line ids, ignoring anything that isn't a known id.
+fn parse_selection(out: &str, count: usize) -> BTreeSet<usize> {
+    out.lines()
+        .filter_map(|l| l.split('\t').next())
+        .filter_map(|id| id.trim().parse::<usize>().ok())
+        .filter(|id| *id < count)
+        .collect()
+}
+
+fn restrict(groups: &[DuplicateGroup], chosen: &BTreeSet<(usize, usize)>) -> Vec<DuplicateGroup> {
+    groups
+        .iter()
+        .enumerate()
+        .filter_map(|(gi, g)| {
+            let picked: Vec<_> = g
+                .members
+                .iter()
+                .enumerate()
+                .skip(1)
+                .filter(|(mi, _)| chosen.contains(&(gi, *mi)))
+                .map(|(_, p)| p.clone())
+                .collect();
+            if picked.is_empty() {
+                return None;
+            }
+            let mut sub = g.clone();
+            sub.members.truncate(1);
+            sub.members.extend(picked);
+            Some(sub)
+        })
+        .collect()
+}
+
+/// The process for `picker`: the default one directly, anything else through the shell.
+fn command(picker: &str) -> Command {
+    if picker != DEFAULT_PICKER {
+        return shell(picker);
+    }
+    let [program, args @ ..] = DEFAULT_PICKER_ARGV;
+    let mut c = Command::new(program);
+    c.args(args);
+    c
+}
+
+#[cfg(unix)]
+fn shell(cmd: &str) -> Command {
+    let mut c = Command::new("sh");
+    c.arg("-c").arg(cmd);
+    c
+}
+
+#[cfg(not(unix))]
+fn shell(cmd: &str) -> Command {
+    let mut c = Command::new("cmd");
+    c.arg("/C").arg(cmd);
+    c
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn selection_maps_back_by_id_only() {
+        let g = DuplicateGroup::new(
+            1,
+            vec!["/a".into(), "/b\tfake\n7".into(), "/c".into()],
+        );
+        let (input, index) = candidate_lines(std::slice::from_ref(&g));
+        assert_eq!(input.lines().count(), 2);
+
+        // Picker echoes the second line plus some garbage.
+        let out = format!("{}\nnot-an-id\n99\tbogus\n", input.lines().nth(1).unwrap());
+        let ids = parse_selection(&out, index.len());
+        let chosen = ids.into_iter().map(|i| index[i]).collect();
+        let picked = restrict(&[g], &chosen);
+        assert_eq!(picked.len(), 1);
+        assert_eq!(picked[0].members, vec![std::path::PathBuf::from("/a"), "/c".into()]);
+    }
+
+    #[test]
+    fn the_default_picker_runs_without_a_shell() {
+        let c = command(DEFAULT_PICKER);
+        assert_eq!(c.get_program(), "fzf");
+        assert_eq!(c.get_args().collect::<Vec<_>>(), ["--multi", "--prompt", "act on> "]);
+        assert_ne!(command("fzf").get_program(), "fzf");
+    }
+}
@@ -0,0 +1,243 @@
+//! Hashing while the walk is still running.
+//!
+//! Nothing could be hashed before the whole tree had been walked, because only then is it
+//! known which sizes repeat. On a large NAS tree that left the disks idle for minutes. The
+//! walk now feeds every file through [`Releases`] as it is found: once a size has been seen
+//! on three distinct files, those files (and every later one of that size) are certain to be
+//! quick-hashed, so they are sent down a channel to hashing workers right away. Sizes seen
+//! exactly twice are left for the pair comparison after the walk, which reads both files
+//! side by side and needs no quick hash.
+//!
+//! The quick hashes go through [`Matches`] the same way: once two files of a size share a
+//! quick hash, both (and every later one with that hash) are certain to be full-hashed, so a
+//! second pool of workers reads them in full while the walk and the quick hashing go on.
+//!
+//! Results are kept by path and taken by the hashing stages instead of reading again, errors
+//! included, so they are reported in one place. Read errors count against the file's device
+//! in the scan's [`MediaHealth`] as they happen, and files on a device it has given up on
+//! are left for the stages, which skip and count them.
+
+use crate::cache::{cached, FileId, HashCache};
+use crate::fs_utils::ScannedFile;
+use crate::hashing::{full_hash, quick_hash, QuickSample};
+use crate::health::MediaHealth;
+use anyhow::Result;
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{mpsc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Hashes (or the errors computing them) and the time they took, by path.
+pub type Hashes = HashMap<PathBuf, (Result<String>, Duration)>;
+
+/// What the workers computed during the walk.
+#[derive(Default)]
+pub struct Prefetched {
+    pub quick: Hashes,
+    pub full: Hashes,
+}
+
+/// Decides which files are certain to be quick-hashed, as the walk finds them.
+#[derive(Default)]
+pub struct Releases {
+    /// Distinct inodes seen per size until the third arrives; then the size is released.
+    held: HashMap<u64, Vec<(u64, u64, PathBuf)>>,
+}
+
+/// Files of one size are quick-hashed once there are this many distinct ones.
+const RELEASE_AT: usize = 3;
+
+impl Releases {
+    /// Files that can be hashed now that `f` has been found.
+    pub fn offer(&mut self, f: &ScannedFile) -> Vec<PathBuf> {
+        let held = self.held.entry(f.meta.size).or_default();
+        let id = (f.meta.dev, f.meta.ino);
+        // Hard links are hashed once, and which name gets hashed is only settled later.
+        if id != (0, 0) && held.iter().any(|(dev, ino, _)| (*dev, *ino) == id) {
+            return Vec::new();
+        }
+        if held.len() >= RELEASE_AT {
+            held.push((id.0, id.1, PathBuf::new()));
+            return vec![f.path.clone()];
+        }
+        held.push((id.0, id.1, f.path.clone()));
+        if held.len() < RELEASE_AT {
+            return Vec::new();
+        }
+        // Keep the ids (paths no longer needed) so later links are still recognized.
+        held.iter_mut().map(|(_, _, p)| std::mem::take(p)).collect()
+    }
+}
+
+/// Decides which files are certain to be full-hashed, as their quick hashes come in.
+#[derive(Default)]
+pub struct Matches {
+    /// The first file of each size and quick hash, until a second one shares it.
+    held: HashMap<(u64, String), Option<PathBuf>>,
+}
+
+impl Matches {
+    /// Files that can be fully hashed now that `path` of `size` quick-hashed to `hash`.
+    pub fn offer(&mut self, size: u64, hash: &str, path: PathBuf) -> Vec<PathBuf> {
+        match self.held.entry((size, hash.to_string())) {
+            Entry::Vacant(e) => {
+                e.insert(Some(path));
+                Vec::new()
+            }
+            Entry::Occupied(mut e) => e.get_mut().take().into_iter().chain([path]).collect(),
+        }
+    }
+}
+
+fn next<T>(rx: &Mutex<mpsc::Receiver<T>>) -> Option<T> {
+    rx.lock().unwrap_or_else(|e| e.into_inner()).recv().ok()
+}
+
+/// Run `walk` on this thread while `threads` workers quick-hash what [`Releases`] lets go
+/// and as many again full-hash what [`Matches`] lets go. Returns the walked files and the
+/// hashes that were computed.
+pub fn walk_and_hash(
+    walk: impl Iterator<Item = ScannedFile>,
+    cache: &HashCache,
+    health: &MediaHealth,
+    limit: u64,
+    sample: QuickSample,
+    threads: usize,
+) -> (Vec<ScannedFile>, Prefetched) {
+    let (quick_tx, quick_rx) = mpsc::channel::<(PathBuf, u64)>();
+    let (full_tx, full_rx) = mpsc::channel::<PathBuf>();
+    let (quick_rx, full_rx) = (Mutex::new(quick_rx), Mutex::new(full_rx));
+    let matches = Mutex::new(Matches::default());
+    std::thread::scope(|s| {
+        let (quick_rx, full_rx, matches) = (&quick_rx, &full_rx, &matches);
+        let quick_workers: Vec<_> = (0..threads.max(1))
+            .map(|_| {
+                // The full-hash workers stop once every quick-hash worker has dropped its
+                // sender, so nothing they could still be handed is missed.
+                let full_tx = full_tx.clone();
+                s.spawn(move || {
+                    let mut done = Hashes::new();
+                    while let Some((p, size)) = next(quick_rx) {
+                        if health.given_up(&p) {
+                            continue;
+                        }
+                        let started = Instant::now();
+                        let tag = sample.cache_tag(limit);
+                        let get = |id: &FileId| cache.quick(id, tag);
+                        let put = |id, h: &str| cache.put_quick(id, tag, h);
+                        let h = cached(&p, cache, get, put, || quick_hash(&p, limit, sample));
+                        match &h {
+                            Ok(h) => {
+                                let mut matches = matches.lock().unwrap_or_else(|e| e.into_inner());
+                                for full in matches.offer(size, h, p.clone()) {
+                                    let _ = full_tx.send(full);
+                                }
+                            }
+                            Err(e) => health.record(&p, e),
+                        }
+                        done.insert(p, (h, started.elapsed()));
+                    }
+                    done
+                })
+            })
+            .collect();
+        drop(full_tx);
+        let full_workers: Vec<_> = (0..threads.max(1))
+            .map(|_| {
+                s.spawn(move || {
+                    let mut done = Hashes::new();
+                    while let Some(p) = next(full_rx) {
+                        if health.given_up(&p) {
+                            continue;
+                        }
+                        let started = Instant::now();
+                        let get = |id: &FileId| cache.full(id);
+                        let put = |id, h: &str| cache.put_full(id, h);
+                        let h = cached(&p, cache, get, put, || full_hash(&p));
+                        if let Err(e) = &h {
+                            health.record(&p, e);
+                        }
+                        done.insert(p, (h, started.elapsed()));
+                    }
+                    done
+                })
+            })
+            .collect();
+        let mut releases = Releases::default();
+        let mut files = Vec::new();
+        for f in walk {
+            for p in releases.offer(&f) {
+                // The workers only stop once the sender is dropped below.
+                let _ = quick_tx.send((p, f.meta.size));
+            }
+            files.push(f);
+        }
+        drop(quick_tx);
+        let join = |w: std::thread::ScopedJoinHandle<'_, Hashes>| {
+            w.join().expect("hashing worker panicked")
+        };
+        let quick = quick_workers.into_iter().flat_map(join).collect();
+        let full = full_workers.into_iter().flat_map(join).collect();
+        (files, Prefetched { quick, full })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs_utils::FileMeta;
+
+    #[test]
+    fn sizes_are_released_at_the_third_distinct_file() {
+        let file = |path: &str, size, ino| ScannedFile {
+            path: path.into(),
+            meta: FileMeta { size, mtime_ns: 0, dev: 1, ino, nlink: 1 },
+        };
+        let mut r = Releases::default();
+        assert!(r.offer(&file("a", 10, 1)).is_empty());
+        assert!(r.offer(&file("b", 10, 2)).is_empty());
+        assert!(r.offer(&file("link", 10, 2)).is_empty());
+        assert!(r.offer(&file("other", 20, 3)).is_empty());
+        let c = r.offer(&file("c", 10, 4));
+        assert_eq!(c, [PathBuf::from("a"), "b".into(), "c".into()]);
+        assert_eq!(r.offer(&file("d", 10, 5)), [PathBuf::from("d")]);
+        assert!(r.offer(&file("link2", 10, 1)).is_empty());
+        assert!(r.offer(&file("link3", 10, 5)).is_empty());
+    }
+
+    #[test]
+    fn quick_hashes_are_released_at_the_second_match() {
+        let mut m = Matches::default();
+        assert!(m.offer(10, "x", "a".into()).is_empty());
+        assert!(m.offer(20, "x", "other-size".into()).is_empty());
+        assert!(m.offer(10, "y", "other-hash".into()).is_empty());
+        assert_eq!(m.offer(10, "x", "b".into()), [PathBuf::from("a"), "b".into()]);
+        assert_eq!(m.offer(10, "x", "c".into()), [PathBuf::from("c")]);
+    }
+
+    #[test]
+    fn matching_files_are_fully_hashed_during_the_walk() {
+        let dir = std::env::temp_dir().join(format!("ducky_prefetch_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        for (name, body) in [("a", b"same"), ("b", b"same"), ("c", b"diff"), ("d", b"same")] {
+            std::fs::write(dir.join(name), body).unwrap();
+        }
+        let walk = ["a", "b", "c", "d"].map(|n| {
+            let path = dir.join(n);
+            ScannedFile { meta: FileMeta::of(&path).unwrap(), path }
+        });
+        let health = MediaHealth::default();
+        let cache = HashCache::disabled();
+        let (files, got) =
+            walk_and_hash(walk.into_iter(), &cache, &health, 4096, QuickSample::Head, 2);
+        assert_eq!(files.len(), 4);
+        assert_eq!(got.quick.len(), 4);
+        let mut full: Vec<&PathBuf> = got.full.keys().collect();
+        full.sort();
+        assert_eq!(full, [&dir.join("a"), &dir.join("b"), &dir.join("d")]);
+        assert!(got.full.values().all(|(h, _)| h.is_ok()));
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
@@ -0,0 +1,209 @@
+//! Progress on stderr: a spinner while files are discovered, then a bar for the hashing
+//! stages with throughput and ETA.
+//!
+//! Before hashing, `predict` estimates how many bytes stages 2 and 3 will read from the
+//! size buckets alone. The bar derives the ETA from observed throughput, and the total is
+//! replaced by the exact figure once the quick-hash stage has narrowed the candidates.
+
+use crate::hashing::QuickSample;
+#[cfg(feature = "cli")]
+use indicatif::{ProgressBar, ProgressStyle};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+#[cfg(feature = "cli")]
+use std::sync::{atomic::AtomicBool, Arc};
+#[cfg(feature = "cli")]
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+/// Bytes the hashing stages are expected to read.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Prediction {
+    /// Exact: every candidate is quick-hashed up to the quick-bytes limit.
+    pub quick_bytes: u64,
+    /// Approximate number of candidates expected to survive the quick hash.
+    pub duplicates: u64,
+    /// Approximate bytes the full hash will read for those survivors.
+    pub full_bytes: u64,
+}
+
+impl Prediction {
+    pub fn total(&self) -> u64 {
+        self.quick_bytes.saturating_add(self.full_bytes)
+    }
+}
+
+/// Share of same-size files expected to be real copies. Small files share a size by chance
+/// far more often than multi-megabyte ones do.
+fn survival(size: u64) -> f64 {
+    match size {
+        0..=4095 => 0.25,
+        4096..=1_048_575 => 0.5,
+        _ => 0.9,
+    }
+}
+
+/// Estimate the hashing work from the size buckets (only buckets with 2+ members are hashed).
+pub fn predict(buckets: &[(u64, &Vec<PathBuf>)], limit: u64, sample: QuickSample) -> Prediction {
+    let mut p = Prediction::default();
+    for (size, paths) in buckets {
+        let n = paths.len() as u64;
+        p.quick_bytes = p.quick_bytes.saturating_add(sample.bytes(*size, limit).saturating_mul(n));
+        // A lone survivor isn't a duplicate, so a bucket yields either 0 or 2+ survivors.
+        let expected = (n as f64 * survival(*size)).round() as u64;
+        let dupes = if expected >= 2 { expected } else { 0 };
+        p.duplicates += dupes;
+        p.full_bytes = p.full_bytes.saturating_add(size.saturating_mul(dupes));
+    }
+    p
+}
+
+/// Remaining time at the throughput seen so far; `None` until there is something to go on.
+pub fn eta(done: u64, total: u64, elapsed: Duration) -> Option<Duration> {
+    if done == 0 || elapsed.is_zero() {
+        return None;
+    }
+    let rate = done as f64 / elapsed.as_secs_f64();
+    Some(Duration::from_secs_f64(total.saturating_sub(done) as f64 / rate))
+}
+
+/// Progress bar for the hashing stages on stderr; hidden when disabled or built without the
+/// `cli` feature.
+pub struct Progress {
+    done: AtomicU64,
+    #[cfg(feature = "cli")]
+    bar: ProgressBar,
+}
+
+impl Progress {
+    pub fn start(total: u64, enabled: bool) -> Self {
+        crate::status::set_total(total);
+        #[cfg(feature = "cli")]
+        let bar = {
+            let bar = if enabled { ProgressBar::new(total) } else { ProgressBar::hidden() };
+            let style = ProgressStyle::with_template(concat!(
+                "{prefix:>10} [{bar:30}] {bytes} / ~{total_bytes}",
+                " · {binary_bytes_per_sec} · ETA {eta}",
+            ))
+            .expect("valid template")
+            .progress_chars("=> ");
+            bar.set_style(style);
+            bar.set_prefix("quick-hash");
+            bar.enable_steady_tick(Duration::from_millis(200));
+            bar
+        };
+        #[cfg(not(feature = "cli"))]
+        let _ = enabled;
+        Progress {
+            done: AtomicU64::new(0),
+            #[cfg(feature = "cli")]
+            bar,
+        }
+    }
+
+    /// Label the bar with the current stage.
+    pub fn stage(&self, name: &'static str) {
+        #[cfg(feature = "cli")]
+        self.bar.set_prefix(name);
+        #[cfg(not(feature = "cli"))]
+        let _ = name;
+    }
+
+    /// Count one file of `bytes` as hashed.
+    pub fn add(&self, bytes: u64) {
+        crate::status::hashed(bytes);
+        self.done.fetch_add(bytes, Ordering::Relaxed);
+        #[cfg(feature = "cli")]
+        self.bar.inc(bytes);
+    }
+
+    /// Bytes hashed so far.
+    pub fn done(&self) -> u64 {
+        self.done.load(Ordering::Relaxed)
+    }
+
+    /// Replace the predicted total with a better one.
+    pub fn set_total(&self, total: u64) {
+        crate::status::set_total(total);
+        #[cfg(feature = "cli")]
+        self.bar.set_length(total);
+    }
+
+    /// Remove the bar.
+    pub fn finish(self) {
+        #[cfg(feature = "cli")]
+        self.bar.finish_and_clear();
+    }
+}
+
+/// Spinner counting files found while the tree is walked; hidden when disabled or built
+/// without the `cli` feature.
+pub struct Discovery {
+    #[cfg(feature = "cli")]
+    ticker: Option<(ProgressBar, Arc<AtomicBool>, JoinHandle<()>)>,
+}
+
+impl Discovery {
+    #[cfg(not(feature = "cli"))]
+    pub fn start(_enabled: bool) -> Self {
+        Discovery {}
+    }
+
+    #[cfg(feature = "cli")]
+    pub fn start(enabled: bool) -> Self {
+        if !enabled {
+            return Discovery { ticker: None };
+        }
+        let bar = ProgressBar::new_spinner();
+        let style = ProgressStyle::with_template("{spinner} discovering: {pos} files · {elapsed}")
+            .expect("valid template");
+        bar.set_style(style);
+        // The walk counts into the shared status counters; mirror them here.
+        let stop = Arc::new(AtomicBool::new(false));
+        let handle = {
+            let (bar, stop) = (bar.clone(), Arc::clone(&stop));
+            std::thread::spawn(move || {
+                while !stop.load(Ordering::Relaxed) {
+                    bar.set_position(crate::status::found());
+                    bar.tick();
+                    std::thread::sleep(Duration::from_millis(100));
+                }
+            })
+        };
+        Discovery { ticker: Some((bar, stop, handle)) }
+    }
+
+    /// Stop the spinner and remove it.
+    pub fn finish(self) {
+        #[cfg(feature = "cli")]
+        if let Some((bar, stop, handle)) = self.ticker {
+            stop.store(true, Ordering::Relaxed);
+            let _ = handle.join();
+            bar.finish_and_clear();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn predicts_from_size_buckets() {
+        let small = vec![PathBuf::from("a"), PathBuf::from("b")];
+        let big: Vec<PathBuf> = (0..10).map(|i| PathBuf::from(i.to_string())).collect();
+        let p = predict(&[(100, &small), (2_000_000, &big)], 4096, QuickSample::Head);
+        assert_eq!(p.quick_bytes, 2 * 100 + 10 * 4096);
+        // Two 100-byte files: one expected survivor, which can't be a duplicate on its own.
+        assert_eq!(p.duplicates, 9);
+        assert_eq!(p.full_bytes, 9 * 2_000_000);
+        assert_eq!(p.total(), p.quick_bytes + p.full_bytes);
+    }
+
+    #[test]
+    fn eta_follows_throughput() {
+        assert_eq!(eta(0, 100, Duration::from_secs(1)), None);
+        assert_eq!(eta(25, 100, Duration::from_secs(1)), Some(Duration::from_secs(3)));
+        assert_eq!(eta(100, 100, Duration::from_secs(4)), Some(Duration::ZERO));
+    }
+}
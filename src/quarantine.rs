@@ -0,0 +1,272 @@
+//! `--move-to DIR`: soft-delete duplicates by moving them into a quarantine directory.
+//!
+//! Files keep their absolute path below `DIR/.ducky-quarantine`
+//! (`/home/a/x.jpg` → `DIR/.ducky-quarantine/home/a/x.jpg`), so restoring is a plain move
+//! back. Only that subdirectory is marked as ducky's own, so anything else in `DIR` is still
+//! scanned. Every quarantined file is listed in
+//! `DIR/.ducky-quarantine.jsonl` with an optional expiry (`--expire-after 30d`);
+//! `ducky quarantine purge DIR` permanently removes files past their expiry.
+
+use crate::actions::{
+    run_action, same_inode, Action, ActionOptions, ActionStats, Outcome, Planned, Step,
+};
+use crate::fs_utils::create_artifact_dir;
+use crate::journal::{Journal, Op};
+use crate::output::DuplicateGroup;
+use crate::replicate::move_file;
+use crate::warnings::warning;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Component, Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const INDEX: &str = ".ducky-quarantine.jsonl";
+/// Subdirectory of the quarantine directory holding the moved files.
+const STORE: &str = ".ducky-quarantine";
+
+/// One quarantined file. `stored` is relative to the quarantine directory.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Entry {
+    pub original: PathBuf,
+    pub stored: PathBuf,
+    pub size: u64,
+    pub hash: String,
+    pub quarantined: u64,
+    /// Unix time after which `purge` may remove the file; `None` keeps it until removed by hand.
+    pub expires: Option<u64>,
+}
+
+/// Parse an expiry such as `30d`, `12h`, `2w` or `90m` into seconds.
+pub fn parse_expiry(s: &str) -> Result<u64, String> {
+    let s = s.trim();
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (num, unit) = s.split_at(split);
+    let n: u64 = num.parse().map_err(|_| format!("invalid duration: {}", s))?;
+    let scale = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 3600,
+        "d" | "" => 86_400,
+        "w" => 7 * 86_400,
+        _ => return Err(format!("unknown unit in {} (use s, m, h, d or w)", s)),
+    };
+    n.checked_mul(scale).filter(|v| *v > 0).ok_or_else(|| format!("invalid duration: {}", s))
+}
+
+/// Where `path` is stored, relative to the quarantine directory.
+fn stored_path(path: &Path) -> PathBuf {
+    let abs = std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+    let store = PathBuf::from(STORE);
+    abs.components()
+        .filter_map(|c| match c {
+            // `C:` becomes `C`; other prefixes keep their spelling minus colons.
+            Component::Prefix(p) => {
+                Some(PathBuf::from(p.as_os_str().to_string_lossy().replace(':', "")))
+            }
+            Component::Normal(n) => Some(PathBuf::from(n)),
+            _ => None,
+        })
+        .fold(store, |acc, c| acc.join(c))
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+/// `--move-to`: move duplicates into the quarantine directory and list them in its index.
+struct Quarantine<'a> {
+    dir: &'a Path,
+    expire_after: Option<u64>,
+    /// Open quarantine index; `None` under --dry-run.
+    index: RefCell<Option<File>>,
+}
+
+impl Action for Quarantine<'_> {
+    fn plan(&self, g: &DuplicateGroup, _: &Journal) -> Vec<Planned> {
+        let canonical = &g.members[0];
+        g.members
+            .iter()
+            .skip(1)
+            .map(|dupe| {
+                if same_inode(canonical, dupe) {
+                    return Planned::SameInode;
+                }
+                let to = self.dir.join(stored_path(dupe));
+                Planned::Run(Step { op: Op::Move, path: dupe.clone(), target: to })
+            })
+            .collect()
+    }
+
+    fn execute(&self, step: &Step, g: &DuplicateGroup, _: &Journal) -> Outcome {
+        if let Err(e) = move_file(&step.path, &step.target, &g.hash) {
+            return Outcome::Failed(e);
+        }
+        let quarantined = now();
+        let entry = Entry {
+            original: std::path::absolute(&step.path).unwrap_or_else(|_| step.path.clone()),
+            stored: stored_path(&step.path),
+            size: g.size,
+            hash: g.hash.clone(),
+            quarantined,
+            expires: self.expire_after.map(|s| quarantined.saturating_add(s)),
+        };
+        if let Some(index) = self.index.borrow_mut().as_mut() {
+            let res = serde_json::to_string(&entry)
+                .map_err(std::io::Error::from)
+                .and_then(|line| writeln!(index, "{}", line));
+            if let Err(e) = res {
+                warning!("failed to write quarantine index: {}", e);
+            }
+        }
+        Outcome::Done
+    }
+}
+
+/// Move every non-canonical member into `dir` (with `--yes`), recording each in the
+/// quarantine index and the action journal.
+pub fn apply_quarantine(
+    groups: &[DuplicateGroup],
+    dir: &Path,
+    expire_after: Option<u64>,
+    opts: &ActionOptions,
+    journal: &mut Journal,
+) -> ActionStats {
+    let index = if opts.dry_run || groups.is_empty() || !opts.yes {
+        Ok(None)
+    } else {
+        create_artifact_dir(&dir.join(STORE))
+            .and_then(|_| OpenOptions::new().create(true).append(true).open(dir.join(INDEX)))
+            .map(Some)
+    };
+    let index = match index {
+        Ok(f) => f,
+        Err(e) => {
+            warning!("cannot use quarantine {}: {}", dir.display(), e);
+            let mut stats = ActionStats::default();
+            opts.failed(&mut stats);
+            return stats;
+        }
+    };
+    let action = Quarantine { dir, expire_after, index: RefCell::new(index) };
+    run_action(&action, groups, opts, journal)
+}
+
+fn load_index(dir: &Path) -> Result<Vec<Entry>> {
+    let path = dir.join(INDEX);
+    let f = fs::File::open(&path).with_context(|| format!("open {}", path.display()))?;
+    let mut out = Vec::new();
+    for line in BufReader::new(f).lines() {
+        let line = line.with_context(|| format!("read {}", path.display()))?;
+        if let Ok(e) = serde_json::from_str::<Entry>(&line) {
+            out.push(e);
+        }
+    }
+    Ok(out)
+}
+
+/// Result of `purge`: files removed (and their bytes) and files still quarantined.
+#[derive(Debug, Default)]
+pub struct PurgeStats {
+    pub purged: usize,
+    pub bytes: u64,
+    pub kept: usize,
+}
+
+/// Permanently remove quarantined files whose expiry is at or before `at` (Unix seconds),
+/// and rewrite the index with the remaining entries.
+pub fn purge(dir: &Path, at: u64) -> Result<PurgeStats> {
+    let mut stats = PurgeStats::default();
+    let mut keep = Vec::new();
+    for e in load_index(dir)? {
+        if e.expires.is_none_or(|t| t > at) {
+            stats.kept += 1;
+            keep.push(e);
+            continue;
+        }
+        match fs::remove_file(dir.join(&e.stored)) {
+            Ok(()) => {
+                stats.purged += 1;
+                stats.bytes += e.size;
+            }
+            // Restored or removed by hand: just drop it from the index.
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+            Err(err) => {
+                warning!("Failed to purge {}: {}", dir.join(&e.stored).display(), err);
+                stats.kept += 1;
+                keep.push(e);
+            }
+        }
+    }
+    let path = dir.join(INDEX);
+    let tmp = dir.join(format!("{}.tmp", INDEX));
+    let mut body = String::new();
+    for e in &keep {
+        body.push_str(&serde_json::to_string(e)?);
+        body.push('\n');
+    }
+    fs::write(&tmp, body).with_context(|| format!("write {}", tmp.display()))?;
+    fs::rename(&tmp, &path).with_context(|| format!("write {}", path.display()))?;
+    Ok(stats)
+}
+
+/// `ducky quarantine purge DIR`.
+pub fn run_purge(dir: &Path) -> Result<()> {
+    let stats = purge(dir, now())?;
+    println!(
+        "Purged {} files ({}); {} still quarantined",
+        stats.purged,
+        bytesize::ByteSize(stats.bytes),
+        stats.kept
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_expiry_units() {
+        assert_eq!(parse_expiry("30d"), Ok(30 * 86_400));
+        assert_eq!(parse_expiry("12h"), Ok(12 * 3600));
+        assert_eq!(parse_expiry("2w"), Ok(14 * 86_400));
+        assert!(parse_expiry("0d").is_err());
+        assert!(parse_expiry("3y").is_err());
+    }
+
+    #[test]
+    fn purge_removes_only_expired_files() {
+        let base = std::env::temp_dir().join(format!("ducky_quarantine_{}", std::process::id()));
+        let data = base.join("data");
+        let q = base.join("q");
+        std::fs::create_dir_all(&data).unwrap();
+        for name in ["a", "b", "c"] {
+            std::fs::write(data.join(name), b"same").unwrap();
+        }
+        let hash = crate::hashing::full_hash(&data.join("a")).unwrap();
+        let group = DuplicateGroup::new(4, vec![data.join("a"), data.join("b")]).with_hash(hash.clone());
+        let opts = ActionOptions { yes: true, ..Default::default() };
+        let stats = apply_quarantine(&[group], &q, Some(60), &opts, &mut Journal::in_memory());
+        assert_eq!(stats.moved, 1);
+        assert!(!data.join("b").exists());
+        let group = DuplicateGroup::new(4, vec![data.join("a"), data.join("c")]).with_hash(hash);
+        apply_quarantine(&[group], &q, None, &opts, &mut Journal::in_memory());
+
+        let stored_b = q.join(stored_path(&data.join("b")));
+        let stored_c = q.join(stored_path(&data.join("c")));
+        assert!(stored_b.exists() && stored_c.exists());
+        assert!(stored_b.starts_with(q.join(STORE)));
+
+        // Not yet expired.
+        assert_eq!(purge(&q, now()).unwrap().purged, 0);
+        let stats = purge(&q, now() + 120).unwrap();
+        assert_eq!((stats.purged, stats.kept), (1, 1));
+        assert!(!stored_b.exists());
+        assert!(stored_c.exists());
+
+        let _ = std::fs::remove_dir_all(base);
+    }
+}
@@ -0,0 +1,169 @@
+//! `--raw-jpeg-pairs`: treat a camera RAW file and the JPEG saved next to it
+//! (`IMG_0001.CR2` + `IMG_0001.JPG`) as one photo.
+//!
+//! Members of duplicate groups are matched with the other half of their pair: a file in the
+//! same directory with the same stem (in any case). Groups then prefer a complete pair as the
+//! canonical, and the group holding those JPEGs keeps the canonical RAW's own JPEG. A half is
+//! only acted on when its other half goes too, in favour of the canonical's; otherwise both
+//! are held back, so an action never leaves a lone RAW or JPEG behind.
+
+use crate::output::DuplicateGroup;
+use crate::reference::Reference;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Extensions of camera RAW formats, lowercase.
+pub const RAW_EXTS: &[&str] = &[
+    "3fr", "arw", "cr2", "cr3", "crw", "dng", "erf", "iiq", "kdc", "mef", "mos", "mrw", "nef",
+    "nrw", "orf", "pef", "raf", "raw", "rw2", "rwl", "sr2", "srf", "srw", "x3f",
+];
+const JPEG_EXTS: &[&str] = &["jpg", "jpeg"];
+
+fn has_ext(path: &Path, exts: &[&str]) -> bool {
+    let ext = path.extension().and_then(|e| e.to_str()).map(|e| e.to_ascii_lowercase());
+    ext.is_some_and(|e| exts.contains(&e.as_str()))
+}
+
+pub fn is_raw(path: &Path) -> bool {
+    has_ext(path, RAW_EXTS)
+}
+
+pub fn is_jpeg(path: &Path) -> bool {
+    has_ext(path, JPEG_EXTS)
+}
+
+/// Directory listings by lowercase file stem, read once per directory.
+#[derive(Default)]
+struct Siblings {
+    dirs: HashMap<PathBuf, HashMap<String, Vec<PathBuf>>>,
+}
+
+impl Siblings {
+    /// The other half of `path`'s pair: a JPEG next to a RAW file, or a RAW file next to a
+    /// JPEG. None when there isn't exactly one.
+    fn partner(&mut self, path: &Path) -> Option<PathBuf> {
+        let want = if is_raw(path) {
+            JPEG_EXTS
+        } else if is_jpeg(path) {
+            RAW_EXTS
+        } else {
+            return None;
+        };
+        let dir = path.parent()?.to_path_buf();
+        let stem = path.file_stem()?.to_str()?.to_lowercase();
+        let by_stem = self.dirs.entry(dir).or_insert_with_key(|dir| {
+            let mut by_stem: HashMap<String, Vec<PathBuf>> = HashMap::new();
+            let listing = match dir.as_os_str().is_empty() {
+                true => fs::read_dir("."),
+                false => fs::read_dir(dir),
+            };
+            for ent in listing.into_iter().flatten().flatten() {
+                let p = ent.path();
+                let Some(s) = p.file_stem().and_then(|s| s.to_str()) else { continue };
+                if ent.file_type().is_ok_and(|t| t.is_file()) {
+                    by_stem.entry(s.to_lowercase()).or_default().push(dir.join(ent.file_name()));
+                }
+            }
+            by_stem
+        });
+        let mut halves = by_stem.get(&stem)?.iter().filter(|p| has_ext(p, want));
+        match (halves.next(), halves.next()) {
+            (Some(p), None) => Some(p.clone()),
+            _ => None,
+        }
+    }
+}
+
+/// Record the other half of every paired member in its group's `pairs`, and order members so
+/// that complete pairs come first and JPEG groups keep the canonical RAW's JPEG. The ordering
+/// is stable, so ranking applied afterwards (`--protect`, `--prefer`) still takes precedence.
+/// A `--compare` reference copy stays the canonical whether or not it is paired.
+pub fn pair_up(groups: &mut [DuplicateGroup], reference: &Reference) {
+    let pinned = |m: &Path| !reference.is_empty() && reference.contains(m);
+    let mut siblings = Siblings::default();
+    for g in groups.iter_mut() {
+        let pairs: BTreeMap<PathBuf, PathBuf> =
+            g.members.iter().filter_map(|m| Some((m.clone(), siblings.partner(m)?))).collect();
+        g.rank_members(|m| (!pinned(m), !pairs.contains_key(m)));
+        g.pairs = pairs;
+    }
+    let canonical_raws: HashSet<PathBuf> =
+        groups.iter().map(|g| g.members[0].clone()).filter(|c| is_raw(c)).collect();
+    for g in groups.iter_mut() {
+        let pairs = std::mem::take(&mut g.pairs);
+        let keeps_canonical_raw =
+            |m: &Path| is_jpeg(m) && pairs.get(m).is_some_and(|r| canonical_raws.contains(r));
+        g.rank_members(|m| (!pinned(m), !keeps_canonical_raw(m)));
+        g.pairs = pairs;
+    }
+}
+
+/// Members that can't be acted on without splitting their pair: their other half is not a
+/// duplicate dropped in favour of the canonical's other half. Call on the groups that will be
+/// acted on, after the final ranking and any review; the first `keep_copies` members of each
+/// group (at least the canonical) are kept.
+pub fn split_pairs(groups: &[DuplicateGroup], keep_copies: usize) -> HashSet<PathBuf> {
+    let kept = keep_copies.max(1);
+    // The canonical each member is dropped for; None for members that are kept.
+    let dropped_for: HashMap<&PathBuf, Option<&PathBuf>> = groups
+        .iter()
+        .flat_map(|g| {
+            let canonical = &g.members[0];
+            g.members.iter().enumerate().map(move |(i, m)| (m, (i >= kept).then_some(canonical)))
+        })
+        .collect();
+    let mut held = HashSet::new();
+    for g in groups {
+        let kept_half = g.pairs.get(&g.members[0]);
+        for m in g.members.iter().skip(kept) {
+            let Some(half) = g.pairs.get(m) else { continue };
+            // The other half goes too only if it is dropped for the kept member's other half.
+            let goes = dropped_for.get(half).is_some_and(|c| c.is_some() && *c == kept_half);
+            if !goes {
+                held.insert(m.clone());
+            }
+        }
+    }
+    held
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pairs_go_together_or_not_at_all() {
+        let dir = std::env::temp_dir().join(format!("ducky_rawpairs_{}", std::process::id()));
+        let p = |s: &str| dir.join(s);
+        // a/ has the RAW alone, b/ another shot whose JPEG matches, c/ and d/ the same pair.
+        for (path, data) in [
+            ("a/IMG_1.CR2", "raw"),
+            ("b/IMG_1.NEF", "other raw"),
+            ("b/IMG_1.JPG", "jpg"),
+            ("c/IMG_1.CR2", "raw"),
+            ("c/img_1.jpg", "jpg"),
+            ("d/IMG_1.CR2", "raw"),
+            ("d/IMG_1.JPG", "jpg"),
+        ] {
+            fs::create_dir_all(p(path).parent().unwrap()).unwrap();
+            fs::write(p(path), data).unwrap();
+        }
+        let group = |names: [&str; 3]| DuplicateGroup::new(3, names.map(p).to_vec());
+        let raws = group(["a/IMG_1.CR2", "c/IMG_1.CR2", "d/IMG_1.CR2"]);
+        let jpegs = group(["b/IMG_1.JPG", "c/img_1.jpg", "d/IMG_1.JPG"]);
+        let mut groups = [raws, jpegs];
+        pair_up(&mut groups, &Reference::default());
+        // The first complete pair is kept, and its JPEG rather than b/'s.
+        assert_eq!(groups[0].members, [p("c/IMG_1.CR2"), p("d/IMG_1.CR2"), p("a/IMG_1.CR2")]);
+        assert_eq!(groups[1].members[0], p("c/img_1.jpg"));
+        assert_eq!(groups[1].pairs[&p("b/IMG_1.JPG")], p("b/IMG_1.NEF"));
+
+        // d/ goes as a pair and a/'s lone RAW on its own; b/'s JPEG stays with its RAW.
+        assert_eq!(split_pairs(&groups, 1), HashSet::from([p("b/IMG_1.JPG")]));
+        // With two copies kept, d/'s RAW stays but its JPEG would go alone.
+        assert_eq!(split_pairs(&groups, 2), HashSet::from([p("d/IMG_1.JPG")]));
+
+        let _ = fs::remove_dir_all(dir);
+    }
+}
@@ -0,0 +1,106 @@
+//! `--compare`: scan against reference trees that are hashed but never modified. Only content
+//! found both in a reference tree and elsewhere is reported, with a reference copy as the
+//! canonical, so listings and actions cover nothing but the copies outside.
+
+use crate::output::DuplicateGroup;
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The (canonicalized) reference directories of a run; empty outside compare mode.
+#[derive(Debug, Default, Clone)]
+pub struct Reference {
+    dirs: Vec<PathBuf>,
+}
+
+impl Reference {
+    pub fn new(dirs: &[PathBuf]) -> Result<Self> {
+        let dirs = dirs
+            .iter()
+            .map(|d| fs::canonicalize(d).with_context(|| format!("--compare {}", d.display())))
+            .collect::<Result<_>>()?;
+        Ok(Self { dirs })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.dirs.is_empty()
+    }
+
+    /// The canonicalized reference directories.
+    pub fn dirs(&self) -> &[PathBuf] {
+        &self.dirs
+    }
+
+    /// Whether `path` lies in a reference tree. Its directory is resolved, so the answer does
+    /// not depend on how the roots were spelled.
+    pub fn contains(&self, path: &Path) -> bool {
+        let dir = match path.parent() {
+            Some(d) if !d.as_os_str().is_empty() => d,
+            _ => Path::new("."),
+        };
+        let (Ok(dir), Some(name)) = (fs::canonicalize(dir), path.file_name()) else {
+            return false;
+        };
+        let real = dir.join(name);
+        self.dirs.iter().any(|r| real.starts_with(r))
+    }
+
+    /// Whether a size bucket can still produce a reported group: it needs a file on each side.
+    pub fn spans(&self, paths: &[PathBuf]) -> bool {
+        if self.is_empty() {
+            return true;
+        }
+        let inside = paths.iter().filter(|p| self.contains(p)).count();
+        inside > 0 && inside < paths.len()
+    }
+
+    /// `group` as compare mode reports it: `None` unless it has members on both sides,
+    /// otherwise the first reference member as canonical followed by the members outside.
+    /// Further reference copies are dropped; duplicates within the reference are not this
+    /// mode's business.
+    pub fn restrict(&self, mut group: DuplicateGroup) -> Option<DuplicateGroup> {
+        if self.is_empty() {
+            return Some(group);
+        }
+        let (inside, outside): (Vec<PathBuf>, Vec<PathBuf>) =
+            group.members.drain(..).partition(|m| self.contains(m));
+        let keep = inside.into_iter().next()?;
+        if outside.is_empty() {
+            return None;
+        }
+        group.members = std::iter::once(keep).chain(outside).collect();
+        let members = &group.members;
+        group.links.retain(|l| members.contains(l));
+        group.degraded.retain(|d| members.contains(d));
+        Some(group)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_groups_spanning_the_reference_are_kept() {
+        let base = std::env::temp_dir().join(format!("ducky_reference_{}", std::process::id()));
+        let (lib, inbox) = (base.join("library"), base.join("inbox"));
+        std::fs::create_dir_all(&lib).unwrap();
+        std::fs::create_dir_all(&inbox).unwrap();
+        let paths = [lib.join("a"), lib.join("b"), inbox.join("a"), inbox.join("c")];
+        for p in &paths {
+            std::fs::write(p, b"x").unwrap();
+        }
+
+        let reference = Reference::new(std::slice::from_ref(&lib)).unwrap();
+        assert!(reference.contains(&lib.join("..").join("library").join("a")));
+        assert!(!reference.spans(&paths[..2]));
+        assert!(reference.spans(&paths[1..3]));
+
+        let group = reference.restrict(DuplicateGroup::new(1, paths.to_vec())).unwrap();
+        // "inbox" sorts first, yet a library copy is the canonical.
+        assert_eq!(group.members, vec![paths[0].clone(), paths[2].clone(), paths[3].clone()]);
+        assert!(reference.restrict(DuplicateGroup::new(1, paths[2..].to_vec())).is_none());
+
+        let _ = std::fs::remove_dir_all(base);
+    }
+}
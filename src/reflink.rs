@@ -0,0 +1,133 @@
+//! Copy-on-write clones: FICLONE on Linux (btrfs, XFS, bcachefs, ...), clonefile on macOS
+//! (APFS). Other platforms report every clone as unsupported.
+//!
+//! [`dedupe_range`] shares the extents of two existing files instead (FIDEDUPERANGE on Linux):
+//! the kernel compares the bytes itself and only shares them when they are identical.
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Create `dst` (which must not exist) as a copy-on-write clone of `src`.
+#[cfg(target_os = "linux")]
+pub fn clone_file(src: &Path, dst: &Path) -> io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+    // _IOW(0x94, 9, int); not exported by every libc version.
+    const FICLONE: u64 = 0x4004_9409;
+
+    let s = fs::File::open(src)?;
+    let d = fs::OpenOptions::new().write(true).create_new(true).open(dst)?;
+    // SAFETY: both descriptors are valid for the duration of the call.
+    let rc = unsafe { libc::ioctl(d.as_raw_fd(), FICLONE as _, s.as_raw_fd()) };
+    if rc == 0 {
+        return Ok(());
+    }
+    let err = io::Error::last_os_error();
+    drop(d);
+    let _ = fs::remove_file(dst);
+    Err(err)
+}
+
+#[cfg(target_os = "macos")]
+pub fn clone_file(src: &Path, dst: &Path) -> io::Result<()> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    extern "C" {
+        fn clonefile(src: *const libc::c_char, dst: *const libc::c_char, flags: u32) -> libc::c_int;
+    }
+    let s = CString::new(src.as_os_str().as_bytes()).map_err(io::Error::other)?;
+    let d = CString::new(dst.as_os_str().as_bytes()).map_err(io::Error::other)?;
+    // SAFETY: both are valid NUL-terminated paths for the duration of the call.
+    if unsafe { clonefile(s.as_ptr(), d.as_ptr(), 0) } == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+pub fn clone_file(_src: &Path, _dst: &Path) -> io::Result<()> {
+    Err(io::Error::from(io::ErrorKind::Unsupported))
+}
+
+/// Make `dst` share `src`'s extents in place with FIDEDUPERANGE. The kernel locks both files,
+/// compares them and only shares ranges that are byte-for-byte identical; if they differ,
+/// nothing changes and an `InvalidData` error is returned.
+#[cfg(target_os = "linux")]
+pub fn dedupe_range(src: &Path, dst: &Path) -> io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+    // _IOWR(0x94, 54, struct file_dedupe_range); not exported by every libc version.
+    const FIDEDUPERANGE: u64 = 0xC018_9436;
+    const FILE_DEDUPE_RANGE_DIFFERS: i32 = 1;
+
+    /// `struct file_dedupe_range` with its single `struct file_dedupe_range_info`.
+    #[repr(C)]
+    struct DedupeRange {
+        src_offset: u64,
+        src_length: u64,
+        dest_count: u16,
+        reserved1: u16,
+        reserved2: u32,
+        dest_fd: i64,
+        dest_offset: u64,
+        bytes_deduped: u64,
+        status: i32,
+        reserved: u32,
+    }
+
+    let s = fs::File::open(src)?;
+    let d = fs::OpenOptions::new().read(true).write(true).open(dst)?;
+    let len = s.metadata()?.len();
+    if d.metadata()?.len() != len {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "sizes differ"));
+    }
+    // Filesystems cap how much one call dedupes (16 MiB on btrfs), so continue where it stopped.
+    let mut offset = 0;
+    while offset < len {
+        let mut arg = DedupeRange {
+            src_offset: offset,
+            src_length: len - offset,
+            dest_count: 1,
+            reserved1: 0,
+            reserved2: 0,
+            dest_fd: d.as_raw_fd() as i64,
+            dest_offset: offset,
+            bytes_deduped: 0,
+            status: 0,
+            reserved: 0,
+        };
+        // SAFETY: both descriptors are valid and `arg` outlives the call.
+        let rc = unsafe { libc::ioctl(s.as_raw_fd(), FIDEDUPERANGE as _, &mut arg) };
+        if rc != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        match arg.status {
+            FILE_DEDUPE_RANGE_DIFFERS => {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "contents differ"));
+            }
+            status if status < 0 => return Err(io::Error::from_raw_os_error(-status)),
+            _ if arg.bytes_deduped == 0 => break,
+            _ => offset += arg.bytes_deduped,
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn dedupe_range(_src: &Path, _dst: &Path) -> io::Result<()> {
+    Err(io::Error::from(io::ErrorKind::Unsupported))
+}
+
+/// Whether `e` means "this filesystem (pair) can't clone" rather than a real failure.
+pub fn is_unsupported(e: &io::Error) -> bool {
+    if e.kind() == io::ErrorKind::Unsupported || e.kind() == io::ErrorKind::CrossesDevices {
+        return true;
+    }
+    #[cfg(unix)]
+    if let Some(code) = e.raw_os_error() {
+        return [libc::EOPNOTSUPP, libc::ENOTSUP, libc::EXDEV, libc::EINVAL, libc::ENOTTY]
+            .contains(&code);
+    }
+    false
+}
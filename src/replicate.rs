@@ -0,0 +1,302 @@
+//! `--replicate N`: keep at most N copies of each content, spread over distinct devices.
+//!
+//! Instead of collapsing a group to one file, the planner keeps up to N copies that live on
+//! different devices. When a group has fewer distinct devices than N but the scan roots span
+//! more, surplus copies are moved onto a root on a missing device rather than deleted.
+
+use crate::actions::{
+    self, run_action, Action, ActionOptions, ActionStats, Delete, Outcome, Planned,
+};
+use crate::hashing::full_hash;
+use crate::journal::{Journal, Op};
+use crate::output::DuplicateGroup;
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// One planned operation for a group member.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Step {
+    Keep(PathBuf),
+    Move { from: PathBuf, to: PathBuf },
+    Delete(PathBuf),
+}
+
+/// A member path with the (device, inode) identity it resolves to.
+#[derive(Debug, Clone)]
+pub struct Located {
+    pub path: PathBuf,
+    pub dev: u64,
+    pub ino: u64,
+}
+
+/// Plan one group. `members` are in canonical-first order; `roots` are the scan roots with
+/// their device ids. Paths sharing an inode are one physical copy and are kept or removed
+/// together; only single-path copies are moved.
+pub fn plan_group(members: &[Located], n: usize, roots: &[(PathBuf, u64)]) -> Vec<Step> {
+    // Physical copies, in first-appearance order so the canonical's copy comes first.
+    let mut copies: Vec<((u64, u64), Vec<&Located>)> = Vec::new();
+    for m in members {
+        match copies.iter_mut().find(|(k, _)| *k == (m.dev, m.ino)) {
+            Some((_, paths)) => paths.push(m),
+            None => copies.push(((m.dev, m.ino), vec![m])),
+        }
+    }
+
+    let mut kept_devs: BTreeSet<u64> = BTreeSet::new();
+    let mut keep = vec![false; copies.len()];
+    for (i, ((dev, _), _)) in copies.iter().enumerate() {
+        if kept_devs.len() < n && !kept_devs.contains(dev) {
+            kept_devs.insert(*dev);
+            keep[i] = true;
+        }
+    }
+
+    let mut steps = Vec::new();
+    let mut moved = vec![false; copies.len()];
+    for (root, dev) in roots {
+        if kept_devs.len() >= n {
+            break;
+        }
+        if kept_devs.contains(dev) {
+            continue;
+        }
+        let candidate = copies
+            .iter()
+            .enumerate()
+            .find(|(i, (_, paths))| !keep[*i] && !moved[*i] && paths.len() == 1);
+        let Some((i, (_, paths))) = candidate else { break };
+        let from = &paths[0].path;
+        let rel = roots
+            .iter()
+            .filter_map(|(r, _)| from.strip_prefix(r).ok())
+            .next()
+            .map(Path::to_path_buf)
+            .or_else(|| from.file_name().map(PathBuf::from))
+            .unwrap_or_default();
+        steps.push(Step::Move { from: from.clone(), to: root.join(rel) });
+        kept_devs.insert(*dev);
+        moved[i] = true;
+    }
+
+    for (i, (_, paths)) in copies.iter().enumerate() {
+        if moved[i] {
+            continue;
+        }
+        for m in paths {
+            steps.push(if keep[i] { Step::Keep(m.path.clone()) } else { Step::Delete(m.path.clone()) });
+        }
+    }
+    steps
+}
+
+/// `--replicate N` as an [`Action`]: each group's surplus copies are moved onto a missing
+/// device or deleted, as `plan_group` decides.
+struct Replicate {
+    n: usize,
+    /// Scan roots with their device ids.
+    roots: Vec<(PathBuf, u64)>,
+}
+
+impl Action for Replicate {
+    fn plan(&self, g: &DuplicateGroup, _: &Journal) -> Vec<Planned> {
+        // Files scanned without metadata (`degraded`) have no known identity: they are neither
+        // counted as a kept copy nor moved or deleted.
+        let located: Vec<Located> = g
+            .members
+            .iter()
+            .filter(|p| !g.degraded.contains(p))
+            .filter_map(|p| {
+                let (dev, ino) = identity(p)?;
+                Some(Located { path: p.clone(), dev, ino })
+            })
+            .collect();
+        let canonical = &g.members[0];
+        plan_group(&located, self.n, &self.roots)
+            .into_iter()
+            .filter_map(|s| match s {
+                Step::Keep(_) => None,
+                Step::Move { from, to } => {
+                    Some(Planned::Run(actions::Step { op: Op::Move, path: from, target: to }))
+                }
+                Step::Delete(path) => {
+                    let target = canonical.clone();
+                    Some(Planned::Run(actions::Step { op: Op::Delete, path, target }))
+                }
+            })
+            .collect()
+    }
+
+    fn execute(&self, step: &actions::Step, g: &DuplicateGroup, journal: &Journal) -> Outcome {
+        match step.op {
+            Op::Move => match move_file(&step.path, &step.target, &g.hash) {
+                Ok(()) => Outcome::Done,
+                Err(e) => Outcome::Failed(e),
+            },
+            _ => Delete.execute(step, g, journal),
+        }
+    }
+}
+
+/// Plan and (with `--yes`) apply replication for every group through `run_action`.
+pub fn apply_replicate(
+    groups: &[DuplicateGroup],
+    n: usize,
+    roots: &[PathBuf],
+    opts: &ActionOptions,
+    journal: &mut Journal,
+) -> ActionStats {
+    let roots = roots.iter().filter_map(|r| device_of(r).map(|d| (r.clone(), d))).collect();
+    run_action(&Replicate { n, roots }, groups, opts, journal)
+}
+
+/// Move `from` to `to`, copying across devices. The copy is re-hashed against `hash`
+/// before the source is removed.
+pub(crate) fn move_file(from: &Path, to: &Path, hash: &str) -> std::io::Result<()> {
+    if to.exists() {
+        return Err(std::io::Error::new(std::io::ErrorKind::AlreadyExists, "destination exists"));
+    }
+    if let Some(parent) = to.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    if fs::rename(from, to).is_ok() {
+        return Ok(());
+    }
+    fs::copy(from, to)?;
+    match full_hash(to) {
+        Ok(h) if h == hash => fs::remove_file(from),
+        _ => {
+            let _ = fs::remove_file(to);
+            Err(std::io::Error::other("copy verification failed"))
+        }
+    }
+}
+
+#[cfg(unix)]
+fn identity(p: &Path) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+    fs::metadata(p).ok().map(|m| (m.dev(), m.ino()))
+}
+
+#[cfg(not(unix))]
+fn identity(p: &Path) -> Option<(u64, u64)> {
+    // Without inode numbers every path is its own copy on a single device.
+    use std::hash::{Hash, Hasher};
+    fs::metadata(p).ok()?;
+    let mut h = std::collections::hash_map::DefaultHasher::new();
+    p.hash(&mut h);
+    Some((0, h.finish()))
+}
+
+fn device_of(p: &Path) -> Option<u64> {
+    identity(p).map(|(dev, _)| dev)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn loc(path: &str, dev: u64, ino: u64) -> Located {
+        Located { path: path.into(), dev, ino }
+    }
+
+    #[test]
+    fn keeps_one_copy_per_device_up_to_n() {
+        let members = [loc("/a/1", 1, 10), loc("/a/2", 1, 11), loc("/b/1", 2, 20), loc("/c/1", 3, 30)];
+        let steps = plan_group(&members, 2, &[]);
+        assert_eq!(
+            steps,
+            vec![
+                Step::Keep("/a/1".into()),
+                Step::Delete("/a/2".into()),
+                Step::Keep("/b/1".into()),
+                Step::Delete("/c/1".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn moves_surplus_copy_to_missing_device() {
+        let members = [loc("/a/x/f", 1, 10), loc("/a/y/f", 1, 11)];
+        let roots = [(PathBuf::from("/a"), 1), (PathBuf::from("/b"), 2)];
+        let steps = plan_group(&members, 2, &roots);
+        assert_eq!(
+            steps,
+            vec![
+                Step::Move { from: "/a/y/f".into(), to: "/b/y/f".into() },
+                Step::Keep("/a/x/f".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn hardlinked_paths_are_one_copy() {
+        let members = [loc("/a/1", 1, 10), loc("/a/2", 1, 10), loc("/a/3", 1, 12)];
+        let steps = plan_group(&members, 1, &[]);
+        assert_eq!(
+            steps,
+            vec![Step::Keep("/a/1".into()), Step::Keep("/a/2".into()), Step::Delete("/a/3".into())]
+        );
+    }
+
+    #[test]
+    fn files_changed_since_the_scan_are_not_replicated_away() {
+        let dir = std::env::temp_dir().join(format!("ducky_repl_changed_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let paths: Vec<PathBuf> = ["a", "b", "c"].iter().map(|n| dir.join(n)).collect();
+        for p in &paths {
+            fs::write(p, b"abc").unwrap();
+        }
+        let group = DuplicateGroup::new(3, paths.clone()).with_hash(full_hash(&paths[0]).unwrap());
+        fs::write(&paths[1], b"abcd").unwrap();
+
+        let opts = ActionOptions { yes: true, ..Default::default() };
+        let roots = std::slice::from_ref(&dir);
+        let stats = apply_replicate(&[group], 1, roots, &opts, &mut Journal::in_memory());
+        assert_eq!((stats.deleted, stats.changed_since_scan), (1, 1));
+        assert!(paths[0].exists() && paths[1].exists() && !paths[2].exists());
+
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn degraded_files_are_left_alone() {
+        let dir = std::env::temp_dir().join(format!("ducky_repl_degraded_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let paths: Vec<PathBuf> = ["a", "b", "c"].iter().map(|n| dir.join(n)).collect();
+        for p in &paths {
+            fs::write(p, b"abc").unwrap();
+        }
+        let hash = full_hash(&paths[0]).unwrap();
+        let mut group = DuplicateGroup::new(3, paths.clone()).with_hash(hash);
+        group.degraded.push(paths[2].clone());
+
+        let opts = ActionOptions { yes: true, ..Default::default() };
+        let roots = std::slice::from_ref(&dir);
+        let stats = apply_replicate(&[group], 1, roots, &opts, &mut Journal::in_memory());
+        assert_eq!((stats.deleted, stats.errors), (1, 0));
+        assert!(paths[0].exists() && !paths[1].exists() && paths[2].exists());
+
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn held_pair_halves_are_not_replicated_away() {
+        let dir = std::env::temp_dir().join(format!("ducky_repl_held_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let paths: Vec<PathBuf> = ["a", "b", "c"].iter().map(|n| dir.join(n)).collect();
+        for p in &paths {
+            fs::write(p, b"abc").unwrap();
+        }
+        let group = DuplicateGroup::new(3, paths.clone()).with_hash(full_hash(&paths[0]).unwrap());
+
+        let mut opts = ActionOptions { yes: true, ..Default::default() };
+        opts.held_pairs.insert(paths[2].clone());
+        let roots = std::slice::from_ref(&dir);
+        let stats = apply_replicate(&[group], 1, roots, &opts, &mut Journal::in_memory());
+        assert_eq!((stats.deleted, stats.skipped_pair), (1, 1));
+        assert!(paths[0].exists() && !paths[1].exists() && paths[2].exists());
+
+        let _ = fs::remove_dir_all(dir);
+    }
+}
@@ -0,0 +1,88 @@
+//! Report files ducky has written (`--groups-out`, `--save`, `--csv-out`, `--action-log`,
+//! `--badge-json`). Their paths are listed in the data directory, so a later scan of a tree
+//! that holds them leaves them out instead of reporting ducky's own output as the user's files.
+
+use crate::fs_utils::{create_artifact_dir, data_dir};
+use anyhow::{Context, Result};
+use std::collections::{BTreeSet, HashSet};
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Name of the list in the data directory: one canonical path per line.
+pub const FILE_NAME: &str = "reports.txt";
+
+/// The list in the data directory; None when there is no data directory.
+pub fn default_path() -> Option<PathBuf> {
+    data_dir().map(|d| d.join(FILE_NAME))
+}
+
+/// Paths listed in `list`; empty when it doesn't exist or can't be read.
+pub fn load(list: &Path) -> Vec<PathBuf> {
+    let text = fs::read_to_string(list).unwrap_or_default();
+    text.lines().filter(|l| !l.is_empty()).map(PathBuf::from).collect()
+}
+
+/// Add the canonical paths of `written` to `list`, dropping entries whose file is gone.
+/// Paths that aren't UTF-8 or contain a newline can't be listed and are left out.
+pub fn record(list: &Path, written: &[&PathBuf]) -> Result<()> {
+    let mut known: BTreeSet<PathBuf> = load(list).into_iter().filter(|p| p.exists()).collect();
+    known.extend(written.iter().filter_map(|p| fs::canonicalize(p).ok()));
+    if let Some(parent) = list.parent() {
+        create_artifact_dir(parent).with_context(|| format!("create {}", parent.display()))?;
+    }
+    let mut text = String::new();
+    for p in known.iter().filter_map(|p| p.to_str()).filter(|p| !p.contains('\n')) {
+        text.push_str(p);
+        text.push('\n');
+    }
+    fs::write(list, text).with_context(|| format!("write {}", list.display()))
+}
+
+/// Report paths to leave out of a scan. Files are matched by name first, so only the few
+/// that share a report's name are canonicalized.
+#[derive(Debug, Default)]
+pub struct Reports {
+    paths: HashSet<PathBuf>,
+    names: HashSet<OsString>,
+}
+
+impl Reports {
+    pub fn new(paths: impl IntoIterator<Item = PathBuf>) -> Self {
+        let paths: HashSet<PathBuf> = paths.into_iter().collect();
+        let names = paths.iter().filter_map(|p| p.file_name().map(OsString::from)).collect();
+        Self { paths, names }
+    }
+
+    /// Whether `path` is one of the reports.
+    pub fn contains(&self, path: &Path) -> bool {
+        path.file_name().is_some_and(|n| self.names.contains(n))
+            && fs::canonicalize(path).is_ok_and(|p| self.paths.contains(&p))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recorded_reports_are_recognised_until_they_are_gone() {
+        let dir = std::env::temp_dir().join(format!("ducky_reports_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let (a, b) = (dir.join("groups.json"), dir.join("plan.csv"));
+        fs::write(&a, b"[]").unwrap();
+        fs::write(&b, b"").unwrap();
+        let list = dir.join("state").join(FILE_NAME);
+
+        record(&list, &[&a, &b]).unwrap();
+        let reports = Reports::new(load(&list));
+        assert!(reports.contains(&a) && reports.contains(&dir.join(".").join("plan.csv")));
+        assert!(!reports.contains(&dir.join("other.csv")));
+
+        fs::remove_file(&a).unwrap();
+        record(&list, &[]).unwrap();
+        assert_eq!(load(&list), vec![fs::canonicalize(&b).unwrap()]);
+
+        let _ = fs::remove_dir_all(dir);
+    }
+}
@@ -0,0 +1,43 @@
+//! The pseudo-random generator behind every sampled choice (`--audit-sample`) and the fixture
+//! patterns. One handle is created per run, from `--seed` or the clock, and passed to whatever
+//! samples, so a run given the same seed makes the same choices.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// xorshift64: fast, and plenty for choosing samples. Not for anything secret.
+#[derive(Debug, Clone)]
+pub struct Rng {
+    seed: u64,
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Self { seed, state: seed | 1 }
+    }
+
+    /// `--seed` when given, otherwise a seed from the clock (reported so the run can be
+    /// repeated).
+    pub fn from_seed_or_clock(seed: Option<u64>) -> Self {
+        Self::new(seed.unwrap_or_else(|| {
+            SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_nanos() as u64).unwrap_or(0)
+        }))
+    }
+
+    /// The seed this generator started from.
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        self.state
+    }
+
+    /// A number in `0..n`; `n` must not be 0.
+    pub fn below(&mut self, n: u64) -> u64 {
+        self.next_u64() % n
+    }
+}
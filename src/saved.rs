@@ -0,0 +1,172 @@
+//! Saved scans: `--save FILE` writes every duplicate group with its hash and the size and
+//! mtime each member had when it was hashed; `ducky apply FILE --delete` acts on it later.
+//!
+//! This splits review from action: scan once, look at the result (or edit the file), then
+//! apply. Before anything is touched, every member is checked against its recorded size and
+//! mtime. Members that changed or disappeared are dropped from their group, and groups left
+//! with a single file are skipped.
+
+use crate::actions::{apply_groups, ActionStats};
+use crate::cache::FileId;
+use crate::output::DuplicateGroup;
+use crate::warnings::warning;
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Format version written to `version`; files from a newer ducky are refused.
+const VERSION: u32 = 1;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SavedScan {
+    pub version: u32,
+    /// Unix time of the scan.
+    pub created: u64,
+    pub groups: Vec<SavedGroup>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SavedGroup {
+    pub size: u64,
+    /// Full content hash (hex).
+    pub hash: String,
+    /// Members in order; the first is the copy that is kept.
+    pub members: Vec<SavedMember>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub links: Vec<PathBuf>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SavedMember {
+    pub path: PathBuf,
+    pub size: u64,
+    /// Modification time in nanoseconds since the Unix epoch.
+    pub mtime_ns: i64,
+}
+
+impl SavedMember {
+    fn of(path: &Path) -> Option<Self> {
+        let id = FileId::of(path)?;
+        Some(Self { path: path.to_path_buf(), size: id.size, mtime_ns: id.mtime_ns })
+    }
+
+    /// Whether the file still has the recorded size and mtime.
+    fn unchanged(&self) -> bool {
+        FileId::of(&self.path)
+            .is_some_and(|id| id.size == self.size && id.mtime_ns == self.mtime_ns)
+    }
+}
+
+impl SavedScan {
+    /// Record `groups` with the current size and mtime of each member.
+    pub fn new(groups: &[DuplicateGroup]) -> Self {
+        let created =
+            SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+        let groups = groups
+            .iter()
+            .map(|g| SavedGroup {
+                size: g.size,
+                hash: g.hash.clone(),
+                members: g.members.iter().filter_map(|p| SavedMember::of(p)).collect(),
+                links: g.links.clone(),
+            })
+            .collect();
+        Self { version: VERSION, created, groups }
+    }
+
+    /// The groups whose members can still be acted on: changed or missing members are
+    /// dropped (with a warning), as are groups left with fewer than two.
+    pub fn validate(self) -> Vec<DuplicateGroup> {
+        let mut out = Vec::new();
+        for g in self.groups {
+            let members: Vec<PathBuf> = g
+                .members
+                .into_iter()
+                .filter(|m| {
+                    let ok = m.size == g.size && m.unchanged();
+                    if !ok {
+                        let p = m.path.display();
+                        warning!("{} changed since the scan was saved; leaving it", p);
+                    }
+                    ok
+                })
+                .map(|m| m.path)
+                .collect();
+            if members.len() < 2 || g.hash.is_empty() {
+                continue;
+            }
+            let links = g.links.into_iter().filter(|l| members.contains(l)).collect();
+            // `new` sorts; the saved order (canonical first) is kept instead.
+            let mut group = DuplicateGroup::new(g.size, Vec::new()).with_hash(g.hash);
+            group.members = members;
+            group.links = links;
+            out.push(group);
+        }
+        out
+    }
+}
+
+/// `--save FILE`.
+pub fn save(path: &Path, groups: &[DuplicateGroup]) -> Result<()> {
+    let mut f = io::BufWriter::new(
+        fs::File::create(path).with_context(|| format!("create {}", path.display()))?,
+    );
+    serde_json::to_writer(&mut f, &SavedScan::new(groups))?;
+    f.flush().with_context(|| format!("write {}", path.display()))
+}
+
+pub fn load(path: &Path) -> Result<SavedScan> {
+    let text = fs::read_to_string(path).with_context(|| format!("read {}", path.display()))?;
+    let scan: SavedScan = serde_json::from_str(&text)
+        .with_context(|| format!("{} is not a scan saved with --save", path.display()))?;
+    if scan.version > VERSION {
+        bail!("{} was saved by a newer ducky (format {})", path.display(), scan.version);
+    }
+    Ok(scan)
+}
+
+/// `ducky apply FILE`: delete (or trash) every member but the first of each saved group
+/// that is still unchanged. With `verify`, members are compared with the kept file byte by
+/// byte (as under `--paranoid`) first.
+pub fn run(
+    file: &Path,
+    trash: bool,
+    yes: bool,
+    dry_run: bool,
+    verify: bool,
+) -> Result<ActionStats> {
+    let groups = load(file)?.validate();
+    Ok(apply_groups(&groups, trash, yes, dry_run, verify))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn changed_members_are_dropped_on_reload() {
+        let dir = std::env::temp_dir().join(format!("ducky_saved_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let (a, b, c) = (dir.join("a"), dir.join("b"), dir.join("c"));
+        for p in [&a, &b, &c] {
+            fs::write(p, b"same").unwrap();
+        }
+        let g = DuplicateGroup::new(4, vec![c.clone(), a.clone(), b.clone()])
+            .with_hash("ab".repeat(32));
+        let file = dir.join("scan.json");
+        save(&file, &[g]).unwrap();
+
+        let groups = load(&file).unwrap().validate();
+        assert_eq!(groups[0].members, vec![a.clone(), b.clone(), c.clone()]);
+
+        // A different size is caught without relying on mtime granularity.
+        fs::write(&b, b"changed").unwrap();
+        fs::remove_file(&c).unwrap();
+        assert!(load(&file).unwrap().validate().is_empty());
+
+        let _ = fs::remove_dir_all(dir);
+    }
+}
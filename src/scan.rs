@@ -0,0 +1,516 @@
+//! The scan pipeline: walk → group by size → collapse hard links → quick hash → full hash.
+//! Quick and full hashing start during the walk for files that are sure to need them (see
+//! `prefetch`).
+//!
+//! `Scanner::run` does all of it in one call. The CLI drives the stages one at a time instead,
+//! so it can report what the walk matched and watch the candidate directories for changes
+//! before the (long) hashing stages start.
+
+use crate::budget::Errors;
+use crate::cache::{cached, FileId, HashCache};
+use crate::estimate;
+use crate::fs_utils::{walk_files, Bounds, PathRegex, ScannedFile, Unreadable};
+use crate::grouping::{self, group_by_size, Links};
+use crate::hashing::{full_hash, quick_hash, QuickSample};
+use crate::health::{MediaHealth, SuspectDevice};
+use crate::output::DuplicateGroup;
+use crate::prefetch::{self, Prefetched};
+use crate::pairs::{self, PairCounters, PairStats};
+use crate::progress::{self, Prediction};
+use crate::reference::Reference;
+use crate::schedule::{self, Strategy};
+use crate::stats::{CostTracker, ExtCost};
+use crate::status;
+use crate::warnings::warning;
+use anyhow::{Context, Result};
+use bytesize::ByteSize;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// What to scan and how to hash it.
+#[derive(Debug)]
+pub struct ScanOptions {
+    pub roots: Vec<PathBuf>,
+    /// Include hidden files and files ignored by .gitignore.
+    pub hidden: bool,
+    pub follow_symlinks: bool,
+    pub bounds: Bounds,
+    /// Lowercase extensions to include; `None` for all.
+    pub exts: Option<HashSet<String>>,
+    pub excludes: Vec<glob::Pattern>,
+    pub regexes: PathRegex,
+    /// Bytes hashed from each sampled spot in the quick hash.
+    pub quick_bytes: u64,
+    pub quick_sample: QuickSample,
+    pub threads: usize,
+    pub schedule: Strategy,
+    /// Group by size through a spill file when the path tables come near this many bytes.
+    pub max_memory: Option<u64>,
+    /// Reference trees for compare mode; empty for a plain scan.
+    pub reference: Reference,
+    /// Use the persistent hash cache.
+    pub cache: bool,
+    /// Show progress bars on stderr (only with the `cli` feature).
+    pub progress: bool,
+    /// Keep files whose metadata can't be read, with only their size (see `degraded`).
+    pub tolerate_metadata_errors: bool,
+}
+
+impl Default for ScanOptions {
+    fn default() -> Self {
+        Self {
+            roots: Vec::new(),
+            hidden: false,
+            follow_symlinks: false,
+            bounds: Bounds::default(),
+            exts: None,
+            excludes: Vec::new(),
+            regexes: PathRegex::default(),
+            quick_bytes: 64 * 1024,
+            quick_sample: QuickSample::default(),
+            threads: schedule::default_threads(),
+            schedule: Strategy::default(),
+            max_memory: None,
+            reference: Reference::default(),
+            cache: true,
+            progress: false,
+            tolerate_metadata_errors: false,
+        }
+    }
+}
+
+/// Files sharing their size with another, ready to be hashed.
+#[derive(Debug, Default)]
+pub struct Candidates {
+    pub by_size: BTreeMap<u64, Vec<PathBuf>>,
+    /// Extra hard links left out of hashing, listed again in their groups.
+    pub links: Links,
+    /// Modification time (ns) of every candidate and link as the walk saw it.
+    pub mtimes: HashMap<PathBuf, i64>,
+}
+
+impl Candidates {
+    /// Number of files that will be hashed.
+    pub fn count(&self) -> usize {
+        self.buckets().map(|(_, v)| v.len()).sum()
+    }
+
+    /// Directories holding candidates.
+    pub fn dirs(&self) -> BTreeSet<&Path> {
+        self.buckets().flat_map(|(_, v)| v).filter_map(|p| p.parent()).collect()
+    }
+
+    fn buckets(&self) -> impl Iterator<Item = (u64, &Vec<PathBuf>)> {
+        self.by_size.iter().filter(|(_, v)| v.len() > 1).map(|(s, v)| (*s, v))
+    }
+}
+
+/// Duplicate groups and what it took to find them.
+#[derive(Debug, Default)]
+pub struct ScanResult {
+    /// Ordered by content key.
+    pub groups: Vec<DuplicateGroup>,
+    /// Naive upper bound of the bytes removing every duplicate would free.
+    pub reclaimable: u64,
+    /// Link- and sparse-aware estimate of the same.
+    pub reclaimable_realistic: u64,
+    pub prediction: Prediction,
+    pub hashed_bytes: u64,
+    pub pairs: PairStats,
+    pub by_extension: Vec<ExtCost>,
+    /// Files served from the cache under a new path: (old, new).
+    pub renames: Vec<(PathBuf, PathBuf)>,
+    pub suspect_media: Vec<SuspectDevice>,
+    /// Files scanned without their metadata (`ScanOptions::tolerate_metadata_errors`).
+    pub degraded: HashSet<PathBuf>,
+    pub quick_hash_time: Duration,
+    pub full_hash_time: Duration,
+}
+
+impl ScanResult {
+    fn push(&mut self, group: DuplicateGroup) {
+        self.reclaimable = self.reclaimable.saturating_add(estimate::upper_bound(&group));
+        let realistic = estimate::realistic(&group);
+        self.reclaimable_realistic = self.reclaimable_realistic.saturating_add(realistic);
+        self.groups.push(group);
+    }
+
+    /// Keep only the groups `keep` accepts, taking the others out of the totals. Returns how
+    /// many were dropped.
+    pub fn retain(&mut self, keep: impl Fn(&DuplicateGroup) -> bool) -> usize {
+        let before = self.groups.len();
+        let groups = std::mem::take(&mut self.groups);
+        self.reclaimable = 0;
+        self.reclaimable_realistic = 0;
+        for g in groups.into_iter().filter(|g| keep(g)) {
+            self.push(g);
+        }
+        before - self.groups.len()
+    }
+
+    /// Leave the non-canonical members `pinned` selects out of the totals: they stay in their
+    /// groups but can't be freed. Returns how many there are and their bytes.
+    pub fn pin_members(&mut self, mut pinned: impl FnMut(&Path) -> bool) -> (usize, u64) {
+        let (mut files, mut bytes) = (0, 0u64);
+        self.reclaimable = 0;
+        self.reclaimable_realistic = 0;
+        for g in &self.groups {
+            let (stuck, free): (Vec<&PathBuf>, Vec<&PathBuf>) =
+                g.members[1..].iter().partition(|m| pinned(m));
+            files += stuck.len();
+            bytes = bytes.saturating_add(g.size.saturating_mul(stuck.len() as u64));
+            let mut live = g.clone();
+            live.members = std::iter::once(&g.members[0]).chain(free).cloned().collect();
+            live.links.retain(|l| live.members.contains(l));
+            self.reclaimable = self.reclaimable.saturating_add(estimate::upper_bound(&live));
+            let realistic = estimate::realistic(&live);
+            self.reclaimable_realistic = self.reclaimable_realistic.saturating_add(realistic);
+        }
+        (files, bytes)
+    }
+}
+
+pub struct Scanner {
+    opts: ScanOptions,
+    cache: HashCache,
+    /// Hashes computed during the walk, taken by the hashing stages.
+    prefetched: Mutex<Prefetched>,
+    /// Read errors per device, from the walk's prefetching through the hashing stages.
+    health: MediaHealth,
+    /// Files the walk kept without their metadata.
+    degraded: Mutex<HashSet<PathBuf>>,
+    errors: Arc<Errors>,
+}
+
+impl Scanner {
+    pub fn new(opts: ScanOptions) -> Self {
+        let cache = if opts.cache { HashCache::open_default() } else { HashCache::disabled() };
+        let (prefetched, health) = (Mutex::default(), MediaHealth::default());
+        Self { opts, cache, prefetched, health, degraded: Mutex::default(), errors: Arc::default() }
+    }
+
+    pub fn options(&self) -> &ScanOptions {
+        &self.opts
+    }
+
+    pub fn cache(&self) -> &HashCache {
+        &self.cache
+    }
+
+    /// Errors of the walk and the hashing stages so far, shared with the actions (see
+    /// `ActionOptions::errors`) so one budget covers the run.
+    pub fn errors(&self) -> &Arc<Errors> {
+        &self.errors
+    }
+
+    /// Walk, group and hash in one go.
+    pub fn run(&self) -> Result<ScanResult> {
+        let mut files = self.collect()?;
+        let candidates = self.group(&mut files)?;
+        Ok(self.hash(&candidates))
+    }
+
+    /// Walk the roots and return every file that passes the filters. Files of sizes that are
+    /// certain to be quick-hashed are hashed in the background meanwhile, except under
+    /// `max_memory` (the hashes would be kept in memory) and in compare mode (most sizes
+    /// don't span the reference).
+    pub fn collect(&self) -> Result<Vec<ScannedFile>> {
+        let o = &self.opts;
+        let discovery = progress::Discovery::start(o.progress);
+        let walk = walk_files(
+            &o.roots,
+            o.hidden,
+            o.follow_symlinks,
+            &o.bounds,
+            o.exts.as_ref(),
+            &o.excludes,
+            &o.regexes,
+        )
+        .filter_map(|f| match f {
+            Ok(f) => Some(f),
+            Err(Unreadable::Metadata(f)) if o.tolerate_metadata_errors => {
+                let f = f?;
+                status::discovered();
+                self.degraded.lock().unwrap_or_else(|e| e.into_inner()).insert(f.path.clone());
+                Some(f)
+            }
+            Err(_) => {
+                self.errors.record(status::Stage::Walk);
+                None
+            }
+        });
+        let files = if o.max_memory.is_some() || !o.reference.is_empty() {
+            walk.collect()
+        } else {
+            let (limit, sample) = (o.quick_bytes, o.quick_sample);
+            let (files, prefetched) =
+                prefetch::walk_and_hash(walk, &self.cache, &self.health, limit, sample, o.threads);
+            *self.prefetched.lock().unwrap_or_else(|e| e.into_inner()) = prefetched;
+            files
+        };
+        discovery.finish();
+        Ok(files)
+    }
+
+    /// Group `files` by size and set extra hard links aside. When the spill path is taken
+    /// (`max_memory`), `files` is emptied.
+    pub fn group(&self, files: &mut Vec<ScannedFile>) -> Result<Candidates> {
+        status::set_stage(status::Stage::Size);
+        let mut by_size = match self.opts.max_memory {
+            Some(cap) if grouping::table_bytes(files) >= cap / 10 * 8 => {
+                let spill = grouping::spill_path(&std::env::temp_dir());
+                let grouped = grouping::group_by_size_spilled(files, &spill);
+                match grouped {
+                    Ok(by_size) => {
+                        warning!(
+                            "path tables near --max-memory {}; grouped by size through a spill file",
+                            ByteSize(cap)
+                        );
+                        by_size
+                    }
+                    Err(e) if !files.is_empty() => {
+                        warning!("could not spill to {}: {}; grouping in memory", spill.display(), e);
+                        group_by_size(files)
+                    }
+                    Err(e) => return Err(e).context("reading back the size spill failed"),
+                }
+            }
+            _ => group_by_size(files),
+        };
+        // Hard links of one file are hashed once and listed again in its group afterwards.
+        let links = grouping::collapse_hardlinks(&mut by_size);
+        // Hashing only needs the paths from here on; actions later check the mtimes.
+        let mut mtimes = HashMap::new();
+        for f in by_size.values().filter(|v| v.len() > 1).flatten() {
+            for link in links.get(&f.path).into_iter().flatten() {
+                mtimes.insert(link.clone(), f.meta.mtime_ns);
+            }
+            mtimes.insert(f.path.clone(), f.meta.mtime_ns);
+        }
+        let mut by_size: BTreeMap<u64, Vec<PathBuf>> = by_size
+            .into_iter()
+            .map(|(size, files)| (size, files.into_iter().map(|f| f.path).collect()))
+            .collect();
+        // In compare mode only sizes present both in and outside the reference can match.
+        let reference = &self.opts.reference;
+        if !reference.is_empty() {
+            by_size.retain(|_, v| v.len() > 1 && reference.spans(v));
+        }
+        Ok(Candidates { by_size, links, mtimes })
+    }
+
+    /// Quick- and full-hash the candidates into duplicate groups, then save the cache.
+    pub fn hash(&self, candidates: &Candidates) -> ScanResult {
+        let (o, cache, links) = (&self.opts, &self.cache, &candidates.links);
+        let mtimes = &candidates.mtimes;
+        let (limit, sample, threads) = (o.quick_bytes, o.quick_sample, o.threads);
+        let started = Instant::now();
+        let mut result = ScanResult::default();
+        let degraded = {
+            let mut files = self.degraded.lock().unwrap_or_else(|e| e.into_inner());
+            std::mem::take(&mut *files)
+        };
+
+        // Stage 2: by quick hash (for all size buckets)
+        status::set_stage(status::Stage::QuickHash);
+        // Work is flattened across buckets and ordered by --schedule; results are regrouped
+        // per bucket, so output order does not depend on the hashing order.
+        let mut size_buckets: Vec<(u64, &Vec<PathBuf>)> = candidates.buckets().collect();
+        result.prediction = progress::predict(&size_buckets, limit, sample);
+        let progress = progress::Progress::start(result.prediction.total(), o.progress);
+        let costs = CostTracker::default();
+        // Files on a device that keeps failing reads are skipped (None) rather than hashed.
+        let health = &self.health;
+        // What the walk already hashed is used once; its errors are already recorded.
+        let prefetched = |stage: fn(&mut Prefetched) -> &mut prefetch::Hashes, p: &PathBuf| {
+            stage(&mut self.prefetched.lock().unwrap_or_else(|e| e.into_inner())).remove(p)
+        };
+
+        // Buckets of exactly two files are compared directly; pairs that can't be read go
+        // through the hashing stages, which report and attribute the errors.
+        let pair_counters = PairCounters::default();
+        let (mut pair_buckets, rest): (Vec<_>, Vec<_>) =
+            size_buckets.into_iter().partition(|(_, v)| v.len() == 2);
+        schedule::order(&mut pair_buckets, o.schedule, |(size, _)| *size, |(size, _)| (0, *size));
+        size_buckets = rest;
+        let compared = schedule::parallel_map(&pair_buckets, threads, |(size, v)| {
+            progress.add(size.saturating_mul(2));
+            if !v.iter().all(|p| health.admit(p)) {
+                return None;
+            }
+            let started = Instant::now();
+            let res = pairs::compare(&v[0], &v[1], *size, cache, &pair_counters);
+            for p in v.iter() {
+                costs.record(p, *size, started.elapsed() / 2, true);
+            }
+            Some(res)
+        });
+        for ((size, v), res) in pair_buckets.into_iter().zip(compared) {
+            match res {
+                Some(Ok(Some(fh))) => {
+                    let group = DuplicateGroup::new(size, v.clone())
+                        .with_hash(fh)
+                        .with_links(links)
+                        .with_mtimes(mtimes)
+                        .with_degraded(&degraded);
+                    let Some(group) = o.reference.restrict(group) else { continue };
+                    result.push(group);
+                }
+                Some(Ok(None)) | None => {}
+                Some(Err(_)) => size_buckets.push((size, v)),
+            }
+        }
+        let mut work: Vec<(usize, u64, &PathBuf)> = size_buckets
+            .iter()
+            .enumerate()
+            .flat_map(|(i, (size, paths))| paths.iter().map(move |p| (i, *size, p)))
+            .collect();
+        let cost = |(_, size, _): &(usize, u64, &PathBuf)| sample.bytes(*size, limit);
+        let bucket_of = |(i, size, _): &(usize, u64, &PathBuf)| {
+            (*i, schedule::worth(*size, size_buckets[*i].1.len()))
+        };
+        schedule::order(&mut work, o.schedule, cost, bucket_of);
+        let hashes = schedule::parallel_map(&work, threads, |(_, size, p)| {
+            let bytes = sample.bytes(*size, limit);
+            progress.add(bytes);
+            if let Some((h, took)) = prefetched(|f| &mut f.quick, p) {
+                costs.record(p, bytes, took, true);
+                return Some(h);
+            }
+            if !health.admit(p) {
+                return None;
+            }
+            let started = Instant::now();
+            let tag = sample.cache_tag(limit);
+            let get = |id: &FileId| cache.quick(id, tag);
+            let put = |id, h: &str| cache.put_quick(id, tag, h);
+            let h = cached(p, cache, get, put, || quick_hash(p, limit, sample));
+            costs.record(p, bytes, started.elapsed(), true);
+            if let Err(e) = &h {
+                health.record(p, e);
+            }
+            Some(h)
+        });
+        let mut by_qh: Vec<BTreeMap<String, Vec<&PathBuf>>> = vec![BTreeMap::new(); size_buckets.len()];
+        for ((i, _, p), res) in work.into_iter().zip(hashes) {
+            match res {
+                Some(Ok(h)) => by_qh[i].entry(h).or_default().push(p),
+                Some(Err(e)) => {
+                    self.errors.record(status::Stage::QuickHash);
+                    warning!("quick-hash failed {}: {}", p.display(), e)
+                }
+                None => {}
+            }
+        }
+        let quick_buckets: Vec<(u64, Vec<&PathBuf>)> = size_buckets
+            .iter()
+            .zip(by_qh)
+            .flat_map(|((size, _), m)| m.into_values().map(move |v| (*size, v)))
+            .filter(|(_, v)| v.len() > 1)
+            .collect();
+        result.quick_hash_time = started.elapsed();
+
+        // Stage 3: by full hash (for all quick-hash buckets)
+        status::set_stage(status::Stage::FullHash);
+        progress.stage("full-hash");
+        let mut work: Vec<(usize, u64, &PathBuf)> = quick_buckets
+            .iter()
+            .enumerate()
+            .flat_map(|(i, (size, paths))| paths.iter().map(move |p| (i, *size, *p)))
+            .collect();
+        let bucket_of = |(i, size, _): &(usize, u64, &PathBuf)| {
+            (*i, schedule::worth(*size, quick_buckets[*i].1.len()))
+        };
+        schedule::order(&mut work, o.schedule, |(_, size, _)| *size, bucket_of);
+        // The quick hash has settled which files need a full read: swap the guess for the real total.
+        progress.set_total(progress.done() + work.iter().map(|(_, size, _)| *size).sum::<u64>());
+        let hashes = schedule::parallel_map(&work, threads, |(_, size, p)| {
+            progress.add(*size);
+            if let Some((h, took)) = prefetched(|f| &mut f.full, p) {
+                costs.record(p, *size, took, false);
+                return Some(h);
+            }
+            if !health.admit(p) {
+                return None;
+            }
+            let started = Instant::now();
+            let get = |id: &FileId| cache.full(id);
+            let h = cached(p, cache, get, |id, h| cache.put_full(id, h), || full_hash(p));
+            costs.record(p, *size, started.elapsed(), false);
+            if let Err(e) = &h {
+                health.record(p, e);
+            }
+            Some(h)
+        });
+        let mut by_fh: Vec<BTreeMap<String, Vec<&PathBuf>>> = vec![BTreeMap::new(); quick_buckets.len()];
+        for ((i, _, p), res) in work.into_iter().zip(hashes) {
+            match res {
+                Some(Ok(h)) => by_fh[i].entry(h).or_default().push(p),
+                Some(Err(e)) => {
+                    self.errors.record(status::Stage::FullHash);
+                    warning!("full-hash failed {}: {}", p.display(), e)
+                }
+                None => {}
+            }
+        }
+        for ((size, _), m) in quick_buckets.iter().zip(by_fh) {
+            for (fh, dupes) in m.into_iter().filter(|(_, v)| v.len() > 1) {
+                let members: Vec<_> = dupes.into_iter().cloned().collect();
+                let group = DuplicateGroup::new(*size, members)
+                    .with_hash(fh)
+                    .with_links(links)
+                    .with_mtimes(mtimes)
+                    .with_degraded(&degraded);
+                let Some(group) = o.reference.restrict(group) else { continue };
+                result.push(group);
+            }
+        }
+        // Order by content rather than by quick-hash bucket so it is stable across --quick-bytes.
+        result.groups.sort_by_key(|g| g.key());
+        result.renames = cache.renames();
+        result.suspect_media = health.report();
+        result.degraded = degraded;
+        result.hashed_bytes = progress.done();
+        result.pairs = pair_counters.report();
+        result.by_extension = costs.report();
+        progress.finish();
+        *self.prefetched.lock().unwrap_or_else(|e| e.into_inner()) = Prefetched::default();
+        if let Err(e) = cache.save() {
+            warning!("failed to save hash cache: {:#}", e);
+        }
+        result.full_hash_time = started.elapsed() - result.quick_hash_time;
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scanner_runs_the_whole_pipeline() {
+        let dir = std::env::temp_dir().join(format!("ducky_scan_{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("sub")).unwrap();
+        let body = vec![5u8; 200_000];
+        std::fs::write(dir.join("a.bin"), &body).unwrap();
+        std::fs::write(dir.join("sub").join("b.bin"), &body).unwrap();
+        std::fs::write(dir.join("sub").join("c.bin"), &body).unwrap();
+        std::fs::write(dir.join("other.bin"), vec![6u8; 200_000]).unwrap();
+
+        let opts = ScanOptions { roots: vec![dir.clone()], cache: false, ..Default::default() };
+        let mut result = Scanner::new(opts).run().unwrap();
+        assert_eq!(result.groups.len(), 1);
+        assert_eq!(result.groups[0].members.len(), 3);
+        assert_eq!(result.reclaimable, 400_000);
+        assert_eq!(result.hashed_bytes, result.prediction.quick_bytes + 3 * 200_000);
+
+        // Pinned copies stay listed but are no longer counted as reclaimable.
+        assert_eq!(result.pin_members(|p| p.ends_with("c.bin")), (1, 200_000));
+        assert_eq!(result.groups[0].members.len(), 3);
+        assert_eq!(result.reclaimable, 200_000);
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
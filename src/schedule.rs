@@ -0,0 +1,216 @@
+//! Work ordering for the hashing and action stages.
+//!
+//! Hashing cost is roughly proportional to bytes read, so handing out the most expensive
+//! files first (longest-processing-time scheduling) keeps one huge file from being picked
+//! up last and leaving every other worker idle while it finishes. By default whole size
+//! buckets go in order of the space they could free (file size × extra copies), so the most
+//! valuable groups are settled first; within a bucket, and between buckets worth the same,
+//! the largest files still go first.
+//!
+//! Actions are the opposite case: they are seek-bound, so they run one device at a time
+//! rather than interleaving a slow HDD with an SSD.
+
+use crate::output::DuplicateGroup;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum Strategy {
+    /// Buckets that could free the most space first, largest files first within each.
+    #[default]
+    Reclaimable,
+    /// Most expensive work first (LPT); ties keep discovery order.
+    LargestFirst,
+    /// Discovery order: size buckets ascending, paths as walked.
+    Fifo,
+}
+
+/// Reorder `items` in place according to `strategy`, using `cost` as the estimated work and
+/// `bucket` as the size bucket an item belongs to with the bytes that bucket could free.
+/// The sort is stable, so equal-cost items keep their relative order.
+pub fn order<T>(
+    items: &mut [T],
+    strategy: Strategy,
+    cost: impl Fn(&T) -> u64,
+    bucket: impl Fn(&T) -> (usize, u64),
+) {
+    use std::cmp::Reverse;
+    match strategy {
+        Strategy::Reclaimable => items.sort_by_key(|t| {
+            let (id, worth) = bucket(t);
+            (Reverse(worth), Reverse(cost(t)), id)
+        }),
+        Strategy::LargestFirst => items.sort_by_key(|t| Reverse(cost(t))),
+        Strategy::Fifo => {}
+    }
+}
+
+/// Bytes a bucket of `count` files of `size` bytes could free if they all match.
+pub fn worth(size: u64, count: usize) -> u64 {
+    size.saturating_mul(count.saturating_sub(1) as u64)
+}
+
+/// Apply `f` to every item on `threads` worker threads and return results in item order.
+///
+/// Workers claim the next unprocessed item from a shared counter, so items start in the
+/// order given (which is what makes `order` effective) and no thread idles while work remains.
+pub fn parallel_map<T, R, F>(items: &[T], threads: usize, f: F) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+    F: Fn(&T) -> R + Sync,
+{
+    let threads = threads.clamp(1, items.len().max(1));
+    if threads == 1 {
+        return items.iter().map(f).collect();
+    }
+    let next = AtomicUsize::new(0);
+    let mut results: Vec<(usize, R)> = std::thread::scope(|s| {
+        let workers: Vec<_> = (0..threads)
+            .map(|_| {
+                s.spawn(|| {
+                    let mut out = Vec::new();
+                    loop {
+                        let i = next.fetch_add(1, Ordering::Relaxed);
+                        let Some(item) = items.get(i) else { break };
+                        out.push((i, f(item)));
+                    }
+                    out
+                })
+            })
+            .collect();
+        workers
+            .into_iter()
+            .flat_map(|w| w.join().expect("hashing worker panicked"))
+            .collect()
+    });
+    results.sort_unstable_by_key(|(i, _)| *i);
+    results.into_iter().map(|(_, r)| r).collect()
+}
+
+/// Default worker count: the number of available CPUs.
+pub fn default_threads() -> usize {
+    std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1)
+}
+
+/// Groups whose duplicates live on one device, in the order they should be applied.
+pub struct DeviceBatch {
+    pub device: u64,
+    pub groups: Vec<DuplicateGroup>,
+}
+
+/// Split `groups` into per-device batches, keyed by the device of each group's first
+/// duplicate (the files actions modify). Batches appear in order of first occurrence and
+/// keep the groups' relative order.
+pub fn by_device(groups: &[DuplicateGroup]) -> Vec<DeviceBatch> {
+    let mut batches: Vec<DeviceBatch> = Vec::new();
+    for g in groups {
+        let device = g.members.get(1).or(g.members.first()).map_or(0, |p| device_of(p));
+        match batches.iter_mut().find(|b| b.device == device) {
+            Some(b) => b.groups.push(g.clone()),
+            None => batches.push(DeviceBatch { device, groups: vec![g.clone()] }),
+        }
+    }
+    batches
+}
+
+/// Device id of the filesystem holding `p`; 0 when unknown.
+#[cfg(unix)]
+pub(crate) fn device_of(p: &Path) -> u64 {
+    use std::os::unix::fs::MetadataExt;
+    std::fs::metadata(p).map_or(0, |m| m.dev())
+}
+
+#[cfg(not(unix))]
+pub(crate) fn device_of(_p: &Path) -> u64 {
+    0
+}
+
+/// Human-readable device id: `major:minor` on Linux, the raw number elsewhere.
+pub fn device_label(device: u64) -> String {
+    #[cfg(target_os = "linux")]
+    {
+        let dev = device as libc::dev_t;
+        format!("{}:{}", libc::major(dev), libc::minor(dev))
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        device.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cmp::Reverse;
+    use std::collections::BinaryHeap;
+
+    /// Simulate `workers` threads each taking the next job from the queue when idle;
+    /// returns the time at which the last worker finishes.
+    fn makespan(jobs: &[u64], workers: usize) -> u64 {
+        let mut free_at: BinaryHeap<Reverse<u64>> = (0..workers).map(|_| Reverse(0)).collect();
+        for &job in jobs {
+            let Reverse(t) = free_at.pop().unwrap();
+            free_at.push(Reverse(t + job));
+        }
+        free_at.into_iter().map(|Reverse(t)| t).max().unwrap_or(0)
+    }
+
+    #[test]
+    fn largest_first_avoids_straggler() {
+        // Many small files discovered before one 80 GB-like straggler.
+        let mut jobs: Vec<u64> = vec![1; 64];
+        jobs.push(80);
+        let fifo = makespan(&jobs, 4);
+
+        order(&mut jobs, Strategy::LargestFirst, |c| *c, |_| (0, 0));
+        let lpt = makespan(&jobs, 4);
+
+        assert_eq!(jobs[0], 80);
+        assert_eq!(fifo, 96); // 16 rounds of small files, then the straggler alone
+        assert_eq!(lpt, 80); // straggler runs while the others drain the small files
+    }
+
+    #[test]
+    fn fifo_keeps_order() {
+        let mut jobs = vec![3u64, 9, 1];
+        order(&mut jobs, Strategy::Fifo, |c| *c, |_| (0, 0));
+        assert_eq!(jobs, vec![3, 9, 1]);
+    }
+
+    #[test]
+    fn most_reclaimable_buckets_go_first() {
+        // (bucket, file size, files in bucket): three 10-byte copies free 20, two 15-byte
+        // copies free 15, and the 8-byte bucket frees 16.
+        let mut jobs =
+            vec![(1, 8, 3), (2, 15, 2), (0, 10, 3), (1, 8, 3), (0, 10, 3), (2, 15, 2), (1, 8, 3)];
+        jobs.push((0, 10, 3));
+        order(&mut jobs, Strategy::Reclaimable, |j| j.1, |j| (j.0, worth(j.1, j.2)));
+        let buckets: Vec<usize> = jobs.iter().map(|j| j.0).collect();
+        assert_eq!(buckets, [0, 0, 0, 1, 1, 1, 2, 2]);
+    }
+
+    #[test]
+    fn parallel_map_preserves_order() {
+        let items: Vec<u64> = (0..100).collect();
+        let out = parallel_map(&items, 8, |x| x * 2);
+        assert_eq!(out, items.iter().map(|x| x * 2).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn batches_keep_order_within_device() {
+        let dir = std::env::temp_dir().join(format!("ducky_sched_dev_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        for name in ["a", "b", "c"] {
+            std::fs::write(dir.join(name), b"x").unwrap();
+        }
+        let g1 = DuplicateGroup::new(1, vec![dir.join("a"), dir.join("b")]);
+        let g2 = DuplicateGroup::new(1, vec![dir.join("a"), dir.join("c")]);
+        let batches = by_device(&[g1, g2]);
+        assert_eq!(batches.len(), 1);
+        assert_eq!(batches[0].groups[1].members[1], dir.join("c"));
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
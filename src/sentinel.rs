@@ -0,0 +1,172 @@
+//! `ducky sentinel DIR --against ARCHIVE`: the everyday "do I already have this?" check for
+//! download folders. Only archive files sharing a size with something in DIR are hashed, and
+//! every hash goes through the persistent cache, so repeated runs against a large archive
+//! only read what changed. Matches can be sent to the trash; archive files are never touched.
+
+use crate::actions::{apply_actions, ActionOptions, ActionStats};
+use crate::cache::{cached, HashCache};
+use crate::fs_utils::{collect_files, Bounds};
+use crate::grouping::group_by_size;
+use crate::hashing::full_hash;
+use crate::journal::Journal;
+use crate::output::DuplicateGroup;
+use crate::schedule;
+use crate::warnings::warning;
+use anyhow::{bail, Context, Result};
+use bytesize::ByteSize;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// A file in the watched directory whose content already exists in the archive.
+#[derive(Debug, Serialize, PartialEq, Eq)]
+pub struct Match {
+    pub path: PathBuf,
+    /// One archive file with the same content.
+    pub archive: PathBuf,
+    pub size: u64,
+    pub hash: String,
+}
+
+/// Files under `dir` (at least `min_size` bytes) whose content is also under `against`.
+pub fn find(
+    dir: &Path,
+    against: &[PathBuf],
+    min_size: u64,
+    cache: &HashCache,
+    threads: usize,
+) -> Result<Vec<Match>> {
+    let real = fs::canonicalize(dir).with_context(|| format!("sentinel {}", dir.display()))?;
+    for a in against {
+        let other = fs::canonicalize(a).with_context(|| format!("--against {}", a.display()))?;
+        if real.starts_with(&other) || other.starts_with(&real) {
+            bail!("{} and --against {} overlap", dir.display(), a.display());
+        }
+    }
+    let bounds = Bounds { min_size, ..Default::default() };
+    let walk = |roots: &[PathBuf]| {
+        collect_files(roots, true, false, &bounds, None, &[], &Default::default())
+    };
+    let watched = group_by_size(&walk(&[dir.to_path_buf()])?);
+    let mut archive = group_by_size(&walk(against)?);
+    archive.retain(|size, _| watched.contains_key(size));
+
+    // Both sides are hashed only where their sizes meet.
+    let work: Vec<(u64, &PathBuf, bool)> = archive
+        .iter()
+        .flat_map(|(size, files)| files.iter().map(move |f| (*size, &f.path, true)))
+        .chain(
+            watched
+                .iter()
+                .filter(|(size, _)| archive.contains_key(size))
+                .flat_map(|(size, files)| files.iter().map(move |f| (*size, &f.path, false))),
+        )
+        .collect();
+    let hashes = schedule::parallel_map(&work, threads, |(_, p, _)| {
+        cached(p, cache, |id| cache.full(id), |id, h| cache.put_full(id, h), || full_hash(p))
+    });
+
+    let mut known: HashMap<(u64, String), &PathBuf> = HashMap::new();
+    let mut found = Vec::new();
+    for ((size, path, in_archive), res) in work.iter().zip(hashes) {
+        let hash = match res {
+            Ok(h) => h,
+            Err(e) => {
+                warning!("hash failed {}: {}", path.display(), e);
+                continue;
+            }
+        };
+        // Archive entries come first, so every lookup sees the whole archive.
+        if *in_archive {
+            known.entry((*size, hash)).or_insert(path);
+        } else if let Some(archive) = known.get(&(*size, hash.clone())) {
+            let (path, archive) = ((*path).clone(), (*archive).clone());
+            found.push(Match { path, archive, size: *size, hash });
+        }
+    }
+    found.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(found)
+}
+
+/// `ducky sentinel`: list (or, with `trash`, move to the trash) what `find` reports. Failed
+/// actions are counted in the returned `errors`.
+pub fn run(
+    dir: &Path,
+    against: &[PathBuf],
+    min_size: u64,
+    trash: bool,
+    yes: bool,
+    dry_run: bool,
+    json: bool,
+) -> Result<ActionStats> {
+    let cache = HashCache::open_default();
+    let matches = find(dir, against, min_size, &cache, schedule::default_threads())?;
+    if let Err(e) = cache.save() {
+        warning!("failed to save hash cache: {:#}", e);
+    }
+    let bytes: u64 = matches.iter().map(|m| m.size).sum();
+    if json {
+        println!("{}", serde_json::to_string_pretty(&matches)?);
+    } else {
+        for m in &matches {
+            println!("{}\talready in {}", m.path.display(), m.archive.display());
+        }
+        println!("{} files ({}) already in the archive", matches.len(), ByteSize(bytes));
+    }
+    if !trash || matches.is_empty() {
+        return Ok(ActionStats::default());
+    }
+
+    // One group per archive file, with the archive copy as the canonical that is kept.
+    let mut by_archive: BTreeMap<&PathBuf, DuplicateGroup> = BTreeMap::new();
+    for m in &matches {
+        let g = by_archive.entry(&m.archive).or_insert_with(|| {
+            let mut g = DuplicateGroup::new(m.size, Vec::new()).with_hash(m.hash.clone());
+            g.members.push(m.archive.clone());
+            g
+        });
+        g.members.push(m.path.clone());
+    }
+    let groups: Vec<DuplicateGroup> = by_archive.into_values().collect();
+    let opts = ActionOptions {
+        trash: true,
+        yes,
+        dry_run,
+        paranoid: true,
+        act_only_under: vec![fs::canonicalize(dir)?],
+        ..Default::default()
+    };
+    let mut journal =
+        if yes && !dry_run { Journal::open_default() } else { Journal::in_memory() };
+    let stats = apply_actions(&groups, &opts, &mut journal);
+    stats.report();
+    Ok(stats)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_only_content_already_in_the_archive() {
+        let base = std::env::temp_dir().join(format!("ducky_sentinel_{}", std::process::id()));
+        let (dl, archive) = (base.join("downloads"), base.join("archive"));
+        std::fs::create_dir_all(&dl).unwrap();
+        std::fs::create_dir_all(archive.join("2024")).unwrap();
+        std::fs::write(dl.join("copy.jpg"), b"photo bytes").unwrap();
+        std::fs::write(dl.join("new.jpg"), b"fresh bytes").unwrap();
+        std::fs::write(dl.join("other.txt"), b"unrelated").unwrap();
+        std::fs::write(archive.join("2024").join("kept.jpg"), b"photo bytes").unwrap();
+
+        let cache = HashCache::disabled();
+        let found = find(&dl, std::slice::from_ref(&archive), 1, &cache, 2).unwrap();
+        assert_eq!(found.len(), 1, "{:?}", found);
+        assert_eq!(found[0].path, dl.join("copy.jpg"));
+        assert_eq!(found[0].archive, archive.join("2024").join("kept.jpg"));
+
+        assert!(find(&dl, std::slice::from_ref(&base), 1, &cache, 2).is_err());
+
+        let _ = std::fs::remove_dir_all(base);
+    }
+}
@@ -0,0 +1,100 @@
+//! Copies seen through read-only filesystem snapshots.
+//!
+//! Snapshots are read-only, so deleting or linking a copy inside one is impossible and counting
+//! it as reclaimable overstates what could be freed. Such members stay in their groups, where
+//! they can still be the canonical or a `--compare` reference, but are never acted on and are
+//! left out of the reclaimable totals.
+//!
+//! The detection is heuristic. A path under a `.zfs/snapshot` directory is a ZFS snapshot.
+//! On Linux, a directory in a btrfs subvolume with the read-only flag set is a btrfs snapshot.
+//! That covers snapper, timeshift and `btrfs subvolume snapshot -r`. Writable btrfs snapshots
+//! can't be told apart from ordinary subvolumes and are treated as live data.
+
+use crate::fs_utils::FileMeta;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+/// Answers "is this path inside a snapshot?", remembering the answer per directory and, for
+/// btrfs, per subvolume.
+#[derive(Default)]
+pub struct Snapshots {
+    dirs: HashMap<PathBuf, bool>,
+    /// Read-only flag by device: every btrfs subvolume has a device number of its own.
+    subvolumes: HashMap<u64, bool>,
+}
+
+impl Snapshots {
+    pub fn contains(&mut self, path: &Path) -> bool {
+        let dir = path.parent().unwrap_or(Path::new("."));
+        if let Some(known) = self.dirs.get(dir) {
+            return *known;
+        }
+        let snapshot = match fs::canonicalize(dir) {
+            Ok(real) => in_zfs_snapshot(&real) || self.read_only_subvolume(&real),
+            Err(_) => false,
+        };
+        self.dirs.insert(dir.to_path_buf(), snapshot);
+        snapshot
+    }
+
+    fn read_only_subvolume(&mut self, dir: &Path) -> bool {
+        let Some(dev) = FileMeta::of(dir).map(|m| m.dev).filter(|d| *d != 0) else {
+            return false;
+        };
+        *self.subvolumes.entry(dev).or_insert_with(|| read_only_subvolume(dir, dev))
+    }
+}
+
+/// Whether `path` lies under `.zfs/snapshot/`.
+fn in_zfs_snapshot(path: &Path) -> bool {
+    let names: Vec<_> = path
+        .components()
+        .filter_map(|c| match c {
+            Component::Normal(n) => Some(n),
+            _ => None,
+        })
+        .collect();
+    names.windows(2).any(|w| w[0] == ".zfs" && w[1] == "snapshot")
+}
+
+/// Whether `dir`, on device `dev`, is in a btrfs subvolume marked read-only.
+#[cfg(target_os = "linux")]
+fn read_only_subvolume(dir: &Path, dev: u64) -> bool {
+    use std::os::unix::io::AsRawFd;
+    // _IOR(0x94, 25, __u64); not exported by every libc version.
+    const BTRFS_IOC_SUBVOL_GETFLAGS: u64 = 0x8008_9419;
+    const BTRFS_SUBVOL_RDONLY: u64 = 1 << 1;
+
+    // The subvolume's root is the topmost ancestor still on its device.
+    let on_dev = |a: &&Path| FileMeta::of(a).is_some_and(|m| m.dev == dev);
+    let Some(Ok(root)) = dir.ancestors().take_while(on_dev).last().map(fs::File::open) else {
+        return false;
+    };
+    let mut flags: u64 = 0;
+    // SAFETY: the descriptor is valid and `flags` outlives the call. Other filesystems reject
+    // the request with ENOTTY.
+    let rc = unsafe { libc::ioctl(root.as_raw_fd(), BTRFS_IOC_SUBVOL_GETFLAGS as _, &mut flags) };
+    rc == 0 && flags & BTRFS_SUBVOL_RDONLY != 0
+}
+
+#[cfg(not(target_os = "linux"))]
+fn read_only_subvolume(_dir: &Path, _dev: u64) -> bool {
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn zfs_snapshot_paths_are_recognized() {
+        assert!(in_zfs_snapshot(Path::new("/tank/home/.zfs/snapshot/daily-1/a.jpg")));
+        assert!(!in_zfs_snapshot(Path::new("/tank/home/.zfs/a.jpg")));
+        assert!(!in_zfs_snapshot(Path::new("/tank/home/snapshot/.zfs")));
+
+        // An ordinary directory is live data.
+        let mut snapshots = Snapshots::default();
+        assert!(!snapshots.contains(&std::env::temp_dir().join("a")));
+    }
+}
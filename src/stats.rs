@@ -0,0 +1,143 @@
+//! Aggregate statistics over duplicate groups and hashing work for summaries.
+
+use crate::output::DuplicateGroup;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Duplicate copies found at one directory depth below the scan roots.
+#[derive(Debug, Serialize, PartialEq, Eq)]
+pub struct DepthStat {
+    /// 0 = directly inside a root.
+    pub depth: usize,
+    /// Redundant copies (every member except the canonical).
+    pub files: usize,
+    pub bytes: u64,
+    /// Directory at this depth holding the most redundant bytes.
+    pub top_dir: PathBuf,
+    pub top_dir_bytes: u64,
+}
+
+/// Break duplicates down by directory depth relative to the root each member was found under.
+pub fn by_depth(groups: &[DuplicateGroup], roots: &[PathBuf]) -> Vec<DepthStat> {
+    // depth -> (files, bytes, dir -> bytes)
+    let mut acc: BTreeMap<usize, (usize, u64, BTreeMap<&Path, u64>)> = BTreeMap::new();
+    for g in groups {
+        for m in g.members.iter().skip(1) {
+            let Some(dir) = m.parent() else { continue };
+            let depth = roots
+                .iter()
+                .filter_map(|r| dir.strip_prefix(r).ok())
+                .map(|rel| rel.components().count())
+                .min()
+                .unwrap_or_else(|| dir.components().count());
+            let e = acc.entry(depth).or_default();
+            e.0 += 1;
+            e.1 = e.1.saturating_add(g.size);
+            *e.2.entry(dir).or_default() += g.size;
+        }
+    }
+    acc.into_iter()
+        .map(|(depth, (files, bytes, dirs))| {
+            // Most bytes wins; ties go to the lexicographically first directory.
+            let (top_dir, top_dir_bytes) = dirs
+                .into_iter()
+                .fold((Path::new(""), 0u64), |best, (d, b)| if b > best.1 { (d, b) } else { best });
+            DepthStat { depth, files, bytes, top_dir: top_dir.to_path_buf(), top_dir_bytes }
+        })
+        .collect()
+}
+
+/// Hashing cost of one file extension (lowercased; `""` for files without one).
+#[derive(Debug, Serialize, PartialEq)]
+pub struct ExtCost {
+    pub ext: String,
+    /// Candidates quick-hashed.
+    pub files: usize,
+    /// Bytes covered by quick and full hashes (cache hits included).
+    pub bytes: u64,
+    /// Time spent hashing, summed over worker threads.
+    pub ms: u64,
+    /// Share of the total hashing time, in percent.
+    pub share: f64,
+}
+
+/// Collects hashing time and bytes per extension from the hashing workers.
+#[derive(Default)]
+pub struct CostTracker {
+    by_ext: Mutex<BTreeMap<String, (usize, u64, Duration)>>,
+}
+
+impl CostTracker {
+    /// Record one hash of `path`; `first` marks the file's first hash (its quick hash).
+    pub fn record(&self, path: &Path, bytes: u64, took: Duration, first: bool) {
+        let ext = path
+            .extension()
+            .map(|e| e.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        let mut map = self.by_ext.lock().unwrap_or_else(|e| e.into_inner());
+        let e = map.entry(ext).or_default();
+        e.0 += usize::from(first);
+        e.1 = e.1.saturating_add(bytes);
+        e.2 += took;
+    }
+
+    /// Costliest extensions first.
+    pub fn report(self) -> Vec<ExtCost> {
+        let map = self.by_ext.into_inner().unwrap_or_else(|e| e.into_inner());
+        let total: Duration = map.values().map(|v| v.2).sum();
+        let mut out: Vec<ExtCost> = map
+            .into_iter()
+            .map(|(ext, (files, bytes, took))| ExtCost {
+                ext,
+                files,
+                bytes,
+                ms: took.as_millis() as u64,
+                share: if total.is_zero() {
+                    0.0
+                } else {
+                    (took.as_secs_f64() / total.as_secs_f64() * 1000.0).round() / 10.0
+                },
+            })
+            .collect();
+        out.sort_by(|a, b| b.share.total_cmp(&a.share).then_with(|| b.bytes.cmp(&a.bytes)));
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn depth_relative_to_root() {
+        let roots = vec![PathBuf::from("/r")];
+        let groups = vec![
+            DuplicateGroup::new(10, vec!["/r/a".into(), "/r/x/y/b".into(), "/r/x/y/c".into()]),
+            DuplicateGroup::new(5, vec!["/r/q".into(), "/r/z/w/q".into()]),
+        ];
+        let stats = by_depth(&groups, &roots);
+        assert_eq!(stats.len(), 1);
+        assert_eq!(stats[0].depth, 2);
+        assert_eq!(stats[0].files, 3);
+        assert_eq!(stats[0].bytes, 25);
+        assert_eq!(stats[0].top_dir, PathBuf::from("/r/x/y"));
+        assert_eq!(stats[0].top_dir_bytes, 20);
+    }
+
+    #[test]
+    fn cost_is_grouped_by_lowercased_extension() {
+        let t = CostTracker::default();
+        t.record(Path::new("a.MKV"), 100, Duration::from_millis(30), true);
+        t.record(Path::new("a.MKV"), 900, Duration::from_millis(50), false);
+        t.record(Path::new("b.mkv"), 100, Duration::from_millis(0), true);
+        t.record(Path::new("notes"), 10, Duration::from_millis(20), true);
+        let report = t.report();
+        assert_eq!(report.len(), 2);
+        assert_eq!((report[0].ext.as_str(), report[0].files, report[0].bytes), ("mkv", 2, 1100));
+        assert_eq!(report[0].share, 80.0);
+        assert_eq!((report[1].ext.as_str(), report[1].share), ("", 20.0));
+    }
+}
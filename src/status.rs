@@ -0,0 +1,153 @@
+//! One-line status on demand: `kill -USR1 <pid>` (or Ctrl-T / SIGINFO on BSD and macOS)
+//! prints the current stage, files and bytes hashed, elapsed time and ETA to stderr while
+//! the scan keeps running.
+//!
+//! Every stage updates the shared counters below; the signal handler only raises a flag,
+//! and a background thread does the printing.
+
+use crate::progress::eta;
+use bytesize::ByteSize;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicU8, Ordering};
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+
+/// Pipeline stages, in order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Stage {
+    Walk,
+    Size,
+    QuickHash,
+    FullHash,
+    Act,
+    Report,
+}
+
+impl Stage {
+    const ALL: [Stage; 6] =
+        [Stage::Walk, Stage::Size, Stage::QuickHash, Stage::FullHash, Stage::Act, Stage::Report];
+
+    fn name(self) -> &'static str {
+        match self {
+            Stage::Walk => "walk",
+            Stage::Size => "size",
+            Stage::QuickHash => "quick-hash",
+            Stage::FullHash => "full-hash",
+            Stage::Act => "act",
+            Stage::Report => "report",
+        }
+    }
+}
+
+struct Counters {
+    stage: AtomicU8,
+    found: AtomicU64,
+    files: AtomicU64,
+    bytes: AtomicU64,
+    total: AtomicU64,
+}
+
+static COUNTERS: Counters = Counters {
+    stage: AtomicU8::new(0),
+    found: AtomicU64::new(0),
+    files: AtomicU64::new(0),
+    bytes: AtomicU64::new(0),
+    total: AtomicU64::new(0),
+};
+static STARTED: OnceLock<Instant> = OnceLock::new();
+static REQUESTED: AtomicBool = AtomicBool::new(false);
+
+pub fn set_stage(stage: Stage) {
+    COUNTERS.stage.store(stage as u8, Ordering::Relaxed);
+}
+
+/// Count one file accepted by the walk.
+pub fn discovered() {
+    COUNTERS.found.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Files accepted by the walk so far.
+pub fn found() -> u64 {
+    COUNTERS.found.load(Ordering::Relaxed)
+}
+
+/// Count one hashed file of `bytes`.
+pub fn hashed(bytes: u64) {
+    COUNTERS.files.fetch_add(1, Ordering::Relaxed);
+    COUNTERS.bytes.fetch_add(bytes, Ordering::Relaxed);
+}
+
+/// Expected hashing work in bytes, for the ETA.
+pub fn set_total(bytes: u64) {
+    COUNTERS.total.store(bytes, Ordering::Relaxed);
+}
+
+/// The status line for the current counters.
+pub fn line() -> String {
+    let stage = Stage::ALL[COUNTERS.stage.load(Ordering::Relaxed) as usize % Stage::ALL.len()];
+    let files = COUNTERS.files.load(Ordering::Relaxed);
+    let bytes = COUNTERS.bytes.load(Ordering::Relaxed);
+    let total = COUNTERS.total.load(Ordering::Relaxed).max(bytes);
+    let elapsed = STARTED.get().map_or(Duration::ZERO, |t| t.elapsed());
+    let eta = match stage {
+        Stage::QuickHash | Stage::FullHash => eta(bytes, total, elapsed),
+        _ => None,
+    };
+    format!(
+        "status: stage={} found={} files={} hashed={} elapsed={}s eta={}",
+        stage.name(),
+        found(),
+        files,
+        ByteSize(bytes),
+        elapsed.as_secs(),
+        eta.map_or_else(|| "--".to_string(), |d| format!("{}s", d.as_secs()))
+    )
+}
+
+#[cfg(unix)]
+extern "C" fn on_signal(_: libc::c_int) {
+    // Only async-signal-safe work here: the watcher thread prints.
+    REQUESTED.store(true, Ordering::Relaxed);
+}
+
+/// Start the clock and answer status signals for the rest of the process.
+pub fn install() {
+    STARTED.get_or_init(Instant::now);
+    #[cfg(unix)]
+    {
+        let handler = on_signal as extern "C" fn(libc::c_int) as libc::sighandler_t;
+        // SAFETY: the handler only stores to an atomic.
+        unsafe {
+            libc::signal(libc::SIGUSR1, handler);
+            #[cfg(any(
+                target_os = "macos",
+                target_os = "ios",
+                target_os = "freebsd",
+                target_os = "netbsd",
+                target_os = "openbsd",
+                target_os = "dragonfly"
+            ))]
+            libc::signal(libc::SIGINFO, handler);
+        }
+        std::thread::spawn(|| loop {
+            std::thread::sleep(Duration::from_millis(100));
+            if REQUESTED.swap(false, Ordering::Relaxed) {
+                eprintln!("{}", line());
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn line_reports_stage_and_counters() {
+        set_stage(Stage::FullHash);
+        hashed(2048);
+        let line = line();
+        assert!(line.starts_with("status: stage=full-hash found="), "{}", line);
+        assert!(line.contains("hashed=") && line.contains("eta="), "{}", line);
+    }
+}
@@ -0,0 +1,168 @@
+//! Finding files ducky sent to the trash again, so `ducky undo` can put the original back
+//! instead of re-creating it from the canonical.
+//!
+//! - Linux and other freedesktop systems, Windows: the trash records each item's original path
+//!   and deletion time. The item trashed from the journaled path closest to the journaled time
+//!   is restored, provided it was trashed within ten minutes of it and still has the journaled
+//!   content hash.
+//! - macOS: the Trash keeps no original path that can be read back, so the item is matched by
+//!   name (Finder appends a suffix on collisions) and by content hash, in `~/.Trash` and in the
+//!   `.Trashes` folder of the volume the file was on.
+//!
+//! Anything not found (the trash was emptied, or the platform has no trash to search) is left
+//! to the caller's fallback.
+
+pub use imp::TrashCan;
+
+#[cfg(any(
+    target_os = "windows",
+    all(unix, not(target_os = "macos"), not(target_os = "ios"), not(target_os = "android"))
+))]
+mod imp {
+    use crate::hashing::full_hash;
+    use anyhow::Result;
+    use std::path::{Path, PathBuf};
+
+    /// How far a trash item's deletion time may be from the journaled one, in seconds.
+    const WINDOW: u64 = 10 * 60;
+
+    /// The trash as listed once per undo run.
+    pub struct TrashCan {
+        items: Vec<trash::TrashItem>,
+    }
+
+    impl TrashCan {
+        /// List the trash. An error means it can't be searched at all.
+        pub fn open() -> Result<Self> {
+            Ok(Self { items: trash::os_limited::list()? })
+        }
+
+        /// Move the file trashed from `path` at about `when` (seconds since the epoch) back
+        /// to `path`, provided its content still hashes to `hash`. Items are hashed where they
+        /// lie in the trash; one that doesn't match is skipped for the next closest. Returns
+        /// false when no such item is in the trash.
+        pub fn restore(&mut self, path: &Path, when: u64, hash: &str) -> Result<bool> {
+            loop {
+                let nearest = self
+                    .items
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, item)| item.original_path() == path)
+                    .map(|(i, item)| (i, (item.time_deleted.max(0) as u64).abs_diff(when)))
+                    .min_by_key(|(_, dt)| *dt);
+                let Some((i, _)) = nearest.filter(|(_, dt)| *dt <= WINDOW) else {
+                    return Ok(false);
+                };
+                let item = self.items.swap_remove(i);
+                if full_hash(&stored_at(&item)).ok().as_deref() != Some(hash) {
+                    continue;
+                }
+                if let Some(parent) = path.parent() {
+                    std::fs::create_dir_all(parent)?;
+                }
+                trash::os_limited::restore_all([item])?;
+                return Ok(true);
+            }
+        }
+    }
+
+    /// Where `item`'s content is kept: `files/NAME` next to the `info/NAME.trashinfo` that is
+    /// its id.
+    #[cfg(not(windows))]
+    fn stored_at(item: &trash::TrashItem) -> PathBuf {
+        let info = Path::new(&item.id);
+        let trash_dir = info.parent().and_then(Path::parent).unwrap_or(Path::new(""));
+        trash_dir.join("files").join(info.file_stem().unwrap_or_default())
+    }
+
+    /// Where `item`'s content is kept: its id is the `$R` file's path in `$Recycle.Bin`.
+    #[cfg(windows)]
+    fn stored_at(item: &trash::TrashItem) -> PathBuf {
+        PathBuf::from(&item.id)
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod imp {
+    use crate::hashing::full_hash;
+    use crate::replicate::move_file;
+    use anyhow::Result;
+    use std::fs;
+    use std::path::{Path, PathBuf};
+
+    pub struct TrashCan {
+        home: Option<PathBuf>,
+    }
+
+    impl TrashCan {
+        pub fn open() -> Result<Self> {
+            Ok(Self { home: std::env::var_os("HOME").map(PathBuf::from) })
+        }
+
+        /// Move the trashed copy of `path` whose content hashes to `hash` back to `path`.
+        /// Returns false when there is none.
+        pub fn restore(&mut self, path: &Path, _when: u64, hash: &str) -> Result<bool> {
+            let Some(name) = path.file_name().map(|n| n.to_string_lossy().into_owned()) else {
+                return Ok(false);
+            };
+            let (stem, ext) = match name.rsplit_once('.') {
+                Some((s, e)) if !s.is_empty() => (s.to_string(), format!(".{}", e)),
+                _ => (name.clone(), String::new()),
+            };
+            for dir in self.dirs(path) {
+                let Ok(entries) = fs::read_dir(&dir) else { continue };
+                for ent in entries.flatten() {
+                    let candidate = ent.file_name().to_string_lossy().into_owned();
+                    // "report.pdf", or Finder's "report 2.pdf" / "report 10.45.32.pdf".
+                    let renamed =
+                        candidate.starts_with(&format!("{} ", stem)) && candidate.ends_with(&ext);
+                    if candidate != name && !renamed {
+                        continue;
+                    }
+                    let from = ent.path();
+                    if full_hash(&from).ok().as_deref() != Some(hash) {
+                        continue;
+                    }
+                    move_file(&from, path, hash)?;
+                    return Ok(true);
+                }
+            }
+            Ok(false)
+        }
+
+        /// `/Volumes/NAME/.Trashes/UID` for files on another volume, then `~/.Trash`.
+        fn dirs(&self, path: &Path) -> Vec<PathBuf> {
+            let mut dirs = Vec::new();
+            let mut parts = path.components().skip(1);
+            if let (Some(first), Some(volume)) = (parts.next(), parts.next()) {
+                if first.as_os_str() == "Volumes" {
+                    // SAFETY: getuid has no preconditions and cannot fail.
+                    let uid = unsafe { libc::getuid() };
+                    let volume = Path::new("/Volumes").join(volume);
+                    dirs.push(volume.join(".Trashes").join(uid.to_string()));
+                }
+            }
+            dirs.extend(self.home.as_ref().map(|h| h.join(".Trash")));
+            dirs
+        }
+    }
+}
+
+#[cfg(any(target_os = "ios", target_os = "android", not(any(unix, windows))))]
+mod imp {
+    use anyhow::Result;
+    use std::path::Path;
+
+    /// No trash to search on this platform.
+    pub struct TrashCan;
+
+    impl TrashCan {
+        pub fn open() -> Result<Self> {
+            Ok(Self)
+        }
+
+        pub fn restore(&mut self, _path: &Path, _when: u64, _hash: &str) -> Result<bool> {
+            Ok(false)
+        }
+    }
+}
@@ -0,0 +1,226 @@
+//! `--tui`: full-screen review of duplicate groups before acting (ratatui on stderr).
+//!
+//! Groups are listed on the left and the selected group's members on the right, with the
+//! metadata of the highlighted member below. The user picks which member to keep, skips
+//! groups, and either applies the action to the rest on exit (`a`) or leaves without
+//! changing anything (`q`).
+
+use crate::output::DuplicateGroup;
+use anyhow::Result;
+use bytesize::ByteSize;
+use ratatui::backend::CrosstermBackend;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::crossterm::execute;
+use ratatui::crossterm::terminal::{
+    disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen,
+};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Modifier, Style};
+use ratatui::widgets::{Block, List, ListItem, ListState, Paragraph};
+use ratatui::{Frame, Terminal};
+use std::io;
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+
+/// What the user decided for each group while browsing.
+struct Review<'a> {
+    groups: &'a [DuplicateGroup],
+    keep: Vec<usize>,
+    skip: Vec<bool>,
+    group: usize,
+    member: usize,
+}
+
+enum Exit {
+    Apply,
+    Quit,
+}
+
+impl<'a> Review<'a> {
+    fn new(groups: &'a [DuplicateGroup]) -> Self {
+        let n = groups.len();
+        Review { groups, keep: vec![0; n], skip: vec![false; n], group: 0, member: 0 }
+    }
+
+    fn key(&mut self, code: KeyCode) -> Option<Exit> {
+        let members = self.groups.get(self.group).map_or(0, |g| g.members.len());
+        match code {
+            KeyCode::Up | KeyCode::Char('k') => {
+                self.group = self.group.saturating_sub(1);
+                self.member = 0;
+            }
+            KeyCode::Down | KeyCode::Char('j') => {
+                self.group = (self.group + 1).min(self.groups.len().saturating_sub(1));
+                self.member = 0;
+            }
+            KeyCode::Left | KeyCode::Char('h') => self.member = self.member.saturating_sub(1),
+            KeyCode::Right | KeyCode::Char('l') => {
+                self.member = (self.member + 1).min(members.saturating_sub(1))
+            }
+            KeyCode::Enter | KeyCode::Char(' ') if members > 0 => self.keep[self.group] = self.member,
+            KeyCode::Char('s') if members > 0 => self.skip[self.group] = !self.skip[self.group],
+            KeyCode::Char('a') => return Some(Exit::Apply),
+            KeyCode::Char('q') | KeyCode::Esc => return Some(Exit::Quit),
+            _ => {}
+        }
+        None
+    }
+
+    /// Groups to act on, each with the chosen keeper moved to the front.
+    fn result(&self) -> Vec<DuplicateGroup> {
+        self.groups
+            .iter()
+            .zip(self.keep.iter().zip(&self.skip))
+            .filter(|(_, (_, skip))| !**skip)
+            .map(|(g, (keep, _))| {
+                let mut g = g.clone();
+                g.members.swap(0, *keep);
+                g
+            })
+            .collect()
+    }
+}
+
+/// Browse `groups` full-screen; returns the groups to `action`, or none if the user quits.
+pub fn review(groups: &[DuplicateGroup], action: &str) -> Result<Vec<DuplicateGroup>> {
+    if groups.is_empty() {
+        return Ok(Vec::new());
+    }
+    enable_raw_mode()?;
+    execute!(io::stderr(), EnterAlternateScreen)?;
+    let res = Terminal::new(CrosstermBackend::new(io::stderr()))
+        .map_err(anyhow::Error::from)
+        .and_then(|mut t| event_loop(&mut t, Review::new(groups), action));
+    // Restore the terminal even if drawing failed.
+    let _ = disable_raw_mode();
+    let _ = execute!(io::stderr(), LeaveAlternateScreen);
+    res
+}
+
+fn event_loop(
+    terminal: &mut Terminal<CrosstermBackend<io::Stderr>>,
+    mut state: Review,
+    action: &str,
+) -> Result<Vec<DuplicateGroup>> {
+    loop {
+        terminal.draw(|f| draw(f, &state, action))?;
+        if let Event::Key(k) = event::read()? {
+            if k.kind != KeyEventKind::Press {
+                continue;
+            }
+            match state.key(k.code) {
+                Some(Exit::Apply) => return Ok(state.result()),
+                Some(Exit::Quit) => return Ok(Vec::new()),
+                None => {}
+            }
+        }
+    }
+}
+
+fn draw(f: &mut Frame, state: &Review, action: &str) {
+    let [main, details, help] =
+        Layout::vertical([Constraint::Min(5), Constraint::Length(6), Constraint::Length(1)])
+            .areas(f.area());
+    let [left, right] =
+        Layout::horizontal([Constraint::Percentage(40), Constraint::Percentage(60)]).areas(main);
+    let bold = Style::default().add_modifier(Modifier::REVERSED);
+
+    let items: Vec<ListItem> = state
+        .groups
+        .iter()
+        .enumerate()
+        .map(|(i, g)| {
+            let name = g.members[state.keep[i]].file_name().unwrap_or_default().to_string_lossy();
+            let mark = if state.skip[i] { "skip" } else { "    " };
+            ListItem::new(format!("{} {}× {} {}", mark, g.members.len(), ByteSize(g.size), name))
+        })
+        .collect();
+    let mut groups_state = ListState::default().with_selected(Some(state.group));
+    let title = format!(" groups ({}) ", state.groups.len());
+    f.render_stateful_widget(
+        List::new(items).block(Block::bordered().title(title)).highlight_style(bold),
+        left,
+        &mut groups_state,
+    );
+
+    let g = &state.groups[state.group];
+    let members: Vec<ListItem> = g
+        .members
+        .iter()
+        .enumerate()
+        .map(|(i, p)| {
+            let mark = if i == state.keep[state.group] { "keep" } else { action };
+            ListItem::new(format!("{:<10} {}", mark, p.display()))
+        })
+        .collect();
+    let mut members_state = ListState::default().with_selected(Some(state.member));
+    f.render_stateful_widget(
+        List::new(members).block(Block::bordered().title(" members ")).highlight_style(bold),
+        right,
+        &mut members_state,
+    );
+
+    let path = &g.members[state.member];
+    f.render_widget(
+        Paragraph::new(metadata(path)).block(Block::bordered().title(" details ")),
+        details,
+    );
+    f.render_widget(
+        Paragraph::new(
+            "↑↓ group  ←→ member  enter keep  s skip group  a apply and exit  q quit without changes",
+        ),
+        help,
+    );
+}
+
+/// A few lines describing `path` for the details pane.
+fn metadata(path: &Path) -> String {
+    let m = match std::fs::symlink_metadata(path) {
+        Ok(m) => m,
+        Err(e) => return format!("{}\n{}", path.display(), e),
+    };
+    let modified = m
+        .modified()
+        .ok()
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map_or_else(|| "?".to_string(), |d| d.as_secs().to_string());
+    #[cfg(unix)]
+    let links = {
+        use std::os::unix::fs::MetadataExt;
+        format!("  links {}  inode {}", m.nlink(), m.ino())
+    };
+    #[cfg(not(unix))]
+    let links = String::new();
+    format!(
+        "{}\nsize {}  modified {} (unix)  {}{}",
+        path.display(),
+        ByteSize(m.len()),
+        modified,
+        if m.permissions().readonly() { "read-only" } else { "writable" },
+        links
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[test]
+    fn keys_choose_keeper_and_skip_groups() {
+        let groups = vec![
+            DuplicateGroup::new(1, vec![PathBuf::from("a"), "b".into(), "c".into()]),
+            DuplicateGroup::new(1, vec![PathBuf::from("d"), "e".into()]),
+        ];
+        let mut r = Review::new(&groups);
+        for k in [KeyCode::Right, KeyCode::Right, KeyCode::Right, KeyCode::Enter] {
+            assert!(r.key(k).is_none());
+        }
+        r.key(KeyCode::Down);
+        r.key(KeyCode::Char('s'));
+        assert!(matches!(r.key(KeyCode::Char('a')), Some(Exit::Apply)));
+        let out = r.result();
+        assert_eq!(out.len(), 1);
+        assert_eq!(out[0].members, vec![PathBuf::from("c"), "b".into(), "a".into()]);
+    }
+}
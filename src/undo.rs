@@ -0,0 +1,221 @@
+//! `ducky undo`: restore what the most recent run changed, using the action journal.
+//!
+//! - trash: the original is restored from the trash when it is still there (see `trashcan`);
+//!   otherwise it is re-created like a deleted file.
+//! - delete: the file is re-created as a copy of the canonical it duplicated.
+//! - hardlink / symlink: the link is replaced by an independent copy of the canonical.
+//! - move (`--move-to`, `--replicate`): the file is moved back.
+//! - reflink, dedupe: nothing to do; shared extents are copied on write, so each file
+//!   already is independent.
+//!
+//! Copies are only made from a canonical whose content still matches the journaled hash.
+
+use crate::actions::same_inode;
+use crate::hashing::{full_hash, ALGORITHM};
+use crate::journal::{Entry, Journal, Op};
+use crate::replicate::move_file;
+use crate::trashcan::TrashCan;
+use crate::warnings::warning;
+use anyhow::Result;
+use std::fs;
+
+#[derive(Debug, Default)]
+pub struct UndoStats {
+    pub restored: usize,
+    /// Of `restored`, originals taken back out of the trash.
+    pub from_trash: usize,
+    pub skipped: usize,
+    pub errors: usize,
+}
+
+/// Why an entry could not be restored; `Skip` is expected drift, `Fail` is an error.
+enum Outcome {
+    Restored,
+    FromTrash,
+    Skip(String),
+    Fail(String),
+}
+
+/// Undo the pending entries of the latest run (newest first). Without `yes`, only print
+/// what would be restored.
+pub fn undo(journal: &mut Journal, yes: bool) -> UndoStats {
+    let mut stats = UndoStats::default();
+    let pending: Vec<Entry> = journal.pending_undo().into_iter().cloned().collect();
+    if pending.is_empty() {
+        warning!("Nothing to undo.");
+        return stats;
+    }
+    // Listed once, and only when something was trashed.
+    let mut trash: Option<TrashCan> = None;
+    if yes && pending.iter().any(|e| e.op == Op::Trash) {
+        match TrashCan::open() {
+            Ok(t) => trash = Some(t),
+            Err(err) => {
+                warning!("undo: cannot list the trash ({:#}); re-creating trashed files", err)
+            }
+        }
+    }
+    for e in &pending {
+        if !yes {
+            eprintln!("undo: would restore {} ({:?})", e.path.display(), e.op);
+            continue;
+        }
+        match restore(e, trash.as_mut()) {
+            Outcome::Restored => {
+                stats.restored += 1;
+                journal.record_undo(e);
+            }
+            Outcome::FromTrash => {
+                stats.restored += 1;
+                stats.from_trash += 1;
+                journal.record_undo(e);
+            }
+            Outcome::Skip(why) => {
+                stats.skipped += 1;
+                warning!("undo: skipped {}: {}", e.path.display(), why);
+            }
+            Outcome::Fail(why) => {
+                stats.errors += 1;
+                warning!("undo: failed to restore {}: {}", e.path.display(), why);
+            }
+        }
+    }
+    if !yes {
+        warning!("Refusing to modify files without --yes.");
+    }
+    stats
+}
+
+fn restore(e: &Entry, trash: Option<&mut TrashCan>) -> Outcome {
+    match e.op {
+        Op::Delete | Op::Trash => {
+            if e.path.symlink_metadata().is_ok() {
+                return Outcome::Skip("path exists again".into());
+            }
+            if let Some(trash) = trash.filter(|_| e.op == Op::Trash) {
+                match trash.restore(&e.path, e.ts, &e.hash) {
+                    Ok(true) => return Outcome::FromTrash,
+                    Ok(false) => {}
+                    Err(err) => {
+                        let path = e.path.display();
+                        warning!("undo: restoring {} from the trash failed: {:#}", path, err)
+                    }
+                }
+            }
+            copy_from_canonical(e)
+        }
+        Op::Hardlink => {
+            if !same_inode(&e.path, &e.canonical) {
+                return Outcome::Skip("no longer linked to the canonical".into());
+            }
+            copy_from_canonical(e)
+        }
+        Op::Symlink => {
+            if !e.path.is_symlink() {
+                return Outcome::Skip("no longer a symlink".into());
+            }
+            copy_from_canonical(e)
+        }
+        Op::Move => {
+            if e.path.symlink_metadata().is_ok() {
+                return Outcome::Skip("original path exists again".into());
+            }
+            match move_file(&e.canonical, &e.path, &e.hash) {
+                Ok(()) => Outcome::Restored,
+                Err(err) => {
+                    Outcome::Fail(format!("move back from {}: {}", e.canonical.display(), err))
+                }
+            }
+        }
+        // A clone (or a file sharing extents) is already an independent copy.
+        Op::Reflink | Op::Dedupe => Outcome::Restored,
+        Op::Undo => Outcome::Skip("not an action".into()),
+    }
+}
+
+/// Replace (or create) `e.path` with a copy of the canonical, via a temporary sibling.
+fn copy_from_canonical(e: &Entry) -> Outcome {
+    if e.algo != ALGORITHM {
+        return Outcome::Skip(format!("journaled with {} (current: {})", e.algo, ALGORITHM));
+    }
+    match full_hash(&e.canonical) {
+        Ok(h) if h == e.hash => {}
+        Ok(_) => return Outcome::Skip(format!("canonical {} has changed", e.canonical.display())),
+        Err(err) => return Outcome::Fail(format!("read {}: {:#}", e.canonical.display(), err)),
+    }
+    let Some(name) = e.path.file_name() else {
+        return Outcome::Fail("path has no file name".into());
+    };
+    let tmp = e.path.with_file_name(format!(".{}.ducky-undo", name.to_string_lossy()));
+    let res = e
+        .path
+        .parent()
+        .map_or(Ok(()), fs::create_dir_all)
+        .and_then(|_| fs::copy(&e.canonical, &tmp))
+        .and_then(|_| fs::rename(&tmp, &e.path));
+    match res {
+        Ok(()) => Outcome::Restored,
+        Err(err) => {
+            let _ = fs::remove_file(&tmp);
+            Outcome::Fail(err.to_string())
+        }
+    }
+}
+
+/// `ducky undo [--yes]`. Entries that failed to restore are counted in the returned `errors`.
+pub fn run(yes: bool) -> Result<UndoStats> {
+    let mut journal = Journal::open_default();
+    let stats = undo(&mut journal, yes);
+    if yes {
+        eprintln!(
+            "undo: restored={} from_trash={} skipped={} errors={}",
+            stats.restored, stats.from_trash, stats.skipped, stats.errors
+        );
+    }
+    Ok(stats)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::actions::{apply_actions, ActionOptions};
+    use crate::output::DuplicateGroup;
+    use std::path::Path;
+
+    #[test]
+    #[cfg(unix)]
+    fn undoes_latest_run_only() {
+        let dir = std::env::temp_dir().join(format!("ducky_undo_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let journal_path = dir.join("journal.jsonl");
+        let (a, b, c) = (dir.join("a"), dir.join("b"), dir.join("c"));
+        for p in [&a, &b, &c] {
+            std::fs::write(p, b"same").unwrap();
+        }
+        let hash = full_hash(&a).unwrap();
+        let opts = ActionOptions { delete: true, yes: true, ..Default::default() };
+        let group = |d: &Path| {
+            DuplicateGroup::new(4, vec![a.clone(), d.to_path_buf()]).with_hash(hash.clone())
+        };
+
+        // Two runs: the first deletes b, the second hardlinks c.
+        apply_actions(&[group(&b)], &opts, &mut Journal::open(&journal_path).unwrap());
+        let link = ActionOptions { hardlink: true, yes: true, ..Default::default() };
+        apply_actions(&[group(&c)], &link, &mut Journal::open(&journal_path).unwrap());
+
+        let mut journal = Journal::open(&journal_path).unwrap();
+        let stats = undo(&mut journal, true);
+        assert_eq!((stats.restored, stats.errors), (1, 0));
+        assert!(!b.exists());
+        assert!(!same_inode(&a, &c));
+        assert_eq!(std::fs::read(&c).unwrap(), b"same");
+
+        // The next undo goes one run further back.
+        let mut journal = Journal::open(&journal_path).unwrap();
+        assert_eq!(undo(&mut journal, true).restored, 1);
+        assert_eq!(std::fs::read(&b).unwrap(), b"same");
+        assert!(Journal::open(&journal_path).unwrap().pending_undo().is_empty());
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
@@ -0,0 +1,40 @@
+//! `--unique`: the inverse of the group listing, every scanned file that has no copy anywhere
+//! in the scanned roots. Run it on a folder before deleting it, together with the places its
+//! files might also live, to confirm nothing in it is the last copy.
+//!
+//! Only files the walk kept take part: anything below `--min-size` or left out by a filter is
+//! neither listed nor counted as a copy. Hard links of one file are a single copy of the data,
+//! so a file whose other names are all hard links is listed as unique.
+
+use crate::fs_utils::ScannedFile;
+use crate::output::DuplicateGroup;
+use std::collections::HashSet;
+use std::path::Path;
+
+/// Files of `files` that are in none of `groups`, by path.
+pub fn unique_files(files: &[ScannedFile], groups: &[DuplicateGroup]) -> Vec<ScannedFile> {
+    let grouped: HashSet<&Path> =
+        groups.iter().flat_map(|g| &g.members).map(|p| p.as_path()).collect();
+    let mut unique: Vec<ScannedFile> =
+        files.iter().filter(|f| !grouped.contains(f.path.as_path())).cloned().collect();
+    unique.sort_by(|a, b| a.path.cmp(&b.path));
+    unique
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs_utils::FileMeta;
+
+    #[test]
+    fn grouped_files_are_left_out() {
+        let file = |path: &str| ScannedFile {
+            path: path.into(),
+            meta: FileMeta { size: 10, ..FileMeta::default() },
+        };
+        let files = [file("/r/b"), file("/r/copy"), file("/r/a"), file("/r/orig")];
+        let groups = [DuplicateGroup::new(10, vec!["/r/orig".into(), "/r/copy".into()])];
+        let unique: Vec<_> = unique_files(&files, &groups).into_iter().map(|f| f.path).collect();
+        assert_eq!(unique, [Path::new("/r/a"), Path::new("/r/b")]);
+    }
+}
@@ -0,0 +1,34 @@
+//! Warning channel: warnings go to stderr, or are captured for embedding in JSON output.
+
+use std::sync::Mutex;
+
+static CAPTURED: Mutex<Option<Vec<String>>> = Mutex::new(None);
+
+/// Start capturing warnings instead of printing them to stderr.
+pub fn capture() {
+    *CAPTURED.lock().unwrap_or_else(|e| e.into_inner()) = Some(Vec::new());
+}
+
+/// Emit a warning: stored when capturing, otherwise printed to stderr.
+pub fn emit(msg: String) {
+    let mut guard = CAPTURED.lock().unwrap_or_else(|e| e.into_inner());
+    match guard.as_mut() {
+        Some(list) => list.push(msg),
+        None => eprintln!("{}", msg),
+    }
+}
+
+/// Return the captured warnings collected so far (empty when not capturing).
+pub fn take() -> Vec<String> {
+    let mut guard = CAPTURED.lock().unwrap_or_else(|e| e.into_inner());
+    guard.as_mut().map(std::mem::take).unwrap_or_default()
+}
+
+/// `eprintln!`-style warning that honours `--embed-warnings`.
+#[macro_export]
+macro_rules! warning {
+    ($($arg:tt)*) => {
+        $crate::warnings::emit(format!($($arg)*))
+    };
+}
+pub use warning;
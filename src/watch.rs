@@ -0,0 +1,184 @@
+//! Best-effort change detection between scanning and acting (Linux inotify).
+//!
+//! Watches are registered on the directories holding candidate files right after the walk.
+//! Before actions run, pending events are drained and groups whose members changed are
+//! marked stale, so they are re-verified instead of acted on blindly. Other platforms get
+//! a watcher that never reports changes.
+
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+/// Paths reported as changed since the watcher was created.
+#[derive(Debug, Default)]
+pub struct Changes {
+    files: HashSet<PathBuf>,
+    dirs: HashSet<PathBuf>,
+    /// Events were lost (queue overflow): treat everything as changed.
+    all: bool,
+}
+
+impl Changes {
+    /// Whether `path` (or its directory as a whole) was reported as changed.
+    pub fn affects(&self, path: &Path) -> bool {
+        self.all
+            || self.files.contains(path)
+            || path.parent().is_some_and(|d| self.dirs.contains(d))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        !self.all && self.files.is_empty() && self.dirs.is_empty()
+    }
+}
+
+#[cfg(target_os = "linux")]
+pub use linux::ChangeWatcher;
+
+#[cfg(not(target_os = "linux"))]
+pub struct ChangeWatcher;
+
+#[cfg(not(target_os = "linux"))]
+impl ChangeWatcher {
+    pub fn new<'a>(_dirs: impl IntoIterator<Item = &'a Path>) -> Option<Self> {
+        None
+    }
+
+    pub fn changes(&mut self) -> Changes {
+        Changes::default()
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use super::Changes;
+    use crate::warnings::warning;
+    use std::collections::HashMap;
+    use std::ffi::{CString, OsStr};
+    use std::os::unix::ffi::OsStrExt;
+    use std::path::{Path, PathBuf};
+
+    /// Hard cap on watches we register, regardless of the system limit.
+    const MAX_WATCHES: usize = 65536;
+
+    pub struct ChangeWatcher {
+        fd: libc::c_int,
+        dirs: HashMap<libc::c_int, PathBuf>,
+    }
+
+    impl ChangeWatcher {
+        /// Watch each directory in `dirs`. Uses at most half of the per-user inotify watch
+        /// limit so other tools keep working. Returns `None` when inotify is unavailable.
+        pub fn new<'a>(dirs: impl IntoIterator<Item = &'a Path>) -> Option<Self> {
+            // SAFETY: plain syscall without pointer arguments.
+            let fd = unsafe { libc::inotify_init1(libc::IN_NONBLOCK | libc::IN_CLOEXEC) };
+            if fd < 0 {
+                return None;
+            }
+            let budget = std::fs::read_to_string("/proc/sys/fs/inotify/max_user_watches")
+                .ok()
+                .and_then(|s| s.trim().parse::<usize>().ok())
+                .map(|n| n / 2)
+                .unwrap_or(4096)
+                .min(MAX_WATCHES);
+
+            let mask = libc::IN_MODIFY
+                | libc::IN_ATTRIB
+                | libc::IN_CLOSE_WRITE
+                | libc::IN_CREATE
+                | libc::IN_DELETE
+                | libc::IN_MOVED_FROM
+                | libc::IN_MOVED_TO
+                | libc::IN_DELETE_SELF
+                | libc::IN_MOVE_SELF;
+            let mut watcher = Self { fd, dirs: HashMap::new() };
+            let mut skipped = 0usize;
+            for dir in dirs {
+                if watcher.dirs.len() >= budget {
+                    skipped += 1;
+                    continue;
+                }
+                let Ok(c) = CString::new(dir.as_os_str().as_bytes()) else { continue };
+                // SAFETY: `c` is a valid NUL-terminated path for the duration of the call.
+                let wd = unsafe { libc::inotify_add_watch(fd, c.as_ptr(), mask) };
+                if wd >= 0 {
+                    watcher.dirs.insert(wd, dir.to_path_buf());
+                } else {
+                    skipped += 1;
+                }
+            }
+            if skipped > 0 {
+                warning!(
+                    "change detection covers {} of {} directories (inotify watch limit)",
+                    watcher.dirs.len(),
+                    watcher.dirs.len() + skipped
+                );
+            }
+            Some(watcher)
+        }
+
+        /// Drain pending events without blocking.
+        pub fn changes(&mut self) -> Changes {
+            let mut out = Changes::default();
+            let header = std::mem::size_of::<libc::inotify_event>();
+            let mut buf = vec![0u8; 64 * 1024];
+            loop {
+                // SAFETY: `buf` is valid for writes of `buf.len()` bytes.
+                let n = unsafe { libc::read(self.fd, buf.as_mut_ptr().cast(), buf.len()) };
+                if n <= 0 {
+                    break;
+                }
+                let mut off = 0usize;
+                while off + header <= n as usize {
+                    // SAFETY: the kernel wrote a full event header at `off`.
+                    let ev: libc::inotify_event =
+                        unsafe { std::ptr::read_unaligned(buf[off..].as_ptr().cast()) };
+                    let name_bytes = &buf[off + header..off + header + ev.len as usize];
+                    off += header + ev.len as usize;
+
+                    if ev.mask & libc::IN_Q_OVERFLOW != 0 {
+                        out.all = true;
+                        continue;
+                    }
+                    let Some(dir) = self.dirs.get(&ev.wd) else { continue };
+                    let end = name_bytes.iter().position(|b| *b == 0).unwrap_or(name_bytes.len());
+                    if end == 0 || ev.mask & (libc::IN_DELETE_SELF | libc::IN_MOVE_SELF) != 0 {
+                        out.dirs.insert(dir.clone());
+                    } else {
+                        out.files.insert(dir.join(OsStr::from_bytes(&name_bytes[..end])));
+                    }
+                }
+            }
+            out
+        }
+    }
+
+    impl Drop for ChangeWatcher {
+        fn drop(&mut self) {
+            // SAFETY: `fd` was returned by inotify_init1 and is closed exactly once.
+            unsafe { libc::close(self.fd) };
+        }
+    }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_modified_file() {
+        let dir = std::env::temp_dir().join(format!("ducky_watch_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let a = dir.join("a");
+        let b = dir.join("b");
+        std::fs::write(&a, b"one").unwrap();
+        std::fs::write(&b, b"one").unwrap();
+
+        let mut w = ChangeWatcher::new([dir.as_path()]).unwrap();
+        assert!(w.changes().is_empty());
+        std::fs::write(&a, b"two").unwrap();
+        let changes = w.changes();
+        assert!(changes.affects(&a));
+        assert!(!changes.affects(&b));
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
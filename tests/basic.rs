@@ -1,4 +1,35 @@
 use assert_cmd::Command;
+use std::path::{Path, PathBuf};
+
+/// A fresh, empty directory for one test; leftovers of an earlier run are removed first.
+fn scratch(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("ducky_it_{}_{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// `ducky` with its cache, data and config directories under `home`, so a test neither reads
+/// nor writes the user's.
+fn ducky(home: &Path) -> Command {
+    let mut cmd = Command::cargo_bin("ducky").unwrap();
+    cmd.env("HOME", home)
+        .env("XDG_CACHE_HOME", home.join("cache"))
+        .env("XDG_DATA_HOME", home.join("share"))
+        .env("XDG_CONFIG_HOME", home.join("conf"))
+        .env("LOCALAPPDATA", home.join("cache"))
+        .env("APPDATA", home.join("conf"));
+    cmd
+}
+
+/// Build a tree from a `gen-fixture` spec in a fresh home directory; returns the home and the
+/// tree, which is a subdirectory of it so ducky's own state is never scanned with it.
+fn fixture(name: &str, spec: &str) -> (PathBuf, PathBuf) {
+    let home = scratch(name);
+    let dir = home.join("tree");
+    ducky(&home).arg("gen-fixture").arg("-").arg(&dir).write_stdin(spec).assert().success();
+    (home, dir)
+}
 
 #[test]
 fn shows_help() {
@@ -6,6 +37,25 @@ fn shows_help() {
     cmd.arg("--help").assert().success();
 }
 
+#[test]
+fn scan_subcommand_matches_flat_invocation() {
+    let (home, dir) = fixture("subcommands", "file a.bin size=8KB\ncopy b.bin of=a.bin\n");
+    let summary = |args: &[&str]| {
+        let mut cmd = ducky(&home);
+        cmd.args(args).arg(&dir).args(["--summary-json", "--no-cache"]);
+        let output = cmd.assert().success().get_output().clone();
+        let v: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        (v["groups"].clone(), v["reclaimable"].clone())
+    };
+    assert_eq!(summary(&[]), summary(&["scan"]));
+    assert_eq!(summary(&["report"]), summary(&[]));
+
+    ducky(&home).arg("report").arg(&dir).arg("--delete").assert().failure();
+    assert!(dir.join("b.bin").exists());
+
+    let _ = std::fs::remove_dir_all(home);
+}
+
 #[test]
 fn json_mode_emits_pure_array() {
     let mut cmd = Command::cargo_bin("ducky").unwrap();
@@ -16,3 +66,917 @@ fn json_mode_emits_pure_array() {
     // stdout should start with [ in JSON array mode
     assert!(output.stdout.starts_with(b"["), "stdout should begin with '[' in --json mode");
 }
+
+#[test]
+fn nested_roots_are_not_double_counted() {
+    let base = scratch("nested");
+    let sub = base.join("sub");
+    std::fs::create_dir_all(&sub).unwrap();
+    std::fs::write(sub.join("a.bin"), vec![7u8; 4096]).unwrap();
+    std::fs::write(sub.join("b.bin"), vec![7u8; 4096]).unwrap();
+
+    let mut cmd = ducky(&base);
+    cmd.arg(&base).arg(&sub).arg(&base).arg("--summary-json");
+    let output = cmd.assert().success().get_output().clone();
+    let summary: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(summary["groups"], 1);
+    assert_eq!(summary["files"], 2);
+    assert_eq!(summary["reclaimable"], 4096);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("is inside"), "expected nested-root warning, got: {stderr}");
+
+    let _ = std::fs::remove_dir_all(base);
+}
+
+#[test]
+fn embed_warnings_moves_warnings_into_json() {
+    let home = scratch("embed_warnings");
+    let mut cmd = ducky(&home);
+    cmd.arg(".")
+        .arg("--summary-json")
+        .arg("--quick-bytes")
+        .arg("1B")
+        .arg("--embed-warnings");
+    let output = cmd.assert().success().get_output().clone();
+    let summary: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let warnings = summary["warnings"].as_array().expect("warnings array");
+    assert!(warnings.iter().any(|w| w.as_str().unwrap().contains("--quick-bytes too small")));
+    assert!(output.stderr.is_empty(), "warnings should not reach stderr");
+
+    let _ = std::fs::remove_dir_all(home);
+}
+
+#[test]
+fn doctor_reports_capabilities() {
+    let home = scratch("doctor");
+    let dir = home.join("target");
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let mut cmd = ducky(&home);
+    cmd.arg("doctor").arg(&dir).arg("--json");
+    let output = cmd.assert().success().get_output().clone();
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let checks = report["targets"][0]["checks"].as_array().unwrap();
+    let status = |name: &str| {
+        checks.iter().find(|c| c["name"] == name).map(|c| c["status"].clone()).unwrap()
+    };
+    assert_eq!(status("writable"), "ok");
+    assert_eq!(status("hardlink"), "ok");
+    // Probe files are cleaned up.
+    assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 0);
+
+    let _ = std::fs::remove_dir_all(home);
+}
+
+#[test]
+fn rerun_after_hardlink_reports_nothing_to_do() {
+    let base = scratch("rerun");
+    let data = base.join("data");
+    std::fs::create_dir_all(&data).unwrap();
+    std::fs::write(data.join("a.bin"), vec![3u8; 4096]).unwrap();
+    std::fs::write(data.join("b.bin"), vec![3u8; 4096]).unwrap();
+
+    let run = || {
+        let mut cmd = ducky(&base);
+        cmd.arg(&data)
+            .arg("--hardlink")
+            .arg("--yes")
+            .arg("--quiet");
+        cmd.assert().success().get_output().clone()
+    };
+    let first = run();
+    assert!(String::from_utf8_lossy(&first.stderr).contains("linked=1"));
+    let second = run();
+    let stderr = String::from_utf8_lossy(&second.stderr);
+    assert!(stderr.contains("nothing to do"), "got: {stderr}");
+
+    let _ = std::fs::remove_dir_all(base);
+}
+
+#[test]
+fn no_fancy_keeps_single_summary_line() {
+    let dir = scratch("fancy");
+    std::fs::write(dir.join("a.bin"), vec![5u8; 2048]).unwrap();
+    std::fs::write(dir.join("b.bin"), vec![5u8; 2048]).unwrap();
+
+    let mut cmd = ducky(&dir);
+    cmd.arg(&dir).arg("--quiet").arg("--no-fancy");
+    let out = cmd.assert().success().get_output().stdout.clone();
+    let out = String::from_utf8(out).unwrap();
+    assert_eq!(out.lines().count(), 1);
+    assert!(out.starts_with("Found 1 duplicate groups; reclaimable: "));
+
+    let mut cmd = ducky(&dir);
+    cmd.arg(&dir).arg("--quiet");
+    let out = cmd.assert().success().get_output().stdout.clone();
+    let out = String::from_utf8(out).unwrap();
+    assert!(out.contains("duplicate groups  1"), "got: {out}");
+    assert!(out.contains("files scanned"));
+
+    let _ = std::fs::remove_dir_all(dir);
+}
+
+#[test]
+fn summary_json_reports_effective_settings() {
+    let home = scratch("settings");
+    let mut cmd = ducky(&home);
+    cmd.arg(".").arg("--summary-json").arg("--quick-bytes").arg("1B");
+    let output = cmd.assert().success().get_output().clone();
+    let summary: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(summary["settings"]["algorithm"], "blake3");
+    // Clamped value, not the requested one.
+    assert_eq!(summary["settings"]["quick_bytes"], 1024);
+
+    let _ = std::fs::remove_dir_all(home);
+}
+
+#[test]
+#[cfg(unix)]
+fn pick_applies_action_only_to_selection() {
+    let base = scratch("pick");
+    let data = base.join("data");
+    std::fs::create_dir_all(&data).unwrap();
+    for name in ["a.bin", "b.bin", "c.bin"] {
+        std::fs::write(data.join(name), vec![9u8; 2048]).unwrap();
+    }
+
+    let mut cmd = ducky(&base);
+    cmd.arg(&data)
+        .arg("--delete")
+        .arg("--yes")
+        .arg("--pick")
+        .arg("--picker")
+        .arg("head -n 1");
+    cmd.assert().success();
+    assert!(data.join("a.bin").exists());
+    assert!(!data.join("b.bin").exists());
+    assert!(data.join("c.bin").exists());
+
+    let _ = std::fs::remove_dir_all(base);
+}
+
+#[test]
+fn json_output_is_identical_across_thread_counts() {
+    let dir = scratch("threads");
+    for i in 0..12u8 {
+        // Four contents, three copies each, of different sizes.
+        let body = vec![i % 4; 2048 + (i % 4) as usize];
+        std::fs::write(dir.join(format!("f{i}.bin")), body).unwrap();
+    }
+
+    let run = |threads: &str| {
+        let mut cmd = ducky(&dir);
+        cmd.arg(&dir).arg("--json").arg("--threads").arg(threads);
+        cmd.assert().success().get_output().stdout.clone()
+    };
+    let one = run("1");
+    assert_eq!(one, run("4"));
+    let groups: serde_json::Value = serde_json::from_slice(&one).unwrap();
+    assert_eq!(groups.as_array().unwrap().len(), 4);
+
+    let _ = std::fs::remove_dir_all(dir);
+}
+
+#[test]
+fn catalog_matches_are_reported_in_scans() {
+    let base = scratch("catalog");
+    let vol = base.join("vol");
+    let data = base.join("data");
+    std::fs::create_dir_all(&vol).unwrap();
+    std::fs::create_dir_all(&data).unwrap();
+    std::fs::write(vol.join("old.bin"), vec![4u8; 3000]).unwrap();
+    std::fs::write(data.join("a.bin"), vec![4u8; 3000]).unwrap();
+    std::fs::write(data.join("b.bin"), vec![4u8; 3000]).unwrap();
+
+    let run = || ducky(&base);
+    run()
+        .args(["catalog", "add"])
+        .arg(&vol)
+        .args(["--name", "backup-2021"])
+        .assert()
+        .success();
+
+    let out = run().arg(&data).arg("--json").assert().success().get_output().stdout.clone();
+    let groups: serde_json::Value = serde_json::from_slice(&out).unwrap();
+    assert_eq!(groups[0]["also_on"][0], "backup-2021:old.bin");
+
+    run().args(["catalog", "remove", "backup-2021"]).assert().success();
+    let out = run().args(["catalog", "list"]).assert().success().get_output().stdout.clone();
+    assert!(out.is_empty());
+
+    let _ = std::fs::remove_dir_all(base);
+}
+
+#[test]
+fn second_scan_is_served_from_hash_cache() {
+    let base = scratch("cache");
+    let data = base.join("data");
+    std::fs::create_dir_all(&data).unwrap();
+    for name in ["a.bin", "b.bin"] {
+        std::fs::write(data.join(name), vec![5u8; 4096]).unwrap();
+    }
+    let scan = || {
+        let mut cmd = ducky(&base);
+        cmd.arg(&data).arg("--summary-json");
+        let output = cmd.assert().success().get_output().clone();
+        serde_json::from_slice::<serde_json::Value>(&output.stdout).unwrap()
+    };
+
+    let first = scan();
+    assert_eq!(first["cache"]["hits"], 0);
+    let second = scan();
+    assert_eq!(second["cache"]["misses"], 0);
+    assert_eq!(second["cache"]["hits"], first["cache"]["misses"]);
+    assert_eq!(second["groups"], 1);
+
+    ducky(&base).args(["cache", "clear"]).assert().success();
+    assert_eq!(scan()["cache"]["hits"], 0);
+
+    let _ = std::fs::remove_dir_all(base);
+}
+
+#[test]
+#[cfg(unix)]
+fn renamed_files_are_served_from_cache_and_reported() {
+    let spec = "file data/a.bin size=8KB\ncopy data/b.bin of=data/a.bin\n";
+    let (home, dir) = fixture("rename", spec);
+    let scan = || {
+        let mut cmd = ducky(&home);
+        cmd.arg(dir.join("data")).arg("--summary-json");
+        let output = cmd.assert().success().get_output().clone();
+        serde_json::from_slice::<serde_json::Value>(&output.stdout).unwrap()
+    };
+    scan();
+    std::fs::create_dir_all(dir.join("data/sorted")).unwrap();
+    std::fs::rename(dir.join("data/b.bin"), dir.join("data/sorted/b.bin")).unwrap();
+
+    let second = scan();
+    assert_eq!(second["cache"]["misses"], 0);
+    let renamed = second["cache"]["renamed"].as_array().unwrap();
+    assert_eq!(renamed.len(), 1);
+    assert!(renamed[0]["to"].as_str().unwrap().ends_with("sorted/b.bin"));
+
+    let _ = std::fs::remove_dir_all(home);
+}
+
+#[test]
+fn groups_out_moves_listing_to_file() {
+    let base = scratch("groups_out");
+    let data = base.join("data");
+    std::fs::create_dir_all(&data).unwrap();
+    for name in ["a.bin", "b.bin"] {
+        std::fs::write(data.join(name), vec![3u8; 2048]).unwrap();
+    }
+    let listing = base.join("groups.json");
+
+    let mut cmd = ducky(&base);
+    cmd.arg(&data).args(["--json", "--no-fancy", "--no-cache", "--groups-out"]).arg(&listing);
+    let output = cmd.assert().success().get_output().clone();
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("Found 1 duplicate groups"), "{}", stdout);
+    assert!(!stdout.contains("a.bin"));
+    let listed = std::fs::read(&listing).unwrap();
+    let groups: serde_json::Value = serde_json::from_slice(&listed).unwrap();
+    assert_eq!(groups.as_array().unwrap().len(), 1);
+
+    let listing = base.join("groups.txt");
+    let mut cmd = ducky(&base);
+    cmd.arg(&data).arg("--no-cache").arg("--groups-out").arg(&listing);
+    let output = cmd.assert().success().get_output().clone();
+    assert!(!String::from_utf8(output.stdout).unwrap().contains("a.bin"));
+    assert!(std::fs::read_to_string(&listing).unwrap().contains("== 2 duplicates"));
+
+    let _ = std::fs::remove_dir_all(base);
+}
+
+#[test]
+fn reports_of_earlier_runs_are_not_scanned() {
+    let home = scratch("reports_home");
+    let data = scratch("reports");
+    for name in ["a.bin", "b.bin"] {
+        std::fs::write(data.join(name), vec![3u8; 2048]).unwrap();
+    }
+    let mut cmd = ducky(&home);
+    cmd.arg(&data).args(["--no-cache", "--min-size", "1B"]);
+    cmd.arg("--save").arg(data.join("saved.json")).arg("--csv-out").arg(data.join("plan.csv"));
+    cmd.arg("--badge-json").arg(data.join("badge.json"));
+    cmd.assert().success();
+
+    let mut cmd = ducky(&home);
+    cmd.arg(&data).args(["--unique", "--json", "--no-cache", "--min-size", "1B"]);
+    let output = cmd.assert().success().get_output().clone();
+    let unique: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(unique.as_array().unwrap().len(), 0, "{}", unique);
+
+    let _ = std::fs::remove_dir_all(home);
+    let _ = std::fs::remove_dir_all(data);
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn trash_moves_duplicates_to_freedesktop_trash() {
+    let base = scratch("trash");
+    let data = base.join("data");
+    std::fs::create_dir_all(&data).unwrap();
+    for name in ["a.bin", "b.bin"] {
+        std::fs::write(data.join(name), vec![4u8; 2048]).unwrap();
+    }
+
+    ducky(&base).arg(&data).arg("--trash").arg("--yes").assert().success();
+    assert!(data.join("a.bin").exists());
+    assert!(!data.join("b.bin").exists());
+    assert!(base.join("share/Trash/files/b.bin").exists());
+
+    let _ = std::fs::remove_dir_all(base);
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn undo_takes_trashed_originals_out_of_the_trash() {
+    let base = scratch("untrash");
+    let data = base.join("data");
+    std::fs::create_dir_all(&data).unwrap();
+    for name in ["a.bin", "b.bin"] {
+        std::fs::write(data.join(name), vec![5u8; 2048]).unwrap();
+    }
+    let old = std::time::UNIX_EPOCH + std::time::Duration::from_secs(1_000_000_000);
+    let b = data.join("b.bin");
+    std::fs::File::options().write(true).open(&b).unwrap().set_modified(old).unwrap();
+    let run = |args: &[&str]| {
+        let output = ducky(&base).args(args).assert().success().get_output().clone();
+        output.stderr
+    };
+    let trash_run = || run(&[data.to_str().unwrap(), "--trash", "--yes"]);
+
+    // The original comes back, with its own timestamp rather than as a fresh copy.
+    trash_run();
+    assert!(!b.exists());
+    let out = String::from_utf8(run(&["undo", "--yes"])).unwrap();
+    assert!(out.contains("restored=1 from_trash=1"), "{}", out);
+    assert_eq!(std::fs::metadata(&b).unwrap().modified().unwrap(), old);
+    assert!(!base.join("share/Trash/files/b.bin").exists());
+
+    // A trashed item whose content changed is left in the trash, untouched.
+    trash_run();
+    std::fs::write(base.join("share/Trash/files/b.bin"), vec![6u8; 2048]).unwrap();
+    let info = base.join("share/Trash/info/b.bin.trashinfo");
+    let trashed = std::fs::read(&info).unwrap();
+    let out = String::from_utf8(run(&["undo", "--yes"])).unwrap();
+    assert!(out.contains("restored=1 from_trash=0"), "{}", out);
+    assert_eq!(std::fs::read(&b).unwrap(), vec![5u8; 2048]);
+    assert_eq!(std::fs::read(base.join("share/Trash/files/b.bin")).unwrap(), vec![6u8; 2048]);
+    assert_eq!(std::fs::read(&info).unwrap(), trashed);
+
+    // Once the trash is emptied, the file is re-created from the canonical.
+    trash_run();
+    std::fs::remove_dir_all(base.join("share/Trash")).unwrap();
+    let out = String::from_utf8(run(&["undo", "--yes"])).unwrap();
+    assert!(out.contains("restored=1 from_trash=0"), "{}", out);
+    assert_eq!(std::fs::read(&b).unwrap(), vec![5u8; 2048]);
+
+    let _ = std::fs::remove_dir_all(base);
+}
+
+#[test]
+#[cfg(unix)]
+fn fixture_tree_reports_hardlinks_in_realistic_estimate() {
+    let (home, dir) = fixture(
+        "fixture_links",
+        "file a.bin size=64KB\ncopy b.bin of=a.bin\nhardlink c.bin to=a.bin\n\
+         file other.bin size=64KB\n",
+    );
+    let mut cmd = ducky(&home);
+    cmd.arg(&dir).arg("--summary-json").arg("--no-cache");
+    let output = cmd.assert().success().get_output().clone();
+    let summary: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(summary["groups"], 1);
+    assert_eq!(summary["files"], 3);
+    // c.bin is a hard link of a.bin: listed in the group, but never counted as reclaimable.
+    assert_eq!(summary["reclaimable"], 64_000);
+    assert_eq!(summary["reclaimable_realistic"], 64_000);
+    let savings = &summary["savings"];
+    let freed = (savings["delete"].clone(), savings["hardlink"].clone());
+    assert_eq!(freed, (64_000.into(), 64_000.into()));
+
+    let _ = std::fs::remove_dir_all(home);
+}
+
+#[test]
+fn a_journal_in_the_cache_directory_is_moved_to_the_data_directory() {
+    let spec = "file data/a.bin size=8KB\ncopy data/b.bin of=data/a.bin\n";
+    let (home, dir) = fixture("journal_move", spec);
+    let (data, q) = (dir.join("data"), dir.join("q"));
+    let run = |args: &[&std::ffi::OsStr]| ducky(&home).args(args).assert().success();
+    run(&[data.as_os_str(), "--move-to".as_ref(), q.as_os_str(), "--yes".as_ref()]);
+    let old = home.join("cache/ducky/journal.jsonl");
+    let new = home.join("share/ducky/journal.jsonl");
+    assert!(new.exists() && !old.exists());
+
+    // As an earlier version left it.
+    std::fs::rename(&new, &old).unwrap();
+    run(&["undo".as_ref(), "--yes".as_ref()]);
+    assert!(data.join("b.bin").exists());
+    assert!(new.exists() && !old.exists());
+
+    let _ = std::fs::remove_dir_all(home);
+}
+
+#[test]
+fn undo_moves_quarantined_files_back() {
+    let (home, dir) = fixture("undo", "file data/a.bin size=8KB\ncopy data/b.bin of=data/a.bin\n");
+    let run = |args: &[&std::ffi::OsStr]| {
+        ducky(&home).args(args).assert().success();
+    };
+    let (data, q) = (dir.join("data"), dir.join("q"));
+    run(&[data.as_os_str(), "--move-to".as_ref(), q.as_os_str(), "--yes".as_ref()]);
+    assert!(!data.join("b.bin").exists());
+
+    // Without --yes nothing is restored.
+    run(&["undo".as_ref()]);
+    assert!(!data.join("b.bin").exists());
+    run(&["undo".as_ref(), "--yes".as_ref()]);
+    assert!(data.join("b.bin").exists());
+
+    let _ = std::fs::remove_dir_all(home);
+}
+
+#[test]
+fn files_already_in_the_quarantine_dir_are_still_scanned() {
+    let (home, dir) = fixture(
+        "quarantine_dir",
+        "file data/a.bin size=8KB\ncopy data/b.bin of=data/a.bin\n\
+         file old/x.bin size=9KB\ncopy old/y.bin of=old/x.bin\n",
+    );
+    let groups = || {
+        let mut cmd = ducky(&home);
+        cmd.arg(dir.join("data")).arg(dir.join("old")).args(["--summary-json", "--no-cache"]);
+        let output = cmd.assert().success().get_output().clone();
+        serde_json::from_slice::<serde_json::Value>(&output.stdout).unwrap()["groups"].clone()
+    };
+    assert_eq!(groups(), 2);
+    let mut cmd = ducky(&home);
+    cmd.arg(dir.join("data")).arg("--move-to").arg(dir.join("old")).arg("--yes");
+    cmd.assert().success();
+    assert!(!dir.join("data/b.bin").exists());
+    // The moved copy is left out; the pair that was in old/ already is still found.
+    assert_eq!(groups(), 1);
+
+    let _ = std::fs::remove_dir_all(home);
+}
+
+#[test]
+fn csv_decisions_are_applied() {
+    let (home, dir) = fixture(
+        "csv",
+        "file data/a.bin size=8KB\ncopy data/b.bin of=data/a.bin\ncopy data/c.bin of=data/a.bin\n",
+    );
+    let run = |args: &[&std::ffi::OsStr]| {
+        ducky(&home).args(args).assert().success();
+    };
+    let (data, csv) = (dir.join("data"), dir.join("review.csv"));
+    run(&[data.as_os_str(), "--csv-out".as_ref(), csv.as_os_str()]);
+    let text = std::fs::read_to_string(&csv).unwrap();
+    assert!(text.starts_with("group,hash,size,path,decision\n"), "{}", text);
+
+    // Keep b, delete a, leave c alone.
+    let filled: String = text
+        .lines()
+        .map(|l| match l {
+            l if l.contains("a.bin") => format!("{}delete\n", l),
+            l if l.contains("b.bin") => format!("{}keep\n", l),
+            l => format!("{}\n", l),
+        })
+        .collect();
+    std::fs::write(&csv, filled).unwrap();
+    let apply = ["apply".as_ref(), "--from-csv".as_ref(), csv.as_os_str()];
+    run(&apply);
+    assert!(data.join("a.bin").exists(), "nothing happens without --yes");
+    run(&[&apply[..], &["--yes".as_ref()]].concat());
+    assert!(!data.join("a.bin").exists());
+    assert!(data.join("b.bin").exists() && data.join("c.bin").exists());
+
+    let _ = std::fs::remove_dir_all(home);
+}
+
+#[test]
+fn saved_scan_is_applied_later_to_unchanged_files() {
+    let (home, dir) = fixture(
+        "saved",
+        "file data/a.bin size=8KB\ncopy data/b.bin of=data/a.bin\ncopy data/c.bin of=data/a.bin\n",
+    );
+    let run = |args: &[&std::ffi::OsStr]| ducky(&home).args(args).assert();
+    let (data, saved) = (dir.join("data"), dir.join("scan.json"));
+    run(&[data.as_os_str(), "--save".as_ref(), saved.as_os_str()]).success();
+
+    // c.bin is edited after the scan and must survive.
+    std::fs::write(data.join("c.bin"), b"edited").unwrap();
+    run(&["apply".as_ref(), saved.as_os_str(), "--yes".as_ref()]).failure();
+    run(&["apply".as_ref(), saved.as_os_str(), "--delete".as_ref(), "--yes".as_ref()]).success();
+    assert!(data.join("a.bin").exists() && data.join("c.bin").exists());
+    assert!(!data.join("b.bin").exists());
+
+    let _ = std::fs::remove_dir_all(home);
+}
+
+#[test]
+fn completions_cover_subcommands_for_every_shell() {
+    let home = scratch("completions");
+    for shell in ["bash", "zsh", "fish", "elvish", "powershell"] {
+        let mut cmd = ducky(&home);
+        cmd.arg("completions").arg(shell);
+        let output = cmd.assert().success().get_output().clone();
+        let script = String::from_utf8(output.stdout).unwrap();
+        for word in ["sentinel", "apply", "min-size"] {
+            assert!(script.contains(word), "{} completions lack {}", shell, word);
+        }
+    }
+
+    let _ = std::fs::remove_dir_all(home);
+}
+
+#[test]
+fn config_file_supplies_defaults_that_flags_override() {
+    let (home, dir) = fixture(
+        "config",
+        "file data/a.bin size=4KB\ncopy data/b.bin of=data/a.bin\n\
+         file data/s1 size=100\ncopy data/s2 of=data/s1\ncopy data/old/c.bin of=data/a.bin\n",
+    );
+    let conf = home.join("conf").join("ducky");
+    std::fs::create_dir_all(&conf).unwrap();
+    let text = "min-size = \"1KB\"\nexclude = [\n  \"old\",  # archived\n]\n";
+    std::fs::write(conf.join("config.toml"), text).unwrap();
+    let summary = |args: &[&str]| {
+        let mut cmd = ducky(&home);
+        cmd.arg(dir.join("data"))
+            .args(["--summary-json", "--no-cache"])
+            .args(args);
+        let output = cmd.assert().success().get_output().clone();
+        serde_json::from_slice::<serde_json::Value>(&output.stdout).unwrap()
+    };
+    // The file leaves out small files and old/.
+    assert_eq!(summary(&[])["files"], 2);
+    assert_eq!(summary(&["--min-size", "0"])["files"], 4);
+    assert_eq!(summary(&["--exclude", "none"])["files"], 3);
+
+    std::fs::write(dir.join("actions.toml"), "delete = true\n").unwrap();
+    let mut cmd = ducky(&home);
+    cmd.arg(dir.join("data")).arg("--config").arg(dir.join("actions.toml"));
+    cmd.assert().failure();
+    // Only scans read the file.
+    std::fs::write(conf.join("config.toml"), "delete = true\n").unwrap();
+    ducky(&home).args(["undo"]).assert().success();
+    ducky(&home).arg("report").arg(dir.join("data")).assert().failure();
+
+    let _ = std::fs::remove_dir_all(home);
+}
+
+#[test]
+fn profile_supplies_roots_and_filters() {
+    let (home, dir) = fixture(
+        "profile",
+        "file photos/a.jpg size=4KB\ncopy backup/a.jpg of=photos/a.jpg\n\
+         file photos/b.txt size=4KB\ncopy backup/b.txt of=photos/b.txt\n",
+    );
+    let conf = home.join("conf").join("ducky");
+    std::fs::create_dir_all(&conf).unwrap();
+    let roots = format!("{:?}, {:?}", dir.join("photos"), dir.join("backup"));
+    let text = format!(
+        "min-size = \"1MB\"\n[profile.photos]\nroots = [{}]\next = \"jpg\"\nmin-size = \"1KB\"\n",
+        roots
+    );
+    std::fs::write(conf.join("config.toml"), text).unwrap();
+    let run = |args: &[&str]| {
+        let mut cmd = ducky(&home);
+        cmd.args(args).args(["--summary-json", "--no-cache"]);
+        cmd.assert()
+    };
+    let files = |args: &[&str]| {
+        let output = run(args).success().get_output().clone();
+        serde_json::from_slice::<serde_json::Value>(&output.stdout).unwrap()["files"].clone()
+    };
+    // Both roots, only .jpg, and the profile's min-size over the top-level one.
+    assert_eq!(files(&["--profile", "photos"]), 2);
+    assert_eq!(files(&["scan", "--profile", "photos", "--ext", "jpg,txt"]), 4);
+    // Paths on the command line replace the roots: nothing is duplicated within photos/.
+    let photos = dir.join("photos");
+    assert_eq!(files(&["--profile=photos", photos.to_str().unwrap()]), 0);
+    run(&["--profile", "music"]).failure();
+
+    let _ = std::fs::remove_dir_all(home);
+}
+
+#[test]
+fn protected_copy_is_kept_as_canonical() {
+    let (home, dir) = fixture(
+        "protect",
+        "file data/originals/a.bin size=8KB\n\
+         copy data/copies/a.bin of=data/originals/a.bin\n\
+         copy data/copies/b.bin of=data/originals/a.bin\n",
+    );
+    let mut cmd = ducky(&home);
+    cmd.arg(dir.join("data"))
+        .args(["--protect", "originals", "--delete", "--yes", "--json", "--no-cache"]);
+    let output = cmd.assert().success().get_output().clone();
+    let groups: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    // copies/a.bin sorts first, but the protected file is the one kept.
+    let kept = groups[0]["entries"][0]["path"].as_str().unwrap();
+    assert!(kept.ends_with("originals/a.bin"), "{}", kept);
+    assert!(dir.join("data/originals/a.bin").exists());
+    assert!(!dir.join("data/copies/a.bin").exists() && !dir.join("data/copies/b.bin").exists());
+
+    let _ = std::fs::remove_dir_all(home);
+}
+
+#[test]
+fn delete_in_only_cleans_up_the_given_root() {
+    let (home, dir) = fixture(
+        "delete_in",
+        "file keep/x.bin size=8KB\ncopy clean/x.bin of=keep/x.bin\n\
+         copy clean/sub/x.bin of=keep/x.bin\n",
+    );
+    let mut cmd = ducky(&home);
+    cmd.arg(dir.join("keep"))
+        .arg(dir.join("clean"))
+        .arg("--delete-in")
+        .arg(dir.join("clean"))
+        .args(["--delete", "--yes", "--no-cache"]);
+    cmd.assert().success();
+    // clean/x.bin sorts first, but the copy outside --delete-in is the one kept.
+    assert!(dir.join("keep/x.bin").exists());
+    assert!(!dir.join("clean/x.bin").exists() && !dir.join("clean/sub/x.bin").exists());
+
+    let _ = std::fs::remove_dir_all(home);
+}
+
+#[test]
+fn raw_jpeg_pairs_are_deleted_together() {
+    let (home, dir) = fixture(
+        "rawpairs",
+        "file a/IMG_1.CR2 size=8KB\nfile a/IMG_1.JPG size=4KB\n\
+         copy b/IMG_1.CR2 of=a/IMG_1.CR2\ncopy b/IMG_1.JPG of=a/IMG_1.JPG\n\
+         copy c/IMG_1.CR2 of=a/IMG_1.CR2\nfile c/IMG_1.JPG size=4KB seed=9\n",
+    );
+    let mut cmd = ducky(&home);
+    cmd.arg(&dir)
+        .args(["--raw-jpeg-pairs", "--delete", "--yes", "--no-cache", "--exclude", "share"]);
+    let output = cmd.assert().success().get_output().clone();
+    assert!(String::from_utf8_lossy(&output.stdout).contains("IMG_1.CR2  (+ IMG_1.JPG)"));
+    // b/ goes as a pair; c/'s RAW stays with the JPEG that only exists there.
+    assert!(dir.join("a/IMG_1.CR2").exists() && dir.join("a/IMG_1.JPG").exists());
+    assert!(!dir.join("b/IMG_1.CR2").exists() && !dir.join("b/IMG_1.JPG").exists());
+    assert!(dir.join("c/IMG_1.CR2").exists() && dir.join("c/IMG_1.JPG").exists());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("skipped_pair=1"));
+
+    let _ = std::fs::remove_dir_all(home);
+}
+
+#[test]
+fn raw_jpeg_pairs_are_refused_with_replicate() {
+    let home = scratch("rawpairs_replicate");
+    let mut cmd = ducky(&home);
+    cmd.arg(&home).args(["--raw-jpeg-pairs", "--replicate", "1", "--dry-run"]);
+    let output = cmd.assert().failure().get_output().clone();
+    assert!(String::from_utf8_lossy(&output.stderr).contains("--replicate"));
+
+    let _ = std::fs::remove_dir_all(home);
+}
+
+#[test]
+fn prefer_and_avoid_choose_the_canonical() {
+    let (home, dir) = fixture(
+        "prefer",
+        "file data/a/scratch/x.bin size=8KB\ncopy data/b/x.bin of=data/a/scratch/x.bin\n\
+         copy data/c/archive/x.bin of=data/a/scratch/x.bin\n",
+    );
+    let order = |args: &[&str]| -> Vec<String> {
+        let mut cmd = ducky(&home);
+        cmd.arg(dir.join("data")).args(["--json", "--no-cache"]).args(args);
+        let output = cmd.assert().success().get_output().clone();
+        let groups: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        let members = groups[0]["members"].as_array().unwrap();
+        let relative = |m: &serde_json::Value| -> String {
+            m.as_str().unwrap().rsplit_once("data/").unwrap().1.into()
+        };
+        members.iter().map(relative).collect()
+    };
+    assert_eq!(order(&[]), ["a/scratch/x.bin", "b/x.bin", "c/archive/x.bin"]);
+    let avoided = order(&["--avoid", "scratch"]);
+    assert_eq!(avoided, ["b/x.bin", "c/archive/x.bin", "a/scratch/x.bin"]);
+    assert_eq!(
+        order(&["--avoid", "scratch", "--prefer", "archive"]),
+        ["c/archive/x.bin", "b/x.bin", "a/scratch/x.bin"]
+    );
+    // A protected member stays first whatever is preferred.
+    assert_eq!(order(&["--prefer", "archive", "--protect", "b"])[0], "b/x.bin");
+
+    let policy = |args: &[&str]| -> String {
+        let mut cmd = ducky(&home);
+        cmd.arg(dir.join("data")).args(["--json", "--no-cache"]).args(args);
+        let output = cmd.assert().success().get_output().clone();
+        let groups: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        groups[0]["canonical_policy"].as_str().unwrap().into()
+    };
+    assert_eq!(policy(&[]), "first-path");
+    assert_eq!(policy(&["--avoid", "scratch", "--prefer", "archive"]), "prefer,avoid,first-path");
+
+    let _ = std::fs::remove_dir_all(home);
+}
+
+#[test]
+#[cfg(unix)]
+fn hostile_names_are_quoted_for_humans_and_exact_for_machines() {
+    let dir = scratch("hostile");
+    let names = ["a\nb.txt", "tab\there's.txt", "plain.txt"];
+    for name in names {
+        std::fs::write(dir.join(name), [7u8; 8192]).unwrap();
+    }
+    let run = |args: &[&str]| {
+        let mut cmd = ducky(&dir);
+        cmd.arg(&dir).arg("--no-cache").args(args);
+        cmd.assert().success().get_output().clone()
+    };
+
+    let human = run(&[]);
+    let stdout = String::from_utf8(human.stdout).unwrap();
+    assert!(stdout.contains("    $'a\\nb.txt'\n"), "{}", stdout);
+    assert!(stdout.contains("    $'tab\\there\\'s.txt'\n"), "{}", stdout);
+    assert!(stdout.contains("    plain.txt\n"), "{}", stdout);
+    let warned = String::from_utf8_lossy(&human.stderr);
+    assert!(warned.contains("2 paths contain control characters"), "{}", warned);
+
+    // --list quotes the same names and counts them too.
+    let listed = run(&["--list"]);
+    let stdout = String::from_utf8(listed.stdout).unwrap();
+    let quoted = |l: &str| l.starts_with("$'") && l.ends_with("/a\\nb.txt'");
+    assert!(stdout.lines().any(quoted), "{}", stdout);
+    let warned = String::from_utf8_lossy(&listed.stderr);
+    assert!(warned.contains("4 paths contain control characters"), "{}", warned);
+
+    let groups: serde_json::Value = serde_json::from_slice(&run(&["--json"]).stdout).unwrap();
+    let mut members: Vec<String> = groups[0]["members"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|m| m.as_str().unwrap().rsplit_once('/').unwrap().1.to_string())
+        .collect();
+    members.sort();
+    assert_eq!(members, ["a\nb.txt", "plain.txt", "tab\there's.txt"]);
+
+    let _ = std::fs::remove_dir_all(dir);
+}
+
+#[test]
+fn small_groups_are_left_out_by_the_thresholds() {
+    let (home, dir) = fixture(
+        "thresholds",
+        "file a.bin size=8KB\ncopy b.bin of=a.bin\ncopy c.bin of=a.bin\n\
+         file big.bin size=64KB\ncopy big2.bin of=big.bin\n",
+    );
+    let summary = |args: &[&str]| -> serde_json::Value {
+        let mut cmd = ducky(&home);
+        cmd.arg(&dir).args(["--summary-json", "--no-cache"]).args(args);
+        serde_json::from_slice(&cmd.assert().success().get_output().stdout).unwrap()
+    };
+    let all = summary(&[]);
+    assert_eq!(all["groups"], 2);
+    assert_eq!(all["groups_below_threshold"], 0);
+    let three = summary(&["--min-group-size", "3"]);
+    assert_eq!((three["files"].clone(), three["reclaimable"].clone()), (3.into(), 16_000.into()));
+    assert_eq!(three["groups_below_threshold"], 1);
+    let big = summary(&["--min-reclaim", "20KB"]);
+    assert_eq!((big["files"].clone(), big["reclaimable"].clone()), (2.into(), 64_000.into()));
+
+    let _ = std::fs::remove_dir_all(home);
+}
+
+#[test]
+#[cfg(unix)]
+fn unique_lists_only_files_without_a_copy() {
+    let (home, dir) = fixture(
+        "unique",
+        "file old/a.bin size=8KB\nfile old/only.bin size=9KB\ncopy new/a.bin of=old/a.bin\n\
+         file new/b.bin size=10KB\n",
+    );
+    let mut cmd = ducky(&home);
+    cmd.arg(&dir).args(["--unique", "--json", "--no-cache"]);
+    let output = cmd.assert().success().get_output().clone();
+    let unique: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let paths: Vec<&str> = unique
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|f| f["path"].as_str().unwrap().strip_prefix(dir.to_str().unwrap()).unwrap())
+        .collect();
+    assert_eq!(paths, ["/new/b.bin", "/old/only.bin"]);
+    assert_eq!(unique[1]["size"], 9_000);
+
+    let mut cmd = ducky(&home);
+    cmd.arg(&dir).args(["--unique", "--delete"]);
+    cmd.assert().failure();
+
+    let _ = std::fs::remove_dir_all(home);
+}
+
+#[test]
+fn capabilities_describe_the_build() {
+    let home = scratch("capabilities");
+    let mut cmd = ducky(&home);
+    cmd.arg("--capabilities");
+    let output = cmd.assert().success().get_output().clone();
+    let caps: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(caps["schema"], 1);
+    assert_eq!(caps["version"], env!("CARGO_PKG_VERSION"));
+    let actions = caps["actions"].as_array().unwrap();
+    let action = |name: &str| actions.iter().any(|a| a["name"] == name);
+    assert!(action("delete") && action("replicate"));
+    assert!(caps["subcommands"].as_array().unwrap().iter().any(|s| s == "doctor"));
+
+    ducky(&home).args(["--capabilities", "."]).assert().failure();
+
+    let _ = std::fs::remove_dir_all(home);
+}
+
+#[test]
+fn compare_reference_survives_protect() {
+    let spec = "file lib/a.bin size=8KB\ncopy inbox/a.bin of=lib/a.bin\n";
+    let (home, dir) = fixture("compare_protect", spec);
+    let mut cmd = ducky(&home);
+    cmd.arg(dir.join("inbox"))
+        .arg("--compare")
+        .arg(dir.join("lib"))
+        .args(["--protect", "inbox", "--delete", "--yes", "--no-cache"]);
+    cmd.assert().success();
+    // The reference copy stays the canonical; the protected inbox copy isn't touched either.
+    assert!(dir.join("lib/a.bin").exists());
+    assert!(dir.join("inbox/a.bin").exists());
+
+    let _ = std::fs::remove_dir_all(home);
+}
+
+#[test]
+fn compare_reference_survives_prefer() {
+    let spec = "file lib/a.bin size=8KB\ncopy inbox/a.bin of=lib/a.bin\n";
+    let (home, dir) = fixture("compare_prefer", spec);
+    let mut cmd = ducky(&home);
+    cmd.arg(dir.join("inbox"))
+        .arg("--compare")
+        .arg(dir.join("lib"))
+        .arg("--prefer")
+        .arg(dir.join("inbox").join("*"))
+        .args(["--delete", "--yes", "--no-cache"]);
+    cmd.assert().success();
+    // --prefer can't promote a copy over the reference: the inbox copy is the one removed.
+    assert!(dir.join("lib/a.bin").exists());
+    assert!(!dir.join("inbox/a.bin").exists());
+
+    let _ = std::fs::remove_dir_all(home);
+}
+
+#[test]
+#[cfg(unix)]
+fn compare_refuses_a_reference_that_is_a_scanned_root() {
+    let spec = "file lib/a.bin size=8KB\ncopy inbox/a.bin of=lib/a.bin\n";
+    let (home, dir) = fixture("compare_alias", spec);
+    std::os::unix::fs::symlink(dir.join("lib"), dir.join("lib-alias")).unwrap();
+    let mut cmd = ducky(&home);
+    cmd.arg(dir.join("inbox"))
+        .arg(dir.join("lib-alias"))
+        .arg("--compare")
+        .arg(dir.join("lib"))
+        .args(["--delete", "--yes", "--no-cache"]);
+    let output = cmd.assert().failure().get_output().clone();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("is the same directory as"), "got: {stderr}");
+    assert!(dir.join("lib/a.bin").exists());
+    assert!(dir.join("inbox/a.bin").exists());
+
+    let _ = std::fs::remove_dir_all(home);
+}
+
+#[test]
+fn snapshot_copies_are_kept_but_never_acted_on() {
+    let (home, dir) = fixture(
+        "snapshots",
+        "file tank/.zfs/snapshot/daily/a.bin size=8KB\n\
+         copy inbox/a.bin of=tank/.zfs/snapshot/daily/a.bin\n",
+    );
+    let snapshot = dir.join("tank/.zfs/snapshot/daily");
+    let summary = |args: &[&str]| -> serde_json::Value {
+        let mut cmd = ducky(&home);
+        cmd.args(args).args(["--summary-json", "--no-cache"]);
+        serde_json::from_slice(&cmd.assert().success().get_output().stdout).unwrap()
+    };
+    // Checking an inbox against a backup snapshot finds the copy.
+    let inbox = dir.join("inbox");
+    let compare = summary(&[inbox.to_str().unwrap(), "--compare", snapshot.to_str().unwrap()]);
+    assert_eq!(compare["groups"], 1);
+    assert_eq!(compare["reclaimable"], 8_000);
+
+    // Scanned side by side, the live file is kept and the snapshot copy isn't counted.
+    let both = summary(&[dir.to_str().unwrap(), "--hidden", "--delete", "--yes"]);
+    assert_eq!((both["groups"].clone(), both["reclaimable"].clone()), (1.into(), 0.into()));
+    assert_eq!(both["snapshot_copies"]["files"], 1);
+    assert!(snapshot.join("a.bin").exists() && inbox.join("a.bin").exists());
+
+    let _ = std::fs::remove_dir_all(home);
+}